@@ -1,6 +1,18 @@
 #[cfg(target_arch = "x86_64")]
 mod x86_64;
 #[cfg(target_arch = "x86_64")]
+pub use x86_64::context;
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::ipi;
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::peripherals::acpi;
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::peripherals::{Uart, _UART as UART};
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::smp::start_application_processors;
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::syscall;
+#[cfg(target_arch = "x86_64")]
 pub use x86_64::IMPLEMENTATION as PLATFORM_MANAGER;
 #[cfg(target_arch = "x86_64")]
 /// The platform type
@@ -0,0 +1,192 @@
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::{arch::PLATFORM_MANAGER, core_local, smp::this_core, traits::Platform};
+
+use super::{
+    peripherals::cpu::{CR0, CR4},
+    structures::ExceptionStackFrame,
+    syscall::{self, KernelGs},
+};
+
+/// The vector the device not available exception is raised on
+const DEVICE_NOT_AVAILABLE_VECTOR: u8 = 7;
+
+/// The x87 control word after `fninit`
+const DEFAULT_FPU_CONTROL_WORD: u16 = 0x037F;
+
+/// The MXCSR value after reset, with every SSE exception masked
+const DEFAULT_MXCSR: u32 = 0x1F80;
+
+/// The x87, MMX and SSE registers, as saved by `fxsave`
+#[repr(C, align(16))]
+struct FpuState([u8; 512]);
+
+impl FpuState {
+    /// The state of a thread that hasn't touched the FPU yet
+    fn new() -> Self {
+        let mut area = [0; 512];
+        area[0..2].copy_from_slice(&DEFAULT_FPU_CONTROL_WORD.to_le_bytes());
+        area[24..28].copy_from_slice(&DEFAULT_MXCSR.to_le_bytes());
+        Self(area)
+    }
+}
+
+core_local! {
+    /// The FPU state of the context running on each core, which is loaded when it first uses the FPU
+    static CURRENT_FPU: AtomicPtr<FpuState> = AtomicPtr::new(ptr::null_mut());
+}
+
+/// A thread's saved registers.
+///
+/// The callee-saved registers are pushed onto the thread's own stack when it's switched out,
+/// so only the stack pointer is kept here. The FPU is switched lazily:
+/// it's saved when a thread that used it is switched out,
+/// and only loaded when the next thread uses it, through the device not available exception
+pub struct Context {
+    rsp: usize,
+    fpu: Box<FpuState>,
+    /// The top of the context's stack, which it enters the kernel on from user mode,
+    /// or zero if it never leaves the kernel
+    kernel_stack: usize,
+}
+
+impl Context {
+    /// A context for the code already running on this core,
+    /// which is filled in the first time it's switched out
+    #[must_use]
+    pub fn new_current() -> Self {
+        Self {
+            rsp: 0,
+            fpu: Box::new(FpuState::new()),
+            kernel_stack: 0,
+        }
+    }
+
+    /// A context which calls `entry(argument)` on the given stack, with interrupts disabled.
+    /// `entry` must never return
+    ///
+    /// # Safety
+    /// The stack must be at least a page long, 16-byte aligned at its top,
+    /// and outlive the context
+    #[must_use]
+    pub unsafe fn new(stack_top: usize, entry: extern "C" fn(usize) -> !, argument: usize) -> Self {
+        // Popped by `switch_stacks`, with the return address landing in `start`
+        let start: unsafe extern "sysv64" fn() -> ! = start;
+        let frame: [usize; 7] = [0, 0, entry as usize, argument, 0, 0, start as usize];
+
+        // `start` is returned to with the stack 16-byte aligned, as `call` expects
+        let rsp = stack_top - 8 - (frame.len() - 1) * 8;
+        ptr::copy_nonoverlapping(frame.as_ptr(), rsp as *mut usize, frame.len());
+
+        Self {
+            rsp,
+            fpu: Box::new(FpuState::new()),
+            kernel_stack: stack_top,
+        }
+    }
+}
+
+/// Switch from the current context to another, saving the current one in `from`.
+/// This returns once `from` is switched back to
+///
+/// # Safety
+/// Interrupts must be disabled, and both contexts must stay alive until `from` is switched back to,
+/// or forever if it never is. `to` must be a context that isn't running
+pub unsafe fn switch(from: *mut Context, to: *const Context) {
+    let mut cr0 = CR0::get();
+    // The task switched flag is only clear if the outgoing thread used the FPU
+    if !cr0.contains(CR0::TASK_SWITCHED) {
+        let fpu: *mut FpuState = &mut *(*from).fpu;
+        asm!("fxsave64 [{}]", in(reg) fpu, options(nostack, preserves_flags));
+        cr0.insert(CR0::TASK_SWITCHED);
+        cr0.update();
+    }
+
+    if let Some(current) = CURRENT_FPU.try_get_on(this_core().index) {
+        let fpu: *const FpuState = &*(*to).fpu;
+        current.store(fpu.cast_mut(), Ordering::Relaxed);
+    }
+
+    if (*to).kernel_stack != 0 {
+        syscall::set_kernel_stack((*to).kernel_stack);
+    }
+
+    switch_stacks(ptr::addr_of_mut!((*from).rsp), (*to).rsp);
+}
+
+/// Push the callee-saved registers, save the stack pointer in `from` and switch to `to`,
+/// popping the registers it pushed
+#[naked]
+unsafe extern "sysv64" fn switch_stacks(_from: *mut usize, _to: usize) {
+    asm!(
+        "push rbp",
+        "push rbx",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbx",
+        "pop rbp",
+        "ret",
+        options(noreturn)
+    )
+}
+
+/// Where new contexts start, with their entry point in r13 and its argument in r12
+#[naked]
+unsafe extern "sysv64" fn start() -> ! {
+    asm!("mov rdi, r12", "call r13", "ud2", options(noreturn))
+}
+
+/// Install the device not available handler, which loads the FPU state
+///
+/// # Safety
+/// This must only be called once the interrupt manager is initialized,
+/// while nothing else is using the IDT
+pub unsafe fn init() {
+    PLATFORM_MANAGER
+        .get_interrupt_manager()
+        .set_vector_handler(DEVICE_NOT_AVAILABLE_VECTOR, device_not_available_interrupt);
+}
+
+/// Enable `fxsave` and make the current core trap on its first use of the FPU.
+/// The core must have been registered with the core manager
+pub fn init_current_core() {
+    CURRENT_FPU.get();
+
+    let mut cr4 = CR4::get();
+    cr4.insert(CR4::OSFXSR | CR4::OSXMMEXCPT);
+    cr4.update();
+
+    let mut cr0 = CR0::get();
+    cr0.remove(CR0::EMULATION);
+    cr0.insert(CR0::MONITOR_COPROCESSOR | CR0::TASK_SWITCHED);
+    cr0.update();
+}
+
+/// The device not available handler, raised the first time a context uses the FPU after a switch
+extern "x86-interrupt" fn device_not_available_interrupt(frame: &mut ExceptionStackFrame) {
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(DEVICE_NOT_AVAILABLE_VECTOR);
+    let fpu = CURRENT_FPU
+        .try_get_on(this_core().index)
+        .map_or(ptr::null_mut(), |current| current.load(Ordering::Relaxed));
+
+    unsafe {
+        asm!("clts", options(nostack, preserves_flags));
+        if fpu.is_null() {
+            asm!("fninit", options(nostack, preserves_flags));
+        } else {
+            asm!("fxrstor64 [{}]", in(reg) fpu, options(nostack, preserves_flags));
+        }
+    }
+}
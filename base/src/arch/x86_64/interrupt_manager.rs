@@ -1,11 +1,91 @@
-use core::cell::UnsafeCell;
+use core::{cell::UnsafeCell, ptr};
 
 use crate::{
+    core_local,
     errors::{GenericError, InterruptManagerError},
+    memory::addresses::{Address, Virtual},
     traits::{Init, InterruptManager as InterruptManagerTrait},
 };
 
-use super::structures::InterruptDescriptorTable;
+use super::structures::{
+    ExceptionStackFrame, GlobalDescriptorTable, InterruptDescriptor, InterruptDescriptorTable,
+    InterruptDescriptorTypeAttributes, SizedDescriptorTable, SystemSegmentDescriptorLongMode,
+    TaskStateSegment, GDT,
+};
+
+/// The size of each of a core's interrupt stacks
+const INTERRUPT_STACK_SIZE: usize = 16 * 1024;
+
+/// A core's GDT and TSS.
+/// Each core needs its own, as loading a TSS marks its descriptor busy
+struct DescriptorTables {
+    gdt: UnsafeCell<[u64; 9]>,
+    tss: UnsafeCell<TaskStateSegment>,
+}
+
+unsafe impl Send for DescriptorTables {}
+unsafe impl Sync for DescriptorTables {}
+
+impl DescriptorTables {
+    /// Make a core's tables, with its own interrupt stacks
+    fn new() -> Self {
+        let mut tss = TaskStateSegment::new_blank();
+        for index in [
+            TaskStateSegment::DOUBLE_FAULT_IST,
+            TaskStateSegment::NMI_IST,
+            TaskStateSegment::MACHINE_CHECK_IST,
+        ] {
+            tss.set_interrupt_stack(index, Self::allocate_stack());
+        }
+
+        Self {
+            gdt: UnsafeCell::new(unsafe { GDT }),
+            tss: UnsafeCell::new(tss),
+        }
+    }
+
+    /// Allocate an interrupt stack, which is never freed, and get its top.
+    /// It's made of u128s so the top is 16-byte aligned
+    fn allocate_stack() -> Address<Virtual> {
+        let stack: Vec<u128> = core::iter::repeat(0)
+            .take(INTERRUPT_STACK_SIZE / 16)
+            .collect();
+        Address::<Virtual>::new(stack.leak().as_ptr_range().end.cast())
+            .expect("The heap isn't canonical")
+    }
+}
+
+core_local! {
+    /// Each core's GDT and TSS
+    static DESCRIPTOR_TABLES: DescriptorTables = DescriptorTables::new();
+}
+
+/// Set the stack the current core switches to when an interrupt arrives from user mode.
+/// The core must have been registered with the core manager
+pub(super) fn set_privileged_stack(top: Address<Virtual>) {
+    let tss = DESCRIPTOR_TABLES.get().tss.get();
+    unsafe { ptr::addr_of_mut!((*tss).rsp[0]).write_unaligned(top) };
+}
+
+/// The interrupt enable flag in RFLAGS
+const INTERRUPT_FLAG: u64 = 1 << 9;
+
+/// Run a closure with interrupts disabled on this core, restoring them afterwards if they were enabled
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    // Host tests run in user mode, where the interrupt flag can't be changed
+    if cfg!(test) {
+        return f();
+    }
+    let flags: u64;
+    unsafe { asm!("pushfq", "pop {}", "cli", out(reg) flags) }
+
+    let result = f();
+
+    if flags & INTERRUPT_FLAG != 0 {
+        unsafe { asm!("sti") }
+    }
+    result
+}
 
 pub struct InterruptManager {
     idt: UnsafeCell<InterruptDescriptorTable>,
@@ -27,6 +107,56 @@ impl InterruptManager {
     pub unsafe fn idt(&self) -> &mut InterruptDescriptorTable {
         &mut *(self.idt.get())
     }
+
+    /// Load the current core's GDT, which the interrupt gates' selectors refer to,
+    /// and its TSS, which holds the interrupt stacks.
+    /// The core must have been registered with the core manager
+    fn load_gdt() {
+        let tables = DESCRIPTOR_TABLES.get();
+        let gdt = unsafe { &mut *tables.gdt.get() };
+
+        // Written on every load, as loading the TSS marks it busy
+        let index = usize::from(GlobalDescriptorTable::TSS >> 3);
+        let tss: [u64; 2] = unsafe {
+            core::mem::transmute(SystemSegmentDescriptorLongMode::new_tss(tables.tss.get()))
+        };
+        gdt[index..index + 2].copy_from_slice(&tss);
+
+        let pointer = SizedDescriptorTable {
+            limit: (core::mem::size_of_val(gdt) - 1) as u16,
+            base: gdt.as_ptr() as u64,
+        };
+        GlobalDescriptorTable::apply(&pointer as *const _ as usize);
+        unsafe { GlobalDescriptorTable::load_tss(GlobalDescriptorTable::TSS) };
+    }
+
+    /// Load the current core's GDT and TSS, and the shared IDT.
+    /// The IDT must have been filled in by [`Init::init`] on the bootstrap core
+    pub fn load_current_core(&self) {
+        Self::load_gdt();
+        unsafe { self.idt() }.load();
+    }
+
+    /// Point an interrupt vector at a handler
+    ///
+    /// # Safety
+    /// The handler must be safe to run whenever the vector is raised,
+    /// and no other references to the IDT may exist. As it can interrupt user mode,
+    /// it has to start with a [`KernelGs`](super::syscall::KernelGs)
+    pub unsafe fn set_vector_handler(
+        &self,
+        vector: u8,
+        handler: extern "x86-interrupt" fn(&mut ExceptionStackFrame),
+    ) {
+        self.idt().inner[usize::from(vector)] = InterruptDescriptor::new_interrupt()
+            .set_type_attributes(
+                InterruptDescriptorTypeAttributes::new_interrupt()
+                    .set_present()
+                    .set_privilege_level(0),
+            )
+            .set_isr_address(handler)
+            .set_segment(GlobalDescriptorTable::KCODE);
+    }
 }
 
 unsafe impl InterruptManagerTrait for InterruptManager {
@@ -40,6 +170,21 @@ unsafe impl InterruptManagerTrait for InterruptManager {
         Ok(())
     }
 
+    fn interrupts_enabled(&self) -> bool {
+        let flags: u64;
+        unsafe { asm!("pushfq", "pop {}", out(reg) flags, options(preserves_flags)) }
+        flags & INTERRUPT_FLAG != 0
+    }
+
+    fn without_interrupts<R>(&self, f: impl FnOnce() -> R) -> R {
+        without_interrupts(f)
+    }
+
+    fn wait_for_interrupt(&self) {
+        // `sti` only takes effect after the next instruction, so nothing arrives before `hlt`
+        unsafe { asm!("sti", "hlt", options(nomem, nostack)) }
+    }
+
     fn set_handler<T: Fn(crate::interrupts::InterruptType)>(
         &self,
         _func: &T,
@@ -54,6 +199,9 @@ impl Init for InterruptManager {
     type Input = ();
 
     fn init(&self, _: Self::Input) -> Result<(), Self::Error> {
+        // Interrupt gates use the kernel's selectors, so its GDT has to be loaded first
+        Self::load_gdt();
+
         unsafe { super::structures::install_interrupt_handler() }
         Ok(())
     }
@@ -0,0 +1,394 @@
+use core::{
+    ops::Range,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    core_local,
+    errors::IpiError,
+    smp::{this_core, try_this_core, MAX_CORES},
+    sync::Mutex,
+    traits::Platform,
+};
+
+pub use super::peripherals::apic::IpiTarget;
+use super::{peripherals::apic::LocalApic, structures::ExceptionStackFrame, syscall::KernelGs};
+use crate::arch::x86_64::interrupt_manager::without_interrupts;
+
+/// The vectors reserved for inter-processor interrupts, just below the spurious vector
+pub const IPI_VECTORS: Range<u8> = 0xF0..0xFF;
+
+/// The vector TLB shootdowns are sent on
+pub const TLB_SHOOTDOWN_VECTOR: u8 = 0xF0;
+
+/// The vector cross-core function calls are sent on
+pub const CALL_FUNCTION_VECTOR: u8 = 0xF1;
+
+/// The vector a core is sent to get it to look at its run queue
+pub const RESCHEDULE_VECTOR: u8 = 0xF2;
+
+/// The most ranges a shootdown carries before the whole TLB is flushed instead
+const MAX_SHOOTDOWN_RANGES: usize = 8;
+
+/// The most pages a shootdown invalidates one by one before the whole TLB is flushed instead
+const MAX_SHOOTDOWN_PAGES: usize = 64;
+
+/// The size of a page
+const PAGE_SIZE: usize = 4096;
+
+/// A set of cores, by index
+struct CoreSet([AtomicU64; MAX_CORES / 64]);
+
+impl CoreSet {
+    /// A word with no cores in it
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: AtomicU64 = AtomicU64::new(0);
+
+    const fn new() -> Self {
+        Self([Self::EMPTY; MAX_CORES / 64])
+    }
+
+    fn insert(&self, index: usize) {
+        self.0[index / 64].fetch_or(1 << (index % 64), Ordering::AcqRel);
+    }
+
+    fn remove(&self, index: usize) {
+        self.0[index / 64].fetch_and(!(1 << (index % 64)), Ordering::AcqRel);
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.0[index / 64].load(Ordering::Acquire) & (1 << (index % 64)) != 0
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|word| word.load(Ordering::Acquire) == 0)
+    }
+
+    /// Iterate over the cores in the set, as it was when each word was read
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(word_index, word)| {
+            let word = word.load(Ordering::Acquire);
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| word_index * 64 + bit)
+        })
+    }
+}
+
+/// Cores with their IDT loaded and interrupts enabled, which IPIs can be sent to
+static ONLINE: CoreSet = CoreSet::new();
+
+/// The TLB shootdown in flight. Only one is sent at a time, under its lock
+struct Shootdown {
+    lock: Mutex<()>,
+    /// The start and page count of each range
+    ranges: [(AtomicUsize, AtomicUsize); MAX_SHOOTDOWN_RANGES],
+    /// The number of ranges, or [`Shootdown::FLUSH_ALL`] to flush everything
+    count: AtomicUsize,
+    /// The cores that haven't invalidated the ranges yet
+    pending: CoreSet,
+}
+
+impl Shootdown {
+    /// A range that hasn't been filled in
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY_RANGE: (AtomicUsize, AtomicUsize) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+    /// The count standing in for a full flush
+    const FLUSH_ALL: usize = usize::MAX;
+
+    const fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+            ranges: [Self::EMPTY_RANGE; MAX_SHOOTDOWN_RANGES],
+            count: AtomicUsize::new(0),
+            pending: CoreSet::new(),
+        }
+    }
+}
+
+static SHOOTDOWN: Shootdown = Shootdown::new();
+
+/// A function another core asked this one to call
+struct Call {
+    func: fn(usize),
+    argument: usize,
+    /// Set once the function has returned, if the caller is waiting
+    done: *const AtomicBool,
+}
+
+unsafe impl Send for Call {}
+
+core_local! {
+    /// The functions other cores have asked each core to call
+    static CALLS: Mutex<VecDeque<Call>> = Mutex::new(VecDeque::new());
+}
+
+/// Install the IPI handlers
+///
+/// # Safety
+/// This must only be called once the interrupt manager is initialized,
+/// while nothing else is using the IDT
+pub unsafe fn init() {
+    let interrupts = PLATFORM_MANAGER.get_interrupt_manager();
+    interrupts.set_vector_handler(TLB_SHOOTDOWN_VECTOR, tlb_shootdown_interrupt);
+    interrupts.set_vector_handler(CALL_FUNCTION_VECTOR, call_function_interrupt);
+    interrupts.set_vector_handler(RESCHEDULE_VECTOR, reschedule_interrupt);
+}
+
+/// Mark the current core as able to take IPIs.
+/// Its IDT must be loaded, its Local APIC enabled and interrupts enabled
+pub fn mark_online() {
+    if let Some(core) = try_this_core() {
+        ONLINE.insert(core.index);
+    }
+}
+
+/// Send an inter-processor interrupt
+///
+/// # Panics
+/// This will panic if the vector isn't in [`IPI_VECTORS`]
+pub fn send(target: IpiTarget, vector: u8) {
+    assert!(
+        IPI_VECTORS.contains(&vector),
+        "Vector {vector:#X} isn't reserved for IPIs"
+    );
+
+    // Without a Local APIC there's only one core to send to
+    if let Some(apic) = LocalApic::current() {
+        without_interrupts(|| apic.send_ipi(target, vector));
+    }
+}
+
+/// Send an inter-processor interrupt to the core with the given index
+///
+/// # Errors
+/// This will return an error if the core isn't online
+pub fn send_to_core(index: usize, vector: u8) -> Result<(), IpiError> {
+    if !ONLINE.contains(index) {
+        return Err(IpiError::CoreNotOnline);
+    }
+    let core = crate::CORE_MANAGER
+        .get_core_by_index(index)
+        .ok_or(IpiError::CoreNotOnline)?;
+    send(IpiTarget::Core(core.id), vector);
+    Ok(())
+}
+
+/// Get the number of pages a range touches
+const fn pages_in(range: &Range<usize>) -> usize {
+    let start = range.start / PAGE_SIZE;
+    let end = (range.end + PAGE_SIZE - 1) / PAGE_SIZE;
+    end.saturating_sub(start)
+}
+
+/// Invalidate the page containing the given address on this core
+fn invalidate_page(address: usize) {
+    unsafe { asm!("invlpg [{}]", in(reg) address, options(nostack, preserves_flags)) }
+}
+
+/// Flush every non-global translation on this core
+fn flush_all() {
+    unsafe {
+        asm!(
+            "mov {0}, cr3",
+            "mov cr3, {0}",
+            out(reg) _,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// Invalidate the current shootdown's ranges on this core, if it's waiting on this core
+fn service_shootdown(index: usize) {
+    if !SHOOTDOWN.pending.contains(index) {
+        return;
+    }
+
+    let count = SHOOTDOWN.count.load(Ordering::Acquire);
+    if count == Shootdown::FLUSH_ALL {
+        flush_all();
+    } else {
+        for (start, pages) in &SHOOTDOWN.ranges[..count] {
+            let start = start.load(Ordering::Relaxed);
+            for page in 0..pages.load(Ordering::Relaxed) {
+                invalidate_page(start + page * PAGE_SIZE);
+            }
+        }
+    }
+
+    SHOOTDOWN.pending.remove(index);
+}
+
+/// Invalidate the given virtual address ranges on every core, and wait for them all to finish.
+/// Large or numerous ranges flush the whole TLB instead
+///
+/// Other cores only take part while they have interrupts enabled,
+/// so this mustn't be called while holding a lock they may spin on with interrupts disabled
+pub fn shootdown(ranges: &[Range<usize>]) {
+    let pages: usize = ranges.iter().map(pages_in).sum();
+    let everything = ranges.len() > MAX_SHOOTDOWN_RANGES || pages > MAX_SHOOTDOWN_PAGES;
+
+    let flush_local = || {
+        if everything {
+            flush_all();
+        } else {
+            for range in ranges {
+                for page in (range.start & !(PAGE_SIZE - 1)..range.end).step_by(PAGE_SIZE) {
+                    invalidate_page(page);
+                }
+            }
+        }
+    };
+
+    // Before this core is registered, it's the only one running
+    let this = match try_this_core() {
+        Some(core) => core.index,
+        None => return flush_local(),
+    };
+
+    // Another core's shootdown may be waiting on this one
+    let _guard = loop {
+        if let Some(guard) = SHOOTDOWN.lock.try_lock() {
+            break guard;
+        }
+        service_shootdown(this);
+        core::hint::spin_loop();
+    };
+
+    if everything {
+        SHOOTDOWN
+            .count
+            .store(Shootdown::FLUSH_ALL, Ordering::Release);
+    } else {
+        for (range, (start, count)) in ranges.iter().zip(&SHOOTDOWN.ranges) {
+            start.store(range.start & !(PAGE_SIZE - 1), Ordering::Relaxed);
+            count.store(pages_in(range), Ordering::Relaxed);
+        }
+        SHOOTDOWN.count.store(ranges.len(), Ordering::Release);
+    }
+
+    for index in ONLINE.iter().filter(|&index| index != this) {
+        SHOOTDOWN.pending.insert(index);
+    }
+    if !SHOOTDOWN.pending.is_empty() {
+        send(IpiTarget::AllButSelf, TLB_SHOOTDOWN_VECTOR);
+    }
+
+    flush_local();
+
+    while !SHOOTDOWN.pending.is_empty() {
+        core::hint::spin_loop();
+    }
+}
+
+/// Queue a call on the core with the given index, or make it now if that's this core
+fn queue_call(index: usize, call: Call) -> Result<(), IpiError> {
+    if try_this_core().map(|core| core.index) == Some(index) {
+        run_call(&call);
+        return Ok(());
+    }
+    if !ONLINE.contains(index) {
+        return Err(IpiError::CoreNotOnline);
+    }
+
+    CALLS.get_on(index).lock().push_back(call);
+    send_to_core(index, CALL_FUNCTION_VECTOR)
+}
+
+/// Call a function, and signal its caller if it's waiting
+fn run_call(call: &Call) {
+    (call.func)(call.argument);
+    if let Some(done) = unsafe { call.done.as_ref() } {
+        done.store(true, Ordering::Release);
+    }
+}
+
+/// Call `func(argument)` on the core with the given index, from an interrupt handler.
+/// This returns without waiting for the call to be made
+///
+/// # Errors
+/// This will return an error if the core isn't online
+pub fn call_on_core(index: usize, func: fn(usize), argument: usize) -> Result<(), IpiError> {
+    queue_call(
+        index,
+        Call {
+            func,
+            argument,
+            done: ptr::null(),
+        },
+    )
+}
+
+/// Call `func(argument)` on the core with the given index, from an interrupt handler,
+/// and wait for it to return
+///
+/// # Errors
+/// This will return an error if the core isn't online
+pub fn call_on_core_and_wait(
+    index: usize,
+    func: fn(usize),
+    argument: usize,
+) -> Result<(), IpiError> {
+    let done = AtomicBool::new(false);
+    queue_call(
+        index,
+        Call {
+            func,
+            argument,
+            done: &done,
+        },
+    )?;
+
+    while !done.load(Ordering::Acquire) {
+        // The other core may be waiting for a shootdown before it gets to this
+        if let Some(core) = try_this_core() {
+            service_shootdown(core.index);
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+/// Signal the end of an IPI
+fn end_of_interrupt() {
+    if let Some(apic) = LocalApic::current() {
+        apic.end_of_interrupt();
+    }
+}
+
+/// The TLB shootdown handler
+extern "x86-interrupt" fn tlb_shootdown_interrupt(frame: &mut ExceptionStackFrame) {
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(TLB_SHOOTDOWN_VECTOR);
+    service_shootdown(this_core().index);
+    end_of_interrupt();
+}
+
+/// The cross-core function call handler. Popping calls never frees memory,
+/// so this is safe even if the heap was locked when it was interrupted
+extern "x86-interrupt" fn call_function_interrupt(frame: &mut ExceptionStackFrame) {
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(CALL_FUNCTION_VECTOR);
+    if let Some(calls) = CALLS.try_get_on(this_core().index) {
+        loop {
+            let call = calls.lock().pop_front();
+            match call {
+                Some(call) => run_call(&call),
+                None => break,
+            }
+        }
+    }
+    end_of_interrupt();
+}
+
+/// The reschedule handler. The sender has already asked for a reschedule,
+/// so this only has to get to the end of an interrupt
+extern "x86-interrupt" fn reschedule_interrupt(frame: &mut ExceptionStackFrame) {
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(RESCHEDULE_VECTOR);
+    end_of_interrupt();
+    crate::scheduler::preempt();
+}
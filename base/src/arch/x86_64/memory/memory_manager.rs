@@ -1,14 +1,50 @@
+use core::ops::Range;
+
 use log::{error, trace};
 
 use crate::{
     errors::MemoryManagerError,
-    memory::addresses::{Address, Physical, Virtual},
+    get_memory_manager,
+    memory::{
+        addresses::{Address, Physical, Virtual},
+        USER_SPACE_END, USER_SPACE_START,
+    },
     traits::{Init, MemoryFlags},
 };
 
 use crate::{memory::addresses::AlignedAddress, traits::MemoryManager as MemoryManagerTrait};
 
-use super::{addresses::AddressWithFlags, tables::TableLevel4};
+use super::{
+    addresses::AddressWithFlags,
+    tables::{TableLevel4, FRAME_LAYOUT},
+};
+use crate::arch::x86_64::ipi;
+
+/// The level 4 entries covering user space, which each address space has its own of
+const USER_TABLE_INDICES: Range<usize> = (USER_SPACE_START >> 39)..(USER_SPACE_END >> 39);
+
+/// The level 4 entries covering the upper half, which every address space shares
+const KERNEL_TABLE_INDICES: Range<usize> = 256..512;
+
+/// The flags page tables are made with, which don't restrict the mappings under them
+const TABLE_FLAGS: MemoryFlags = MemoryFlags::from_bits_truncate(
+    MemoryFlags::KERNEL_ONLY.bits()
+        | MemoryFlags::READABLE.bits()
+        | MemoryFlags::WRITABLE.bits()
+        | MemoryFlags::EXECUTABLE.bits(),
+);
+
+/// Free a page table, which was allocated and mapped like [`TableLevel4::sub_table_create`] does
+///
+/// # Safety
+/// Nothing may refer to the table any more
+unsafe fn free_table<T>(table: &mut T) -> Result<(), MemoryManagerError> {
+    let table: *mut T = table;
+    let address =
+        AlignedAddress::<Virtual>::new(table.cast()).map_err(MemoryManagerError::Address)?;
+    let memory_manager = get_memory_manager();
+    memory_manager.deallocate_and_unmap(memory_manager.get_current_table()?, address, FRAME_LAYOUT)
+}
 
 /// I'm not gonna have this hold data rn, might later for reasons.
 pub struct MemoryManager {}
@@ -36,7 +72,8 @@ unsafe impl MemoryManagerTrait for MemoryManager {
     }
 
     /// Map the specified frame to the destination, with the option to provide additional flags
-    /// These flags will be applied to all **created** frames, it will not affect already present entries
+    /// The tables created on the way are as permissive as the flags' privilege level allows,
+    /// so they don't restrict what's mapped under them later
     unsafe fn map(
         &self,
         rtable: &mut Self::RootTable,
@@ -45,8 +82,10 @@ unsafe impl MemoryManagerTrait for MemoryManager {
         flags: MemoryFlags,
     ) -> Result<(), MemoryManagerError> {
         let dst = dst.inner();
+        let table_flags =
+            flags | MemoryFlags::READABLE | MemoryFlags::WRITABLE | MemoryFlags::EXECUTABLE;
 
-        let p3 = rtable.sub_table_create(dst.p4_index(), flags)?;
+        let p3 = rtable.sub_table_create(dst.p4_index(), table_flags)?;
 
         if p3.data[dst.p3_index()]
             .get_flags()
@@ -55,7 +94,7 @@ unsafe impl MemoryManagerTrait for MemoryManager {
             return Err(MemoryManagerError::CannotMapToHugePage);
         }
 
-        let p2 = p3.sub_table_create(dst.p3_index(), flags)?;
+        let p2 = p3.sub_table_create(dst.p3_index(), table_flags)?;
 
         if p2.data[dst.p2_index()]
             .get_flags()
@@ -64,7 +103,7 @@ unsafe impl MemoryManagerTrait for MemoryManager {
             return Err(MemoryManagerError::CannotMapToHugePage);
         }
 
-        let p1 = p2.sub_table_create(dst.p2_index(), flags)?;
+        let p1 = p2.sub_table_create(dst.p2_index(), table_flags)?;
 
         let _frame = p1.frame_set_specified(dst.p1_index(), src, flags);
 
@@ -110,6 +149,114 @@ unsafe impl MemoryManagerTrait for MemoryManager {
         Ok(())
     }
 
+    /// Change the flags of the specified virtual address, keeping the frame it's mapped to
+    unsafe fn protect(
+        &self,
+        rtable: &mut Self::RootTable,
+        addr: AlignedAddress<Virtual>,
+        flags: MemoryFlags,
+    ) -> Result<(), MemoryManagerError> {
+        let addr = addr.inner();
+
+        let p3 = rtable
+            .sub_table_mut(addr.p4_index())
+            .ok_or(MemoryManagerError::AddressUnmapped)?;
+
+        if p3.data[addr.p3_index()]
+            .get_flags()
+            .contains(AddressWithFlags::HUGE_PAGE)
+        {
+            return Err(MemoryManagerError::CannotMapToHugePage);
+        }
+
+        let p2 = p3
+            .sub_table_mut(addr.p3_index())
+            .ok_or(MemoryManagerError::AddressUnmapped)?;
+
+        if p2.data[addr.p2_index()]
+            .get_flags()
+            .contains(AddressWithFlags::HUGE_PAGE)
+        {
+            return Err(MemoryManagerError::CannotMapToHugePage);
+        }
+
+        let p1 = p2
+            .sub_table_mut(addr.p2_index())
+            .ok_or(MemoryManagerError::AddressUnmapped)?;
+
+        let frame = *p1
+            .frame(addr.p1_index())
+            .ok_or(MemoryManagerError::AddressUnmapped)?;
+        p1.frame_set_specified(addr.p1_index(), frame, flags);
+
+        Ok(())
+    }
+
+    /// Invalidate the ranges on this core, and shoot them down on every other core
+    fn flush_tlb(&self, ranges: &[Range<usize>]) {
+        ipi::shootdown(ranges);
+    }
+
+    /// Share the upper half's level 3 tables, making any that are missing first
+    unsafe fn new_user_table(
+        &self,
+        kernel: &mut TableLevel4,
+    ) -> Result<&'static mut TableLevel4, MemoryManagerError> {
+        for index in KERNEL_TABLE_INDICES {
+            kernel.sub_table_create(index, TABLE_FLAGS)?;
+        }
+
+        let memory_manager = get_memory_manager();
+        let mut address = memory_manager.allocate_and_map(
+            memory_manager.get_current_table()?,
+            (*crate::SAFE_UPPER_HALF_RANGE).clone(),
+            TABLE_FLAGS,
+            FRAME_LAYOUT,
+        )?;
+        let table = &mut *address.get_inner_ptr_mut().cast::<TableLevel4>();
+        for (index, entry) in table.data.iter_mut().enumerate() {
+            *entry = kernel.data[index].clone();
+            if USER_TABLE_INDICES.contains(&index) {
+                entry.0 = AddressWithFlags::none();
+            }
+        }
+        Ok(table)
+    }
+
+    /// Free the level 3, 2 and 1 tables under user space, and then the root
+    unsafe fn free_user_table(
+        &self,
+        table: &'static mut TableLevel4,
+    ) -> Result<(), MemoryManagerError> {
+        for index in USER_TABLE_INDICES {
+            let p3 = match table.sub_table_mut(index) {
+                Some(p3) => p3,
+                None => continue,
+            };
+            for p3_entry in &mut p3.data {
+                if p3_entry.get_flags().contains(AddressWithFlags::HUGE_PAGE) {
+                    continue;
+                }
+                let p2 = match p3_entry.get_item_mut() {
+                    Some(p2) => p2,
+                    None => continue,
+                };
+                for p2_entry in &mut p2.data {
+                    if p2_entry.get_flags().contains(AddressWithFlags::HUGE_PAGE) {
+                        continue;
+                    }
+                    if let Some(p1) = p2_entry.get_item_mut() {
+                        free_table(p1)?;
+                    }
+                }
+                free_table(p2)?;
+            }
+            free_table(p3)?;
+        }
+
+        free_table(table)
+    }
+
     /// Convert a given virtual address to its physical counterpart
     fn virtual_to_physical(
         &self,
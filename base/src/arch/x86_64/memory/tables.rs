@@ -9,7 +9,8 @@ use crate::{
 
 use super::addresses::AddressWithFlags;
 
-const FRAME_LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(4096, 4096) };
+/// The layout of a single frame, which every page table takes up
+pub const FRAME_LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(4096, 4096) };
 
 /// Allocate a frame with a generic physical allocator
 pub fn allocate_frame<A: PhysicalAllocator>(
@@ -1,11 +1,17 @@
-use log::info;
+use log::{info, warn};
 
-use crate::traits::{Init, Platform};
+use crate::{
+    smp::CoreLocalData,
+    traits::{Init, InterruptManager as InterruptManagerTrait, Platform},
+};
 
 use self::{
     interrupt_manager::InterruptManager,
     memory::{addresses::RawAddress, memory_manager::MemoryManager},
-    peripherals::{TimerManager, Uart},
+    peripherals::{
+        cpu::{read_msr, write_msr},
+        SerialConsole, TimerManager,
+    },
     power_manager::PowerManager,
 };
 
@@ -22,10 +28,30 @@ pub mod interrupt_manager;
 
 mod power_manager;
 
+/// Starting the application processors
+pub mod smp;
+
+/// Inter-processor interrupts, TLB shootdowns and cross-core calls
+pub mod ipi;
+
+/// Thread contexts and switching between them
+pub mod context;
+
+/// User mode, and the `syscall` entry into the kernel
+pub mod syscall;
+
+/// The MSR holding the GS segment's base, which points to the current core's local data
+const GS_BASE_MSR: u32 = 0xC000_0101;
+
+/// The MSR `swapgs` exchanges with the GS segment's base, which holds the user's GS base
+/// while the kernel runs
+const KERNEL_GS_BASE_MSR: u32 = 0xC000_0102;
+
 pub struct X86_64 {
     memory_manager: MemoryManager,
     interrupt_manager: InterruptManager,
     power_manager: PowerManager,
+    timer_manager: TimerManager,
 }
 
 impl X86_64 {
@@ -34,6 +60,7 @@ impl X86_64 {
             memory_manager: MemoryManager::new(),
             interrupt_manager: InterruptManager::new(),
             power_manager: PowerManager::new(),
+            timer_manager: TimerManager::new(),
         }
     }
 }
@@ -49,7 +76,7 @@ unsafe impl Platform for X86_64 {
 
     type RawAddress = RawAddress;
 
-    type TextOutput = Uart;
+    type TextOutput = SerialConsole;
 
     fn get_memory_manager(&'static self) -> &'static Self::MemoryManager {
         &self.memory_manager
@@ -63,18 +90,52 @@ unsafe impl Platform for X86_64 {
         &self.power_manager
     }
 
-    fn get_text_output(&'static self) -> &'static mut Self::TextOutput {
-        // UART.get()
-        // TODO: Add a ring buffer-ish solution, to prevent locking between kernel threads
-        todo!()
+    fn get_timer_manager(&'static self) -> &'static Self::TimerManager {
+        &self.timer_manager
+    }
+
+    fn get_text_output(&'static self) -> Self::TextOutput {
+        SerialConsole
     }
 
     fn initialize_current_core(&'static self) {
-        todo!()
+        self.interrupt_manager.load_current_core();
+        context::init_current_core();
+        syscall::init_current_core();
+        self.timer_manager.init_current_core();
+        if let Err(e) = self.interrupt_manager.enable_interrupts() {
+            warn!("Couldn't enable interrupts on this core: {e:?}");
+            return;
+        }
+        ipi::mark_online();
+    }
+
+    fn get_core_local(&'static self) -> &'static CoreLocalData {
+        // The data's first field points to itself
+        let data: *const CoreLocalData;
+        unsafe {
+            asm!(
+                "mov {}, gs:[0]",
+                out(reg) data,
+                options(nostack, preserves_flags, readonly)
+            );
+            &*data
+        }
+    }
+
+    fn try_get_core_local(&'static self) -> Option<&'static CoreLocalData> {
+        let base = unsafe { read_msr(GS_BASE_MSR) };
+        (base != 0).then(|| self.get_core_local())
     }
 
-    fn get_core_local(&'static self) -> &'static crate::smp::CoreLocalData {
-        todo!()
+    fn set_core_local(&'static self, data: &'static CoreLocalData) {
+        let base = data as *const CoreLocalData as u64;
+        unsafe {
+            write_msr(GS_BASE_MSR, base);
+            // User mode starts with no GS base, and gets it swapped in by `swapgs` on the way out.
+            // Every way in from user mode swaps the core's local data back
+            write_msr(KERNEL_GS_BASE_MSR, 0);
+        }
     }
 }
 
@@ -83,6 +144,8 @@ pub enum X86_64InitError {
     MemoryManager(<<X86_64 as Platform>::MemoryManager as Init>::Error),
     InterruptManager(<<X86_64 as Platform>::InterruptManager as Init>::Error),
     PowerManager(<<X86_64 as Platform>::PowerManager as Init>::Error),
+    TimerManager(<<X86_64 as Platform>::TimerManager as Init>::Error),
+    InterruptsUnavailable(crate::errors::InterruptManagerError),
 }
 
 impl Init for X86_64 {
@@ -101,11 +164,35 @@ impl Init for X86_64 {
             return Err(X86_64InitError::InterruptManager(e));
         }
 
+        info!("Loading ACPI namespace");
+        if let Err(e) = peripherals::acpi::init() {
+            // Power management falls back to legacy methods without it
+            warn!("Failed to load the ACPI namespace: {e:?}");
+        }
+
         info!("Initializing Power Manager");
         if let Err(e) = self.power_manager.init(()) {
             return Err(X86_64InitError::PowerManager(e));
         }
 
+        info!("Initializing Timer Manager");
+        if let Err(e) = self.timer_manager.init(()) {
+            return Err(X86_64InitError::TimerManager(e));
+        }
+
+        unsafe {
+            ipi::init();
+            context::init();
+        }
+        context::init_current_core();
+        syscall::init_current_core();
+
+        info!("Enabling interrupts");
+        if let Err(e) = self.interrupt_manager.enable_interrupts() {
+            return Err(X86_64InitError::InterruptsUnavailable(e));
+        }
+        ipi::mark_online();
+
         Ok(())
     }
 }
@@ -0,0 +1,2255 @@
+// AML integers are always 64-bit and get narrowed to whatever the hardware or
+// encoding calls for, and the opcode dispatch is one long match by nature
+#![allow(clippy::cast_possible_truncation, clippy::too_many_lines)]
+
+use log::{debug, trace, warn};
+
+extern crate alloc;
+use alloc::format;
+
+use crate::errors::AmlError;
+
+use super::{
+    name::{path_to_string, AmlPath, NameSeg},
+    stream::Stream,
+    value::{
+        AmlValue, Container, FieldKind, FieldUnit, Method, MethodCode, OpRegion, Reference,
+        RegionSpace,
+    },
+    AmlContext, Frame, PciAddress,
+};
+
+/// How execution continues after a term
+pub(super) enum Flow {
+    /// Carry on with the next term
+    Normal,
+    /// Return from the current method
+    Return(AmlValue),
+    /// Break out of the current `While`
+    Break,
+    /// Start the next iteration of the current `While`
+    Continue,
+}
+
+/// Where the result of an operation is stored
+enum Target {
+    /// The result is discarded
+    Null,
+    /// The result is logged
+    Debug,
+    /// A local variable in the current frame
+    Local(usize),
+    /// An argument in the current frame
+    Arg(usize),
+    /// A named object
+    Named(AmlPath),
+    /// A reference returned by `Index` or `RefOf`
+    Reference(Reference),
+}
+
+/// AML opcodes
+mod op {
+    pub const ZERO: u8 = 0x00;
+    pub const ONE: u8 = 0x01;
+    pub const ALIAS: u8 = 0x06;
+    pub const NAME: u8 = 0x08;
+    pub const BYTE_PREFIX: u8 = 0x0A;
+    pub const WORD_PREFIX: u8 = 0x0B;
+    pub const DWORD_PREFIX: u8 = 0x0C;
+    pub const STRING_PREFIX: u8 = 0x0D;
+    pub const QWORD_PREFIX: u8 = 0x0E;
+    pub const SCOPE: u8 = 0x10;
+    pub const BUFFER: u8 = 0x11;
+    pub const PACKAGE: u8 = 0x12;
+    pub const VAR_PACKAGE: u8 = 0x13;
+    pub const METHOD: u8 = 0x14;
+    pub const EXTERNAL: u8 = 0x15;
+    pub const EXT_PREFIX: u8 = 0x5B;
+    pub const LOCAL0: u8 = 0x60;
+    pub const LOCAL7: u8 = 0x67;
+    pub const ARG0: u8 = 0x68;
+    pub const ARG6: u8 = 0x6E;
+    pub const STORE: u8 = 0x70;
+    pub const REF_OF: u8 = 0x71;
+    pub const ADD: u8 = 0x72;
+    pub const CONCAT: u8 = 0x73;
+    pub const SUBTRACT: u8 = 0x74;
+    pub const INCREMENT: u8 = 0x75;
+    pub const DECREMENT: u8 = 0x76;
+    pub const MULTIPLY: u8 = 0x77;
+    pub const DIVIDE: u8 = 0x78;
+    pub const SHIFT_LEFT: u8 = 0x79;
+    pub const SHIFT_RIGHT: u8 = 0x7A;
+    pub const AND: u8 = 0x7B;
+    pub const NAND: u8 = 0x7C;
+    pub const OR: u8 = 0x7D;
+    pub const NOR: u8 = 0x7E;
+    pub const XOR: u8 = 0x7F;
+    pub const NOT: u8 = 0x80;
+    pub const FIND_SET_LEFT_BIT: u8 = 0x81;
+    pub const FIND_SET_RIGHT_BIT: u8 = 0x82;
+    pub const DEREF_OF: u8 = 0x83;
+    pub const CONCAT_RES: u8 = 0x84;
+    pub const MOD: u8 = 0x85;
+    pub const NOTIFY: u8 = 0x86;
+    pub const SIZE_OF: u8 = 0x87;
+    pub const INDEX: u8 = 0x88;
+    pub const MATCH: u8 = 0x89;
+    pub const CREATE_DWORD_FIELD: u8 = 0x8A;
+    pub const CREATE_WORD_FIELD: u8 = 0x8B;
+    pub const CREATE_BYTE_FIELD: u8 = 0x8C;
+    pub const CREATE_BIT_FIELD: u8 = 0x8D;
+    pub const OBJECT_TYPE: u8 = 0x8E;
+    pub const CREATE_QWORD_FIELD: u8 = 0x8F;
+    pub const LAND: u8 = 0x90;
+    pub const LOR: u8 = 0x91;
+    pub const LNOT: u8 = 0x92;
+    pub const LEQUAL: u8 = 0x93;
+    pub const LGREATER: u8 = 0x94;
+    pub const LLESS: u8 = 0x95;
+    pub const TO_BUFFER: u8 = 0x96;
+    pub const TO_DECIMAL_STRING: u8 = 0x97;
+    pub const TO_HEX_STRING: u8 = 0x98;
+    pub const TO_INTEGER: u8 = 0x99;
+    pub const TO_STRING: u8 = 0x9C;
+    pub const COPY_OBJECT: u8 = 0x9D;
+    pub const MID: u8 = 0x9E;
+    pub const CONTINUE: u8 = 0x9F;
+    pub const IF: u8 = 0xA0;
+    pub const ELSE: u8 = 0xA1;
+    pub const WHILE: u8 = 0xA2;
+    pub const NOOP: u8 = 0xA3;
+    pub const RETURN: u8 = 0xA4;
+    pub const BREAK: u8 = 0xA5;
+    pub const BREAKPOINT: u8 = 0xCC;
+    pub const ONES: u8 = 0xFF;
+
+    /// Opcodes following `EXT_PREFIX`
+    pub mod ext {
+        pub const MUTEX: u8 = 0x01;
+        pub const EVENT: u8 = 0x02;
+        pub const COND_REF_OF: u8 = 0x12;
+        pub const CREATE_FIELD: u8 = 0x13;
+        pub const LOAD_TABLE: u8 = 0x1F;
+        pub const LOAD: u8 = 0x20;
+        pub const STALL: u8 = 0x21;
+        pub const SLEEP: u8 = 0x22;
+        pub const ACQUIRE: u8 = 0x23;
+        pub const SIGNAL: u8 = 0x24;
+        pub const WAIT: u8 = 0x25;
+        pub const RESET: u8 = 0x26;
+        pub const RELEASE: u8 = 0x27;
+        pub const FROM_BCD: u8 = 0x28;
+        pub const TO_BCD: u8 = 0x29;
+        pub const REVISION: u8 = 0x30;
+        pub const DEBUG: u8 = 0x31;
+        pub const FATAL: u8 = 0x32;
+        pub const TIMER: u8 = 0x33;
+        pub const OP_REGION: u8 = 0x80;
+        pub const FIELD: u8 = 0x81;
+        pub const DEVICE: u8 = 0x82;
+        pub const PROCESSOR: u8 = 0x83;
+        pub const POWER_RES: u8 = 0x84;
+        pub const THERMAL_ZONE: u8 = 0x85;
+        pub const INDEX_FIELD: u8 = 0x86;
+        pub const BANK_FIELD: u8 = 0x87;
+        pub const DATA_REGION: u8 = 0x88;
+    }
+}
+
+/// Get the current stack pointer
+#[inline]
+fn stack_pointer() -> usize {
+    let rsp: usize;
+    unsafe { asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags)) };
+    rsp
+}
+
+/// Get a bit from a little endian byte slice
+fn get_bit(bytes: &[u8], bit: usize) -> bool {
+    bytes
+        .get(bit / 8)
+        .map_or(false, |b| (b >> (bit % 8)) & 1 != 0)
+}
+
+/// Set a bit in a little endian byte slice
+fn set_bit(bytes: &mut [u8], bit: usize, value: bool) {
+    if let Some(b) = bytes.get_mut(bit / 8) {
+        if value {
+            *b |= 1 << (bit % 8);
+        } else {
+            *b &= !(1 << (bit % 8));
+        }
+    }
+}
+
+/// Turn the bits of a field into an integer if they fit, or a buffer if they don't
+fn field_value(bytes: Vec<u8>, bit_length: usize) -> AmlValue {
+    if bit_length <= 64 {
+        AmlValue::Integer(
+            bytes
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, &b)| acc | (u64::from(b) << (i * 8))),
+        )
+    } else {
+        AmlValue::Buffer(bytes)
+    }
+}
+
+impl AmlContext {
+    /// How many iterations a `While` may run for before it's considered stuck
+    const LOOP_LIMIT: usize = 0x10_0000;
+
+    /// How much stack the interpreter may use below where it was entered. Kernel stacks are
+    /// 64 KiB, and this leaves room for the caller, interrupts, and the deepest frame between
+    /// checks, which is several KiB in a debug build
+    const STACK_BUDGET: usize = 32 * 1024;
+
+    /// Run `f`, measuring the stack it uses from here unless the interpreter is already running
+    pub(super) fn enter<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        if self.stack_base.is_some() {
+            return f(self);
+        }
+        self.stack_base = Some(stack_pointer());
+        let result = f(self);
+        self.stack_base = None;
+        result
+    }
+
+    /// Fail before going any deeper if the interpreter has used up its stack budget.
+    /// Method calls and nested terms both recurse, so a malicious or broken table
+    /// could otherwise overflow the stack
+    fn check_stack(&self) -> Result<(), AmlError> {
+        let used = self
+            .stack_base
+            .map_or(0, |base| base.saturating_sub(stack_pointer()));
+        if used > Self::STACK_BUDGET {
+            return Err(AmlError::RecursionLimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// The index of the frame currently executing
+    fn current_frame(&self) -> usize {
+        self.frames.len() - 1
+    }
+
+    /// The scope currently executing
+    fn scope(&self) -> &[NameSeg] {
+        &self.frames[self.current_frame()].scope
+    }
+
+    /// Truncate an integer to the width used by the loaded tables
+    pub(super) const fn mask(&self, v: u64) -> u64 {
+        if self.integer_bits == 32 {
+            v & 0xFFFF_FFFF
+        } else {
+            v
+        }
+    }
+
+    /// The value for logical true
+    const fn ones(&self) -> u64 {
+        self.mask(u64::MAX)
+    }
+
+    /// Make a logical value with the loaded integer width
+    const fn boolean(&self, v: bool) -> AmlValue {
+        AmlValue::Integer(if v { self.ones() } else { 0 })
+    }
+
+    /// Add an object, remembering it if it was declared inside a method so it can be
+    /// removed when the method returns
+    fn add_object(&mut self, path: AmlPath, value: AmlValue) -> Result<(), AmlError> {
+        let loading = self.frames.len() == 1;
+        match self.namespace.add(path.clone(), value) {
+            Ok(()) => {
+                if !loading {
+                    let frame = self.current_frame();
+                    self.frames[frame].created.push(path);
+                }
+                Ok(())
+            }
+            // Firmware declaring things twice is common enough to not fail the whole table
+            Err(AmlError::ObjectAlreadyExists) if loading => {
+                warn!("AML object {} declared twice", path_to_string(&path));
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Run a list of terms
+    pub(super) fn run_term_list(
+        &mut self,
+        table: &Arc<[u8]>,
+        start: usize,
+        end: usize,
+    ) -> Result<Flow, AmlError> {
+        let mut s = Stream::new(table, start, end);
+        while !s.is_done() {
+            match self.run_term(table, &mut s)? {
+                Flow::Normal => {}
+                other => return Ok(other),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Run a list of terms with a different scope
+    fn run_in_scope(
+        &mut self,
+        table: &Arc<[u8]>,
+        scope: AmlPath,
+        start: usize,
+        end: usize,
+    ) -> Result<Flow, AmlError> {
+        let frame = self.current_frame();
+        let old = core::mem::replace(&mut self.frames[frame].scope, scope);
+        let result = self.run_term_list(table, start, end);
+        self.frames[frame].scope = old;
+        result
+    }
+
+    /// Run a single term.
+    /// This and `eval_term_arg` recurse, so anything needing more than a few locals is in its
+    /// own function, which keeps their stack frames small in debug builds
+    fn run_term(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Flow, AmlError> {
+        self.check_stack()?;
+        match s.peek()? {
+            op::SCOPE => self.run_scope(table, s),
+            op::IF => self.run_if(table, s),
+            op::WHILE => self.run_while(table, s),
+            op::RETURN | op::BREAK | op::CONTINUE => self.run_jump(table, s),
+            op::EXT_PREFIX => self.run_ext_term(table, s),
+            op::ALIAS
+            | op::NAME
+            | op::METHOD
+            | op::EXTERNAL
+            | op::ELSE
+            | op::NOOP
+            | op::BREAKPOINT
+            | op::NOTIFY
+            | op::CREATE_BIT_FIELD
+            | op::CREATE_BYTE_FIELD
+            | op::CREATE_WORD_FIELD
+            | op::CREATE_DWORD_FIELD
+            | op::CREATE_QWORD_FIELD => self.run_simple_term(table, s).map(|()| Flow::Normal),
+            _ => self.eval_term_arg(table, s).map(|_| Flow::Normal),
+        }
+    }
+
+    /// Run a `Return`, `Break` or `Continue`
+    fn run_jump(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Flow, AmlError> {
+        match s.next()? {
+            op::RETURN => self.eval_term_arg(table, s).map(Flow::Return),
+            op::BREAK => Ok(Flow::Break),
+            _ => Ok(Flow::Continue),
+        }
+    }
+
+    /// Run a term that doesn't change where execution continues
+    fn run_simple_term(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        match s.peek()? {
+            op::ALIAS => self.run_alias(s),
+            op::NAME => self.run_name(table, s),
+            op::METHOD => self.run_method(table, s),
+            op::EXTERNAL => {
+                s.next()?;
+                s.name_string()?;
+                // Object type and argument count
+                s.take(2).map(drop)
+            }
+            op::ELSE => {
+                // An `Else` without an `If`, skip it
+                s.next()?;
+                s.pos = s.pkg_length()?;
+                Ok(())
+            }
+            op::NOTIFY => self.run_notify(table, s),
+            op::NOOP | op::BREAKPOINT => s.next().map(drop),
+            _ => self.run_create_field(table, s),
+        }
+    }
+
+    /// Run an `Alias`
+    fn run_alias(&mut self, s: &mut Stream) -> Result<(), AmlError> {
+        s.next()?;
+        let source = s.name_string()?;
+        let alias = s.name_string()?;
+        let target = self.namespace.search(&source, self.scope())?;
+        let path = alias.resolve(self.scope())?;
+        self.add_object(path, AmlValue::Alias(target))
+    }
+
+    /// Run a `Name`
+    fn run_name(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        s.next()?;
+        let name = s.name_string()?;
+        let path = name.resolve(self.scope())?;
+        let value = self.eval_term_arg(table, s)?;
+        self.add_object(path, value)
+    }
+
+    /// Run a `Scope`, adding it if it doesn't exist
+    fn run_scope(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Flow, AmlError> {
+        let (path, end) = self.parse_scope(s)?;
+        let flow = self.run_in_scope(table, path, s.pos, end);
+        s.pos = end;
+        flow
+    }
+
+    /// Parse the start of a `Scope`, returning its path and where it ends
+    fn parse_scope(&mut self, s: &mut Stream) -> Result<(AmlPath, usize), AmlError> {
+        s.next()?;
+        let end = s.pkg_length()?;
+        let path = s.name_string()?.resolve(self.scope())?;
+        if !self.namespace.contains(&path) {
+            warn!("AML scope {} doesn't exist", path_to_string(&path));
+            self.add_object(path.clone(), AmlValue::Scope)?;
+        }
+        Ok((path, end))
+    }
+
+    /// Run a `Method`, which declares it to be run later
+    fn run_method(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        s.next()?;
+        let end = s.pkg_length()?;
+        let path = s.name_string()?.resolve(self.scope())?;
+        let flags = s.next()?;
+        let method = Method {
+            flags,
+            code: MethodCode::Aml {
+                table: table.clone(),
+                start: s.pos,
+                end,
+            },
+        };
+        self.add_object(path, AmlValue::Method(method))?;
+        s.pos = end;
+        Ok(())
+    }
+
+    /// Run a `Notify`
+    fn run_notify(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        s.next()?;
+        let target = self.parse_super_name(table, s)?;
+        let value = self.eval_term_arg(table, s)?.as_integer()?;
+        if let Target::Named(path) = target {
+            self.handler.notify(&path, value);
+        }
+        Ok(())
+    }
+
+    /// Run a `CreateBitField`, `CreateByteField`, `CreateWordField`, `CreateDWordField` or
+    /// `CreateQWordField`
+    fn run_create_field(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        let opcode = s.next()?;
+        let source = self.parse_container(table, s)?;
+        let index = self.eval_term_arg(table, s)?.as_integer()? as usize;
+        let (bit_offset, bit_length) = match opcode {
+            op::CREATE_BIT_FIELD => (index, 1),
+            op::CREATE_BYTE_FIELD => (index * 8, 8),
+            op::CREATE_WORD_FIELD => (index * 8, 16),
+            op::CREATE_DWORD_FIELD => (index * 8, 32),
+            _ => (index * 8, 64),
+        };
+        let path = s.name_string()?.resolve(self.scope())?;
+        self.add_object(
+            path,
+            AmlValue::BufferField {
+                source,
+                bit_offset,
+                bit_length,
+            },
+        )
+    }
+
+    /// Run a term starting with the extended opcode prefix
+    fn run_ext_term(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Flow, AmlError> {
+        match s.peek_at(1)? {
+            op::ext::DEVICE | op::ext::THERMAL_ZONE | op::ext::PROCESSOR | op::ext::POWER_RES => {
+                self.run_device(table, s)
+            }
+            op::ext::FATAL => self.run_fatal(table, s),
+            op::ext::MUTEX
+            | op::ext::EVENT
+            | op::ext::CREATE_FIELD
+            | op::ext::STALL
+            | op::ext::SLEEP
+            | op::ext::SIGNAL
+            | op::ext::RESET
+            | op::ext::RELEASE
+            | op::ext::OP_REGION
+            | op::ext::DATA_REGION
+            | op::ext::FIELD
+            | op::ext::INDEX_FIELD
+            | op::ext::BANK_FIELD => self.run_simple_ext_term(table, s).map(|()| Flow::Normal),
+            _ => self.eval_term_arg(table, s).map(|_| Flow::Normal),
+        }
+    }
+
+    /// Run an extended term that doesn't change where execution continues
+    fn run_simple_ext_term(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        match s.peek_at(1)? {
+            op::ext::MUTEX => {
+                s.take(2)?;
+                let path = s.name_string()?.resolve(self.scope())?;
+                let sync_level = s.next()? & 0xF;
+                self.add_object(path, AmlValue::Mutex { sync_level })
+            }
+            op::ext::EVENT => {
+                s.take(2)?;
+                let path = s.name_string()?.resolve(self.scope())?;
+                self.add_object(path, AmlValue::Event)
+            }
+            op::ext::CREATE_FIELD => self.run_create_bit_field(table, s),
+            op::ext::STALL => {
+                s.take(2)?;
+                let us = self.eval_term_arg(table, s)?.as_integer()?;
+                self.handler.stall(us);
+                Ok(())
+            }
+            op::ext::SLEEP => {
+                s.take(2)?;
+                let ms = self.eval_term_arg(table, s)?.as_integer()?;
+                self.handler.sleep(ms);
+                Ok(())
+            }
+            op::ext::SIGNAL | op::ext::RESET | op::ext::RELEASE => {
+                // Only one thread runs AML at a time, so events and mutexes are no-ops
+                s.take(2)?;
+                self.parse_super_name(table, s).map(drop)
+            }
+            op::ext::OP_REGION => self.run_op_region(table, s),
+            op::ext::DATA_REGION => self.run_data_region(table, s),
+            _ => self.run_field(table, s),
+        }
+    }
+
+    /// Run a `CreateField`
+    fn run_create_bit_field(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        s.take(2)?;
+        let source = self.parse_container(table, s)?;
+        let bit_offset = self.eval_term_arg(table, s)?.as_integer()? as usize;
+        let bit_length = self.eval_term_arg(table, s)?.as_integer()? as usize;
+        let path = s.name_string()?.resolve(self.scope())?;
+        self.add_object(
+            path,
+            AmlValue::BufferField {
+                source,
+                bit_offset,
+                bit_length,
+            },
+        )
+    }
+
+    /// Run a `Fatal`, which stops execution with an error
+    fn run_fatal(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Flow, AmlError> {
+        s.take(2)?;
+        let kind = s.next()?;
+        let code = s.integer(4)? as u32;
+        let argument = self.eval_term_arg(table, s)?.as_integer()?;
+        Err(AmlError::Fatal {
+            kind,
+            code,
+            argument,
+        })
+    }
+
+    /// Run an `OperationRegion`
+    fn run_op_region(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        s.take(2)?;
+        let path = s.name_string()?.resolve(self.scope())?;
+        let space = RegionSpace::from(s.next()?);
+        let offset = self.eval_term_arg(table, s)?.as_integer()?;
+        let length = self.eval_term_arg(table, s)?.as_integer()?;
+        let parent = self.scope().to_vec();
+        self.add_object(
+            path,
+            AmlValue::OpRegion(OpRegion {
+                space,
+                offset,
+                length,
+                parent,
+            }),
+        )
+    }
+
+    /// Run a `DataTableRegion`, which is declared but can't be accessed
+    fn run_data_region(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        s.take(2)?;
+        let path = s.name_string()?.resolve(self.scope())?;
+        let signature = self.eval_term_arg(table, s)?;
+        self.eval_term_arg(table, s)?;
+        self.eval_term_arg(table, s)?;
+        warn!(
+            "Data table regions aren't supported, {} ({:?}) won't be accessible",
+            path_to_string(&path),
+            signature
+        );
+        let parent = self.scope().to_vec();
+        self.add_object(
+            path,
+            AmlValue::OpRegion(OpRegion {
+                space: RegionSpace::Other(0x80),
+                offset: 0,
+                length: 0,
+                parent,
+            }),
+        )
+    }
+
+    /// Run a `Field`, `IndexField` or `BankField`
+    fn run_field(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<(), AmlError> {
+        let opcode = s.peek_at(1)?;
+        s.take(2)?;
+        let end = s.pkg_length()?;
+        let first = self.namespace.search(&s.name_string()?, self.scope())?;
+        let kind = match opcode {
+            op::ext::FIELD => FieldKind::Normal { region: first },
+            op::ext::INDEX_FIELD => FieldKind::Index {
+                index: first,
+                data: self.namespace.search(&s.name_string()?, self.scope())?,
+            },
+            _ => FieldKind::Bank {
+                region: first,
+                bank: self.namespace.search(&s.name_string()?, self.scope())?,
+                value: self.eval_term_arg(table, s)?.as_integer()?,
+            },
+        };
+        let flags = s.next()?;
+        self.parse_field_list(table, s, end, &kind, flags)
+    }
+
+    /// Run a `Device`, `ThermalZone`, `Processor` or `PowerResource`, which are all scopes
+    fn run_device(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Flow, AmlError> {
+        let (path, end) = self.parse_device(s)?;
+        let flow = self.run_in_scope(table, path, s.pos, end);
+        s.pos = end;
+        flow
+    }
+
+    /// Parse the start of a `Device` or similar and add it, returning its path and where it ends
+    fn parse_device(&mut self, s: &mut Stream) -> Result<(AmlPath, usize), AmlError> {
+        let opcode = s.peek_at(1)?;
+        s.take(2)?;
+        let end = s.pkg_length()?;
+        let path = s.name_string()?.resolve(self.scope())?;
+        let value = match opcode {
+            op::ext::DEVICE => AmlValue::Device,
+            op::ext::THERMAL_ZONE => AmlValue::ThermalZone,
+            op::ext::PROCESSOR => AmlValue::Processor {
+                id: s.next()?,
+                block_address: s.integer(4)? as u32,
+                block_length: s.next()?,
+            },
+            _ => AmlValue::PowerResource {
+                system_level: s.next()?,
+                resource_order: s.integer(2)? as u16,
+            },
+        };
+        self.add_object(path.clone(), value)?;
+        Ok((path, end))
+    }
+
+    /// Run an `If`, and the `Else` following it
+    fn run_if(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Flow, AmlError> {
+        s.next()?;
+        let end = s.pkg_length()?;
+        let predicate = self.eval_term_arg(table, s)?.as_bool()?;
+        let body = s.pos;
+        s.pos = end;
+
+        let otherwise = if !s.is_done() && s.peek()? == op::ELSE {
+            s.next()?;
+            let else_end = s.pkg_length()?;
+            let range = (s.pos, else_end);
+            s.pos = else_end;
+            Some(range)
+        } else {
+            None
+        };
+
+        if predicate {
+            self.run_term_list(table, body, end)
+        } else if let Some((start, end)) = otherwise {
+            self.run_term_list(table, start, end)
+        } else {
+            Ok(Flow::Normal)
+        }
+    }
+
+    /// Run a `While`
+    fn run_while(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Flow, AmlError> {
+        s.next()?;
+        let end = s.pkg_length()?;
+        let predicate = s.pos;
+        s.pos = end;
+
+        for _ in 0..Self::LOOP_LIMIT {
+            let mut ps = Stream::new(table, predicate, end);
+            if !self.eval_term_arg(table, &mut ps)?.as_bool()? {
+                return Ok(Flow::Normal);
+            }
+
+            match self.run_term_list(table, ps.pos, end)? {
+                Flow::Break => return Ok(Flow::Normal),
+                Flow::Return(v) => return Ok(Flow::Return(v)),
+                Flow::Normal | Flow::Continue => {}
+            }
+        }
+
+        Err(AmlError::LoopLimitExceeded)
+    }
+
+    /// Parse the elements of a `Field`, `IndexField`, or `BankField`
+    fn parse_field_list(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        end: usize,
+        kind: &FieldKind,
+        mut flags: u8,
+    ) -> Result<(), AmlError> {
+        let mut bit_offset = 0;
+        while s.pos < end {
+            match s.peek()? {
+                // ReservedField
+                0x00 => {
+                    s.next()?;
+                    bit_offset += s.pkg_length_raw()?;
+                }
+                // AccessField
+                0x01 => {
+                    s.next()?;
+                    let access_type = s.next()?;
+                    s.next()?;
+                    flags = (flags & 0xF0) | (access_type & 0x0F);
+                }
+                // ConnectField
+                0x02 => {
+                    s.next()?;
+                    if s.peek()? == op::BUFFER {
+                        self.eval_term_arg(table, s)?;
+                    } else {
+                        s.name_string()?;
+                    }
+                }
+                // ExtendedAccessField
+                0x03 => {
+                    s.next()?;
+                    let access_type = s.next()?;
+                    s.take(2)?;
+                    flags = (flags & 0xF0) | (access_type & 0x0F);
+                }
+                _ => {
+                    let seg = s.name_seg()?;
+                    let bit_length = s.pkg_length_raw()?;
+                    let mut path = self.scope().to_vec();
+                    path.push(seg);
+                    self.add_object(
+                        path,
+                        AmlValue::FieldUnit(FieldUnit {
+                            kind: kind.clone(),
+                            bit_offset,
+                            bit_length,
+                            flags,
+                        }),
+                    )?;
+                    bit_offset += bit_length;
+                }
+            }
+        }
+        s.pos = end;
+        Ok(())
+    }
+
+    /// Evaluate a term argument
+    pub(super) fn eval_term_arg(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+    ) -> Result<AmlValue, AmlError> {
+        self.check_stack()?;
+        if s.at_name_string() {
+            return self.eval_name(table, s);
+        }
+
+        let opcode = s.next()?;
+        match opcode {
+            op::ZERO => Ok(AmlValue::Integer(0)),
+            op::ONE => Ok(AmlValue::Integer(1)),
+            op::ONES => Ok(AmlValue::Integer(self.ones())),
+            op::BYTE_PREFIX => s.integer(1).map(AmlValue::Integer),
+            op::WORD_PREFIX => s.integer(2).map(AmlValue::Integer),
+            op::DWORD_PREFIX => s.integer(4).map(AmlValue::Integer),
+            op::QWORD_PREFIX => s.integer(8).map(AmlValue::Integer),
+            op::STRING_PREFIX => s.string().map(AmlValue::String),
+            op::BUFFER => self.eval_buffer(table, s),
+            op::PACKAGE | op::VAR_PACKAGE => self.eval_package(table, s, opcode),
+            op::LOCAL0..=op::LOCAL7 | op::ARG0..=op::ARG6 => self.eval_local_or_arg(opcode),
+            op::STORE | op::COPY_OBJECT => self.eval_store(table, s, opcode),
+            op::REF_OF => self.eval_ref_of(table, s),
+            op::DEREF_OF => self.eval_deref_of(table, s),
+            op::ADD
+            | op::SUBTRACT
+            | op::MULTIPLY
+            | op::SHIFT_LEFT
+            | op::SHIFT_RIGHT
+            | op::AND
+            | op::NAND
+            | op::OR
+            | op::NOR
+            | op::XOR
+            | op::MOD => self.eval_binary(table, s, opcode),
+            op::NOT | op::FIND_SET_LEFT_BIT | op::FIND_SET_RIGHT_BIT => {
+                self.eval_unary(table, s, opcode)
+            }
+            op::INCREMENT | op::DECREMENT => self.eval_increment(table, s, opcode),
+            op::DIVIDE => self.eval_divide(table, s),
+            op::LAND | op::LOR | op::LNOT => self.eval_logical(table, s, opcode),
+            op::LEQUAL | op::LGREATER | op::LLESS => self.eval_compare(table, s, opcode),
+            op::CONCAT => self.eval_concat(table, s),
+            op::CONCAT_RES => self.eval_concat_res(table, s),
+            op::SIZE_OF => self.eval_size_of(table, s),
+            op::INDEX => self.eval_index(table, s),
+            op::MATCH => self.eval_match(table, s),
+            op::OBJECT_TYPE => self.eval_object_type(table, s),
+            op::TO_BUFFER => self.eval_to_buffer(table, s),
+            op::TO_INTEGER => self.eval_to_integer(table, s),
+            op::TO_DECIMAL_STRING | op::TO_HEX_STRING => {
+                self.eval_to_number_string(table, s, opcode)
+            }
+            op::TO_STRING => self.eval_to_string(table, s),
+            op::MID => self.eval_mid(table, s),
+            op::EXT_PREFIX => self.eval_ext_term_arg(table, s),
+            _ => Err(AmlError::UnsupportedOpcode(u16::from(opcode))),
+        }
+    }
+
+    /// Evaluate a name, calling it if it's a method
+    fn eval_name(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let name = s.name_string()?;
+        let path = self.namespace.search(&name, self.scope()).map_err(|e| {
+            trace!("Couldn't resolve AML name {name}");
+            e
+        })?;
+        self.read_named_or_call(table, s, &path)
+    }
+
+    /// Read a local or argument, which is read through if it's a reference
+    fn eval_local_or_arg(&mut self, opcode: u8) -> Result<AmlValue, AmlError> {
+        let frame = &self.frames[self.current_frame()];
+        if opcode <= op::LOCAL7 {
+            return Ok(frame.locals[usize::from(opcode - op::LOCAL0)].clone());
+        }
+        match frame.args[usize::from(opcode - op::ARG0)].clone() {
+            AmlValue::Reference(r) => self.read_reference(&r),
+            v => Ok(v),
+        }
+    }
+
+    /// Evaluate a `Buffer`
+    fn eval_buffer(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let end = s.pkg_length()?;
+        let size = self.eval_term_arg(table, s)?.as_integer()? as usize;
+        let count = end
+            .checked_sub(s.pos)
+            .ok_or(AmlError::UnexpectedEndOfStream)?;
+        let mut bytes = s.take(count)?.to_vec();
+        if bytes.len() < size {
+            bytes.resize(size, 0);
+        }
+        Ok(AmlValue::Buffer(bytes))
+    }
+
+    /// Evaluate a `Package` or `VarPackage`
+    fn eval_package(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let end = s.pkg_length()?;
+        let count = if opcode == op::PACKAGE {
+            usize::from(s.next()?)
+        } else {
+            self.eval_term_arg(table, s)?.as_integer()? as usize
+        };
+
+        let mut elements = Vec::new();
+        while s.pos < end {
+            if s.at_name_string() {
+                let name = s.name_string()?;
+                let element = self.namespace.search(&name, self.scope()).map_or_else(
+                    |_| AmlValue::String(format!("{name}")),
+                    |path| AmlValue::Reference(Reference::Object(Container::Named(path))),
+                );
+                elements.push(element);
+            } else {
+                elements.push(self.eval_term_arg(table, s)?);
+            }
+        }
+        s.pos = end;
+
+        if elements.len() < count {
+            elements.resize(count, AmlValue::Uninitialized);
+        }
+        Ok(AmlValue::Package(elements))
+    }
+
+    /// Evaluate a `Store` or `CopyObject`
+    fn eval_store(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let value = self.eval_term_arg(table, s)?;
+        let target = self.parse_super_name(table, s)?;
+        if opcode == op::STORE {
+            self.store(target, value.clone())?;
+        } else {
+            self.copy_object(target, value.clone())?;
+        }
+        Ok(value)
+    }
+
+    /// Evaluate a `RefOf`
+    fn eval_ref_of(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let target = self.parse_super_name(table, s)?;
+        Ok(AmlValue::Reference(Reference::Object(
+            self.target_to_container(target)?,
+        )))
+    }
+
+    /// Evaluate a `DerefOf`
+    fn eval_deref_of(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        match self.eval_term_arg(table, s)? {
+            AmlValue::Reference(r) => self.read_reference(&r),
+            AmlValue::String(name) => {
+                let name = name.parse::<super::AmlName>()?;
+                let path = self.namespace.search(&name, self.scope())?;
+                self.read_named(&path)
+            }
+            _ => Err(AmlError::IncompatibleType),
+        }
+    }
+
+    /// Evaluate an integer operation with two operands and a target
+    fn eval_binary(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let a = self.eval_term_arg(table, s)?.as_integer()?;
+        let b = self.eval_term_arg(table, s)?.as_integer()?;
+        let result = match opcode {
+            op::ADD => a.wrapping_add(b),
+            op::SUBTRACT => a.wrapping_sub(b),
+            op::MULTIPLY => a.wrapping_mul(b),
+            op::SHIFT_LEFT => u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_shl(b))
+                .unwrap_or(0),
+            op::SHIFT_RIGHT => u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_shr(b))
+                .unwrap_or(0),
+            op::AND => a & b,
+            op::NAND => !(a & b),
+            op::OR => a | b,
+            op::NOR => !(a | b),
+            op::XOR => a ^ b,
+            _ => a.checked_rem(b).ok_or(AmlError::DivideByZero)?,
+        };
+        let result = AmlValue::Integer(self.mask(result));
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate an integer operation with one operand and a target
+    fn eval_unary(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let v = self.eval_term_arg(table, s)?.as_integer()?;
+        let result = match opcode {
+            op::NOT => self.mask(!v),
+            op::FIND_SET_LEFT_BIT => u64::from(64 - v.leading_zeros()),
+            _ if v == 0 => 0,
+            _ => u64::from(v.trailing_zeros() + 1),
+        };
+        let result = AmlValue::Integer(result);
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate an `Increment` or `Decrement`
+    fn eval_increment(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let target = self.parse_super_name(table, s)?;
+        let v = self.read_target(&target)?.as_integer()?;
+        let v = if opcode == op::INCREMENT {
+            v.wrapping_add(1)
+        } else {
+            v.wrapping_sub(1)
+        };
+        let result = AmlValue::Integer(self.mask(v));
+        self.store(target, result.clone())?;
+        Ok(result)
+    }
+
+    /// Evaluate a `Divide`, which stores the remainder and the quotient
+    fn eval_divide(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let a = self.eval_term_arg(table, s)?.as_integer()?;
+        let b = self.eval_term_arg(table, s)?.as_integer()?;
+        if b == 0 {
+            return Err(AmlError::DivideByZero);
+        }
+        self.store_to_target(table, s, &AmlValue::Integer(a % b))?;
+        let quotient = AmlValue::Integer(a / b);
+        self.store_to_target(table, s, &quotient)?;
+        Ok(quotient)
+    }
+
+    /// Evaluate a logical operation
+    fn eval_logical(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let a = self.eval_term_arg(table, s)?.as_bool()?;
+        // Both operands are always evaluated, as the second has to be parsed either way
+        let result = match opcode {
+            op::LAND => self.eval_term_arg(table, s)?.as_bool()? & a,
+            op::LOR => self.eval_term_arg(table, s)?.as_bool()? | a,
+            _ => !a,
+        };
+        Ok(self.boolean(result))
+    }
+
+    /// Evaluate a comparison
+    fn eval_compare(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let a = self.eval_term_arg(table, s)?;
+        let b = self.eval_term_arg(table, s)?;
+        let ordering = a.compare(&b)?;
+        let result = match opcode {
+            op::LEQUAL => ordering.is_eq(),
+            op::LGREATER => ordering.is_gt(),
+            _ => ordering.is_lt(),
+        };
+        Ok(self.boolean(result))
+    }
+
+    /// Evaluate a `Concatenate`
+    fn eval_concat(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let a = self.eval_term_arg(table, s)?;
+        let b = self.eval_term_arg(table, s)?;
+        let result = match a {
+            AmlValue::Integer(a) => {
+                let width = if self.integer_bits == 32 { 4 } else { 8 };
+                let mut bytes = a.to_le_bytes()[..width].to_vec();
+                bytes.extend_from_slice(&b.as_integer()?.to_le_bytes()[..width]);
+                AmlValue::Buffer(bytes)
+            }
+            AmlValue::Buffer(mut a) => {
+                a.extend_from_slice(&b.as_buffer()?);
+                AmlValue::Buffer(a)
+            }
+            a => {
+                let mut a = a.as_string()?;
+                a.push_str(&b.as_string()?);
+                AmlValue::String(a)
+            }
+        };
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a `ConcatenateResTemplate`, which joins two resource templates
+    fn eval_concat_res(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let strip = |mut bytes: Vec<u8>| {
+            let len = bytes.len();
+            if len >= 2 && bytes[len - 2] == 0x79 {
+                bytes.truncate(len - 2);
+            }
+            bytes
+        };
+        let mut a = strip(self.eval_term_arg(table, s)?.as_buffer()?);
+        let b = strip(self.eval_term_arg(table, s)?.as_buffer()?);
+        a.extend_from_slice(&b);
+        a.extend_from_slice(&[0x79, 0]);
+        let result = AmlValue::Buffer(a);
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a `SizeOf`
+    fn eval_size_of(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let target = self.parse_super_name(table, s)?;
+        Ok(AmlValue::Integer(self.read_target(&target)?.len()? as u64))
+    }
+
+    /// Evaluate an `Index`, which makes a reference to an element
+    fn eval_index(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let container = self.parse_container(table, s)?;
+        let index = self.eval_term_arg(table, s)?.as_integer()? as usize;
+        let result = AmlValue::Reference(Reference::Element { container, index });
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a `Match`, which searches a package
+    fn eval_match(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let package = match self.eval_term_arg(table, s)? {
+            AmlValue::Package(elements) => elements,
+            _ => return Err(AmlError::IncompatibleType),
+        };
+        let op1 = s.next()?;
+        let obj1 = self.eval_term_arg(table, s)?;
+        let op2 = s.next()?;
+        let obj2 = self.eval_term_arg(table, s)?;
+        let start = self.eval_term_arg(table, s)?.as_integer()? as usize;
+
+        let matches = |element: &AmlValue, opcode: u8, obj: &AmlValue| {
+            if opcode == 0 {
+                return true;
+            }
+            element.compare(obj).map_or(false, |ordering| match opcode {
+                1 => ordering.is_eq(),
+                2 => ordering.is_le(),
+                3 => ordering.is_lt(),
+                4 => ordering.is_ge(),
+                5 => ordering.is_gt(),
+                _ => false,
+            })
+        };
+
+        Ok(AmlValue::Integer(
+            package
+                .iter()
+                .enumerate()
+                .skip(start)
+                .find(|(_, e)| matches(e, op1, &obj1) && matches(e, op2, &obj2))
+                .map_or(self.ones(), |(i, _)| i as u64),
+        ))
+    }
+
+    /// Evaluate an `ObjectType`
+    fn eval_object_type(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+    ) -> Result<AmlValue, AmlError> {
+        let target = self.parse_super_name(table, s)?;
+        let kind = match &target {
+            Target::Named(path) => self
+                .namespace
+                .get(path)
+                .ok_or(AmlError::ObjectNotFound)?
+                .object_type(),
+            Target::Debug => super::ObjectType::Debug,
+            target => self.read_target(target)?.object_type(),
+        };
+        Ok(AmlValue::Integer(kind as u64))
+    }
+
+    /// Evaluate a `ToBuffer`
+    fn eval_to_buffer(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let result = AmlValue::Buffer(self.eval_term_arg(table, s)?.as_buffer()?);
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a `ToInteger`, which parses strings as decimal unless they start with `0x`
+    fn eval_to_integer(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let result = match self.eval_term_arg(table, s)? {
+            AmlValue::String(v) => {
+                let v = v.trim();
+                if v.starts_with("0x") || v.starts_with("0X") {
+                    AmlValue::String(String::from(v)).as_integer()?
+                } else {
+                    v.chars()
+                        .map_while(|c| c.to_digit(10))
+                        .fold(0u64, |acc, d| {
+                            acc.wrapping_mul(10).wrapping_add(u64::from(d))
+                        })
+                }
+            }
+            v => v.as_integer()?,
+        };
+        let result = AmlValue::Integer(self.mask(result));
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a `ToDecimalString` or `ToHexString`
+    fn eval_to_number_string(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let value = self.eval_term_arg(table, s)?;
+        let hex = opcode == op::TO_HEX_STRING;
+        let result = match value {
+            AmlValue::Integer(v) if hex => format!("0x{v:X}"),
+            AmlValue::Integer(v) => format!("{v}"),
+            AmlValue::Buffer(bytes) => bytes
+                .iter()
+                .map(|b| {
+                    if hex {
+                        format!("0x{b:02X}")
+                    } else {
+                        format!("{b}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            AmlValue::String(v) => v,
+            _ => return Err(AmlError::IncompatibleType),
+        };
+        let result = AmlValue::String(result);
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a `ToString`, which reads a buffer up to a length or a NUL
+    fn eval_to_string(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let bytes = self.eval_term_arg(table, s)?.as_buffer()?;
+        let length = self.eval_term_arg(table, s)?.as_integer()?;
+        let result = AmlValue::String(
+            bytes
+                .iter()
+                .take(usize::try_from(length).unwrap_or(usize::MAX))
+                .take_while(|&&b| b != 0)
+                .map(|&b| char::from(b))
+                .collect(),
+        );
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a `Mid`, which takes part of a string or buffer
+    fn eval_mid(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<AmlValue, AmlError> {
+        let source = self.eval_term_arg(table, s)?;
+        let index = self.eval_term_arg(table, s)?.as_integer()? as usize;
+        let length = self.eval_term_arg(table, s)?.as_integer()? as usize;
+        let range = |len: usize| index.min(len)..index.saturating_add(length).min(len);
+        let result = match source {
+            AmlValue::String(v) => {
+                AmlValue::String(v.get(range(v.len())).map(String::from).unwrap_or_default())
+            }
+            v => {
+                let bytes = v.as_buffer()?;
+                AmlValue::Buffer(bytes[range(bytes.len())].to_vec())
+            }
+        };
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate a term argument starting with the extended opcode prefix, which has
+    /// already been consumed
+    // `Load` and `LoadTable` are kept apart from unknown opcodes, even though both fail
+    #[allow(clippy::match_same_arms)]
+    fn eval_ext_term_arg(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+    ) -> Result<AmlValue, AmlError> {
+        let opcode = s.next()?;
+        match opcode {
+            op::ext::COND_REF_OF => self.eval_cond_ref_of(table, s),
+            op::ext::ACQUIRE => {
+                self.parse_super_name(table, s)?;
+                s.take(2)?;
+                // Acquired, as only one thread runs AML at a time
+                Ok(AmlValue::Integer(0))
+            }
+            op::ext::WAIT => {
+                self.parse_super_name(table, s)?;
+                self.eval_term_arg(table, s)?;
+                Ok(AmlValue::Integer(0))
+            }
+            op::ext::FROM_BCD | op::ext::TO_BCD => self.eval_bcd(table, s, opcode),
+            op::ext::REVISION => Ok(AmlValue::Integer(u64::from(Self::REVISION))),
+            op::ext::DEBUG => Ok(AmlValue::Uninitialized),
+            op::ext::TIMER => Ok(AmlValue::Integer(self.handler.timer())),
+            op::ext::LOAD | op::ext::LOAD_TABLE => Err(AmlError::UnsupportedOpcode(
+                (u16::from(op::EXT_PREFIX) << 8) | u16::from(opcode),
+            )),
+            _ => Err(AmlError::UnsupportedOpcode(
+                (u16::from(op::EXT_PREFIX) << 8) | u16::from(opcode),
+            )),
+        }
+    }
+
+    /// Evaluate a `CondRefOf`, which makes a reference only if the object exists
+    fn eval_cond_ref_of(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+    ) -> Result<AmlValue, AmlError> {
+        let container = if s.at_name_string() {
+            let name = s.name_string()?;
+            self.namespace
+                .search(&name, self.scope())
+                .ok()
+                .map(Container::Named)
+        } else {
+            let target = self.parse_super_name(table, s)?;
+            self.target_to_container(target).ok()
+        };
+
+        if let Some(container) = container {
+            let reference = AmlValue::Reference(Reference::Object(container));
+            self.store_to_target(table, s, &reference)?;
+            Ok(AmlValue::Integer(self.ones()))
+        } else {
+            self.parse_super_name(table, s)?;
+            Ok(AmlValue::Integer(0))
+        }
+    }
+
+    /// Evaluate a `FromBCD` or `ToBCD`
+    fn eval_bcd(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        opcode: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let mut v = self.eval_term_arg(table, s)?.as_integer()?;
+        let mut result = 0;
+        let mut shift = 1;
+        while v != 0 {
+            if opcode == op::ext::FROM_BCD {
+                result += (v & 0xF) * shift;
+                v >>= 4;
+                shift *= 10;
+            } else {
+                result |= (v % 10) * shift;
+                v /= 10;
+                shift <<= 4;
+            }
+        }
+        let result = AmlValue::Integer(self.mask(result));
+        self.store_to_target(table, s, &result)?;
+        Ok(result)
+    }
+
+    /// Read a named object, calling it if it's a method
+    fn read_named_or_call(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        path: &[NameSeg],
+    ) -> Result<AmlValue, AmlError> {
+        if let Some(AmlValue::Method(method)) = self.namespace.get(path) {
+            let method = method.clone();
+            let mut args = Vec::new();
+            for _ in 0..method.arg_count() {
+                args.push(self.eval_term_arg(table, s)?);
+            }
+            self.call_method(path, &method, args)
+        } else {
+            self.read_named(path)
+        }
+    }
+
+    /// Read the value of a named object, reading fields from their backing storage
+    pub(super) fn read_named(&mut self, path: &[NameSeg]) -> Result<AmlValue, AmlError> {
+        match self
+            .namespace
+            .get(path)
+            .ok_or(AmlError::ObjectNotFound)?
+            .clone()
+        {
+            AmlValue::FieldUnit(field) => self.read_field(&field),
+            AmlValue::BufferField {
+                source,
+                bit_offset,
+                bit_length,
+            } => {
+                let bytes = self.read_container(&source)?.as_buffer()?;
+                let mut out = Vec::new();
+                out.resize((bit_length + 7) / 8, 0);
+                for bit in 0..bit_length {
+                    if bit_offset + bit >= bytes.len() * 8 {
+                        return Err(AmlError::IndexOutOfBounds);
+                    }
+                    set_bit(&mut out, bit, get_bit(&bytes, bit_offset + bit));
+                }
+                Ok(field_value(out, bit_length))
+            }
+            v => Ok(v),
+        }
+    }
+
+    /// Call a method
+    pub(super) fn call_method(
+        &mut self,
+        path: &[NameSeg],
+        method: &Method,
+        args: Vec<AmlValue>,
+    ) -> Result<AmlValue, AmlError> {
+        if args.len() > 7 {
+            return Err(AmlError::TooManyArguments);
+        }
+
+        match &method.code {
+            MethodCode::Native(f) => f(self, &args),
+            MethodCode::Aml { table, start, end } => {
+                self.push_frame(path, args);
+                let result = self.run_term_list(table, *start, *end);
+                self.pop_frame();
+                match result {
+                    Ok(Flow::Return(v)) => Ok(v),
+                    Ok(Flow::Normal) => Ok(AmlValue::Integer(0)),
+                    Ok(Flow::Break | Flow::Continue) => Err(AmlError::InvalidControlFlow),
+                    Err(e) => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Start running an AML method
+    fn push_frame(&mut self, path: &[NameSeg], args: Vec<AmlValue>) {
+        trace!("Calling AML method {}", path_to_string(path));
+
+        let mut frame = Frame::new(path.to_vec());
+        for (slot, arg) in frame.args.iter_mut().zip(args) {
+            *slot = arg;
+        }
+        self.frames.push(frame);
+    }
+
+    /// Finish running an AML method, removing the objects it declared
+    fn pop_frame(&mut self) {
+        if let Some(frame) = self.frames.pop() {
+            for path in frame.created.iter().rev() {
+                self.namespace.remove(path);
+            }
+        }
+    }
+
+    /// Parse a `SuperName`, which is where a result is stored
+    fn parse_super_name(&mut self, table: &Arc<[u8]>, s: &mut Stream) -> Result<Target, AmlError> {
+        let opcode = s.peek()?;
+        match opcode {
+            op::ZERO => {
+                s.next()?;
+                Ok(Target::Null)
+            }
+            op::LOCAL0..=op::LOCAL7 => {
+                s.next()?;
+                Ok(Target::Local(usize::from(opcode - op::LOCAL0)))
+            }
+            op::ARG0..=op::ARG6 => {
+                s.next()?;
+                Ok(Target::Arg(usize::from(opcode - op::ARG0)))
+            }
+            op::EXT_PREFIX if s.peek_at(1)? == op::ext::DEBUG => {
+                s.take(2)?;
+                Ok(Target::Debug)
+            }
+            _ if s.at_name_string() => {
+                let name = s.name_string()?;
+                Ok(Target::Named(self.namespace.search(&name, self.scope())?))
+            }
+            _ => match self.eval_term_arg(table, s)? {
+                AmlValue::Reference(r) => Ok(Target::Reference(r)),
+                _ => Err(AmlError::IncompatibleType),
+            },
+        }
+    }
+
+    /// Parse the target of an operation and store the result into it
+    fn store_to_target(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+        value: &AmlValue,
+    ) -> Result<(), AmlError> {
+        let target = self.parse_super_name(table, s)?;
+        self.store(target, value.clone())
+    }
+
+    /// Parse the source of a buffer field or `Index`, keeping track of where it came from
+    /// so writes go back to it
+    fn parse_container(
+        &mut self,
+        table: &Arc<[u8]>,
+        s: &mut Stream,
+    ) -> Result<Container, AmlError> {
+        let opcode = s.peek()?;
+        if s.at_name_string()
+            || (op::LOCAL0..=op::LOCAL7).contains(&opcode)
+            || (op::ARG0..=op::ARG6).contains(&opcode)
+        {
+            let target = self.parse_super_name(table, s)?;
+            self.target_to_container(target)
+        } else {
+            match self.eval_term_arg(table, s)? {
+                AmlValue::Reference(Reference::Object(container)) => Ok(container),
+                v => Ok(Container::Value(Box::new(v))),
+            }
+        }
+    }
+
+    /// Turn a target into something a reference can point to
+    fn target_to_container(&mut self, target: Target) -> Result<Container, AmlError> {
+        let frame = self.current_frame();
+        match target {
+            Target::Local(index) => Ok(Container::Local { frame, index }),
+            Target::Arg(index) => match &self.frames[frame].args[index] {
+                AmlValue::Reference(Reference::Object(container)) => Ok(container.clone()),
+                _ => Ok(Container::Arg { frame, index }),
+            },
+            Target::Named(path) => Ok(Container::Named(path)),
+            Target::Reference(Reference::Object(container)) => Ok(container),
+            Target::Reference(r) => Ok(Container::Value(Box::new(self.read_reference(&r)?))),
+            Target::Null | Target::Debug => Err(AmlError::IncompatibleType),
+        }
+    }
+
+    /// Read the current value of a target
+    fn read_target(&mut self, target: &Target) -> Result<AmlValue, AmlError> {
+        let frame = self.current_frame();
+        match target {
+            Target::Null | Target::Debug => Ok(AmlValue::Uninitialized),
+            Target::Local(index) => Ok(self.frames[frame].locals[*index].clone()),
+            Target::Arg(index) => match self.frames[frame].args[*index].clone() {
+                AmlValue::Reference(r) => self.read_reference(&r),
+                v => Ok(v),
+            },
+            Target::Named(path) => self.read_named(path),
+            Target::Reference(r) => self.read_reference(r),
+        }
+    }
+
+    /// Read what a container holds
+    fn read_container(&mut self, container: &Container) -> Result<AmlValue, AmlError> {
+        let slot = |frames: &[Frame], frame: usize, index: usize, local: bool| {
+            frames
+                .get(frame)
+                .and_then(|f| {
+                    if local {
+                        f.locals.get(index)
+                    } else {
+                        f.args.get(index)
+                    }
+                })
+                .cloned()
+                .ok_or(AmlError::ObjectNotFound)
+        };
+
+        match container {
+            Container::Named(path) => self.read_named(path),
+            Container::Local { frame, index } => slot(&self.frames, *frame, *index, true),
+            Container::Arg { frame, index } => slot(&self.frames, *frame, *index, false),
+            Container::Value(v) => Ok((**v).clone()),
+        }
+    }
+
+    /// Read what a reference points to
+    fn read_reference(&mut self, reference: &Reference) -> Result<AmlValue, AmlError> {
+        match reference {
+            Reference::Object(container) => self.read_container(container),
+            Reference::Element { container, index } => match self.read_container(container)? {
+                AmlValue::Package(elements) => elements
+                    .get(*index)
+                    .cloned()
+                    .ok_or(AmlError::IndexOutOfBounds),
+                AmlValue::Buffer(bytes) => bytes
+                    .get(*index)
+                    .map(|&b| AmlValue::Integer(u64::from(b)))
+                    .ok_or(AmlError::IndexOutOfBounds),
+                AmlValue::String(v) => v
+                    .as_bytes()
+                    .get(*index)
+                    .map(|&b| AmlValue::Integer(u64::from(b)))
+                    .ok_or(AmlError::IndexOutOfBounds),
+                _ => Err(AmlError::IncompatibleType),
+            },
+        }
+    }
+
+    /// Run a closure on the value a container holds. Writes to temporary values are lost
+    fn with_container_mut<R>(
+        &mut self,
+        container: &Container,
+        f: impl FnOnce(&mut AmlValue) -> Result<R, AmlError>,
+    ) -> Result<R, AmlError> {
+        match container {
+            Container::Named(path) => f(self
+                .namespace
+                .get_mut(path)
+                .ok_or(AmlError::ObjectNotFound)?),
+            Container::Local { frame, index } => f(self
+                .frames
+                .get_mut(*frame)
+                .and_then(|fr| fr.locals.get_mut(*index))
+                .ok_or(AmlError::ObjectNotFound)?),
+            Container::Arg { frame, index } => f(self
+                .frames
+                .get_mut(*frame)
+                .and_then(|fr| fr.args.get_mut(*index))
+                .ok_or(AmlError::ObjectNotFound)?),
+            Container::Value(v) => f(&mut (**v).clone()),
+        }
+    }
+
+    /// Store a value into a target, converting it to the target's type where needed
+    fn store(&mut self, target: Target, value: AmlValue) -> Result<(), AmlError> {
+        let frame = self.current_frame();
+        match target {
+            Target::Null => Ok(()),
+            Target::Debug => {
+                debug!("AML debug: {value:?}");
+                Ok(())
+            }
+            Target::Local(index) => {
+                self.frames[frame].locals[index] = value;
+                Ok(())
+            }
+            Target::Arg(index) => {
+                if let AmlValue::Reference(r) = &self.frames[frame].args[index] {
+                    let r = r.clone();
+                    self.store_reference(&r, value)
+                } else {
+                    self.frames[frame].args[index] = value;
+                    Ok(())
+                }
+            }
+            Target::Named(path) => self.store_named(&path, value),
+            Target::Reference(r) => self.store_reference(&r, value),
+        }
+    }
+
+    /// Replace the value of a target without any conversion
+    fn copy_object(&mut self, target: Target, value: AmlValue) -> Result<(), AmlError> {
+        match target {
+            Target::Named(path) => {
+                *self
+                    .namespace
+                    .get_mut(&path)
+                    .ok_or(AmlError::ObjectNotFound)? = value;
+                Ok(())
+            }
+            target => self.store(target, value),
+        }
+    }
+
+    /// Store a value through a reference
+    fn store_reference(&mut self, reference: &Reference, value: AmlValue) -> Result<(), AmlError> {
+        match reference {
+            Reference::Object(Container::Named(path)) => self.store_named(path, value),
+            Reference::Object(container) => self.with_container_mut(container, |slot| {
+                *slot = value;
+                Ok(())
+            }),
+            Reference::Element { container, index } => {
+                let index = *index;
+                self.with_container_mut(container, |slot| match slot {
+                    AmlValue::Package(elements) => {
+                        *elements.get_mut(index).ok_or(AmlError::IndexOutOfBounds)? = value;
+                        Ok(())
+                    }
+                    AmlValue::Buffer(bytes) => {
+                        *bytes.get_mut(index).ok_or(AmlError::IndexOutOfBounds)? =
+                            value.as_integer()? as u8;
+                        Ok(())
+                    }
+                    _ => Err(AmlError::IncompatibleType),
+                })
+            }
+        }
+    }
+
+    /// Store a value into a named object
+    pub(super) fn store_named(
+        &mut self,
+        path: &[NameSeg],
+        value: AmlValue,
+    ) -> Result<(), AmlError> {
+        match self
+            .namespace
+            .get(path)
+            .ok_or(AmlError::ObjectNotFound)?
+            .clone()
+        {
+            AmlValue::FieldUnit(field) => self.write_field(&field, &value),
+            AmlValue::BufferField {
+                source,
+                bit_offset,
+                bit_length,
+            } => {
+                let bytes = value.as_buffer()?;
+                self.with_container_mut(&source, |slot| match slot {
+                    AmlValue::Buffer(buffer) => {
+                        if bit_offset + bit_length > buffer.len() * 8 {
+                            return Err(AmlError::IndexOutOfBounds);
+                        }
+                        for bit in 0..bit_length {
+                            set_bit(buffer, bit_offset + bit, get_bit(&bytes, bit));
+                        }
+                        Ok(())
+                    }
+                    _ => Err(AmlError::IncompatibleType),
+                })
+            }
+            AmlValue::Method(_)
+            | AmlValue::Device
+            | AmlValue::Scope
+            | AmlValue::Processor { .. }
+            | AmlValue::PowerResource { .. }
+            | AmlValue::ThermalZone
+            | AmlValue::OpRegion(_) => Err(AmlError::IncompatibleType),
+            existing => {
+                let converted = existing.convert_to_type_of(value)?;
+                *self
+                    .namespace
+                    .get_mut(path)
+                    .ok_or(AmlError::ObjectNotFound)? = converted;
+                Ok(())
+            }
+        }
+    }
+
+    /// Get the range of access units a field covers
+    fn field_units(field: &FieldUnit) -> core::ops::RangeInclusive<usize> {
+        let width_bits = field.access_width() * 8;
+        let first = field.bit_offset / width_bits;
+        let last = (field.bit_offset + field.bit_length.max(1) - 1) / width_bits;
+        first..=last
+    }
+
+    /// Read a field from its backing storage
+    fn read_field(&mut self, field: &FieldUnit) -> Result<AmlValue, AmlError> {
+        let width = field.access_width();
+        let width_bits = width * 8;
+        let mut out = Vec::new();
+        out.resize((field.bit_length + 7) / 8, 0);
+
+        for unit in Self::field_units(field) {
+            let unit_start = unit * width_bits;
+            let raw = self.read_field_unit(field, unit * width, width)?;
+            let low = field.bit_offset.max(unit_start);
+            let high = (field.bit_offset + field.bit_length).min(unit_start + width_bits);
+            for bit in low..high {
+                set_bit(
+                    &mut out,
+                    bit - field.bit_offset,
+                    (raw >> (bit - unit_start)) & 1 != 0,
+                );
+            }
+        }
+
+        Ok(field_value(out, field.bit_length))
+    }
+
+    /// Write a field to its backing storage, honoring its update rule
+    fn write_field(&mut self, field: &FieldUnit, value: &AmlValue) -> Result<(), AmlError> {
+        let width = field.access_width();
+        let width_bits = width * 8;
+        let bytes = value.as_buffer()?;
+
+        for unit in Self::field_units(field) {
+            let unit_start = unit * width_bits;
+            let low = field.bit_offset.max(unit_start);
+            let high = (field.bit_offset + field.bit_length).min(unit_start + width_bits);
+
+            let mut raw = if low == unit_start && high == unit_start + width_bits {
+                0
+            } else {
+                match field.update_rule() {
+                    // Preserve
+                    0 => self.read_field_unit(field, unit * width, width)?,
+                    // WriteAsOnes
+                    1 => u64::MAX,
+                    // WriteAsZeros
+                    _ => 0,
+                }
+            };
+
+            for bit in low..high {
+                let mask = 1 << (bit - unit_start);
+                if get_bit(&bytes, bit - field.bit_offset) {
+                    raw |= mask;
+                } else {
+                    raw &= !mask;
+                }
+            }
+
+            self.write_field_unit(field, unit * width, width, raw)?;
+        }
+        Ok(())
+    }
+
+    /// Read a single access unit of a field
+    fn read_field_unit(
+        &mut self,
+        field: &FieldUnit,
+        offset: usize,
+        width: usize,
+    ) -> Result<u64, AmlError> {
+        match &field.kind {
+            FieldKind::Normal { region } => self.read_region(region, offset, width),
+            FieldKind::Index { index, data } => {
+                self.store_named(index, AmlValue::Integer(offset as u64))?;
+                self.read_named(data)?.as_integer()
+            }
+            FieldKind::Bank {
+                region,
+                bank,
+                value,
+            } => {
+                self.store_named(bank, AmlValue::Integer(*value))?;
+                self.read_region(region, offset, width)
+            }
+        }
+    }
+
+    /// Write a single access unit of a field
+    fn write_field_unit(
+        &mut self,
+        field: &FieldUnit,
+        offset: usize,
+        width: usize,
+        value: u64,
+    ) -> Result<(), AmlError> {
+        match &field.kind {
+            FieldKind::Normal { region } => self.write_region(region, offset, width, value),
+            FieldKind::Index { index, data } => {
+                self.store_named(index, AmlValue::Integer(offset as u64))?;
+                self.store_named(data, AmlValue::Integer(value))
+            }
+            FieldKind::Bank {
+                region,
+                bank,
+                value: bank_value,
+            } => {
+                self.store_named(bank, AmlValue::Integer(*bank_value))?;
+                self.write_region(region, offset, width, value)
+            }
+        }
+    }
+
+    /// Get an operation region
+    fn region(&self, path: &[NameSeg]) -> Result<OpRegion, AmlError> {
+        match self.namespace.get(path) {
+            Some(AmlValue::OpRegion(region)) => Ok(region.clone()),
+            Some(_) => Err(AmlError::IncompatibleType),
+            None => Err(AmlError::ObjectNotFound),
+        }
+    }
+
+    /// Read from an operation region
+    fn read_region(
+        &mut self,
+        path: &[NameSeg],
+        offset: usize,
+        width: usize,
+    ) -> Result<u64, AmlError> {
+        let region = self.region(path)?;
+        let address = region.offset + offset as u64;
+        let width = width as u8;
+        match region.space {
+            RegionSpace::SystemMemory => Ok(self.handler.read_memory(address, width)),
+            RegionSpace::SystemIo => Ok(self.handler.read_io(address as u16, width)),
+            RegionSpace::PciConfig => {
+                let pci = self.pci_address(&region)?;
+                Ok(self.handler.read_pci(pci, address as u16, width))
+            }
+            space => Err(AmlError::UnsupportedRegionSpace(space.into())),
+        }
+    }
+
+    /// Write to an operation region
+    fn write_region(
+        &mut self,
+        path: &[NameSeg],
+        offset: usize,
+        width: usize,
+        value: u64,
+    ) -> Result<(), AmlError> {
+        let region = self.region(path)?;
+        let address = region.offset + offset as u64;
+        let width = width as u8;
+        match region.space {
+            RegionSpace::SystemMemory => self.handler.write_memory(address, width, value),
+            RegionSpace::SystemIo => self.handler.write_io(address as u16, width, value),
+            RegionSpace::PciConfig => {
+                let pci = self.pci_address(&region)?;
+                self.handler.write_pci(pci, address as u16, width, value);
+            }
+            space => return Err(AmlError::UnsupportedRegionSpace(space.into())),
+        }
+        Ok(())
+    }
+
+    /// Find the PCI function a configuration space region belongs to, from the `_ADR`
+    /// of its device and the `_BBN` and `_SEG` of the nearest host bridge
+    fn pci_address(&mut self, region: &OpRegion) -> Result<PciAddress, AmlError> {
+        let address = self
+            .evaluate_child(&region.parent, *b"_ADR")?
+            .map_or(Ok(0), |v| v.as_integer())?;
+
+        let mut find_upwards = |seg: [u8; 4]| -> Result<u64, AmlError> {
+            for depth in (0..=region.parent.len()).rev() {
+                if let Some(v) = self.evaluate_child(&region.parent[..depth], seg)? {
+                    return v.as_integer();
+                }
+            }
+            Ok(0)
+        };
+
+        let bus = find_upwards(*b"_BBN")?;
+        let segment = find_upwards(*b"_SEG")?;
+
+        Ok(PciAddress {
+            segment: segment as u16,
+            bus: bus as u8,
+            device: (address >> 16) as u8,
+            function: address as u8,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::tests::*, op, AmlError, AmlValue};
+
+    /// Load a table, and call a method in it
+    fn call(aml: &[u8], method: &str, args: &[u64]) -> Result<AmlValue, AmlError> {
+        let (mut context, _) = TestHandler::context();
+        context.load_table(aml, 2)?;
+        let args = args.iter().map(|&arg| AmlValue::Integer(arg)).collect();
+        context.evaluate(method, args)
+    }
+
+    /// Join the parts of a term
+    fn term(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn arithmetic() {
+        // Return ((Arg0 * Arg1) + (1 << 4) - (Arg0 % Arg1))
+        let aml = method(
+            "CALC",
+            2,
+            &[returns(term(&[
+                &[
+                    op::SUBTRACT,
+                    op::ADD,
+                    op::MULTIPLY,
+                    op::ARG0,
+                    op::ARG0 + 1,
+                    op::ZERO,
+                ],
+                &[
+                    op::SHIFT_LEFT,
+                    op::ONE,
+                    op::BYTE_PREFIX,
+                    4,
+                    op::ZERO,
+                    op::ZERO,
+                ],
+                &[op::MOD, op::ARG0, op::ARG0 + 1, op::ZERO, op::ZERO],
+            ]))],
+        );
+        assert_eq!(
+            call(&aml, "\\CALC", &[7, 3]).unwrap().as_integer().unwrap(),
+            7 * 3 + 16 - 1
+        );
+        assert!(matches!(
+            call(&aml, "\\CALC", &[7, 0]),
+            Err(AmlError::DivideByZero)
+        ));
+    }
+
+    #[test]
+    fn integer_width() {
+        let aml = method(
+            "ONES",
+            0,
+            &[returns(Vec::from([op::NOT, op::ZERO, op::ZERO]))],
+        );
+        // Tables before revision 2 have 32 bit integers
+        for (revision, ones) in [(1, u64::from(u32::MAX)), (2, u64::MAX)] {
+            let (mut context, _) = TestHandler::context();
+            context.load_table(&aml, revision).unwrap();
+            let value = context.evaluate("\\ONES", Vec::new()).unwrap();
+            assert_eq!(value.as_integer().unwrap(), ones);
+        }
+    }
+
+    #[test]
+    fn control_flow() {
+        // Local0 = 0; Local1 = 0
+        // While (Local1 < Arg0) {
+        //     Local1++
+        //     If (Local1 == 3) { Continue }
+        //     Local0 += Local1
+        //     If (Local1 > 5) { Break }
+        // }
+        // Return (Local0)
+        let body = term(&[
+            &[op::INCREMENT, op::LOCAL0 + 1],
+            &package_length(
+                &[op::IF],
+                &[op::LEQUAL, op::LOCAL0 + 1, op::BYTE_PREFIX, 3, op::CONTINUE],
+            ),
+            &[op::ADD, op::LOCAL0, op::LOCAL0 + 1, op::LOCAL0],
+            &package_length(
+                &[op::IF],
+                &[op::LGREATER, op::LOCAL0 + 1, op::BYTE_PREFIX, 5, op::BREAK],
+            ),
+        ]);
+        let aml = method(
+            "SUM_",
+            1,
+            &[
+                Vec::from([op::STORE, op::ZERO, op::LOCAL0]),
+                Vec::from([op::STORE, op::ZERO, op::LOCAL0 + 1]),
+                package_length(
+                    &[op::WHILE],
+                    &[&[op::LLESS, op::LOCAL0 + 1, op::ARG0][..], &body].concat(),
+                ),
+                returns(Vec::from([op::LOCAL0])),
+            ],
+        );
+        assert_eq!(
+            call(&aml, "\\SUM_", &[10]).unwrap().as_integer().unwrap(),
+            1 + 2 + 4 + 5 + 6
+        );
+        assert_eq!(call(&aml, "\\SUM_", &[2]).unwrap().as_integer().unwrap(), 3);
+
+        // If (Arg0) { Return (1) } Else { Return (2) }
+        let aml = method(
+            "PICK",
+            1,
+            &[
+                package_length(&[op::IF], &[op::ARG0, op::RETURN, op::ONE]),
+                package_length(&[op::ELSE], &[op::RETURN, op::BYTE_PREFIX, 2]),
+            ],
+        );
+        assert_eq!(call(&aml, "\\PICK", &[9]).unwrap().as_integer().unwrap(), 1);
+        assert_eq!(call(&aml, "\\PICK", &[0]).unwrap().as_integer().unwrap(), 2);
+    }
+
+    #[test]
+    fn packages_and_buffers() {
+        let aml = [
+            named(
+                "PKG_",
+                package(&[integer(1), string("two"), package(&[integer(3)])]),
+            ),
+            named("BUF_", buffer(&[0; 8])),
+            // Return (DerefOf (Index (DerefOf (Index (PKG, 2)), 0)) + SizeOf (PKG))
+            method(
+                "NEST",
+                0,
+                &[returns(term(&[
+                    &[op::ADD, op::DEREF_OF, op::INDEX, op::DEREF_OF, op::INDEX],
+                    &name("PKG_"),
+                    &[op::BYTE_PREFIX, 2, op::ZERO, op::ZERO, op::ZERO],
+                    &[op::SIZE_OF],
+                    &name("PKG_"),
+                    &[op::ZERO],
+                ]))],
+            ),
+            // CreateDWordField (BUF, 4, DWRD); DWRD = Arg0; Return (BUF)
+            method(
+                "FILL",
+                1,
+                &[
+                    term(&[
+                        &[op::CREATE_DWORD_FIELD],
+                        &name("BUF_"),
+                        &[op::BYTE_PREFIX, 4],
+                    ]),
+                    name("DWRD"),
+                    term(&[&[op::STORE, op::ARG0], &name("DWRD")]),
+                    returns(name("BUF_")),
+                ],
+            ),
+            // Return (Concatenate ("ab", "cd"))
+            method(
+                "JOIN",
+                0,
+                &[returns(term(&[
+                    &[op::CONCAT],
+                    &string("ab"),
+                    &string("cd"),
+                    &[op::ZERO],
+                ]))],
+            ),
+        ]
+        .concat();
+        assert_eq!(call(&aml, "\\NEST", &[]).unwrap().as_integer().unwrap(), 6);
+        assert_eq!(
+            call(&aml, "\\FILL", &[0x1234_5678])
+                .unwrap()
+                .as_buffer()
+                .unwrap(),
+            [0, 0, 0, 0, 0x78, 0x56, 0x34, 0x12]
+        );
+        assert_eq!(
+            call(&aml, "\\JOIN", &[]).unwrap().as_string().unwrap(),
+            "abcd"
+        );
+    }
+
+    #[test]
+    fn io_fields() {
+        // OperationRegion (DBG, SystemIO, 0x402, 2)
+        // Field (DBG, ByteAcc, NoLock, Preserve) { LOW, 8, HIGH, 8 }
+        // Method (SWAP, 1) { HIGH = Arg0; Return (LOW) }
+        let aml = [
+            term(&[
+                &[op::EXT_PREFIX, op::ext::OP_REGION],
+                &name("DBG_"),
+                &[0x01],
+                &integer(0x402),
+                &[op::BYTE_PREFIX, 2],
+            ]),
+            package_length(
+                &[op::EXT_PREFIX, op::ext::FIELD],
+                &term(&[&name("DBG_"), &[0x01], b"LOW_", &[8], b"HIGH", &[8]]),
+            ),
+            method(
+                "SWAP",
+                1,
+                &[
+                    term(&[&[op::STORE, op::ARG0], &name("HIGH")]),
+                    returns(name("LOW_")),
+                ],
+            ),
+        ]
+        .concat();
+        let (mut context, writes) = TestHandler::context();
+        context.load_table(&aml, 2).unwrap();
+        let value = context
+            .evaluate("\\SWAP", Vec::from([AmlValue::Integer(0xAB)]))
+            .unwrap();
+        // Reading a port gets its number, cut down to the field's width
+        assert_eq!(value.as_integer().unwrap(), 0x02);
+        assert_eq!(writes.lock()[..], [(0x403, 1, 0xAB)]);
+    }
+
+    #[test]
+    fn method_scope() {
+        // Method (TEMP) { Name (LOCL, 5); Return (LOCL) }, which can be called again as its
+        // objects go when it returns
+        let aml = method(
+            "TEMP",
+            0,
+            &[named("LOCL", integer(5)), returns(name("LOCL"))],
+        );
+        let (mut context, _) = TestHandler::context();
+        context.load_table(&aml, 2).unwrap();
+        for _ in 0..2 {
+            let value = context.evaluate("\\TEMP", Vec::new()).unwrap();
+            assert_eq!(value.as_integer().unwrap(), 5);
+        }
+        assert!(matches!(
+            context.evaluate("\\TEMP.LOCL", Vec::new()),
+            Err(AmlError::ObjectNotFound)
+        ));
+
+        // A method that calls itself forever, and one nesting thousands of Adds, both of which
+        // fail once they've used the stack budget rather than overflowing the stack
+        let aml = method("LOOP", 0, &[returns(name("LOOP"))]);
+        assert!(matches!(
+            call(&aml, "\\LOOP", &[]),
+            Err(AmlError::RecursionLimitExceeded)
+        ));
+        let depth = 2000;
+        let mut sum = vec![op::ADD; depth];
+        sum.push(op::ONE);
+        for _ in 0..depth {
+            sum.extend_from_slice(&[op::ONE, op::ZERO]);
+        }
+        let aml = method("DEEP", 0, &[returns(sum)]);
+        assert!(matches!(
+            call(&aml, "\\DEEP", &[]),
+            Err(AmlError::RecursionLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn predefined_objects() {
+        let (mut context, _) = TestHandler::context();
+        let osi = |context: &mut super::AmlContext, interface: &str| {
+            context
+                .evaluate("\\_OSI", Vec::from([AmlValue::String(interface.into())]))
+                .unwrap()
+                .as_integer()
+                .unwrap()
+        };
+        assert_eq!(osi(&mut context, "Windows 2015"), u64::MAX);
+        assert_eq!(osi(&mut context, "Linux"), 0);
+        assert_eq!(
+            context
+                .evaluate("\\_REV", Vec::new())
+                .unwrap()
+                .as_integer()
+                .unwrap(),
+            u64::from(super::AmlContext::REVISION)
+        );
+    }
+
+    #[test]
+    fn malformed_tables() {
+        let (mut context, _) = TestHandler::context();
+        // A method that runs past the end of the table
+        let mut aml = method("CUT_", 0, &[returns(integer(1))]);
+        aml.pop();
+        assert!(context.load_table(&aml, 2).is_err());
+        // An opcode that doesn't exist
+        let aml = method("BAD_", 0, &[Vec::from([0x02])]);
+        assert!(matches!(
+            call(&aml, "\\BAD_", &[]),
+            Err(AmlError::UnsupportedOpcode(0x02))
+        ));
+        // Firmware declaring a name twice is let off while loading, keeping the first, but not
+        // while running a method
+        let aml = [
+            named("TWCE", integer(1)),
+            named("TWCE", integer(2)),
+            method("DUPE", 0, &[named("\\TWCE", integer(3))]),
+        ]
+        .concat();
+        context.load_table(&aml, 2).unwrap();
+        let value = context.evaluate("\\TWCE", Vec::new()).unwrap();
+        assert_eq!(value.as_integer().unwrap(), 1);
+        assert!(matches!(
+            context.evaluate("\\DUPE", Vec::new()),
+            Err(AmlError::ObjectAlreadyExists)
+        ));
+        // Declaring something in a scope that doesn't exist
+        assert!(matches!(
+            context.load_table(&named("\\NONE.NAME", integer(1)), 2),
+            Err(AmlError::ObjectNotFound)
+        ));
+    }
+}
@@ -0,0 +1,799 @@
+use log::{trace, warn};
+
+use crate::{errors::AmlError, macros::bitflags::bitflags};
+
+mod interpreter;
+
+mod name;
+pub use name::{path_to_string, AmlName, AmlPath, NameSeg};
+
+mod namespace;
+pub use namespace::Namespace;
+
+/// Resource template decoding
+pub mod resource;
+pub use resource::Resource;
+
+mod stream;
+
+mod value;
+pub use value::{
+    AmlValue, Container, FieldKind, FieldUnit, Method, MethodCode, NativeMethod, ObjectType,
+    OpRegion, Reference, RegionSpace,
+};
+
+/// The location of a PCI function's configuration space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PciAddress {
+    /// The PCI segment group
+    pub segment: u16,
+    /// The bus number
+    pub bus: u8,
+    /// The device number
+    pub device: u8,
+    /// The function number
+    pub function: u8,
+}
+
+/// Access to the hardware AML operates on
+///
+/// Widths are in bytes, and are always 1, 2, 4, or 8
+pub trait Handler {
+    /// Read from physical memory
+    fn read_memory(&self, address: u64, width: u8) -> u64;
+
+    /// Write to physical memory
+    fn write_memory(&self, address: u64, width: u8, value: u64);
+
+    /// Read from an I/O port
+    fn read_io(&self, port: u16, width: u8) -> u64;
+
+    /// Write to an I/O port
+    fn write_io(&self, port: u16, width: u8, value: u64);
+
+    /// Read from PCI configuration space
+    fn read_pci(&self, address: PciAddress, offset: u16, width: u8) -> u64;
+
+    /// Write to PCI configuration space
+    fn write_pci(&self, address: PciAddress, offset: u16, width: u8, value: u64);
+
+    /// Busy wait for a number of microseconds
+    fn stall(&self, microseconds: u64);
+
+    /// Wait for a number of milliseconds
+    fn sleep(&self, milliseconds: u64);
+
+    /// Get a monotonic timer in 100 nanosecond units
+    fn timer(&self) -> u64 {
+        0
+    }
+
+    /// Handle a `Notify` on an object
+    fn notify(&self, path: &[NameSeg], value: u64) {
+        trace!("AML notify {} with {:#X}", path_to_string(path), value);
+    }
+}
+
+bitflags! {
+    /// What a device's `_STA` method says about it
+    pub struct DeviceStatus: u64 {
+        /// The device is present
+        const PRESENT = 1 << 0;
+        /// The device is enabled and decoding its resources
+        const ENABLED = 1 << 1;
+        /// The device should be shown in the UI
+        const SHOWN = 1 << 2;
+        /// The device is functioning properly
+        const FUNCTIONING = 1 << 3;
+        /// A battery is present
+        const BATTERY_PRESENT = 1 << 4;
+    }
+}
+
+/// An entry of a PCI routing table (`_PRT`)
+#[derive(Clone, Debug)]
+pub struct PciRoute {
+    /// The device number the entry applies to
+    pub device: u8,
+    /// The interrupt pin, 0 being `INTA#`
+    pub pin: u8,
+    /// The link device the pin is routed to, or `None` if it's hardwired
+    pub source: Option<AmlPath>,
+    /// The index into the link device's resources, or the global system interrupt
+    /// if the pin is hardwired
+    pub source_index: u32,
+}
+
+/// The state of a method being executed
+struct Frame {
+    /// The scope names are resolved relative to
+    scope: AmlPath,
+    /// `Local0` through `Local7`
+    locals: Vec<AmlValue>,
+    /// `Arg0` through `Arg6`
+    args: Vec<AmlValue>,
+    /// Objects declared while running, which are removed when it returns
+    created: Vec<AmlPath>,
+}
+
+impl Frame {
+    /// Make a frame with the given scope
+    fn new(scope: AmlPath) -> Self {
+        let empty = |count| (0..count).map(|_| AmlValue::Uninitialized).collect();
+        Self {
+            scope,
+            locals: empty(8),
+            args: empty(7),
+            created: Vec::new(),
+        }
+    }
+}
+
+/// An AML interpreter and the namespace it has loaded
+#[allow(clippy::module_name_repetitions)]
+pub struct AmlContext {
+    namespace: Namespace,
+    handler: Box<dyn Handler>,
+    frames: Vec<Frame>,
+    integer_bits: u8,
+    /// The stack pointer when the interpreter was entered from outside, which the stack
+    /// it uses is measured from
+    stack_base: Option<usize>,
+}
+
+impl AmlContext {
+    /// The revision of the interpreter, as returned by `Revision` and `\_REV`
+    pub const REVISION: u8 = 2;
+
+    /// The interfaces `\_OSI` reports as supported. Firmware is tested against Windows,
+    /// so claiming to be it gets the most reliable code paths
+    const SUPPORTED_INTERFACES: [&'static str; 26] = [
+        "Windows 2000",
+        "Windows 2001",
+        "Windows 2001 SP1",
+        "Windows 2001.1",
+        "Windows 2001 SP2",
+        "Windows 2001.1 SP1",
+        "Windows 2006",
+        "Windows 2006 SP1",
+        "Windows 2006.1",
+        "Windows 2009",
+        "Windows 2012",
+        "Windows 2013",
+        "Windows 2015",
+        "Windows 2016",
+        "Windows 2017",
+        "Windows 2017.2",
+        "Windows 2018",
+        "Windows 2018.2",
+        "Windows 2019",
+        "Windows 2020",
+        "Windows 2021",
+        "Windows 2022",
+        "Module Device",
+        "Processor Device",
+        "3.0 Thermal Model",
+        "Processor Aggregator Device",
+    ];
+
+    /// Make a new interpreter with an empty namespace
+    pub fn new(handler: Box<dyn Handler>) -> Self {
+        let mut namespace = Namespace::new();
+        let root = |name: &[u8; 4]| Vec::from([NameSeg(*name)]);
+
+        // The root exists, so adding predefined objects to it can't fail
+        let _ = namespace.add(
+            root(b"_OSI"),
+            AmlValue::Method(Method {
+                flags: 1,
+                code: MethodCode::Native(Self::osi),
+            }),
+        );
+        let _ = namespace.add(
+            root(b"_OS_"),
+            AmlValue::String(String::from("Microsoft Windows NT")),
+        );
+        let _ = namespace.add(root(b"_REV"), AmlValue::Integer(u64::from(Self::REVISION)));
+
+        Self {
+            namespace,
+            handler,
+            frames: Vec::from([Frame::new(Vec::new())]),
+            integer_bits: 64,
+            stack_base: None,
+        }
+    }
+
+    /// The implementation of `\_OSI`
+    fn osi(context: &mut Self, args: &[AmlValue]) -> Result<AmlValue, AmlError> {
+        let interface = args
+            .first()
+            .ok_or(AmlError::IncompatibleType)?
+            .as_string()?;
+        let supported = Self::SUPPORTED_INTERFACES.contains(&interface.as_str());
+        trace!("_OSI({:?}) = {}", interface, supported);
+        Ok(AmlValue::Integer(if supported {
+            context.mask(u64::MAX)
+        } else {
+            0
+        }))
+    }
+
+    /// Get the namespace
+    #[must_use]
+    pub const fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    /// Load the definition block of a table into the namespace
+    ///
+    /// # Arguments
+    /// * `aml` - The body of the table, after its header
+    /// * `revision` - The revision from the table's header, which sets the integer
+    ///   width for the DSDT
+    ///
+    /// # Errors
+    /// This will return an error if the AML is malformed or uses unsupported features
+    pub fn load_table(&mut self, aml: &[u8], revision: u8) -> Result<(), AmlError> {
+        if revision < 2 {
+            self.integer_bits = 32;
+        }
+
+        let table: Arc<[u8]> = Arc::from(aml);
+        self.frames.truncate(1);
+        match self.enter(|context| context.run_term_list(&table, 0, table.len()))? {
+            interpreter::Flow::Normal => Ok(()),
+            _ => Err(AmlError::InvalidControlFlow),
+        }
+    }
+
+    /// Evaluate an object by its absolute name, such as `\_SB.PCI0._CRS`, calling it if
+    /// it's a method
+    ///
+    /// # Errors
+    /// This will return an error if the object doesn't exist or evaluation fails
+    pub fn evaluate(&mut self, name: &str, args: Vec<AmlValue>) -> Result<AmlValue, AmlError> {
+        let name: AmlName = name.parse()?;
+        let path = self.namespace.search(&name, &[])?;
+        self.evaluate_path(&path, args)
+    }
+
+    /// Evaluate an object by its path, calling it if it's a method
+    ///
+    /// # Errors
+    /// This will return an error if the object doesn't exist or evaluation fails
+    pub fn evaluate_path(
+        &mut self,
+        path: &[NameSeg],
+        args: Vec<AmlValue>,
+    ) -> Result<AmlValue, AmlError> {
+        match self.namespace.get(path) {
+            Some(AmlValue::Method(method)) => {
+                let method = method.clone();
+                self.enter(|context| context.call_method(path, &method, args))
+            }
+            Some(_) => self.enter(|context| context.read_named(path)),
+            None => Err(AmlError::ObjectNotFound),
+        }
+    }
+
+    /// Evaluate a child of an object without arguments, if it exists
+    ///
+    /// # Errors
+    /// This will return an error if evaluation fails
+    pub fn evaluate_child(
+        &mut self,
+        parent: &[NameSeg],
+        name: [u8; 4],
+    ) -> Result<Option<AmlValue>, AmlError> {
+        let mut path = parent.to_vec();
+        path.push(NameSeg(name));
+        if self.namespace.contains(&path) {
+            self.evaluate_path(&path, Vec::new()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get the status of a device from its `_STA`, which defaults to present and working
+    ///
+    /// # Errors
+    /// This will return an error if `_STA` fails
+    pub fn device_status(&mut self, device: &[NameSeg]) -> Result<DeviceStatus, AmlError> {
+        Ok(match self.evaluate_child(device, *b"_STA")? {
+            Some(v) => DeviceStatus::from_bits_truncate(v.as_integer()?),
+            None => {
+                DeviceStatus::PRESENT
+                    | DeviceStatus::ENABLED
+                    | DeviceStatus::SHOWN
+                    | DeviceStatus::FUNCTIONING
+            }
+        })
+    }
+
+    /// Run `\_SB._INI`, then `_INI` on every present device, as the spec requires after
+    /// the tables are loaded
+    ///
+    /// # Errors
+    /// This will return an error if `\_SB._INI` fails, failures of a single device are
+    /// only logged
+    pub fn initialize_devices(&mut self) -> Result<(), AmlError> {
+        let system_bus = Vec::from([NameSeg(*b"_SB_")]);
+        self.evaluate_child(&system_bus, *b"_INI")?;
+
+        // Tell the firmware it can use PCI configuration space
+        let pci_regions = self
+            .namespace
+            .iter()
+            .filter_map(|(_, value)| match value {
+                AmlValue::OpRegion(region) if region.space == RegionSpace::PciConfig => {
+                    Some(region.parent.clone())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for parent in pci_regions {
+            let mut reg = parent.clone();
+            reg.push(NameSeg(*b"_REG"));
+            if self.namespace.contains(&reg) {
+                let args = Vec::from([AmlValue::Integer(2), AmlValue::Integer(1)]);
+                if let Err(e) = self.evaluate_path(&reg, args) {
+                    warn!("{} failed: {:?}", path_to_string(&reg), e);
+                }
+            }
+        }
+
+        self.initialize_children(&system_bus);
+        Ok(())
+    }
+
+    /// Initialize the devices under an object
+    fn initialize_children(&mut self, parent: &[NameSeg]) {
+        let children = self
+            .namespace
+            .children(parent)
+            .filter(|(_, value)| {
+                matches!(
+                    value,
+                    AmlValue::Device | AmlValue::Processor { .. } | AmlValue::ThermalZone
+                )
+            })
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        for child in children {
+            let status = match self.device_status(&child) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("{}._STA failed: {:?}", path_to_string(&child), e);
+                    continue;
+                }
+            };
+
+            if status.contains(DeviceStatus::PRESENT) {
+                if let Err(e) = self.evaluate_child(&child, *b"_INI") {
+                    warn!("{}._INI failed: {:?}", path_to_string(&child), e);
+                }
+            }
+
+            // Children of a device that isn't present may still be functioning
+            if status.contains(DeviceStatus::PRESENT) || status.contains(DeviceStatus::FUNCTIONING)
+            {
+                self.initialize_children(&child);
+            }
+        }
+    }
+
+    /// Get the current resources of a device from its `_CRS`
+    ///
+    /// # Errors
+    /// This will return an error if `_CRS` doesn't exist, fails, or is malformed
+    pub fn current_resources(&mut self, device: &[NameSeg]) -> Result<Vec<Resource>, AmlError> {
+        let crs = self
+            .evaluate_child(device, *b"_CRS")?
+            .ok_or(AmlError::ObjectNotFound)?;
+        resource::parse(&crs.as_buffer()?)
+    }
+
+    /// Get the PCI routing table of a host bridge or PCI-to-PCI bridge from its `_PRT`
+    ///
+    /// # Errors
+    /// This will return an error if `_PRT` doesn't exist, fails, or is malformed
+    pub fn pci_routing_table(&mut self, bridge: &[NameSeg]) -> Result<Vec<PciRoute>, AmlError> {
+        let entries = match self
+            .evaluate_child(bridge, *b"_PRT")?
+            .ok_or(AmlError::ObjectNotFound)?
+        {
+            AmlValue::Package(entries) => entries,
+            _ => return Err(AmlError::IncompatibleType),
+        };
+
+        entries
+            .iter()
+            .map(|entry| {
+                let fields = match entry {
+                    AmlValue::Package(fields) if fields.len() >= 4 => fields,
+                    _ => return Err(AmlError::IncompatibleType),
+                };
+
+                let source = match &fields[2] {
+                    AmlValue::Integer(_) => None,
+                    AmlValue::Reference(Reference::Object(Container::Named(path))) => {
+                        Some(path.clone())
+                    }
+                    AmlValue::String(name) => {
+                        Some(self.namespace.search(&name.parse::<AmlName>()?, bridge)?)
+                    }
+                    _ => return Err(AmlError::IncompatibleType),
+                };
+
+                // The device is in the high word of the address, and the pin and source index
+                // are small
+                #[allow(clippy::cast_possible_truncation)]
+                let route = PciRoute {
+                    device: (fields[0].as_integer()? >> 16) as u8,
+                    pin: fields[1].as_integer()? as u8,
+                    source,
+                    source_index: fields[3].as_integer()? as u32,
+                };
+                Ok(route)
+            })
+            .collect()
+    }
+
+    /// Find the global system interrupt a PCI routing table entry is routed to
+    ///
+    /// # Errors
+    /// This will return an error if the link device's resources can't be read
+    pub fn route_interrupt(&mut self, route: &PciRoute) -> Result<u32, AmlError> {
+        let link = match &route.source {
+            Some(link) => link.clone(),
+            None => return Ok(route.source_index),
+        };
+
+        self.current_resources(&link)?
+            .into_iter()
+            .find_map(|resource| match resource {
+                Resource::Irq { irqs, .. } => irqs.first().map(|&irq| u32::from(irq)),
+                Resource::ExtendedIrq { interrupts, .. } => interrupts
+                    .get(route.source_index as usize)
+                    .or_else(|| interrupts.first())
+                    .copied(),
+                _ => None,
+            })
+            .ok_or(AmlError::ObjectNotFound)
+    }
+}
+
+impl core::fmt::Debug for AmlContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AmlContext")
+            .field("integer_bits", &self.integer_bits)
+            .field("objects", &self.namespace.iter().count())
+            .finish()
+    }
+}
+
+/// A handler and a small AML assembler for tests, which other tests in `acpi` use too
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::sync::Mutex;
+
+    /// Every `(port, width, value)` written to an I/O port
+    pub type Writes = Arc<Mutex<Vec<(u16, u8, u64)>>>;
+
+    /// Pretends to be hardware. I/O port writes are recorded, and reading a port gets its number
+    pub struct TestHandler {
+        /// The writes so far
+        pub writes: Writes,
+    }
+
+    impl TestHandler {
+        /// Make an interpreter with a test handler, and get the writes it records
+        #[must_use]
+        pub fn context() -> (AmlContext, Writes) {
+            let writes = Arc::new(Mutex::new(Vec::new()));
+            let handler = Self {
+                writes: writes.clone(),
+            };
+            (AmlContext::new(Box::new(handler)), writes)
+        }
+    }
+
+    impl Handler for TestHandler {
+        fn read_memory(&self, _: u64, _: u8) -> u64 {
+            0
+        }
+
+        fn write_memory(&self, _: u64, _: u8, _: u64) {}
+
+        fn read_io(&self, port: u16, _: u8) -> u64 {
+            u64::from(port)
+        }
+
+        fn write_io(&self, port: u16, width: u8, value: u64) {
+            self.writes.lock().push((port, width, value));
+        }
+
+        fn read_pci(&self, _: PciAddress, _: u16, _: u8) -> u64 {
+            u64::MAX
+        }
+
+        fn write_pci(&self, _: PciAddress, _: u16, _: u8, _: u64) {}
+
+        fn stall(&self, _: u64) {}
+
+        fn sleep(&self, _: u64) {}
+    }
+
+    /// Encode a name like `\_SB.PCI0.LNKA`, padding each segment with underscores
+    #[must_use]
+    pub fn name(path: &str) -> Vec<u8> {
+        let (mut encoded, path) = path
+            .strip_prefix('\\')
+            .map_or((Vec::new(), path), |rest| (Vec::from(*b"\\"), rest));
+        let segments: Vec<&str> = path.split('.').collect();
+        match segments.len() {
+            1 => {}
+            2 => encoded.push(0x2E),
+            count => encoded.extend_from_slice(&[0x2F, count as u8]),
+        }
+        for segment in segments {
+            let mut segment = Vec::from(segment.as_bytes());
+            segment.resize(4, b'_');
+            encoded.extend_from_slice(&segment);
+        }
+        encoded
+    }
+
+    /// Put an opcode before a body, with the package length between them
+    ///
+    /// # Panics
+    /// If the body is too long for a package length
+    #[must_use]
+    pub fn package_length(opcode: &[u8], body: &[u8]) -> Vec<u8> {
+        // The length counts the one to four bytes it's encoded in
+        let (extra, length) = (0..4)
+            .map(|extra| (extra, body.len() + 1 + extra))
+            .find(|&(extra, length)| {
+                if extra == 0 {
+                    length < 0x40
+                } else {
+                    length < 1 << (4 + 8 * extra)
+                }
+            })
+            .unwrap();
+        let mut encoded = Vec::from(opcode);
+        if extra == 0 {
+            encoded.push(length as u8);
+        } else {
+            encoded.push(((extra as u8) << 6) | (length as u8 & 0xF));
+            for byte in 0..extra {
+                encoded.push((length >> (4 + 8 * byte)) as u8);
+            }
+        }
+        encoded.extend_from_slice(body);
+        encoded
+    }
+
+    /// `Scope (path) { body }`
+    #[must_use]
+    pub fn scope(path: &str, body: &[Vec<u8>]) -> Vec<u8> {
+        package_length(&[0x10], &[name(path), body.concat()].concat())
+    }
+
+    /// `Device (path) { body }`
+    #[must_use]
+    pub fn device(path: &str, body: &[Vec<u8>]) -> Vec<u8> {
+        package_length(&[0x5B, 0x82], &[name(path), body.concat()].concat())
+    }
+
+    /// `Method (path, arguments, NotSerialized) { body }`
+    #[must_use]
+    pub fn method(path: &str, arguments: u8, body: &[Vec<u8>]) -> Vec<u8> {
+        package_length(
+            &[0x14],
+            &[name(path), Vec::from([arguments]), body.concat()].concat(),
+        )
+    }
+
+    /// `Name (path, value)`
+    #[must_use]
+    pub fn named(path: &str, value: Vec<u8>) -> Vec<u8> {
+        [Vec::from([0x08]), name(path), value].concat()
+    }
+
+    /// `Return (value)`
+    #[must_use]
+    pub fn returns(value: Vec<u8>) -> Vec<u8> {
+        [Vec::from([0xA4]), value].concat()
+    }
+
+    /// An integer, in the smallest encoding that fits it
+    #[must_use]
+    pub fn integer(value: u64) -> Vec<u8> {
+        match value {
+            0 | 1 => Vec::from([value as u8]),
+            2..=0xFF => Vec::from([0x0A, value as u8]),
+            0x100..=0xFFFF => [&[0x0B][..], &(value as u16).to_le_bytes()].concat(),
+            0x1_0000..=0xFFFF_FFFF => [&[0x0C][..], &(value as u32).to_le_bytes()].concat(),
+            _ => [&[0x0E][..], &value.to_le_bytes()].concat(),
+        }
+    }
+
+    /// A string
+    #[must_use]
+    pub fn string(value: &str) -> Vec<u8> {
+        [&[0x0D][..], value.as_bytes(), &[0]].concat()
+    }
+
+    /// `Buffer () { bytes }`
+    #[must_use]
+    pub fn buffer(bytes: &[u8]) -> Vec<u8> {
+        package_length(
+            &[0x11],
+            &[integer(bytes.len() as u64), Vec::from(bytes)].concat(),
+        )
+    }
+
+    /// `Package () { elements }`
+    #[must_use]
+    pub fn package(elements: &[Vec<u8>]) -> Vec<u8> {
+        package_length(
+            &[0x12],
+            &[Vec::from([elements.len() as u8]), elements.concat()].concat(),
+        )
+    }
+
+    /// The firmware Firecracker gives its virtual machines, as read from
+    /// `/sys/firmware/acpi/tables/DSDT` in one
+    const FIRECRACKER: &[u8] = include_bytes!("tables/firecracker-dsdt.aml");
+
+    /// Get a path from a name like `\_SB.PCI0`
+    fn path(name: &str) -> AmlPath {
+        name.trim_start_matches('\\')
+            .split('.')
+            .map(|segment| NameSeg(segment.as_bytes().try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn loads_firecracker() {
+        let (mut context, _) = TestHandler::context();
+        let (header, body) = FIRECRACKER.split_at(36);
+        assert_eq!(&header[..4], b"DSDT");
+        context.load_table(body, header[8]).unwrap();
+        context.initialize_devices().unwrap();
+
+        let serial = path("\\_SB_.COM1");
+        assert!(context
+            .device_status(&serial)
+            .unwrap()
+            .contains(DeviceStatus::PRESENT | DeviceStatus::ENABLED | DeviceStatus::FUNCTIONING));
+        let resources = context.current_resources(&serial).unwrap();
+        assert!(resources.iter().any(|resource| matches!(
+            resource,
+            Resource::ExtendedIrq { interrupts, .. } if interrupts[..] == [4]
+        )));
+        assert!(resources.iter().any(|resource| matches!(
+            resource,
+            Resource::Io {
+                minimum: 0x3F8,
+                length: 8,
+                ..
+            }
+        )));
+        assert_eq!(
+            context
+                .evaluate("\\_SB_.VCLK._HID", Vec::new())
+                .unwrap()
+                .as_string()
+                .unwrap(),
+            "AMZNC10C"
+        );
+
+        // Every slot is hardwired, as Firecracker's devices use message signalled interrupts
+        let bridge = path("\\_SB_.PC00");
+        let routes = context.pci_routing_table(&bridge).unwrap();
+        assert_eq!(routes.len(), 32);
+        for (slot, route) in routes.iter().enumerate() {
+            assert_eq!(usize::from(route.device), slot);
+            assert!(route.source.is_none());
+            assert_eq!(context.route_interrupt(route).unwrap(), route.source_index);
+        }
+        // Each hotplug slot is named after its device number
+        assert_eq!(
+            context
+                .evaluate("\\_SB_.PC00.S005._ADR", Vec::new())
+                .unwrap()
+                .as_integer()
+                .unwrap(),
+            5 << 16
+        );
+    }
+
+    #[test]
+    fn routes_through_link_devices() {
+        // An `Interrupt (ResourceConsumer, Edge, ActiveLow, Shared) { 11 }`, then the end tag
+        let interrupt = [
+            0x89, 0x06, 0x00, 0x0F, 0x01, 0x0B, 0x00, 0x00, 0x00, 0x79, 0x00,
+        ];
+        let aml = scope(
+            "\\_SB",
+            &[
+                device("LNKA", &[named("_CRS", buffer(&interrupt))]),
+                device(
+                    "PCI0",
+                    &[named(
+                        "_PRT",
+                        package(&[
+                            package(&[integer(0x0001_FFFF), integer(0), name("LNKA"), integer(0)]),
+                            package(&[integer(0x0002_FFFF), integer(1), integer(0), integer(17)]),
+                        ]),
+                    )],
+                ),
+            ],
+        );
+        let (mut context, _) = TestHandler::context();
+        context.load_table(&aml, 2).unwrap();
+
+        let routes = context.pci_routing_table(&path("\\_SB_.PCI0")).unwrap();
+        assert_eq!((routes[0].device, routes[0].pin), (1, 0));
+        assert_eq!(routes[0].source, Some(path("\\_SB_.LNKA")));
+        assert_eq!(context.route_interrupt(&routes[0]).unwrap(), 11);
+        assert_eq!((routes[1].device, routes[1].pin), (2, 1));
+        assert_eq!(context.route_interrupt(&routes[1]).unwrap(), 17);
+        assert!(matches!(
+            context.pci_routing_table(&path("\\_SB_.LNKA")),
+            Err(AmlError::ObjectNotFound)
+        ));
+    }
+
+    #[test]
+    fn initializes_present_devices() {
+        // Each `_INI` sets its own bit in BITS
+        let init = |bit: u64| {
+            method(
+                "_INI",
+                0,
+                &[[&[0x72][..], &name("\\BITS"), &integer(bit), &name("\\BITS")].concat()],
+            )
+        };
+        let status = |value: u64| method("_STA", 0, &[returns(integer(value))]);
+        let aml = [
+            named("\\BITS", integer(0)),
+            scope(
+                "\\_SB",
+                &[
+                    init(1),
+                    // Absent, so its `_INI` isn't run
+                    device("ABSN", &[status(0), init(2)]),
+                    // No `_STA` means it's present
+                    device("PRES", &[init(4)]),
+                    // Not present but functioning, so only its children are initialized
+                    device(
+                        "FUNC",
+                        &[status(0x08), init(8), device("CHLD", &[init(16)])],
+                    ),
+                ],
+            ),
+        ]
+        .concat();
+        let (mut context, _) = TestHandler::context();
+        context.load_table(&aml, 2).unwrap();
+        context.initialize_devices().unwrap();
+        assert_eq!(
+            context
+                .evaluate("\\BITS", Vec::new())
+                .unwrap()
+                .as_integer()
+                .unwrap(),
+            1 | 4 | 16
+        );
+        assert!(context
+            .device_status(&path("\\_SB_.ABSN"))
+            .unwrap()
+            .is_empty());
+    }
+}
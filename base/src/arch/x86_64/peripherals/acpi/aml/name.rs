@@ -0,0 +1,199 @@
+use core::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
+
+use crate::errors::AmlError;
+
+/// A four character segment of an AML name, such as `_SB_`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[allow(clippy::module_name_repetitions)]
+pub struct NameSeg(pub [u8; 4]);
+
+impl NameSeg {
+    /// Check if a character can start a name segment
+    #[must_use]
+    pub const fn is_lead_char(c: u8) -> bool {
+        c.is_ascii_uppercase() || c == b'_'
+    }
+
+    /// Check if a character can appear in a name segment
+    #[must_use]
+    pub const fn is_name_char(c: u8) -> bool {
+        Self::is_lead_char(c) || c.is_ascii_digit()
+    }
+
+    /// Get the name segment as a string
+    #[must_use]
+    pub const fn as_str(&self) -> &str {
+        match core::str::from_utf8(&self.0) {
+            Ok(s) => s,
+            Err(_) => "????",
+        }
+    }
+}
+
+impl FromStr for NameSeg {
+    type Err = AmlError;
+
+    /// Make a name segment from a string, padding it with underscores.
+    /// This fails if the string is empty, too long, or has invalid characters
+    fn from_str(s: &str) -> Result<Self, AmlError> {
+        let bytes = s.as_bytes();
+        if bytes.is_empty() || bytes.len() > 4 || !Self::is_lead_char(bytes[0]) {
+            return Err(AmlError::InvalidName);
+        }
+
+        let mut seg = [b'_'; 4];
+        for (i, &c) in bytes.iter().enumerate() {
+            if !Self::is_name_char(c) {
+                return Err(AmlError::InvalidName);
+            }
+            seg[i] = c;
+        }
+        Ok(Self(seg))
+    }
+}
+
+impl Debug for NameSeg {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Display for NameSeg {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// An absolute path in the namespace, the root being an empty path
+pub type AmlPath = Vec<NameSeg>;
+
+/// Format an absolute path, such as `\_SB_.PCI0`
+#[must_use]
+pub fn path_to_string(path: &[NameSeg]) -> String {
+    let mut out = String::from("\\");
+    for (i, seg) in path.iter().enumerate() {
+        if i != 0 {
+            out.push('.');
+        }
+        out.push_str(seg.as_str());
+    }
+    out
+}
+
+/// A name as it appears in AML, which may be relative to the current scope
+#[derive(Clone, PartialEq, Eq)]
+#[allow(clippy::module_name_repetitions)]
+pub struct AmlName {
+    /// If the name starts at the root of the namespace
+    pub root: bool,
+    /// The amount of parent prefixes (`^`) before the segments
+    pub parents: usize,
+    /// The segments of the name
+    pub segments: Vec<NameSeg>,
+}
+
+impl AmlName {
+    /// Make a name referring to the root of the namespace
+    #[must_use]
+    pub const fn root() -> Self {
+        Self {
+            root: true,
+            parents: 0,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Check if the namespace search rules apply to this name, which is the case for
+    /// single segment names without any prefixes
+    #[must_use]
+    pub fn uses_search_rules(&self) -> bool {
+        !self.root && self.parents == 0 && self.segments.len() == 1
+    }
+
+    /// Check if the name has no segments, as in a `NullName`
+    #[must_use]
+    pub fn is_null(&self) -> bool {
+        !self.root && self.parents == 0 && self.segments.is_empty()
+    }
+
+    /// Resolve the name into an absolute path from the given scope
+    ///
+    /// # Errors
+    /// This will return an error if the name goes above the root of the namespace
+    pub fn resolve(&self, scope: &[NameSeg]) -> Result<AmlPath, AmlError> {
+        let mut path = if self.root {
+            Vec::new()
+        } else {
+            let depth = scope
+                .len()
+                .checked_sub(self.parents)
+                .ok_or(AmlError::InvalidName)?;
+            scope[..depth].to_vec()
+        };
+        path.extend_from_slice(&self.segments);
+        Ok(path)
+    }
+}
+
+impl FromStr for AmlName {
+    type Err = AmlError;
+
+    /// Parse a name written in ASL notation, such as `\_SB.PCI0._PRT` or `^^FOO`
+    fn from_str(s: &str) -> Result<Self, AmlError> {
+        let mut rest = s;
+        let root = rest.starts_with('\\');
+        if root {
+            rest = &rest[1..];
+        }
+
+        let mut parents = 0;
+        while let Some(stripped) = rest.strip_prefix('^') {
+            if root {
+                return Err(AmlError::InvalidName);
+            }
+            parents += 1;
+            rest = stripped;
+        }
+
+        let segments = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split('.')
+                .map(str::parse)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        Ok(Self {
+            root,
+            parents,
+            segments,
+        })
+    }
+}
+
+impl Display for AmlName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.root {
+            write!(f, "\\")?;
+        }
+        for _ in 0..self.parents {
+            write!(f, "^")?;
+        }
+        for (i, seg) in self.segments.iter().enumerate() {
+            if i != 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{seg}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for AmlName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{self}")
+    }
+}
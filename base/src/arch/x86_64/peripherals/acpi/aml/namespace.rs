@@ -0,0 +1,159 @@
+use core::ops::Bound;
+
+use crate::errors::AmlError;
+
+use super::{
+    name::{path_to_string, AmlName, AmlPath, NameSeg},
+    value::AmlValue,
+};
+
+/// The ACPI namespace, a tree of named objects
+///
+/// Objects are kept in a map keyed by their absolute path, so the children of an
+/// object directly follow it
+pub struct Namespace {
+    objects: BTreeMap<AmlPath, AmlValue>,
+}
+
+impl Namespace {
+    /// The scopes every namespace has
+    const PREDEFINED_SCOPES: [[u8; 4]; 5] = [*b"_GPE", *b"_PR_", *b"_SB_", *b"_SI_", *b"_TZ_"];
+
+    /// Make a new namespace, with the predefined scopes
+    #[must_use]
+    pub fn new() -> Self {
+        let mut objects = BTreeMap::new();
+        objects.insert(Vec::new(), AmlValue::Scope);
+        for seg in Self::PREDEFINED_SCOPES {
+            objects.insert(Vec::from([NameSeg(seg)]), AmlValue::Scope);
+        }
+        Self { objects }
+    }
+
+    /// Add an object to the namespace
+    ///
+    /// # Errors
+    /// This will return an error if the parent doesn't exist, or if the name is in use
+    pub fn add(&mut self, path: AmlPath, value: AmlValue) -> Result<(), AmlError> {
+        let parent = path
+            .split_last()
+            .map(|(_, parent)| parent)
+            .ok_or(AmlError::ObjectAlreadyExists)?;
+
+        if !self.objects.contains_key(parent) {
+            return Err(AmlError::ObjectNotFound);
+        }
+        if self.objects.contains_key(&path) {
+            return Err(AmlError::ObjectAlreadyExists);
+        }
+
+        self.objects.insert(path, value);
+        Ok(())
+    }
+
+    /// Remove an object and all of its children from the namespace
+    pub fn remove(&mut self, path: &[NameSeg]) {
+        let children = self
+            .objects
+            .range::<[NameSeg], _>((Bound::Included(path), Bound::Unbounded))
+            .map(|(k, _)| k)
+            .take_while(|k| k.starts_with(path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for child in children {
+            self.objects.remove(&child);
+        }
+    }
+
+    /// Check if an object exists
+    #[must_use]
+    pub fn contains(&self, path: &[NameSeg]) -> bool {
+        self.objects.contains_key(path)
+    }
+
+    /// Get the path an alias points to, or the path itself
+    #[must_use]
+    pub fn follow_alias<'a>(&'a self, path: &'a [NameSeg]) -> &'a [NameSeg] {
+        match self.objects.get(path) {
+            Some(AmlValue::Alias(target)) => target,
+            _ => path,
+        }
+    }
+
+    /// Get an object, following aliases
+    #[must_use]
+    pub fn get(&self, path: &[NameSeg]) -> Option<&AmlValue> {
+        self.objects.get(self.follow_alias(path))
+    }
+
+    /// Get an object mutably, following aliases
+    pub fn get_mut(&mut self, path: &[NameSeg]) -> Option<&mut AmlValue> {
+        let path = self.follow_alias(path).to_vec();
+        self.objects.get_mut(&path)
+    }
+
+    /// Find the object a name refers to from the given scope
+    ///
+    /// Single segment names are searched for in each parent scope until one is found
+    ///
+    /// # Errors
+    /// This will return an error if the object doesn't exist
+    pub fn search(&self, name: &AmlName, scope: &[NameSeg]) -> Result<AmlPath, AmlError> {
+        if name.uses_search_rules() {
+            for depth in (0..=scope.len()).rev() {
+                let mut path = scope[..depth].to_vec();
+                path.extend_from_slice(&name.segments);
+                if self.objects.contains_key(&path) {
+                    return Ok(path);
+                }
+            }
+            Err(AmlError::ObjectNotFound)
+        } else {
+            let path = name.resolve(scope)?;
+            if self.objects.contains_key(&path) {
+                Ok(path)
+            } else {
+                Err(AmlError::ObjectNotFound)
+            }
+        }
+    }
+
+    /// Iterate over the direct children of an object
+    pub fn children<'a>(
+        &'a self,
+        path: &'a [NameSeg],
+    ) -> impl Iterator<Item = (&'a AmlPath, &'a AmlValue)> + 'a {
+        self.objects
+            .range::<[NameSeg], _>((Bound::Excluded(path), Bound::Unbounded))
+            .take_while(move |(k, _)| k.starts_with(path))
+            .filter(move |(k, _)| k.len() == path.len() + 1)
+    }
+
+    /// Iterate over every object in the namespace
+    pub fn iter(&self) -> impl Iterator<Item = (&AmlPath, &AmlValue)> {
+        self.objects.iter()
+    }
+}
+
+impl Default for Namespace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for Namespace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (path, value) in &self.objects {
+            writeln!(
+                f,
+                "{:width$}{} = {:?}",
+                "",
+                path_to_string(path),
+                value.object_type(),
+                width = path.len() * 2
+            )?;
+        }
+        Ok(())
+    }
+}
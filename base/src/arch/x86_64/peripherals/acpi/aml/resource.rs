@@ -0,0 +1,248 @@
+#![allow(clippy::cast_possible_truncation)]
+
+use crate::errors::AmlError;
+
+/// The kind of resource an address space descriptor describes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressKind {
+    /// A memory range
+    Memory,
+    /// An I/O port range
+    Io,
+    /// A range of bus numbers
+    BusNumber,
+    /// Some other, vendor defined, kind
+    Other(u8),
+}
+
+impl From<u8> for AddressKind {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Self::Memory,
+            1 => Self::Io,
+            2 => Self::BusNumber,
+            v => Self::Other(v),
+        }
+    }
+}
+
+/// A resource described by a resource template, as returned by `_CRS` and `_PRS`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Resource {
+    /// Legacy IRQs
+    Irq {
+        /// The IRQs that may be used
+        irqs: Vec<u8>,
+        /// If the IRQ is edge triggered
+        edge_triggered: bool,
+        /// If the IRQ is active low
+        active_low: bool,
+        /// If the IRQ may be shared
+        shared: bool,
+    },
+    /// Legacy DMA channels
+    Dma {
+        /// A mask of the DMA channels that may be used
+        channels: u8,
+        /// The DMA flags
+        flags: u8,
+    },
+    /// A range of I/O ports
+    Io {
+        /// If all 16 address bits are decoded, rather than 10
+        decodes_16_bit: bool,
+        /// The lowest base address
+        minimum: u16,
+        /// The highest base address
+        maximum: u16,
+        /// The alignment of the base address
+        alignment: u8,
+        /// The amount of ports
+        length: u8,
+    },
+    /// A fixed range of I/O ports
+    FixedIo {
+        /// The base address
+        base: u16,
+        /// The amount of ports
+        length: u8,
+    },
+    /// A 32-bit memory range
+    Memory32 {
+        /// If the range is writable
+        writable: bool,
+        /// The lowest base address
+        minimum: u32,
+        /// The highest base address
+        maximum: u32,
+        /// The alignment of the base address
+        alignment: u32,
+        /// The length of the range
+        length: u32,
+    },
+    /// A fixed 32-bit memory range
+    FixedMemory32 {
+        /// If the range is writable
+        writable: bool,
+        /// The base address
+        base: u32,
+        /// The length of the range
+        length: u32,
+    },
+    /// A word, dword, qword, or extended address space
+    Address {
+        /// The kind of address space
+        kind: AddressKind,
+        /// The general flags
+        flags: u8,
+        /// The flags specific to the kind of address space
+        type_flags: u8,
+        /// The address granularity
+        granularity: u64,
+        /// The lowest address
+        minimum: u64,
+        /// The highest address
+        maximum: u64,
+        /// The offset to add to translate the address to the other side of a bridge
+        translation: u64,
+        /// The length of the range
+        length: u64,
+    },
+    /// Extended interrupts, which can be global system interrupts
+    ExtendedIrq {
+        /// The interrupts that may be used
+        interrupts: Vec<u32>,
+        /// If the interrupt is edge triggered
+        edge_triggered: bool,
+        /// If the interrupt is active low
+        active_low: bool,
+        /// If the interrupt may be shared
+        shared: bool,
+    },
+    /// A descriptor that isn't decoded
+    Other {
+        /// The descriptor's tag
+        tag: u8,
+        /// If the descriptor is a large descriptor
+        large: bool,
+    },
+}
+
+/// Read a little endian integer from a descriptor
+fn read(bytes: &[u8], offset: usize, size: usize) -> Result<u64, AmlError> {
+    Ok(bytes
+        .get(offset..offset + size)
+        .ok_or(AmlError::IndexOutOfBounds)?
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (i, &b)| acc | (u64::from(b) << (i * 8))))
+}
+
+/// Parse an address space descriptor, with each address field being `size` bytes and
+/// the first field starting at `fields`
+fn parse_address(data: &[u8], size: usize, fields: usize) -> Result<Resource, AmlError> {
+    Ok(Resource::Address {
+        kind: AddressKind::from(read(data, 0, 1)? as u8),
+        flags: read(data, 1, 1)? as u8,
+        type_flags: read(data, 2, 1)? as u8,
+        granularity: read(data, fields, size)?,
+        minimum: read(data, fields + size, size)?,
+        maximum: read(data, fields + size * 2, size)?,
+        translation: read(data, fields + size * 3, size)?,
+        length: read(data, fields + size * 4, size)?,
+    })
+}
+
+/// Parse a resource template into its descriptors
+///
+/// # Errors
+/// This will return an error if a descriptor is truncated
+pub fn parse(bytes: &[u8]) -> Result<Vec<Resource>, AmlError> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let lead = bytes[pos];
+        if lead & 0x80 == 0 {
+            let tag = (lead >> 3) & 0xF;
+            let length = usize::from(lead & 0b111);
+            let data = bytes
+                .get(pos + 1..pos + 1 + length)
+                .ok_or(AmlError::IndexOutOfBounds)?;
+            pos += 1 + length;
+
+            out.push(match tag {
+                0x04 => {
+                    let mask = read(data, 0, 2)? as u16;
+                    let info = data.get(2).copied().unwrap_or(1);
+                    Resource::Irq {
+                        irqs: (0..16).filter(|i| mask & (1 << i) != 0).collect(),
+                        edge_triggered: info & 1 != 0,
+                        active_low: info & (1 << 3) != 0,
+                        shared: info & (1 << 4) != 0,
+                    }
+                }
+                0x05 => Resource::Dma {
+                    channels: read(data, 0, 1)? as u8,
+                    flags: read(data, 1, 1)? as u8,
+                },
+                0x08 => Resource::Io {
+                    decodes_16_bit: read(data, 0, 1)? & 1 != 0,
+                    minimum: read(data, 1, 2)? as u16,
+                    maximum: read(data, 3, 2)? as u16,
+                    alignment: read(data, 5, 1)? as u8,
+                    length: read(data, 6, 1)? as u8,
+                },
+                0x09 => Resource::FixedIo {
+                    base: (read(data, 0, 2)? & 0x3FF) as u16,
+                    length: read(data, 2, 1)? as u8,
+                },
+                // End tag
+                0x0F => break,
+                tag => Resource::Other { tag, large: false },
+            });
+        } else {
+            let tag = lead & 0x7F;
+            let length = read(bytes, pos + 1, 2)? as usize;
+            let data = bytes
+                .get(pos + 3..pos + 3 + length)
+                .ok_or(AmlError::IndexOutOfBounds)?;
+            pos += 3 + length;
+
+            out.push(match tag {
+                0x05 => Resource::Memory32 {
+                    writable: read(data, 0, 1)? & 1 != 0,
+                    minimum: read(data, 1, 4)? as u32,
+                    maximum: read(data, 5, 4)? as u32,
+                    alignment: read(data, 9, 4)? as u32,
+                    length: read(data, 13, 4)? as u32,
+                },
+                0x06 => Resource::FixedMemory32 {
+                    writable: read(data, 0, 1)? & 1 != 0,
+                    base: read(data, 1, 4)? as u32,
+                    length: read(data, 5, 4)? as u32,
+                },
+                0x07 => parse_address(data, 4, 3)?,
+                0x08 => parse_address(data, 2, 3)?,
+                0x0A => parse_address(data, 8, 3)?,
+                // Extended address descriptors have a revision and reserved byte first
+                0x0B => parse_address(data, 8, 5)?,
+                0x09 => {
+                    let flags = read(data, 0, 1)?;
+                    let count = read(data, 1, 1)? as usize;
+                    Resource::ExtendedIrq {
+                        interrupts: (0..count)
+                            .map(|i| read(data, 2 + i * 4, 4).map(|v| v as u32))
+                            .collect::<Result<Vec<_>, _>>()?,
+                        edge_triggered: flags & (1 << 1) != 0,
+                        active_low: flags & (1 << 2) != 0,
+                        shared: flags & (1 << 3) != 0,
+                    }
+                }
+                tag => Resource::Other { tag, large: true },
+            });
+        }
+    }
+
+    Ok(out)
+}
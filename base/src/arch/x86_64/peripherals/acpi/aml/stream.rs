@@ -0,0 +1,184 @@
+use crate::errors::AmlError;
+
+use super::name::{AmlName, NameSeg};
+
+/// A cursor over a range of AML bytes
+pub struct Stream<'a> {
+    data: &'a [u8],
+    /// The current position in the data
+    pub pos: usize,
+    /// The end of the range being read
+    pub end: usize,
+}
+
+impl<'a> Stream<'a> {
+    /// The root character
+    const ROOT_CHAR: u8 = b'\\';
+
+    /// The parent prefix character
+    const PARENT_PREFIX: u8 = b'^';
+
+    /// The prefix for a name with two segments
+    const DUAL_NAME_PREFIX: u8 = 0x2E;
+
+    /// The prefix for a name with more than two segments
+    const MULTI_NAME_PREFIX: u8 = 0x2F;
+
+    /// Make a new stream over part of some data
+    pub fn new(data: &'a [u8], start: usize, end: usize) -> Self {
+        Self {
+            data,
+            pos: start,
+            end: end.min(data.len()),
+        }
+    }
+
+    /// Check if the whole range has been read
+    pub const fn is_done(&self) -> bool {
+        self.pos >= self.end
+    }
+
+    /// Get a byte relative to the current position without consuming it
+    pub const fn peek_at(&self, offset: usize) -> Result<u8, AmlError> {
+        let index = self.pos + offset;
+        if index >= self.end {
+            return Err(AmlError::UnexpectedEndOfStream);
+        }
+        Ok(self.data[index])
+    }
+
+    /// Get the next byte without consuming it
+    pub const fn peek(&self) -> Result<u8, AmlError> {
+        self.peek_at(0)
+    }
+
+    /// Consume the next byte
+    pub fn next(&mut self) -> Result<u8, AmlError> {
+        let v = self.peek()?;
+        self.pos += 1;
+        Ok(v)
+    }
+
+    /// Consume a slice of bytes
+    pub fn take(&mut self, count: usize) -> Result<&'a [u8], AmlError> {
+        let end = self
+            .pos
+            .checked_add(count)
+            .ok_or(AmlError::UnexpectedEndOfStream)?;
+        if end > self.end {
+            return Err(AmlError::UnexpectedEndOfStream);
+        }
+        let data = self.data;
+        let v = &data[self.pos..end];
+        self.pos = end;
+        Ok(v)
+    }
+
+    /// Consume a little endian integer of the given size in bytes
+    pub fn integer(&mut self, size: usize) -> Result<u64, AmlError> {
+        Ok(self
+            .take(size)?
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, &b)| acc | (u64::from(b) << (i * 8))))
+    }
+
+    /// Consume a package length and return the raw value
+    pub fn pkg_length_raw(&mut self) -> Result<usize, AmlError> {
+        let lead = self.next()?;
+        let follow = usize::from(lead >> 6);
+        if follow == 0 {
+            return Ok(usize::from(lead & 0x3F));
+        }
+
+        let mut length = usize::from(lead & 0x0F);
+        for i in 0..follow {
+            length |= usize::from(self.next()?) << (4 + i * 8);
+        }
+        Ok(length)
+    }
+
+    /// Consume a package length and return the position the package ends at
+    pub fn pkg_length(&mut self) -> Result<usize, AmlError> {
+        let start = self.pos;
+        let end = start + self.pkg_length_raw()?;
+        if end > self.end {
+            return Err(AmlError::UnexpectedEndOfStream);
+        }
+        Ok(end)
+    }
+
+    /// Consume a name segment
+    pub fn name_seg(&mut self) -> Result<NameSeg, AmlError> {
+        let bytes = self.take(4)?;
+        if !NameSeg::is_lead_char(bytes[0]) || !bytes.iter().all(|&c| NameSeg::is_name_char(c)) {
+            return Err(AmlError::InvalidName);
+        }
+        Ok(NameSeg([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Check if the next byte starts a name string
+    pub fn at_name_string(&self) -> bool {
+        self.peek().map_or(false, |c| {
+            NameSeg::is_lead_char(c)
+                || matches!(
+                    c,
+                    Self::ROOT_CHAR
+                        | Self::PARENT_PREFIX
+                        | Self::DUAL_NAME_PREFIX
+                        | Self::MULTI_NAME_PREFIX
+                )
+        })
+    }
+
+    /// Consume a name string, which may be a `NullName`
+    pub fn name_string(&mut self) -> Result<AmlName, AmlError> {
+        let mut name = AmlName {
+            root: false,
+            parents: 0,
+            segments: Vec::new(),
+        };
+
+        if self.peek()? == Self::ROOT_CHAR {
+            self.next()?;
+            name.root = true;
+        } else {
+            while self.peek()? == Self::PARENT_PREFIX {
+                self.next()?;
+                name.parents += 1;
+            }
+        }
+
+        let count = match self.peek()? {
+            0x00 => {
+                self.next()?;
+                0
+            }
+            Self::DUAL_NAME_PREFIX => {
+                self.next()?;
+                2
+            }
+            Self::MULTI_NAME_PREFIX => {
+                self.next()?;
+                usize::from(self.next()?)
+            }
+            _ => 1,
+        };
+
+        for _ in 0..count {
+            name.segments.push(self.name_seg()?);
+        }
+        Ok(name)
+    }
+
+    /// Consume a null terminated ASCII string
+    pub fn string(&mut self) -> Result<String, AmlError> {
+        let mut out = String::new();
+        loop {
+            match self.next()? {
+                0 => return Ok(out),
+                c => out.push(char::from(c)),
+            }
+        }
+    }
+}
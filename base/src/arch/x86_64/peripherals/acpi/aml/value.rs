@@ -0,0 +1,492 @@
+use core::cmp::Ordering;
+
+extern crate alloc;
+use alloc::format;
+
+use crate::errors::AmlError;
+
+use super::{name::AmlPath, AmlContext};
+
+/// The type of an object, as returned by `ObjectType`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ObjectType {
+    /// An object without a value
+    Uninitialized = 0,
+    /// An integer
+    Integer = 1,
+    /// A string
+    String = 2,
+    /// A buffer
+    Buffer = 3,
+    /// A package
+    Package = 4,
+    /// A field unit
+    FieldUnit = 5,
+    /// A device
+    Device = 6,
+    /// An event
+    Event = 7,
+    /// A control method
+    Method = 8,
+    /// A mutex
+    Mutex = 9,
+    /// An operation region
+    OpRegion = 10,
+    /// A power resource
+    PowerResource = 11,
+    /// A processor
+    Processor = 12,
+    /// A thermal zone
+    ThermalZone = 13,
+    /// A buffer field
+    BufferField = 14,
+    /// The debug object
+    Debug = 16,
+}
+
+/// The address space an operation region is in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionSpace {
+    /// Physical memory
+    SystemMemory,
+    /// I/O ports
+    SystemIo,
+    /// PCI configuration space
+    PciConfig,
+    /// The embedded controller
+    EmbeddedControl,
+    /// The SMBus
+    SmBus,
+    /// CMOS
+    Cmos,
+    /// PCI BAR targets
+    PciBarTarget,
+    /// Some other space
+    Other(u8),
+}
+
+impl From<u8> for RegionSpace {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Self::SystemMemory,
+            1 => Self::SystemIo,
+            2 => Self::PciConfig,
+            3 => Self::EmbeddedControl,
+            4 => Self::SmBus,
+            5 => Self::Cmos,
+            6 => Self::PciBarTarget,
+            v => Self::Other(v),
+        }
+    }
+}
+
+impl From<RegionSpace> for u8 {
+    fn from(v: RegionSpace) -> Self {
+        match v {
+            RegionSpace::SystemMemory => 0,
+            RegionSpace::SystemIo => 1,
+            RegionSpace::PciConfig => 2,
+            RegionSpace::EmbeddedControl => 3,
+            RegionSpace::SmBus => 4,
+            RegionSpace::Cmos => 5,
+            RegionSpace::PciBarTarget => 6,
+            RegionSpace::Other(v) => v,
+        }
+    }
+}
+
+/// An operation region
+#[derive(Clone, Debug)]
+pub struct OpRegion {
+    /// The address space of the region
+    pub space: RegionSpace,
+    /// The offset of the region in its address space
+    pub offset: u64,
+    /// The length of the region in bytes
+    pub length: u64,
+    /// The scope the region was declared in, used to find `_ADR`, `_BBN`, and `_SEG`
+    /// for PCI configuration regions
+    pub parent: AmlPath,
+}
+
+/// How a field is connected to its backing storage
+#[derive(Clone, Debug)]
+pub enum FieldKind {
+    /// A field directly in an operation region
+    Normal {
+        /// The region the field is in
+        region: AmlPath,
+    },
+    /// A field accessed by writing an index field, then accessing a data field
+    Index {
+        /// The index field
+        index: AmlPath,
+        /// The data field
+        data: AmlPath,
+    },
+    /// A field in an operation region after a bank has been selected
+    Bank {
+        /// The region the field is in
+        region: AmlPath,
+        /// The field that selects the bank
+        bank: AmlPath,
+        /// The value to write to the bank field
+        value: u64,
+    },
+}
+
+/// A field unit, declared with `Field`, `IndexField`, or `BankField`
+#[derive(Clone, Debug)]
+pub struct FieldUnit {
+    /// How the field is accessed
+    pub kind: FieldKind,
+    /// The offset of the field in bits
+    pub bit_offset: usize,
+    /// The length of the field in bits
+    pub bit_length: usize,
+    /// The field flags, holding the access type and update rule
+    pub flags: u8,
+}
+
+impl FieldUnit {
+    /// Get the width of each access in bytes
+    #[must_use]
+    pub const fn access_width(&self) -> usize {
+        match self.flags & 0xF {
+            2 => 2,
+            3 => 4,
+            4 => 8,
+            // AnyAcc, ByteAcc, and BufferAcc
+            _ => 1,
+        }
+    }
+
+    /// Get the update rule for bits not covered by the field
+    #[must_use]
+    pub const fn update_rule(&self) -> u8 {
+        (self.flags >> 5) & 0b11
+    }
+}
+
+/// A native method implemented by the kernel, such as `_OSI`
+pub type NativeMethod = fn(&mut AmlContext, &[AmlValue]) -> Result<AmlValue, AmlError>;
+
+/// The body of a control method
+#[derive(Clone)]
+pub enum MethodCode {
+    /// AML code, as a range in the table the method was declared in
+    Aml {
+        /// The table the method was declared in
+        table: Arc<[u8]>,
+        /// The start of the method body
+        start: usize,
+        /// The end of the method body
+        end: usize,
+    },
+    /// A method implemented by the kernel
+    Native(NativeMethod),
+}
+
+impl core::fmt::Debug for MethodCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Aml { start, end, .. } => write!(f, "Aml({start:#X}..{end:#X})"),
+            Self::Native(_) => write!(f, "Native"),
+        }
+    }
+}
+
+/// A control method
+#[derive(Clone, Debug)]
+pub struct Method {
+    /// The method flags, holding the argument count and serialization
+    pub flags: u8,
+    /// The body of the method
+    pub code: MethodCode,
+}
+
+impl Method {
+    /// Get the amount of arguments the method takes
+    #[must_use]
+    pub const fn arg_count(&self) -> usize {
+        (self.flags & 0b111) as usize
+    }
+}
+
+/// Something that holds a value that can be referenced
+#[derive(Clone, Debug)]
+pub enum Container {
+    /// A named object
+    Named(AmlPath),
+    /// A local variable in a method frame
+    Local {
+        /// The index of the frame
+        frame: usize,
+        /// The index of the local
+        index: usize,
+    },
+    /// An argument in a method frame
+    Arg {
+        /// The index of the frame
+        frame: usize,
+        /// The index of the argument
+        index: usize,
+    },
+    /// A temporary value, writes to it are discarded
+    Value(Box<AmlValue>),
+}
+
+/// A reference to an object, as created by `RefOf` and `Index`
+#[derive(Clone, Debug)]
+pub enum Reference {
+    /// A reference to a whole object
+    Object(Container),
+    /// A reference to an element of a package, buffer, or string
+    Element {
+        /// The object holding the element
+        container: Container,
+        /// The index of the element
+        index: usize,
+    },
+}
+
+/// A value in the AML namespace or on the interpreter's stack
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug)]
+pub enum AmlValue {
+    /// A value that hasn't been initialized
+    Uninitialized,
+    /// An integer
+    Integer(u64),
+    /// A string
+    String(String),
+    /// A buffer
+    Buffer(Vec<u8>),
+    /// A package
+    Package(Vec<AmlValue>),
+    /// A control method
+    Method(Method),
+    /// A scope without an object, such as `\_SB_`
+    Scope,
+    /// A device
+    Device,
+    /// A processor
+    Processor {
+        /// The processor ID
+        id: u8,
+        /// The address of the processor block
+        block_address: u32,
+        /// The length of the processor block
+        block_length: u8,
+    },
+    /// A power resource
+    PowerResource {
+        /// The deepest system sleep level the resource is needed in
+        system_level: u8,
+        /// The order the resource is enabled in
+        resource_order: u16,
+    },
+    /// A thermal zone
+    ThermalZone,
+    /// An operation region
+    OpRegion(OpRegion),
+    /// A field unit
+    FieldUnit(FieldUnit),
+    /// A field in a buffer
+    BufferField {
+        /// The buffer the field is in
+        source: Container,
+        /// The offset of the field in bits
+        bit_offset: usize,
+        /// The length of the field in bits
+        bit_length: usize,
+    },
+    /// A mutex
+    Mutex {
+        /// The sync level of the mutex
+        sync_level: u8,
+    },
+    /// An event
+    Event,
+    /// A reference to another object
+    Reference(Reference),
+    /// An alias of another named object
+    Alias(AmlPath),
+}
+
+impl AmlValue {
+    /// The value for logical true
+    pub const TRUE: Self = Self::Integer(u64::MAX);
+
+    /// The value for logical false
+    pub const FALSE: Self = Self::Integer(0);
+
+    /// Make a logical value
+    #[must_use]
+    pub const fn from_bool(v: bool) -> Self {
+        if v {
+            Self::TRUE
+        } else {
+            Self::FALSE
+        }
+    }
+
+    /// Get the type of the value
+    #[must_use]
+    pub const fn object_type(&self) -> ObjectType {
+        match self {
+            Self::Uninitialized | Self::Reference(_) | Self::Alias(_) => ObjectType::Uninitialized,
+            Self::Integer(_) => ObjectType::Integer,
+            Self::String(_) => ObjectType::String,
+            Self::Buffer(_) => ObjectType::Buffer,
+            Self::Package(_) => ObjectType::Package,
+            Self::Method(_) => ObjectType::Method,
+            Self::Scope | Self::Device => ObjectType::Device,
+            Self::Processor { .. } => ObjectType::Processor,
+            Self::PowerResource { .. } => ObjectType::PowerResource,
+            Self::ThermalZone => ObjectType::ThermalZone,
+            Self::OpRegion(_) => ObjectType::OpRegion,
+            Self::FieldUnit(_) => ObjectType::FieldUnit,
+            Self::BufferField { .. } => ObjectType::BufferField,
+            Self::Mutex { .. } => ObjectType::Mutex,
+            Self::Event => ObjectType::Event,
+        }
+    }
+
+    /// Check if the value opens a scope that other objects can be declared in
+    #[must_use]
+    pub const fn is_scope(&self) -> bool {
+        matches!(
+            self,
+            Self::Scope
+                | Self::Device
+                | Self::Processor { .. }
+                | Self::PowerResource { .. }
+                | Self::ThermalZone
+                | Self::Method(_)
+        )
+    }
+
+    /// Convert the value to an integer
+    ///
+    /// Strings are interpreted as hexadecimal, and buffers as little endian
+    ///
+    /// # Errors
+    /// This will return an error if the value can't be converted
+    pub fn as_integer(&self) -> Result<u64, AmlError> {
+        match self {
+            Self::Integer(v) => Ok(*v),
+            Self::Buffer(bytes) => Ok(bytes
+                .iter()
+                .take(8)
+                .enumerate()
+                .fold(0, |acc, (i, &b)| acc | (u64::from(b) << (i * 8)))),
+            Self::String(s) => {
+                let s = s.trim_start();
+                let s = s
+                    .strip_prefix("0x")
+                    .or_else(|| s.strip_prefix("0X"))
+                    .unwrap_or(s);
+                Ok(s.chars()
+                    .map_while(|c| c.to_digit(16))
+                    .take(16)
+                    .fold(0, |acc, d| (acc << 4) | u64::from(d)))
+            }
+            _ => Err(AmlError::IncompatibleType),
+        }
+    }
+
+    /// Convert the value to a buffer
+    ///
+    /// # Errors
+    /// This will return an error if the value can't be converted
+    pub fn as_buffer(&self) -> Result<Vec<u8>, AmlError> {
+        match self {
+            Self::Buffer(bytes) => Ok(bytes.clone()),
+            Self::Integer(v) => Ok(v.to_le_bytes().to_vec()),
+            Self::String(s) => {
+                let mut bytes = s.as_bytes().to_vec();
+                bytes.push(0);
+                Ok(bytes)
+            }
+            _ => Err(AmlError::IncompatibleType),
+        }
+    }
+
+    /// Convert the value to a string
+    ///
+    /// Integers are formatted as hexadecimal, and buffers as space separated hex bytes
+    ///
+    /// # Errors
+    /// This will return an error if the value can't be converted
+    pub fn as_string(&self) -> Result<String, AmlError> {
+        match self {
+            Self::String(s) => Ok(s.clone()),
+            Self::Integer(v) => Ok(format!("{v:016X}")),
+            Self::Buffer(bytes) => Ok(bytes
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect::<Vec<_>>()
+                .join(" ")),
+            _ => Err(AmlError::IncompatibleType),
+        }
+    }
+
+    /// Convert the value to a logical value
+    ///
+    /// # Errors
+    /// This will return an error if the value can't be converted to an integer
+    pub fn as_bool(&self) -> Result<bool, AmlError> {
+        Ok(self.as_integer()? != 0)
+    }
+
+    /// Get the length of a buffer, string, or package
+    ///
+    /// # Errors
+    /// This will return an error if the value doesn't have a length
+    // Only some values have a length, so there's no obvious meaning for `is_empty`
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> Result<usize, AmlError> {
+        match self {
+            Self::Buffer(bytes) => Ok(bytes.len()),
+            Self::String(s) => Ok(s.len()),
+            Self::Package(elements) => Ok(elements.len()),
+            _ => Err(AmlError::IncompatibleType),
+        }
+    }
+
+    /// Convert a value to the type of this one, as done when storing into a named object
+    ///
+    /// # Errors
+    /// This will return an error if the value can't be converted
+    pub fn convert_to_type_of(&self, value: Self) -> Result<Self, AmlError> {
+        match self {
+            Self::Integer(_) => Ok(Self::Integer(value.as_integer()?)),
+            Self::String(_) => Ok(Self::String(value.as_string()?)),
+            Self::Buffer(old) => {
+                let mut bytes = value.as_buffer()?;
+                // Buffers keep their size when stored into
+                if bytes.len() < old.len() {
+                    bytes.resize(old.len(), 0);
+                }
+                Ok(Self::Buffer(bytes))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Compare two values for `LEqual`, `LGreater`, and `LLess`, converting the second
+    /// to the type of the first
+    ///
+    /// # Errors
+    /// This will return an error if the values can't be compared
+    pub fn compare(&self, other: &Self) -> Result<Ordering, AmlError> {
+        match self {
+            Self::Integer(a) => Ok(a.cmp(&other.as_integer()?)),
+            Self::String(a) => Ok(a.as_str().cmp(other.as_string()?.as_str())),
+            Self::Buffer(a) => Ok(a.as_slice().cmp(other.as_buffer()?.as_slice())),
+            _ => Err(AmlError::IncompatibleType),
+        }
+    }
+}
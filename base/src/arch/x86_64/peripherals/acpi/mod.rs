@@ -0,0 +1,936 @@
+use core::{
+    fmt::Debug,
+    mem::size_of,
+    ptr::{read_unaligned, read_volatile, write_volatile},
+};
+
+use log::{debug, info, trace, warn};
+
+use crate::{
+    errors::{AcpiError, AmlError},
+    macros::bitflags::bitflags,
+    sync::Mutex,
+};
+
+use super::uart::{inb, inl, inw, outb, outl, outw};
+
+/// The ACPI Machine Language interpreter
+pub mod aml;
+use aml::{AmlContext, AmlValue, Handler, PciAddress};
+
+/// Convert a physical address into a pointer through the Higher Half Direct Map
+#[must_use]
+// Addresses are 64 bits on x86_64
+#[allow(clippy::cast_possible_truncation)]
+pub fn physical_to_virtual(addr: u64) -> *mut u8 {
+    (crate::HHDM_RANGE.start + addr as usize) as *mut u8
+}
+
+/// Sum all bytes in a region, as ACPI checksums require the sum to be zero
+fn checksum(ptr: *const u8, len: usize) -> u8 {
+    unsafe { core::slice::from_raw_parts(ptr, len) }
+        .iter()
+        .fold(0u8, |acc, b| acc.wrapping_add(*b))
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// The Root System Description Pointer, revision 0
+pub struct Rsdp {
+    /// Must be `RSD PTR `
+    pub signature: [u8; 8],
+    /// Checksum of the first 20 bytes
+    pub checksum: u8,
+    /// OEM identifier
+    pub oem_id: [u8; 6],
+    /// 0 for ACPI 1.0, 2 for ACPI 2.0+
+    pub revision: u8,
+    /// Physical address of the RSDT
+    pub rsdt_address: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// The Root System Description Pointer, revision 2
+pub struct RsdpExtended {
+    /// The revision 0 part of the structure
+    pub base: Rsdp,
+    /// Length of the whole structure
+    pub length: u32,
+    /// Physical address of the XSDT
+    pub xsdt_address: u64,
+    /// Checksum of the whole structure
+    pub extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+impl Rsdp {
+    /// The expected signature
+    pub const SIGNATURE: [u8; 8] = *b"RSD PTR ";
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// The header every System Description Table begins with
+pub struct SdtHeader {
+    /// The table's signature, such as `FACP` or `APIC`
+    pub signature: [u8; 4],
+    /// The length of the table, including the header
+    pub length: u32,
+    /// The table's revision
+    pub revision: u8,
+    /// Checksum of the whole table
+    pub checksum: u8,
+    /// OEM identifier
+    pub oem_id: [u8; 6],
+    /// OEM table identifier
+    pub oem_table_id: [u8; 8],
+    /// OEM revision
+    pub oem_revision: u32,
+    /// The ID of the utility that created the table
+    pub creator_id: u32,
+    /// The revision of the utility that created the table
+    pub creator_revision: u32,
+}
+
+#[derive(Clone, Copy)]
+/// A System Description Table whose checksum has been validated
+pub struct Sdt {
+    ptr: *const SdtHeader,
+}
+
+impl Sdt {
+    /// Validate the table at the given physical address
+    ///
+    /// # Errors
+    /// This will return an error if the table's checksum is invalid
+    pub fn from_physical(addr: u64) -> Result<Self, AcpiError> {
+        let ptr = physical_to_virtual(addr) as *const SdtHeader;
+        let header = unsafe { read_unaligned(ptr) };
+        if checksum(ptr.cast(), header.length as usize) != 0 {
+            return Err(AcpiError::InvalidChecksum);
+        }
+        Ok(Self { ptr })
+    }
+
+    /// Get a copy of the table's header
+    #[must_use]
+    pub fn header(&self) -> SdtHeader {
+        unsafe { read_unaligned(self.ptr) }
+    }
+
+    /// Get the table's signature
+    #[must_use]
+    pub fn signature(&self) -> [u8; 4] {
+        self.header().signature
+    }
+
+    /// Get the whole table, including the header
+    #[must_use]
+    pub fn data(&self) -> &'static [u8] {
+        unsafe { core::slice::from_raw_parts(self.ptr.cast(), self.header().length as usize) }
+    }
+
+    /// Get the table's contents, excluding the header
+    #[must_use]
+    pub fn body(&self) -> &'static [u8] {
+        &self.data()[size_of::<SdtHeader>()..]
+    }
+
+    /// Read the table as the type `T`. Fields past the end of the table are zeroed,
+    /// as older firmware provides shorter revisions of tables such as the FADT
+    #[must_use]
+    pub fn read_as<T: Copy>(&self) -> T {
+        let mut out = core::mem::MaybeUninit::<T>::zeroed();
+        let len = core::cmp::min(size_of::<T>(), self.data().len());
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.ptr.cast::<u8>(), out.as_mut_ptr().cast(), len);
+            out.assume_init()
+        }
+    }
+}
+
+impl Debug for Sdt {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let header = self.header();
+        f.debug_struct("Sdt")
+            .field(
+                "Signature",
+                &core::str::from_utf8(&header.signature).unwrap_or("????"),
+            )
+            .field("Length", &{ header.length })
+            .field("Revision", &header.revision)
+            .field("Address", &self.ptr)
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+/// The address space a [`GenericAddress`] lives in
+pub enum AddressSpace {
+    /// System memory
+    SystemMemory = 0,
+    /// System I/O ports
+    SystemIo = 1,
+    /// PCI configuration space
+    PciConfig = 2,
+    /// Something this kernel doesn't understand
+    Unsupported = 0xFF,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Default)]
+/// The ACPI Generic Address Structure
+pub struct GenericAddress {
+    /// The address space, see [`AddressSpace`]
+    pub address_space: u8,
+    /// The width of the register in bits
+    pub bit_width: u8,
+    /// The bit offset of the register
+    pub bit_offset: u8,
+    /// The access size, 1 = byte, 2 = word, 3 = dword, 4 = qword
+    pub access_size: u8,
+    /// The address in the given address space
+    pub address: u64,
+}
+
+impl GenericAddress {
+    /// Create a [`GenericAddress`] for a legacy I/O port block
+    #[must_use]
+    pub const fn from_io_port(port: u32, bit_width: u8) -> Self {
+        Self {
+            address_space: AddressSpace::SystemIo as u8,
+            bit_width,
+            bit_offset: 0,
+            access_size: 0,
+            address: port as u64,
+        }
+    }
+
+    /// Whether or not the structure points anywhere
+    #[must_use]
+    pub const fn is_present(&self) -> bool {
+        self.address != 0
+    }
+
+    /// Get the address space of the structure
+    #[must_use]
+    pub const fn space(&self) -> AddressSpace {
+        match self.address_space {
+            0 => AddressSpace::SystemMemory,
+            1 => AddressSpace::SystemIo,
+            2 => AddressSpace::PciConfig,
+            _ => AddressSpace::Unsupported,
+        }
+    }
+
+    /// Get the width of a single access in bytes
+    const fn access_width(&self) -> u8 {
+        match self.access_size {
+            1 => 1,
+            2 => 2,
+            3 => 4,
+            4 => 8,
+            _ => match self.bit_width {
+                0..=8 => 1,
+                9..=16 => 2,
+                17..=32 => 4,
+                _ => 8,
+            },
+        }
+    }
+
+    /// Get the `0xCF8` configuration address for a PCI register.
+    /// Only segment 0, bus 0 is addressable, as the spec mandates for FADT registers
+    const fn pci_config_address(&self) -> (u32, u16) {
+        let device = ((self.address >> 32) & 0x1F) as u32;
+        let function = ((self.address >> 16) & 0x7) as u32;
+        let offset = (self.address & 0xFF) as u32;
+        (
+            0x8000_0000 | (device << 11) | (function << 8) | (offset & 0xFC),
+            0xCFC + (offset & 0x3) as u16,
+        )
+    }
+
+    /// Read from the register
+    ///
+    /// # Errors
+    /// This will return an error if the address space isn't supported
+    // I/O ports are 16 bits, and memory registers are aligned to their access width
+    #[allow(clippy::cast_possible_truncation, clippy::cast_ptr_alignment)]
+    pub fn read(&self) -> Result<u64, AcpiError> {
+        let width = self.access_width();
+        Ok(match self.space() {
+            AddressSpace::SystemIo => {
+                let port = self.address as u16;
+                match width {
+                    1 => u64::from(inb(port)),
+                    2 => u64::from(inw(port)),
+                    _ => u64::from(inl(port)),
+                }
+            }
+            AddressSpace::SystemMemory => {
+                let ptr = physical_to_virtual(self.address);
+                unsafe {
+                    match width {
+                        1 => u64::from(read_volatile(ptr)),
+                        2 => u64::from(read_volatile(ptr.cast::<u16>())),
+                        4 => u64::from(read_volatile(ptr.cast::<u32>())),
+                        _ => read_volatile(ptr.cast::<u64>()),
+                    }
+                }
+            }
+            AddressSpace::PciConfig => {
+                let (address, port) = self.pci_config_address();
+                outl(address, 0xCF8);
+                u64::from(inb(port))
+            }
+            AddressSpace::Unsupported => {
+                return Err(AcpiError::Generic(
+                    crate::errors::GenericError::NotSupported,
+                ))
+            }
+        })
+    }
+
+    /// Write to the register
+    ///
+    /// # Errors
+    /// This will return an error if the address space isn't supported
+    // I/O ports are 16 bits, the value is cut to the access width,
+    // and memory registers are aligned to their access width
+    #[allow(clippy::cast_possible_truncation, clippy::cast_ptr_alignment)]
+    pub fn write(&self, value: u64) -> Result<(), AcpiError> {
+        let width = self.access_width();
+        match self.space() {
+            AddressSpace::SystemIo => {
+                let port = self.address as u16;
+                match width {
+                    1 => outb(value as u8, port),
+                    2 => outw(value as u16, port),
+                    _ => outl(value as u32, port),
+                }
+            }
+            AddressSpace::SystemMemory => {
+                let ptr = physical_to_virtual(self.address);
+                unsafe {
+                    match width {
+                        1 => write_volatile(ptr, value as u8),
+                        2 => write_volatile(ptr.cast::<u16>(), value as u16),
+                        4 => write_volatile(ptr.cast::<u32>(), value as u32),
+                        _ => write_volatile(ptr.cast::<u64>(), value),
+                    }
+                }
+            }
+            AddressSpace::PciConfig => {
+                let (address, port) = self.pci_config_address();
+                outl(address, 0xCF8);
+                outb(value as u8, port);
+            }
+            AddressSpace::Unsupported => {
+                return Err(AcpiError::Generic(
+                    crate::errors::GenericError::NotSupported,
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+bitflags! {
+    /// The features the FADT says the platform has
+    pub struct FadtFlags: u32 {
+        /// The `WBINVD` instruction works properly
+        const WBINVD = 1 << 0;
+        /// The power button is a control method device
+        const POWER_BUTTON = 1 << 4;
+        /// The sleep button is a control method device
+        const SLEEP_BUTTON = 1 << 5;
+        /// The RTC can wake the system from S4
+        const RTC_S4 = 1 << 7;
+        /// The `RESET_REG` is supported
+        const RESET_REG_SUPPORTED = 1 << 10;
+        /// The platform is hardware reduced, meaning there are no fixed hardware registers
+        const HW_REDUCED_ACPI = 1 << 20;
+    }
+}
+
+bitflags! {
+    /// What the FADT says is present on an IA-PC platform
+    pub struct BootArchitectureFlags: u16 {
+        /// Legacy devices are present on the LPC or ISA bus
+        const LEGACY_DEVICES = 1 << 0;
+        /// An 8042 keyboard controller is present
+        const KEYBOARD_8042 = 1 << 1;
+        /// VGA isn't present
+        const VGA_NOT_PRESENT = 1 << 2;
+        /// MSIs must not be enabled
+        const MSI_NOT_SUPPORTED = 1 << 3;
+        /// The CMOS RTC isn't present
+        const CMOS_RTC_NOT_PRESENT = 1 << 5;
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// The Fixed ACPI Description Table, signature `FACP`
+pub struct Fadt {
+    /// The table header
+    pub header: SdtHeader,
+    /// Physical address of the FACS
+    pub firmware_ctrl: u32,
+    /// Physical address of the DSDT
+    pub dsdt: u32,
+    reserved1: u8,
+    /// The preferred power management profile
+    pub preferred_pm_profile: u8,
+    /// The interrupt the SCI is wired to
+    pub sci_interrupt: u16,
+    /// The port for the SMI command register
+    pub smi_command: u32,
+    /// The value to write to `smi_command` to hand power management over to the OS
+    pub acpi_enable: u8,
+    /// The value to write to `smi_command` to hand power management back to the firmware
+    pub acpi_disable: u8,
+    /// The value to write to `smi_command` to enter S4BIOS
+    pub s4bios_req: u8,
+    /// The value to write to `smi_command` to take over processor performance control
+    pub pstate_control: u8,
+    /// Port for the PM1a event block
+    pub pm1a_event_block: u32,
+    /// Port for the PM1b event block
+    pub pm1b_event_block: u32,
+    /// Port for the PM1a control block
+    pub pm1a_control_block: u32,
+    /// Port for the PM1b control block
+    pub pm1b_control_block: u32,
+    /// Port for the PM2 control block
+    pub pm2_control_block: u32,
+    /// Port for the power management timer
+    pub pm_timer_block: u32,
+    /// Port for the GPE0 block
+    pub gpe0_block: u32,
+    /// Port for the GPE1 block
+    pub gpe1_block: u32,
+    /// Length of the PM1 event blocks
+    pub pm1_event_length: u8,
+    /// Length of the PM1 control blocks
+    pub pm1_control_length: u8,
+    /// Length of the PM2 control block
+    pub pm2_control_length: u8,
+    /// Length of the power management timer block
+    pub pm_timer_length: u8,
+    /// Length of the GPE0 block
+    pub gpe0_length: u8,
+    /// Length of the GPE1 block
+    pub gpe1_length: u8,
+    /// The offset GPE1 events begin at
+    pub gpe1_base: u8,
+    /// The value to write to `smi_command` to support C states
+    pub c_state_control: u8,
+    /// Worst case latency to enter C2, in microseconds
+    pub worst_c2_latency: u16,
+    /// Worst case latency to enter C3, in microseconds
+    pub worst_c3_latency: u16,
+    /// Cache flush size
+    pub flush_size: u16,
+    /// Cache flush stride
+    pub flush_stride: u16,
+    /// The duty cycle offset in the processor's P_CNT register
+    pub duty_offset: u8,
+    /// The duty cycle width in the processor's P_CNT register
+    pub duty_width: u8,
+    /// The RTC CMOS index of the day of month alarm
+    pub day_alarm: u8,
+    /// The RTC CMOS index of the month of year alarm
+    pub month_alarm: u8,
+    /// The RTC CMOS index of the century
+    pub century: u8,
+    /// IA-PC boot architecture flags, see [`BootArchitectureFlags`]
+    pub boot_architecture_flags: u16,
+    reserved2: u8,
+    /// Fixed feature flags, see [`FadtFlags`]
+    pub flags: u32,
+    /// The reset register
+    pub reset_register: GenericAddress,
+    /// The value to write to the reset register
+    pub reset_value: u8,
+    /// ARM boot architecture flags
+    pub arm_boot_architecture_flags: u16,
+    /// The minor version of the FADT
+    pub minor_version: u8,
+    /// 64 bit physical address of the FACS
+    pub x_firmware_control: u64,
+    /// 64 bit physical address of the DSDT
+    pub x_dsdt: u64,
+    /// The PM1a event block
+    pub x_pm1a_event_block: GenericAddress,
+    /// The PM1b event block
+    pub x_pm1b_event_block: GenericAddress,
+    /// The PM1a control block
+    pub x_pm1a_control_block: GenericAddress,
+    /// The PM1b control block
+    pub x_pm1b_control_block: GenericAddress,
+    /// The PM2 control block
+    pub x_pm2_control_block: GenericAddress,
+    /// The power management timer block
+    pub x_pm_timer_block: GenericAddress,
+    /// The GPE0 block
+    pub x_gpe0_block: GenericAddress,
+    /// The GPE1 block
+    pub x_gpe1_block: GenericAddress,
+    /// The sleep control register, for hardware reduced platforms
+    pub sleep_control: GenericAddress,
+    /// The sleep status register, for hardware reduced platforms
+    pub sleep_status: GenericAddress,
+    /// The hypervisor vendor identity
+    pub hypervisor_vendor: u64,
+}
+
+impl Fadt {
+    /// The table's signature
+    pub const SIGNATURE: [u8; 4] = *b"FACP";
+
+    /// The `SCI_EN` bit in the PM1 control registers
+    pub const SCI_ENABLE: u16 = 1 << 0;
+
+    /// The `SLP_EN` bit in the PM1 control registers
+    pub const SLEEP_ENABLE: u16 = 1 << 13;
+
+    /// Get the fixed feature flags
+    #[must_use]
+    pub const fn flags(&self) -> FadtFlags {
+        FadtFlags::from_bits_truncate(self.flags)
+    }
+
+    /// Get the IA-PC boot architecture flags
+    #[must_use]
+    pub const fn boot_architecture(&self) -> BootArchitectureFlags {
+        BootArchitectureFlags::from_bits_truncate(self.boot_architecture_flags)
+    }
+
+    /// Get the physical address of the DSDT, preferring the 64 bit field
+    #[must_use]
+    pub fn dsdt_address(&self) -> u64 {
+        if self.x_dsdt == 0 {
+            u64::from(self.dsdt)
+        } else {
+            self.x_dsdt
+        }
+    }
+
+    /// Get the `PM1a` control block, preferring the extended field
+    #[must_use]
+    pub const fn pm1a_control(&self) -> GenericAddress {
+        let ext = self.x_pm1a_control_block;
+        if ext.is_present() {
+            ext
+        } else {
+            GenericAddress::from_io_port(self.pm1a_control_block, 16)
+        }
+    }
+
+    /// Get the `PM1b` control block, preferring the extended field
+    #[must_use]
+    pub const fn pm1b_control(&self) -> Option<GenericAddress> {
+        let ext = self.x_pm1b_control_block;
+        if ext.is_present() {
+            Some(ext)
+        } else if self.pm1b_control_block != 0 {
+            Some(GenericAddress::from_io_port(self.pm1b_control_block, 16))
+        } else {
+            None
+        }
+    }
+
+    /// Get the reset register and the value to write to it, if it's supported
+    #[must_use]
+    pub const fn reset(&self) -> Option<(GenericAddress, u8)> {
+        let reg = self.reset_register;
+        // The reset register was introduced in revision 2
+        if self.header.revision >= 2
+            && self.flags().contains(FadtFlags::RESET_REG_SUPPORTED)
+            && reg.is_present()
+        {
+            Some((reg, self.reset_value))
+        } else {
+            None
+        }
+    }
+}
+
+/// The ACPI tables handed to us by the firmware
+pub struct Acpi {
+    root: Sdt,
+    extended: bool,
+}
+
+impl Acpi {
+    /// Find the ACPI tables through the bootloader's RSDP response
+    ///
+    /// # Errors
+    /// This will return an error if the RSDP or root table are invalid or missing
+    pub fn new() -> Result<Self, AcpiError> {
+        let response = crate::RSDP_REQUEST
+            .response
+            .ok_or(AcpiError::RsdpNotPresent)?;
+        let address = unsafe { response.as_ref() }
+            .address
+            .ok_or(AcpiError::RsdpNotPresent)?;
+        unsafe { Self::from_rsdp(address) }
+    }
+
+    /// Parse the ACPI tables from a pointer to the RSDP
+    ///
+    /// # Safety
+    /// The pointer must point to a mapped RSDP
+    ///
+    /// # Errors
+    /// This will return an error if the RSDP or root table are invalid
+    pub unsafe fn from_rsdp(ptr: *const u8) -> Result<Self, AcpiError> {
+        let rsdp = read_unaligned(ptr.cast::<Rsdp>());
+        if rsdp.signature != Rsdp::SIGNATURE {
+            return Err(AcpiError::InvalidSignature);
+        }
+        if checksum(ptr, size_of::<Rsdp>()) != 0 {
+            return Err(AcpiError::InvalidChecksum);
+        }
+
+        let (root, extended) = if rsdp.revision >= 2 {
+            let ext = read_unaligned(ptr.cast::<RsdpExtended>());
+            if checksum(ptr, ext.length as usize) != 0 {
+                return Err(AcpiError::InvalidChecksum);
+            }
+            (Sdt::from_physical(ext.xsdt_address)?, true)
+        } else {
+            (Sdt::from_physical(u64::from(rsdp.rsdt_address))?, false)
+        };
+
+        let expected = if extended { *b"XSDT" } else { *b"RSDT" };
+        if root.signature() != expected {
+            return Err(AcpiError::InvalidSignature);
+        }
+
+        debug!("ACPI root table: {:?}", root);
+
+        Ok(Self { root, extended })
+    }
+
+    /// Iterate over the physical addresses of every table in the root table
+    fn table_addresses(&self) -> impl Iterator<Item = u64> + '_ {
+        let body = self.root.body();
+        let width = if self.extended { 8 } else { 4 };
+        body.chunks_exact(width).map(move |chunk| {
+            let mut raw = [0u8; 8];
+            raw[..width].copy_from_slice(chunk);
+            u64::from_le_bytes(raw)
+        })
+    }
+
+    /// Iterate over every valid table
+    pub fn tables(&self) -> impl Iterator<Item = Sdt> + '_ {
+        self.table_addresses()
+            .filter_map(|addr| match Sdt::from_physical(addr) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    trace!("Skipping ACPI table at {addr:#X}: {e:?}");
+                    None
+                }
+            })
+    }
+
+    /// Find the first table with the given signature
+    ///
+    /// # Errors
+    /// This will return an error if no table matched
+    pub fn find_table(&self, signature: [u8; 4]) -> Result<Sdt, AcpiError> {
+        self.tables()
+            .find(|table| table.signature() == signature)
+            .ok_or(AcpiError::TableNotFound)
+    }
+
+    /// Get the FADT
+    ///
+    /// # Errors
+    /// This will return an error if the FADT isn't present
+    pub fn fadt(&self) -> Result<Fadt, AcpiError> {
+        Ok(self.find_table(Fadt::SIGNATURE)?.read_as::<Fadt>())
+    }
+
+    /// Get the DSDT
+    ///
+    /// # Errors
+    /// This will return an error if the FADT or DSDT are missing or invalid
+    pub fn dsdt(&self) -> Result<Sdt, AcpiError> {
+        let table = Sdt::from_physical(self.fadt()?.dsdt_address())?;
+        if table.signature() == *b"DSDT" {
+            Ok(table)
+        } else {
+            Err(AcpiError::InvalidSignature)
+        }
+    }
+}
+
+/// The sleep type values for a sleep state, to be written into the `SLP_TYP` field
+/// of the `PM1a` and `PM1b` control registers
+#[derive(Clone, Copy, Debug)]
+pub struct SleepType {
+    /// The value for the PM1a control register
+    pub a: u8,
+    /// The value for the PM1b control register
+    pub b: u8,
+}
+
+impl SleepType {
+    /// Get the sleep type values for a sleep state from its `\_Sx` package
+    ///
+    /// # Errors
+    /// This will return an error if the object doesn't exist or is malformed
+    pub fn from_namespace(context: &mut AmlContext, state: u8) -> Result<Self, AcpiError> {
+        let name = [b'_', b'S', b'0' + state, b'_'];
+        let package = match context
+            .evaluate_child(&[], name)
+            .map_err(AcpiError::Aml)?
+            .ok_or(AcpiError::Aml(AmlError::ObjectNotFound))?
+        {
+            AmlValue::Package(elements) => elements,
+            _ => return Err(AcpiError::Aml(AmlError::IncompatibleType)),
+        };
+
+        // `SLP_TYP` is three bits
+        let value = |index: usize| -> Result<u8, AcpiError> {
+            package
+                .get(index)
+                .map_or(Ok(0), AmlValue::as_integer)
+                .map(|v| (v & 0b111) as u8)
+                .map_err(AcpiError::Aml)
+        };
+
+        let sleep_type = Self {
+            a: value(0)?,
+            b: value(1)?,
+        };
+
+        trace!(
+            "Found sleep type for S{}: {:#X}, {:#X}",
+            state,
+            sleep_type.a,
+            sleep_type.b
+        );
+
+        Ok(sleep_type)
+    }
+}
+
+/// Gives the AML interpreter access to the hardware
+struct KernelHandler;
+
+impl KernelHandler {
+    /// The port used for PCI configuration addresses
+    const PCI_CONFIG_ADDRESS: u16 = 0xCF8;
+
+    /// The port used for PCI configuration data
+    const PCI_CONFIG_DATA: u16 = 0xCFC;
+
+    /// Select a dword of PCI configuration space, returning the shift of the requested
+    /// offset in it
+    fn select_pci(address: PciAddress, offset: u16) -> u32 {
+        if address.segment != 0 {
+            warn!("PCI segment {} isn't supported", address.segment);
+        }
+        outl(
+            0x8000_0000
+                | (u32::from(address.bus) << 16)
+                | (u32::from(address.device & 0x1F) << 11)
+                | (u32::from(address.function & 0x7) << 8)
+                | u32::from(offset & 0xFC),
+            Self::PCI_CONFIG_ADDRESS,
+        );
+        u32::from(offset & 0x3) * 8
+    }
+
+    /// Get a mask covering a width in bytes
+    const fn width_mask(width: u8) -> u64 {
+        if width >= 8 {
+            u64::MAX
+        } else {
+            (1 << (width as u64 * 8)) - 1
+        }
+    }
+}
+
+impl Handler for KernelHandler {
+    // AML accesses are aligned to their width
+    #[allow(clippy::cast_ptr_alignment)]
+    fn read_memory(&self, address: u64, width: u8) -> u64 {
+        let ptr = physical_to_virtual(address);
+        unsafe {
+            match width {
+                1 => u64::from(read_volatile(ptr)),
+                2 => u64::from(read_volatile(ptr.cast::<u16>())),
+                4 => u64::from(read_volatile(ptr.cast::<u32>())),
+                _ => read_volatile(ptr.cast::<u64>()),
+            }
+        }
+    }
+
+    // AML accesses are aligned to their width
+    #[allow(clippy::cast_possible_truncation, clippy::cast_ptr_alignment)]
+    fn write_memory(&self, address: u64, width: u8, value: u64) {
+        let ptr = physical_to_virtual(address);
+        unsafe {
+            match width {
+                1 => write_volatile(ptr, value as u8),
+                2 => write_volatile(ptr.cast::<u16>(), value as u16),
+                4 => write_volatile(ptr.cast::<u32>(), value as u32),
+                _ => write_volatile(ptr.cast::<u64>(), value),
+            }
+        }
+    }
+
+    fn read_io(&self, port: u16, width: u8) -> u64 {
+        match width {
+            1 => u64::from(inb(port)),
+            2 => u64::from(inw(port)),
+            4 => u64::from(inl(port)),
+            _ => u64::from(inl(port)) | (u64::from(inl(port + 4)) << 32),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_io(&self, port: u16, width: u8, value: u64) {
+        match width {
+            1 => outb(value as u8, port),
+            2 => outw(value as u16, port),
+            4 => outl(value as u32, port),
+            _ => {
+                outl(value as u32, port);
+                outl((value >> 32) as u32, port + 4);
+            }
+        }
+    }
+
+    fn read_pci(&self, address: PciAddress, offset: u16, width: u8) -> u64 {
+        if width == 8 {
+            return self.read_pci(address, offset, 4)
+                | (self.read_pci(address, offset + 4, 4) << 32);
+        }
+        let shift = Self::select_pci(address, offset);
+        (u64::from(inl(Self::PCI_CONFIG_DATA)) >> shift) & Self::width_mask(width)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_pci(&self, address: PciAddress, offset: u16, width: u8, value: u64) {
+        if width == 8 {
+            self.write_pci(address, offset, 4, value);
+            self.write_pci(address, offset + 4, 4, value >> 32);
+            return;
+        }
+        let shift = Self::select_pci(address, offset);
+        let mask = Self::width_mask(width) << shift;
+        let current = u64::from(inl(Self::PCI_CONFIG_DATA));
+        let new = (current & !mask) | ((value << shift) & mask);
+        outl(new as u32, Self::PCI_CONFIG_DATA);
+    }
+
+    fn stall(&self, microseconds: u64) {
+        // Each write to the POST port takes about a microsecond
+        for _ in 0..microseconds {
+            outb(0, 0x80);
+        }
+    }
+
+    fn sleep(&self, milliseconds: u64) {
+        self.stall(milliseconds * 1000);
+    }
+}
+
+/// The AML namespace, loaded from the DSDT and SSDTs by [`init`]
+pub static AML_CONTEXT: Mutex<Option<AmlContext>> = Mutex::new(None);
+
+/// Load the DSDT and every SSDT into the AML namespace, then initialize the devices
+/// in it
+///
+/// # Errors
+/// This will return an error if the tables can't be found or the DSDT fails to load.
+/// SSDTs that fail to load are skipped
+pub fn init() -> Result<(), AcpiError> {
+    let acpi = Acpi::new()?;
+    let dsdt = acpi.dsdt()?;
+
+    let mut context = AmlContext::new(Box::new(KernelHandler));
+    context
+        .load_table(dsdt.body(), dsdt.header().revision)
+        .map_err(AcpiError::Aml)?;
+
+    for ssdt in acpi.tables().filter(|table| table.signature() == *b"SSDT") {
+        if let Err(e) = context.load_table(ssdt.body(), ssdt.header().revision) {
+            warn!("Failed to load {:?}: {:?}", ssdt, e);
+        }
+    }
+
+    if let Err(e) = context.initialize_devices() {
+        warn!("Failed to initialize ACPI devices: {e:?}");
+    }
+
+    info!(
+        "Loaded {} objects into the ACPI namespace",
+        context.namespace().iter().count()
+    );
+    trace!("{:?}", context.namespace());
+
+    *AML_CONTEXT.lock() = Some(context);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::aml::tests::*;
+    use super::{AcpiError, AmlError, SleepType};
+
+    #[test]
+    fn sleep_types() {
+        let aml = [
+            named(
+                "\\_S5",
+                package(&[integer(5), integer(7), integer(0), integer(0)]),
+            ),
+            // Some firmware leaves out PM1b's value when there's only PM1a
+            named("\\_S3", package(&[integer(1)])),
+            named("\\_S1", integer(1)),
+        ]
+        .concat();
+        let (mut context, _) = TestHandler::context();
+        context.load_table(&aml, 2).unwrap();
+
+        let s5 = SleepType::from_namespace(&mut context, 5).unwrap();
+        assert_eq!((s5.a, s5.b), (5, 7));
+        let s3 = SleepType::from_namespace(&mut context, 3).unwrap();
+        assert_eq!((s3.a, s3.b), (1, 0));
+        assert!(matches!(
+            SleepType::from_namespace(&mut context, 4),
+            Err(AcpiError::Aml(AmlError::ObjectNotFound))
+        ));
+        assert!(matches!(
+            SleepType::from_namespace(&mut context, 1),
+            Err(AcpiError::Aml(AmlError::IncompatibleType))
+        ));
+    }
+
+    /// `Name (_S5, Package (0x04) { Zero, Zero, Zero, Zero })`, assembled by hand from what
+    /// QEMU's `hw/i386/acpi-build.c` puts in the DSDT of both its i440FX and Q35 machines
+    const QEMU_S5: [u8; 12] = [
+        0x08, b'_', b'S', b'5', b'_', 0x12, 0x06, 0x04, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn qemu_soft_off() {
+        let (mut context, _) = TestHandler::context();
+        context.load_table(&QEMU_S5, 1).unwrap();
+
+        // QEMU powers off when `SLP_EN` is written with a `SLP_TYP` of 0
+        let s5 = SleepType::from_namespace(&mut context, 5).unwrap();
+        assert_eq!((s5.a, s5.b), (0, 0));
+    }
+}
@@ -0,0 +1,258 @@
+use core::{
+    arch::x86_64::__cpuid,
+    ptr::{read_volatile, write_volatile},
+};
+
+use super::{
+    acpi::physical_to_virtual,
+    cpu::{read_msr, write_msr},
+};
+
+/// The mode the Local APIC timer counts down in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApicTimerMode {
+    /// Count down once, then stop
+    OneShot,
+    /// Reload the initial count after each interrupt
+    Periodic,
+    /// Fire once the TSC reaches the value in the deadline MSR, ignoring the initial count
+    TscDeadline,
+}
+
+/// Which cores an inter-processor interrupt is sent to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpiTarget {
+    /// The core with the given APIC ID
+    Core(u32),
+    /// Every core, including this one
+    All,
+    /// Every core but this one
+    AllButSelf,
+}
+
+/// How the Local APIC's registers are accessed
+#[derive(Clone, Copy, Debug)]
+enum ApicAccess {
+    /// Through memory mapped registers, at the given virtual address
+    XApic(usize),
+    /// Through MSRs
+    X2Apic,
+}
+
+/// The Local APIC of the core this was made on.
+/// Every core sees its own Local APIC at the same address, so this may be shared
+#[derive(Clone, Copy, Debug)]
+pub struct LocalApic {
+    access: ApicAccess,
+}
+
+impl LocalApic {
+    /// The MSR holding the APIC's base address and mode
+    const BASE_MSR: u32 = 0x1B;
+
+    /// The MSR holding the TSC value the timer fires at, in TSC-deadline mode
+    const TSC_DEADLINE_MSR: u32 = 0x6E0;
+
+    /// The first MSR of the x2APIC's registers
+    const X2APIC_MSR: u32 = 0x800;
+
+    /// Set in the base MSR if the APIC is in x2APIC mode
+    const BASE_X2APIC: u64 = 1 << 10;
+
+    /// The bits of the base MSR holding the base address
+    const BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+    /// The ID register
+    const ID: u32 = 0x20;
+
+    /// The task priority register
+    const TASK_PRIORITY: u32 = 0x80;
+
+    /// The end of interrupt register
+    const END_OF_INTERRUPT: u32 = 0xB0;
+
+    /// The spurious interrupt vector register
+    const SPURIOUS: u32 = 0xF0;
+
+    /// The low half of the interrupt command register, which sends the IPI when written
+    const ICR_LOW: u32 = 0x300;
+
+    /// The high half of the interrupt command register, holding the destination
+    const ICR_HIGH: u32 = 0x310;
+
+    /// The timer's local vector table entry
+    const LVT_TIMER: u32 = 0x320;
+
+    /// The timer's initial count register
+    const TIMER_INITIAL: u32 = 0x380;
+
+    /// The timer's current count register
+    const TIMER_CURRENT: u32 = 0x390;
+
+    /// The timer's divide configuration register
+    const TIMER_DIVIDE: u32 = 0x3E0;
+
+    /// Set in the spurious interrupt vector register to enable the APIC
+    const SOFTWARE_ENABLE: u32 = 1 << 8;
+
+    /// Set in a local vector table entry to mask it
+    const LVT_MASKED: u32 = 1 << 16;
+
+    /// Set in the timer's local vector table entry for periodic mode
+    const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+    /// Set in the timer's local vector table entry for TSC-deadline mode
+    const LVT_TIMER_TSC_DEADLINE: u32 = 1 << 18;
+
+    /// Set in the interrupt command register while an xAPIC IPI is being sent
+    const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+
+    /// Set in the interrupt command register for anything but an INIT level de-assert
+    const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+
+    /// The interrupt command register's destination shorthand for every core
+    const ICR_ALL: u32 = 0b10 << 18;
+
+    /// The interrupt command register's destination shorthand for every other core
+    const ICR_ALL_BUT_SELF: u32 = 0b11 << 18;
+
+    /// Divide the timer's input clock by 16
+    const DIVIDE_BY_16: u32 = 0b0011;
+
+    /// Get the Local APIC of this core, if the CPU has one
+    pub fn current() -> Option<Self> {
+        // CPUID.01h:EDX[9] reports an on-chip APIC
+        if unsafe { __cpuid(1) }.edx & (1 << 9) == 0 {
+            return None;
+        }
+
+        let base = unsafe { read_msr(Self::BASE_MSR) };
+        let access = if base & Self::BASE_X2APIC == 0 {
+            ApicAccess::XApic(physical_to_virtual(base & Self::BASE_ADDRESS_MASK) as usize)
+        } else {
+            ApicAccess::X2Apic
+        };
+        Some(Self { access })
+    }
+
+    /// Read a register
+    fn read(&self, register: u32) -> u32 {
+        match self.access {
+            ApicAccess::XApic(base) => unsafe {
+                read_volatile((base + register as usize) as *const u32)
+            },
+            #[allow(clippy::cast_possible_truncation)]
+            ApicAccess::X2Apic => unsafe { read_msr(Self::X2APIC_MSR + (register >> 4)) as u32 },
+        }
+    }
+
+    /// Write a register
+    fn write(&self, register: u32, value: u32) {
+        match self.access {
+            ApicAccess::XApic(base) => unsafe {
+                write_volatile((base + register as usize) as *mut u32, value);
+            },
+            ApicAccess::X2Apic => unsafe {
+                write_msr(Self::X2APIC_MSR + (register >> 4), u64::from(value));
+            },
+        }
+    }
+
+    /// Get the ID of this core's APIC
+    pub fn id(&self) -> u32 {
+        match self.access {
+            ApicAccess::XApic(_) => self.read(Self::ID) >> 24,
+            ApicAccess::X2Apic => self.read(Self::ID),
+        }
+    }
+
+    /// Enable the APIC and accept every interrupt priority
+    pub fn enable(&self, spurious_vector: u8) {
+        self.write(Self::TASK_PRIORITY, 0);
+        self.write(
+            Self::SPURIOUS,
+            Self::SOFTWARE_ENABLE | u32::from(spurious_vector),
+        );
+    }
+
+    /// Signal the end of an interrupt
+    pub fn end_of_interrupt(&self) {
+        self.write(Self::END_OF_INTERRUPT, 0);
+    }
+
+    /// Send a fixed inter-processor interrupt.
+    /// With an xAPIC this must not be interrupted, as the command is written in two halves
+    pub fn send_ipi(&self, target: IpiTarget, vector: u8) {
+        let (shorthand, destination) = match target {
+            IpiTarget::Core(id) => (0, id),
+            IpiTarget::All => (Self::ICR_ALL, 0),
+            IpiTarget::AllButSelf => (Self::ICR_ALL_BUT_SELF, 0),
+        };
+        let command = Self::ICR_LEVEL_ASSERT | shorthand | u32::from(vector);
+
+        match self.access {
+            ApicAccess::XApic(_) => {
+                // xAPIC IDs are 8 bits, in the top byte
+                self.write(Self::ICR_HIGH, destination << 24);
+                self.write(Self::ICR_LOW, command);
+                while self.read(Self::ICR_LOW) & Self::ICR_DELIVERY_PENDING != 0 {
+                    core::hint::spin_loop();
+                }
+            }
+            // The x2APIC's command register is a single MSR
+            ApicAccess::X2Apic => unsafe {
+                write_msr(
+                    Self::X2APIC_MSR + (Self::ICR_LOW >> 4),
+                    u64::from(destination) << 32 | u64::from(command),
+                );
+            },
+        }
+    }
+
+    /// Start the timer, which counts down from `initial_count` at the bus
+    /// frequency divided by 16 and raises `vector` when it reaches zero
+    pub fn start_timer(&self, vector: u8, initial_count: u32, mode: ApicTimerMode) {
+        let mode = match mode {
+            ApicTimerMode::OneShot => 0,
+            ApicTimerMode::Periodic => Self::LVT_TIMER_PERIODIC,
+            ApicTimerMode::TscDeadline => Self::LVT_TIMER_TSC_DEADLINE,
+        };
+        self.write(Self::TIMER_DIVIDE, Self::DIVIDE_BY_16);
+        self.write(Self::LVT_TIMER, mode | u32::from(vector));
+        self.write(Self::TIMER_INITIAL, initial_count);
+    }
+
+    /// Stop and mask the timer
+    pub fn stop_timer(&self) {
+        self.write(Self::LVT_TIMER, Self::LVT_MASKED);
+        self.write(Self::TIMER_INITIAL, 0);
+        if Self::supports_tsc_deadline() {
+            unsafe { write_msr(Self::TSC_DEADLINE_MSR, 0) };
+        }
+    }
+
+    /// Check if the timer supports TSC-deadline mode
+    pub fn supports_tsc_deadline() -> bool {
+        // CPUID.01h:ECX[24] reports TSC-deadline support
+        unsafe { __cpuid(1) }.ecx & (1 << 24) != 0
+    }
+
+    /// Switch the timer to TSC-deadline mode, without arming it
+    pub fn start_tsc_deadline(&self, vector: u8) {
+        self.start_timer(vector, 0, ApicTimerMode::TscDeadline);
+        // The mode switch has to land before the deadline MSR is written
+        unsafe { core::arch::x86_64::_mm_mfence() };
+    }
+
+    /// Fire the timer once the TSC reaches `deadline`, or disarm it with zero.
+    /// The timer must be in TSC-deadline mode
+    #[allow(clippy::unused_self)]
+    pub fn set_tsc_deadline(&self, deadline: u64) {
+        unsafe { write_msr(Self::TSC_DEADLINE_MSR, deadline) };
+    }
+
+    /// Get the timer's current count
+    pub fn timer_count(&self) -> u32 {
+        self.read(Self::TIMER_CURRENT)
+    }
+}
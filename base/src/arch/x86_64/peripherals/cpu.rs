@@ -36,6 +36,45 @@ impl CR0 {
     }
 }
 
+bitflags! {
+    pub struct CR4: u64 {
+        const VIRTUAL_8086_EXTENSIONS = 1 << 0;
+        const PROTECTED_VIRTUAL_INTERRUPTS = 1 << 1;
+        const TIME_STAMP_DISABLE = 1 << 2;
+        const DEBUGGING_EXTENSIONS = 1 << 3;
+        const PAGE_SIZE_EXTENSION = 1 << 4;
+        const PHYSICAL_ADDRESS_EXTENSION = 1 << 5;
+        const MACHINE_CHECK = 1 << 6;
+        const PAGE_GLOBAL = 1 << 7;
+        const PERFORMANCE_COUNTER = 1 << 8;
+        /// `fxsave`, `fxrstor` and SSE instructions are usable
+        const OSFXSR = 1 << 9;
+        /// Unmasked SSE exceptions raise #XM
+        const OSXMMEXCPT = 1 << 10;
+        const USER_MODE_INSTRUCTION_PREVENTION = 1 << 11;
+        const FSGSBASE = 1 << 16;
+        const PCID = 1 << 17;
+        const OSXSAVE = 1 << 18;
+        const SUPERVISOR_EXECUTION_PROTECTION = 1 << 20;
+        const SUPERVISOR_ACCESS_PREVENTION = 1 << 21;
+    }
+}
+
+#[allow(dead_code)]
+impl CR4 {
+    /// Get the CR4 register
+    pub fn get() -> Self {
+        let mut cr4: u64;
+        unsafe { asm!("mov {}, cr4", out(reg) cr4) }
+        Self { bits: cr4 }
+    }
+
+    /// Push the new value
+    pub fn update(&mut self) {
+        unsafe { asm!("mov cr4, {}", in(reg) self.bits) }
+    }
+}
+
 // TODO: Determine need for this
 #[allow(dead_code)]
 /// Struct representing the RSP register
@@ -51,3 +90,35 @@ impl RSP {
         Self(rsp)
     }
 }
+
+/// Read a model specific register
+///
+/// # Safety
+/// The MSR must exist, otherwise this raises a general protection fault
+pub unsafe fn read_msr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") low,
+        out("edx") high,
+        options(nomem, nostack, preserves_flags)
+    );
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// Write a model specific register
+///
+/// # Safety
+/// The MSR must exist and the value must be valid for it,
+/// as MSRs can change the behaviour of the whole core
+#[allow(clippy::cast_possible_truncation)]
+pub unsafe fn write_msr(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags)
+    );
+}
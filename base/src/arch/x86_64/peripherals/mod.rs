@@ -1,11 +1,21 @@
 /// The UART
 pub mod uart;
-pub use uart::Uart;
+pub use uart::{SerialConsole, Uart};
 
-mod timer;
+/// ACPI table discovery and parsing
+pub mod acpi;
+
+/// Timekeeping and timer interrupts
+pub mod timer;
 pub use timer::TimerManager;
 
-use crate::sync::{lazy_static, Mutex};
+/// The Local APIC
+pub mod apic;
+
+/// The legacy 8259 Programmable Interrupt Controllers
+pub mod pic;
+
+use crate::sync::{lazy_static, IrqSpinlock};
 
 /// Structures and functions relating to the CPU
 pub mod cpu;
@@ -18,8 +28,9 @@ pub mod cpu;
 // pub static UART: Singleton<Uart> = Singleton::new(Uart::new());
 
 lazy_static! {
-    /// UART Structure
-    pub lazy static _UART: Mutex<Uart> = {
+    /// UART Structure. Interrupts are disabled while it's held,
+    /// as the logger writes to it, and interrupt handlers log
+    pub lazy static _UART: IrqSpinlock<Uart> = {
         use self::uart::{inb, outb, COM_1};
 
         outb(0, COM_1 + 1); // Disable all interrupts
@@ -32,14 +43,15 @@ lazy_static! {
         outb(0x1E, COM_1 + 4); // Set in loopback mode, test the serial chip
         outb(0xAE, COM_1); // Test serial chip (send byte 0xAE and check if serial returns same byte)
 
-        // Check if serial is faulty (i.e: not same byte as sent)
+        // Check if serial is faulty (i.e: not same byte as sent).
+        // This is first used while logging, so it can't panic or log
         if inb(COM_1) != 0xAE {
-            panic!("SERIAL FAILED LOOPBACK TEST");
+            return IrqSpinlock::new(Uart::missing());
         }
 
         // If serial is not faulty set it in normal operation mode
         // (not-loopback with IRQs enabled and OUT#1 and OUT#2 bits enabled)
         outb(0x0F, COM_1 + 4);
-        Mutex::new(Uart::new())
+        IrqSpinlock::new(Uart::new())
     };
 }
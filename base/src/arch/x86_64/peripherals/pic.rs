@@ -0,0 +1,84 @@
+use super::uart::{inb, outb};
+
+/// The primary PIC's command port
+const PRIMARY_COMMAND: u16 = 0x20;
+
+/// The primary PIC's data port
+const PRIMARY_DATA: u16 = 0x21;
+
+/// The secondary PIC's command port
+const SECONDARY_COMMAND: u16 = 0xA0;
+
+/// The secondary PIC's data port
+const SECONDARY_DATA: u16 = 0xA1;
+
+/// Start initialization, with a fourth initialization word
+const ICW1_INIT: u8 = 0x11;
+
+/// Use 8086 mode
+const ICW4_8086: u8 = 0x01;
+
+/// The end of interrupt command
+const END_OF_INTERRUPT: u8 = 0x20;
+
+/// Give the PIC a moment to handle the last command
+fn io_wait() {
+    outb(0, 0x80);
+}
+
+/// Remap the PICs so IRQs 0-15 are delivered starting at `offset`, and mask every IRQ.
+/// The PICs' default vectors overlap CPU exceptions, so this must be done even if they aren't used
+pub fn init(offset: u8) {
+    outb(ICW1_INIT, PRIMARY_COMMAND);
+    io_wait();
+    outb(ICW1_INIT, SECONDARY_COMMAND);
+    io_wait();
+    outb(offset, PRIMARY_DATA);
+    io_wait();
+    outb(offset + 8, SECONDARY_DATA);
+    io_wait();
+    // The secondary PIC is cascaded through IRQ 2
+    outb(1 << 2, PRIMARY_DATA);
+    io_wait();
+    outb(2, SECONDARY_DATA);
+    io_wait();
+    outb(ICW4_8086, PRIMARY_DATA);
+    io_wait();
+    outb(ICW4_8086, SECONDARY_DATA);
+    io_wait();
+
+    outb(0xFF, PRIMARY_DATA);
+    outb(0xFF, SECONDARY_DATA);
+}
+
+/// Get the data port and bit for an IRQ
+const fn irq_port(irq: u8) -> (u16, u8) {
+    if irq < 8 {
+        (PRIMARY_DATA, irq)
+    } else {
+        (SECONDARY_DATA, irq - 8)
+    }
+}
+
+/// Stop an IRQ from being delivered
+pub fn mask(irq: u8) {
+    let (port, bit) = irq_port(irq);
+    outb(inb(port) | (1 << bit), port);
+}
+
+/// Allow an IRQ to be delivered
+pub fn unmask(irq: u8) {
+    let (port, bit) = irq_port(irq);
+    outb(inb(port) & !(1 << bit), port);
+    if irq >= 8 {
+        unmask(2);
+    }
+}
+
+/// Signal the end of an IRQ
+pub fn end_of_interrupt(irq: u8) {
+    if irq >= 8 {
+        outb(END_OF_INTERRUPT, SECONDARY_COMMAND);
+    }
+    outb(END_OF_INTERRUPT, PRIMARY_COMMAND);
+}
@@ -0,0 +1,238 @@
+use core::{
+    ptr::{read_volatile, write_volatile},
+    time::Duration,
+};
+
+use crate::{
+    arch::x86_64::peripherals::acpi::{
+        physical_to_virtual, Acpi, AddressSpace, GenericAddress, SdtHeader,
+    },
+    errors::TimerManagerError,
+};
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// The High Precision Event Timer description table
+pub struct HpetTable {
+    /// The table's header
+    pub header: SdtHeader,
+    /// The hardware ID of the event timer block
+    pub event_timer_block_id: u32,
+    /// The base address of the HPET's registers
+    pub base_address: GenericAddress,
+    /// The HPET's sequence number
+    pub hpet_number: u8,
+    /// The minimum tick in periodic mode without losing interrupts
+    pub minimum_tick: u16,
+    /// Page protection and OEM attributes
+    pub page_protection: u8,
+}
+
+impl HpetTable {
+    /// The table's signature
+    pub const SIGNATURE: [u8; 4] = *b"HPET";
+}
+
+/// A High Precision Event Timer
+#[derive(Clone, Copy, Debug)]
+pub struct Hpet {
+    /// The virtual address of the registers
+    base: usize,
+    /// The period of the main counter, in femtoseconds
+    period: u64,
+    /// If the main counter is 64 bits wide
+    wide: bool,
+    /// If the HPET can replace the PIT and RTC interrupts
+    legacy_replacement: bool,
+}
+
+impl Hpet {
+    /// The general capabilities and ID register
+    const CAPABILITIES: usize = 0x00;
+
+    /// The general configuration register
+    const CONFIGURATION: usize = 0x10;
+
+    /// The main counter register
+    const MAIN_COUNTER: usize = 0xF0;
+
+    /// Timer 0's configuration and capabilities register
+    const TIMER_0_CONFIGURATION: usize = 0x100;
+
+    /// Timer 0's comparator register
+    const TIMER_0_COMPARATOR: usize = 0x108;
+
+    /// Set in the capabilities if the main counter is 64 bits wide
+    const CAPABILITY_64_BIT: u64 = 1 << 13;
+
+    /// Set in the capabilities if legacy replacement routing is supported
+    const CAPABILITY_LEGACY: u64 = 1 << 15;
+
+    /// Set in the configuration to start the main counter
+    const ENABLE: u64 = 1 << 0;
+
+    /// Set in the configuration to route timer 0 to IRQ 0 and timer 1 to IRQ 8
+    const LEGACY_REPLACEMENT: u64 = 1 << 1;
+
+    /// Set in a timer's configuration to enable its interrupt
+    const TIMER_INTERRUPT_ENABLE: u64 = 1 << 2;
+
+    /// Set in a timer's configuration to make it periodic
+    const TIMER_PERIODIC: u64 = 1 << 3;
+
+    /// Femtoseconds in a nanosecond
+    const FEMTOSECONDS_PER_NANOSECOND: u64 = 1_000_000;
+
+    /// Find the HPET through the ACPI tables and start its main counter
+    ///
+    /// # Errors
+    /// This will return an error if there's no HPET table, or if the HPET is unusable
+    pub fn new(acpi: &Acpi) -> Result<Self, TimerManagerError> {
+        let table = acpi
+            .find_table(HpetTable::SIGNATURE)
+            .map_err(TimerManagerError::Acpi)?
+            .read_as::<HpetTable>();
+
+        let address = table.base_address;
+        if address.space() != AddressSpace::SystemMemory || !address.is_present() {
+            return Err(TimerManagerError::UnsupportedHardware);
+        }
+
+        let mut hpet = Self {
+            base: physical_to_virtual(address.address) as usize,
+            period: 0,
+            wide: false,
+            legacy_replacement: false,
+        };
+
+        let capabilities = hpet.read(Self::CAPABILITIES);
+        hpet.period = capabilities >> 32;
+        hpet.wide = capabilities & Self::CAPABILITY_64_BIT != 0;
+        hpet.legacy_replacement = capabilities & Self::CAPABILITY_LEGACY != 0;
+
+        // The specification caps the period at 100ns
+        if hpet.period == 0 || hpet.period > 100 * Self::FEMTOSECONDS_PER_NANOSECOND {
+            return Err(TimerManagerError::UnsupportedHardware);
+        }
+
+        let configuration = hpet.read(Self::CONFIGURATION);
+        hpet.write(Self::CONFIGURATION, configuration | Self::ENABLE);
+
+        Ok(hpet)
+    }
+
+    /// Read a register
+    fn read(&self, register: usize) -> u64 {
+        unsafe { read_volatile((self.base + register) as *const u64) }
+    }
+
+    /// Write a register
+    fn write(&self, register: usize, value: u64) {
+        unsafe { write_volatile((self.base + register) as *mut u64, value) }
+    }
+
+    /// Get the frequency of the main counter in Hz
+    pub const fn frequency(&self) -> u64 {
+        1_000_000_000_000_000 / self.period
+    }
+
+    /// Check if the main counter is 64 bits wide, and so won't wrap around
+    pub const fn is_64_bit(&self) -> bool {
+        self.wide
+    }
+
+    /// Check if the HPET can take over the PIT's interrupt
+    pub const fn supports_legacy_replacement(&self) -> bool {
+        self.legacy_replacement
+    }
+
+    /// Read the main counter
+    pub fn counter(&self) -> u64 {
+        if self.wide {
+            self.read(Self::MAIN_COUNTER)
+        } else {
+            self.read(Self::MAIN_COUNTER) & u64::from(u32::MAX)
+        }
+    }
+
+    /// Convert a count of main counter ticks to a duration
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        #[allow(clippy::cast_possible_truncation)]
+        let nanos = (u128::from(ticks) * u128::from(self.period)
+            / u128::from(Self::FEMTOSECONDS_PER_NANOSECOND)) as u64;
+        Duration::from_nanos(nanos)
+    }
+
+    /// Convert a duration to a count of main counter ticks
+    pub fn duration_to_ticks(&self, duration: Duration) -> u64 {
+        #[allow(clippy::cast_possible_truncation)]
+        let ticks = (duration.as_nanos() * u128::from(Self::FEMTOSECONDS_PER_NANOSECOND)
+            / u128::from(self.period)) as u64;
+        ticks
+    }
+
+    /// Busy wait for the given duration
+    pub fn stall(&self, duration: Duration) {
+        let ticks = self.duration_to_ticks(duration);
+        let start = self.counter();
+        let mask = if self.wide {
+            u64::MAX
+        } else {
+            u64::from(u32::MAX)
+        };
+        while self.counter().wrapping_sub(start) & mask < ticks {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Route timer 0 to IRQ 0 through legacy replacement, as a one-shot timer
+    ///
+    /// # Errors
+    /// This will return an error if the HPET doesn't support legacy replacement
+    pub fn enable_legacy_one_shot(&self) -> Result<(), TimerManagerError> {
+        if !self.legacy_replacement {
+            return Err(TimerManagerError::UnsupportedHardware);
+        }
+
+        let timer = self.read(Self::TIMER_0_CONFIGURATION) & !Self::TIMER_PERIODIC;
+        self.write(
+            Self::TIMER_0_CONFIGURATION,
+            timer | Self::TIMER_INTERRUPT_ENABLE,
+        );
+        let configuration = self.read(Self::CONFIGURATION);
+        self.write(
+            Self::CONFIGURATION,
+            configuration | Self::ENABLE | Self::LEGACY_REPLACEMENT,
+        );
+        Ok(())
+    }
+
+    /// Make timer 0 fire once, after the given duration
+    pub fn arm_one_shot(&self, duration: Duration) {
+        /// The shortest time to arm for, so the comparator isn't passed while it's written
+        const MINIMUM: Duration = Duration::from_micros(10);
+
+        let mask = if self.wide {
+            u64::MAX
+        } else {
+            u64::from(u32::MAX)
+        };
+        // A 32-bit comparator only reaches so far, so longer waits fire early and are re-armed
+        let mut ticks = self
+            .duration_to_ticks(duration.max(MINIMUM))
+            .clamp(1, mask >> 1);
+
+        loop {
+            let target = self.counter().wrapping_add(ticks) & mask;
+            self.write(Self::TIMER_0_COMPARATOR, target);
+
+            // If the counter already passed the comparator the interrupt would be missed,
+            // so try again further out
+            let remaining = target.wrapping_sub(self.counter()) & mask;
+            if remaining != 0 && remaining <= ticks {
+                return;
+            }
+            ticks = ticks.saturating_mul(2).min(mask >> 1);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    core_local,
+    errors::{GenericError, TimerManagerError},
+    smp::try_this_core,
+    sync::{Mutex, RwLock},
+    timer::{TimerHandle, TimerQueue},
+    traits::{
+        Init, Platform, TimerCallback, TimerId, TimerKind, TimerManager as TimerManagerTrait,
+    },
+};
+
+use super::{
+    acpi::Acpi,
+    apic::{ApicTimerMode, LocalApic},
+    pic,
+};
+use crate::arch::x86_64::{
+    interrupt_manager::without_interrupts, structures::ExceptionStackFrame, syscall::KernelGs,
+};
+
+/// The High Precision Event Timer
+pub mod hpet;
+use hpet::Hpet;
+
+/// The Programmable Interval Timer
+pub mod pit;
+
+/// The CMOS real-time clock
+pub mod rtc;
+
+/// The Time Stamp Counter
+pub mod tsc;
+
+/// The vector timer interrupts are delivered on, which is also where the PIC's IRQ 0 lands
+pub const TIMER_VECTOR: u8 = 0x20;
+
+/// The vector spurious Local APIC interrupts are delivered on
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+core_local! {
+    /// Each core's timers. These are only locked with interrupts disabled,
+    /// as the timer interrupt locks them too
+    static QUEUES: Mutex<TimerQueue> = Mutex::new(TimerQueue::new());
+}
+
+/// Where the monotonic clock is read from
+enum ClockSource {
+    /// The TSC, running at `frequency` Hz
+    Tsc { frequency: u64, start: u64 },
+    /// The HPET's main counter, if the TSC isn't reliable
+    Hpet { hpet: Hpet, start: u64 },
+}
+
+impl ClockSource {
+    /// Get the time since the clock source was chosen
+    fn elapsed(&self) -> Duration {
+        match self {
+            Self::Tsc { frequency, start } => {
+                tsc::ticks_to_duration(tsc::read().wrapping_sub(*start), *frequency)
+            }
+            Self::Hpet { hpet, start } => {
+                hpet.ticks_to_duration(hpet.counter().wrapping_sub(*start))
+            }
+        }
+    }
+
+    /// Get the TSC value at the given time since the clock source was chosen,
+    /// if the TSC is the clock source
+    fn tsc_at(&self, time: Duration) -> Option<u64> {
+        match self {
+            Self::Tsc { frequency, start } => {
+                let ticks = time.as_nanos() * u128::from(*frequency) / 1_000_000_000;
+                Some(start.wrapping_add(u64::try_from(ticks).unwrap_or(u64::MAX)))
+            }
+            Self::Hpet { .. } => None,
+        }
+    }
+}
+
+/// What raises timer interrupts. Each is programmed for the next deadline only,
+/// so idle cores aren't woken by a periodic tick
+#[derive(Clone, Copy, Debug)]
+enum EventSource {
+    /// The Local APIC timer in TSC-deadline mode
+    TscDeadline(LocalApic),
+    /// The Local APIC timer in one-shot mode, counting at `frequency` Hz
+    Apic { apic: LocalApic, frequency: u64 },
+    /// HPET timer 0, through the PIC's IRQ 0
+    Hpet(Hpet),
+    /// PIT channel 0, through the PIC's IRQ 0
+    Pit,
+}
+
+impl EventSource {
+    /// Acknowledge a timer interrupt
+    fn end_of_interrupt(self) {
+        match self {
+            Self::TscDeadline(apic) | Self::Apic { apic, .. } => apic.end_of_interrupt(),
+            Self::Hpet(_) | Self::Pit => pic::end_of_interrupt(0),
+        }
+    }
+
+    /// Get the index of the core whose queue this core's timers go in.
+    /// The HPET and PIT only interrupt the bootstrap core, so they only use its queue
+    fn queue_core(self) -> Option<usize> {
+        match self {
+            Self::TscDeadline(_) | Self::Apic { .. } => try_this_core().map(|core| core.index),
+            Self::Hpet(_) | Self::Pit => Some(0),
+        }
+    }
+}
+
+/// The `x86_64` timer manager. The TSC (or HPET) is used as the monotonic clock,
+/// and each core's timers are kept in its own queue, with the Local APIC timer
+/// (or HPET, or PIT) armed for the earliest deadline
+pub struct TimerManager {
+    clock: RwLock<Option<ClockSource>>,
+    /// The time since the UNIX epoch when the clock source was chosen
+    boot_time: RwLock<Option<Duration>>,
+    events: RwLock<Option<EventSource>>,
+    next_id: AtomicU64,
+}
+
+impl TimerManager {
+    pub const fn new() -> Self {
+        Self {
+            clock: RwLock::new(None),
+            boot_time: RwLock::new(None),
+            events: RwLock::new(None),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Pick and calibrate the monotonic clock source
+    fn init_clock(hpet: Option<Hpet>) -> Result<ClockSource, TimerManagerError> {
+        if let Some(hpet) = hpet.filter(|hpet| !tsc::is_invariant() && hpet.is_64_bit()) {
+            info!(
+                "Using the HPET as the clock source at {} Hz",
+                hpet.frequency()
+            );
+            return Ok(ClockSource::Hpet {
+                hpet,
+                start: hpet.counter(),
+            });
+        }
+
+        if !tsc::is_invariant() {
+            warn!("The TSC isn't invariant, the clock may drift");
+        }
+
+        let frequency = tsc::frequency_from_cpuid().unwrap_or_else(|| {
+            hpet.map_or_else(
+                || tsc::calibrate(pit::stall),
+                |hpet| tsc::calibrate(|duration| hpet.stall(duration)),
+            )
+        });
+        if frequency == 0 {
+            return Err(TimerManagerError::CalibrationFailed);
+        }
+
+        info!("Using the TSC as the clock source at {frequency} Hz");
+        Ok(ClockSource::Tsc {
+            frequency,
+            start: tsc::read(),
+        })
+    }
+
+    /// Pick the event source, leaving it disarmed
+    fn init_events(&self, hpet: Option<Hpet>) -> Result<EventSource, TimerManagerError> {
+        if let Some(apic) = LocalApic::current() {
+            /// How long to count the APIC timer for
+            const CALIBRATION: Duration = Duration::from_millis(10);
+
+            apic.enable(SPURIOUS_VECTOR);
+
+            let tsc_clock = matches!(*self.clock.read(), Some(ClockSource::Tsc { .. }));
+            if tsc_clock && LocalApic::supports_tsc_deadline() {
+                apic.start_tsc_deadline(TIMER_VECTOR);
+                info!("Using the Local APIC timer in TSC-deadline mode as the event source");
+                return Ok(EventSource::TscDeadline(apic));
+            }
+
+            // Interrupts are still disabled, and the count won't reach zero in this time anyway
+            apic.start_timer(TIMER_VECTOR, u32::MAX, ApicTimerMode::OneShot);
+            self.stall(CALIBRATION);
+            let elapsed = u32::MAX - apic.timer_count();
+            apic.stop_timer();
+
+            let frequency = u128::from(elapsed) * 1_000_000_000 / CALIBRATION.as_nanos();
+            let frequency = u64::try_from(frequency)
+                .map_err(|_| TimerManagerError::Generic(GenericError::IntConversionError))?;
+            if frequency == 0 {
+                return Err(TimerManagerError::CalibrationFailed);
+            }
+
+            info!("Using the Local APIC timer at {frequency} Hz as the event source");
+            return Ok(EventSource::Apic { apic, frequency });
+        }
+
+        let source = match hpet.map(|hpet| hpet.enable_legacy_one_shot().map(|()| hpet)) {
+            Some(Ok(hpet)) => {
+                info!("Using the HPET as the event source");
+                EventSource::Hpet(hpet)
+            }
+            other => {
+                if let Some(Err(e)) = other {
+                    debug!("HPET can't be used as the event source: {e:?}");
+                }
+                info!("Using the PIT as the event source");
+                EventSource::Pit
+            }
+        };
+        pic::unmask(0);
+        Ok(source)
+    }
+
+    /// Set up the Local APIC timer of a core other than the bootstrap core,
+    /// using the event source it picked. The HPET and PIT only interrupt the bootstrap core,
+    /// so with those, other cores' timers are kept in its queue
+    pub fn init_current_core(&self) {
+        match *self.events.read() {
+            Some(EventSource::TscDeadline(apic)) => {
+                apic.enable(SPURIOUS_VECTOR);
+                apic.start_tsc_deadline(TIMER_VECTOR);
+            }
+            // The timer is set up each time it's armed, and every core's runs at the same rate
+            Some(EventSource::Apic { apic, .. }) => apic.enable(SPURIOUS_VECTOR),
+            Some(EventSource::Hpet(_) | EventSource::Pit) | None => {}
+        }
+    }
+
+    /// Program this core's event source to interrupt at the given time.
+    /// Deadlines too far out for the hardware interrupt early, and are re-armed then
+    fn arm(&self, deadline: Duration) {
+        let source = match *self.events.read() {
+            Some(v) => v,
+            None => return,
+        };
+        let remaining = deadline.saturating_sub(self.monotonic());
+
+        match source {
+            EventSource::TscDeadline(apic) => {
+                let target = self
+                    .clock
+                    .read()
+                    .as_ref()
+                    .and_then(|clock| clock.tsc_at(deadline));
+                // Zero would disarm the timer, while any time in the past fires immediately
+                apic.set_tsc_deadline(target.unwrap_or(1).max(1));
+            }
+            EventSource::Apic { apic, frequency } => {
+                let count = remaining.as_nanos() * u128::from(frequency) / 1_000_000_000;
+                let count = u32::try_from(count).unwrap_or(u32::MAX).max(1);
+                apic.start_timer(TIMER_VECTOR, count, ApicTimerMode::OneShot);
+            }
+            EventSource::Hpet(hpet) => hpet.arm_one_shot(remaining),
+            EventSource::Pit => pit::start_one_shot(remaining),
+        }
+    }
+
+    /// Stop this core's event source, if it can be stopped
+    fn disarm(&self) {
+        match *self.events.read() {
+            Some(EventSource::TscDeadline(apic)) => apic.set_tsc_deadline(0),
+            Some(EventSource::Apic { apic, .. }) => apic.stop_timer(),
+            // A stray interrupt from these finds nothing to do, and leaves them be
+            Some(EventSource::Hpet(_) | EventSource::Pit) | None => {}
+        }
+    }
+
+    /// Fire this core's expired timers, then arm the event source for the next one
+    fn tick(&self) {
+        // The queue is made before the first timer is set, so this never allocates
+        let queue = match (*self.events.read())
+            .and_then(EventSource::queue_core)
+            .and_then(|core| QUEUES.try_get_on(core))
+        {
+            Some(v) => v,
+            None => return,
+        };
+        let now = self.monotonic();
+
+        loop {
+            let expired = queue.lock().take_expired(now);
+            let (id, mut callback) = match expired {
+                Some(v) => v,
+                None => break,
+            };
+
+            callback(id);
+
+            queue.lock().restore(id, callback);
+        }
+
+        let next = queue.lock().next_deadline();
+        match next {
+            Some(deadline) => self.arm(deadline),
+            None => self.disarm(),
+        }
+    }
+}
+
+unsafe impl TimerManagerTrait for TimerManager {
+    fn monotonic(&self) -> Duration {
+        self.clock
+            .read()
+            .as_ref()
+            .map_or(Duration::ZERO, ClockSource::elapsed)
+    }
+
+    fn now(&self) -> Option<Duration> {
+        self.boot_time
+            .read()
+            .map(|boot_time| boot_time + self.monotonic())
+    }
+
+    fn set_timer(
+        &self,
+        duration: Duration,
+        kind: TimerKind,
+        callback: TimerCallback,
+    ) -> Result<TimerHandle, TimerManagerError> {
+        let source = (*self.events.read())
+            .ok_or(TimerManagerError::Generic(GenericError::NotInitialized))?;
+        let id = TimerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        without_interrupts(|| {
+            let core = source
+                .queue_core()
+                .ok_or(TimerManagerError::Generic(GenericError::NotInitialized))?;
+            let deadline = self.monotonic() + duration;
+
+            let mut queue = QUEUES.get_on(core).lock();
+            queue.insert(id, deadline, kind, duration, callback)?;
+
+            if queue.next_deadline() == Some(deadline) {
+                self.arm(deadline);
+            }
+            Ok(TimerHandle { core, id })
+        })
+    }
+
+    fn clear_timer(&self, handle: &TimerHandle) -> Result<(), TimerManagerError> {
+        let queue = QUEUES
+            .try_get_on(handle.core)
+            .ok_or(TimerManagerError::TimerNotPresent)?;
+
+        // An earlier deadline stays armed, and finds nothing to do when it fires
+        without_interrupts(|| queue.lock().cancel(handle.id))
+    }
+}
+
+impl Init for TimerManager {
+    type Error = TimerManagerError;
+
+    type Input = ();
+
+    fn init(&self, _: Self::Input) -> Result<(), Self::Error> {
+        let acpi = Acpi::new().map_err(TimerManagerError::Acpi);
+        let hpet = match acpi.as_ref().map_err(|e| *e).and_then(Hpet::new) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                debug!("No usable HPET: {e:?}");
+                None
+            }
+        };
+
+        // The PIC is remapped even if it isn't used, so stray IRQs don't look like exceptions
+        pic::init(TIMER_VECTOR);
+
+        *self.clock.write() = Some(Self::init_clock(hpet)?);
+
+        // A century register of zero means the RTC doesn't have one
+        let century = acpi
+            .and_then(|acpi| acpi.fadt().map_err(TimerManagerError::Acpi))
+            .ok()
+            .map(|fadt| fadt.century)
+            .filter(|&register| register != 0);
+        let time = rtc::read(century);
+        info!("The time is {time}");
+        *self.boot_time.write() = Some(time.to_unix().saturating_sub(self.monotonic()));
+
+        unsafe {
+            let interrupts = PLATFORM_MANAGER.get_interrupt_manager();
+            interrupts.set_vector_handler(TIMER_VECTOR, timer_interrupt);
+            interrupts.set_vector_handler(SPURIOUS_VECTOR, spurious_interrupt);
+        }
+
+        *self.events.write() = Some(self.init_events(hpet)?);
+
+        Ok(())
+    }
+}
+
+unsafe impl Sync for TimerManager {}
+
+/// The timer interrupt handler
+extern "x86-interrupt" fn timer_interrupt(frame: &mut ExceptionStackFrame) {
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(TIMER_VECTOR);
+    let manager = PLATFORM_MANAGER.get_timer_manager();
+    if let Some(source) = *manager.events.read() {
+        source.end_of_interrupt();
+    }
+    manager.tick();
+    crate::scheduler::preempt();
+}
+
+/// The spurious interrupt handler, which mustn't be acknowledged
+extern "x86-interrupt" fn spurious_interrupt(frame: &mut ExceptionStackFrame) {
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(SPURIOUS_VECTOR);
+}
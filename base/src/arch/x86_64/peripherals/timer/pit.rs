@@ -0,0 +1,76 @@
+use core::time::Duration;
+
+use crate::arch::x86_64::peripherals::uart::{inb, outb};
+
+/// The frequency of the PIT's input clock, in Hz
+pub const FREQUENCY: u64 = 1_193_182;
+
+/// Channel 0's data port, which is wired to IRQ 0
+const CHANNEL_0: u16 = 0x40;
+
+/// Channel 2's data port, which is wired to the PC speaker
+const CHANNEL_2: u16 = 0x42;
+
+/// The mode/command port
+const COMMAND: u16 = 0x43;
+
+/// The port controlling channel 2's gate, and reporting its output
+const CHANNEL_2_GATE: u16 = 0x61;
+
+/// Channel 0, low then high byte, interrupt on terminal count
+const CHANNEL_0_ONE_SHOT: u8 = 0b0011_0000;
+
+/// Channel 2, low then high byte, interrupt on terminal count
+const CHANNEL_2_ONE_SHOT: u8 = 0b1011_0000;
+
+/// Convert a duration to a reload value, clamped to the 16 bit counter
+fn reload_value(duration: Duration) -> u16 {
+    let ticks = duration.as_nanos() * u128::from(FREQUENCY) / 1_000_000_000;
+    u16::try_from(ticks.clamp(1, u128::from(u16::MAX))).unwrap_or(u16::MAX)
+}
+
+/// Busy wait for the given duration using channel 2
+pub fn stall(duration: Duration) {
+    // The counter can't hold much more than 54ms at once
+    let longest = longest_count();
+    let mut remaining = duration;
+
+    while !remaining.is_zero() {
+        let chunk = remaining.min(longest);
+        remaining -= chunk;
+
+        let [low, high] = reload_value(chunk).to_le_bytes();
+
+        // Open the gate and keep the speaker disconnected
+        let gate = (inb(CHANNEL_2_GATE) & !0b10) | 0b1;
+        outb(gate, CHANNEL_2_GATE);
+
+        outb(CHANNEL_2_ONE_SHOT, COMMAND);
+        outb(low, CHANNEL_2);
+        outb(high, CHANNEL_2);
+
+        // Restart the count by pulsing the gate
+        outb(gate & !0b1, CHANNEL_2_GATE);
+        outb(gate, CHANNEL_2_GATE);
+
+        // The output goes high once the count reaches zero
+        while inb(CHANNEL_2_GATE) & (1 << 5) == 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// The longest a single count can last
+pub fn longest_count() -> Duration {
+    Duration::from_nanos(u64::from(u16::MAX) * 1_000_000_000 / FREQUENCY)
+}
+
+/// Make channel 0 raise IRQ 0 once, after the given duration
+/// or [`longest_count`], whichever is shorter
+pub fn start_one_shot(duration: Duration) {
+    let [low, high] = reload_value(duration).to_le_bytes();
+
+    outb(CHANNEL_0_ONE_SHOT, COMMAND);
+    outb(low, CHANNEL_0);
+    outb(high, CHANNEL_0);
+}
@@ -0,0 +1,132 @@
+use crate::{
+    arch::x86_64::peripherals::uart::{inb, outb},
+    time::DateTime,
+};
+
+/// The CMOS register select port
+const ADDRESS: u16 = 0x70;
+
+/// The CMOS data port
+const DATA: u16 = 0x71;
+
+/// The seconds register
+const SECONDS: u8 = 0x00;
+
+/// The minutes register
+const MINUTES: u8 = 0x02;
+
+/// The hours register
+const HOURS: u8 = 0x04;
+
+/// The day of the month register
+const DAY: u8 = 0x07;
+
+/// The month register
+const MONTH: u8 = 0x08;
+
+/// The year register, which only holds the last two digits
+const YEAR: u8 = 0x09;
+
+/// Status register A
+const STATUS_A: u8 = 0x0A;
+
+/// Status register B
+const STATUS_B: u8 = 0x0B;
+
+/// Set in status register A while the RTC is updating its registers
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+
+/// Set in status register B if the registers are binary, rather than BCD
+const BINARY: u8 = 1 << 2;
+
+/// Set in status register B if the hours register is in 24 hour mode
+const TWENTY_FOUR_HOUR: u8 = 1 << 1;
+
+/// Set in the hours register for PM, in 12 hour mode
+const PM: u8 = 1 << 7;
+
+/// Read a CMOS register
+fn read_register(register: u8) -> u8 {
+    outb(register, ADDRESS);
+    inb(DATA)
+}
+
+/// The registers as the RTC reports them
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+/// Read the registers once the RTC isn't in the middle of an update
+fn read_raw(century_register: Option<u8>) -> RawTime {
+    while read_register(STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+
+    RawTime {
+        second: read_register(SECONDS),
+        minute: read_register(MINUTES),
+        hour: read_register(HOURS),
+        day: read_register(DAY),
+        month: read_register(MONTH),
+        year: read_register(YEAR),
+        century: century_register.map_or(0, read_register),
+    }
+}
+
+/// Read the current date and time from the RTC
+///
+/// # Arguments
+/// * `century_register` - The CMOS index of the century register from the FADT, if it has one.
+///   Without it the year is assumed to be in the 2000s
+pub fn read(century_register: Option<u8>) -> DateTime {
+    // An update can still start between reads, so read until two reads agree
+    let mut raw = read_raw(century_register);
+    loop {
+        let next = read_raw(century_register);
+        if next == raw {
+            break;
+        }
+        raw = next;
+    }
+
+    let status = read_register(STATUS_B);
+    let decode = |v: u8| {
+        if status & BINARY == 0 {
+            (v & 0x0F) + (v >> 4) * 10
+        } else {
+            v
+        }
+    };
+
+    let mut hour = decode(raw.hour & !PM);
+    if status & TWENTY_FOUR_HOUR == 0 {
+        // 12 AM is midnight and 12 PM is noon
+        hour %= 12;
+        if raw.hour & PM != 0 {
+            hour += 12;
+        }
+    }
+
+    let century = if century_register.is_some() {
+        u16::from(decode(raw.century))
+    } else {
+        20
+    };
+
+    DateTime {
+        year: century * 100 + u16::from(decode(raw.year)),
+        month: decode(raw.month),
+        day: decode(raw.day),
+        hour,
+        minute: decode(raw.minute),
+        second: decode(raw.second),
+        nanosecond: 0,
+    }
+}
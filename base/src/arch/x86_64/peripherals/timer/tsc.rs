@@ -0,0 +1,50 @@
+use core::{
+    arch::x86_64::{__cpuid, _rdtsc},
+    time::Duration,
+};
+
+/// Read the time stamp counter
+pub fn read() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// Check if the TSC runs at a constant rate, regardless of power states
+pub fn is_invariant() -> bool {
+    // CPUID.80000007h:EDX[8] reports an invariant TSC
+    unsafe { __cpuid(0x8000_0000).eax >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0 }
+}
+
+/// Get the TSC's frequency in Hz from CPUID, if the CPU reports it
+pub fn frequency_from_cpuid() -> Option<u64> {
+    if unsafe { __cpuid(0) }.eax < 0x15 {
+        return None;
+    }
+
+    // The TSC runs at the crystal clock's frequency multiplied by EBX / EAX
+    let leaf = unsafe { __cpuid(0x15) };
+    if leaf.eax == 0 || leaf.ebx == 0 || leaf.ecx == 0 {
+        return None;
+    }
+    Some(u64::from(leaf.ecx) * u64::from(leaf.ebx) / u64::from(leaf.eax))
+}
+
+/// Measure the TSC's frequency in Hz, using `stall` to wait for a known duration
+pub fn calibrate(stall: impl Fn(Duration)) -> u64 {
+    /// How long to measure for
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    let start = read();
+    stall(PERIOD);
+    let elapsed = read() - start;
+
+    #[allow(clippy::cast_possible_truncation)]
+    let frequency = (u128::from(elapsed) * 1_000_000_000 / PERIOD.as_nanos()) as u64;
+    frequency
+}
+
+/// Convert a count of TSC ticks to a duration
+pub fn ticks_to_duration(ticks: u64, frequency: u64) -> Duration {
+    #[allow(clippy::cast_possible_truncation)]
+    let nanos = (u128::from(ticks) * 1_000_000_000 / u128::from(frequency)) as u64;
+    Duration::from_nanos(nanos)
+}
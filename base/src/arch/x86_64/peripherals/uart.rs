@@ -5,8 +5,13 @@ use core::{
 
 use crate::traits::Init;
 
+use super::_UART;
+
 /// Uart structure for reading and writing to itself
-pub struct Uart {}
+pub struct Uart {
+    /// Whether the port passed its loopback test. Without one, writes are dropped and nothing is read
+    present: bool,
+}
 
 /// COM Port 1 location
 pub const COM_1: u16 = 0x3F8;
@@ -15,7 +20,13 @@ pub const COM_1: u16 = 0x3F8;
 impl Uart {
     /// Construct a new UART instance. There should be no more than one
     pub const fn new() -> Self {
-        Uart {}
+        Uart { present: true }
+    }
+
+    /// Construct a UART instance for a port that isn't there
+    #[must_use]
+    pub const fn missing() -> Self {
+        Self { present: false }
     }
 
     /// Check if the outbound fifo is full
@@ -31,17 +42,27 @@ impl Uart {
     /// Read a byte from the UART
     pub fn read_byte(&mut self) -> u8 {
         loop {
-            if self.read_empty() {
-                unsafe { asm!("nop") }
-            } else {
-                break;
+            if let Some(byte) = self.try_read_byte() {
+                return byte;
             }
+            unsafe { asm!("nop") }
+        }
+    }
+
+    /// Read a byte from the UART if one has arrived, without waiting
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        if !self.present || self.read_empty() {
+            None
+        } else {
+            Some(inb(COM_1))
         }
-        inb(COM_1)
     }
 
     /// Write a byte to the UART
     pub fn write_byte(&mut self, c: u8) {
+        if !self.present {
+            return;
+        }
         loop {
             if self.write_full() {
                 unsafe { asm!("nop") }
@@ -80,6 +101,58 @@ pub fn inb(port: u16) -> u8 {
     result
 }
 
+/// Write a word wide value to a port
+pub fn outw(val: u16, port: u16) {
+    unsafe {
+        asm!(
+            "out dx, ax",
+            in("dx") port,
+            in("ax") val,
+            options(nomem, nostack, preserves_flags)
+        )
+    }
+}
+
+/// Read a word wide value from a port
+pub fn inw(port: u16) -> u16 {
+    let result: u16;
+    unsafe {
+        asm!(
+            "in ax, dx",
+            in("dx") port,
+            out("ax") result,
+            options(nomem, nostack, preserves_flags)
+        )
+    }
+    result
+}
+
+/// Write a double word wide value to a port
+pub fn outl(val: u32, port: u16) {
+    unsafe {
+        asm!(
+            "out dx, eax",
+            in("dx") port,
+            in("eax") val,
+            options(nomem, nostack, preserves_flags)
+        )
+    }
+}
+
+/// Read a double word wide value from a port
+pub fn inl(port: u16) -> u32 {
+    let result: u32;
+    unsafe {
+        asm!(
+            "in eax, dx",
+            in("dx") port,
+            out("eax") result,
+            options(nomem, nostack, preserves_flags)
+        )
+    }
+    result
+}
+
 impl Write for Uart {
     fn write_str(&mut self, data: &str) -> Result<(), Error> {
         for c in data.chars() {
@@ -90,3 +163,16 @@ impl Write for Uart {
 }
 
 impl Init for Uart {}
+
+/// The platform's text output, which locks the UART for each write,
+/// so it can be written to from any core or interrupt handler
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SerialConsole;
+
+impl Write for SerialConsole {
+    fn write_str(&mut self, data: &str) -> Result<(), Error> {
+        _UART.lock_irqsave().write_str(data)
+    }
+}
+
+impl Init for SerialConsole {}
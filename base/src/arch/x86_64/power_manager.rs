@@ -1,13 +1,213 @@
+use log::{error, info, warn};
+
 use crate::{
-    errors::{GenericError, PowerManagerError},
-    traits::{Init, PowerManager as PowerManagerTrait},
+    errors::{AcpiError, AmlError, GenericError, PowerManagerError},
+    sync::RwLock,
+    traits::{Init, PowerManager as PowerManagerTrait, PowerOffKind, PowerState},
+};
+
+use super::{
+    peripherals::{
+        acpi::{
+            aml::AmlValue, Acpi, BootArchitectureFlags, Fadt, GenericAddress, SleepType,
+            AML_CONTEXT,
+        },
+        uart::{inb, outb, outw},
+    },
+    structures::SizedDescriptorTable,
 };
 
-pub struct PowerManager {}
+/// Everything needed from the ACPI tables to switch power states
+struct AcpiPowerInfo {
+    pm1a_control: GenericAddress,
+    pm1b_control: Option<GenericAddress>,
+    smi_command: u32,
+    acpi_enable: u8,
+    s5: Option<SleepType>,
+    reset: Option<(GenericAddress, u8)>,
+    has_8042: bool,
+}
+
+pub struct PowerManager {
+    info: RwLock<Option<AcpiPowerInfo>>,
+}
 
 impl PowerManager {
+    /// The 8042's status port
+    const KBC_STATUS: u16 = 0x64;
+
+    /// The 8042's command that pulses the CPU reset line
+    const KBC_RESET: u8 = 0xFE;
+
+    /// QEMU's `isa-debug-exit` device, which exits with `(value << 1) | 1` when written to.
+    /// It's only there when QEMU is started with
+    /// `-device isa-debug-exit,iobase=0xf4,iosize=0x01`
+    const DEBUG_EXIT: u16 = 0xF4;
+
+    /// Shutdown ports and values for emulators that honor them, tried if ACPI fails
+    const EMULATOR_SHUTDOWN: [(u16, u16); 3] =
+        [(0x604, 0x2000), (0xB004, 0x2000), (0x4004, 0x3400)];
+
     pub const fn new() -> Self {
-        Self {}
+        Self {
+            info: RwLock::new(None),
+        }
+    }
+
+    /// Read everything needed for power management out of the ACPI tables
+    fn read_acpi() -> Result<AcpiPowerInfo, PowerManagerError> {
+        let acpi = Acpi::new().map_err(PowerManagerError::Acpi)?;
+        let fadt = acpi.fadt().map_err(PowerManagerError::Acpi)?;
+
+        let s5 = match AML_CONTEXT.lock().as_mut().map_or(
+            Err(AcpiError::Generic(GenericError::NotInitialized)),
+            |context| SleepType::from_namespace(context, 5),
+        ) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                warn!("Failed to find \\_S5 sleep type: {e:?}");
+                None
+            }
+        };
+
+        Ok(AcpiPowerInfo {
+            pm1a_control: fadt.pm1a_control(),
+            pm1b_control: fadt.pm1b_control(),
+            smi_command: fadt.smi_command,
+            acpi_enable: fadt.acpi_enable,
+            s5,
+            reset: fadt.reset(),
+            has_8042: fadt.header.revision < 2
+                || fadt
+                    .boot_architecture()
+                    .contains(BootArchitectureFlags::KEYBOARD_8042),
+        })
+    }
+
+    /// Hand power management over from SMM to the OS, if it hasn't been already
+    fn enable_acpi_mode(info: &AcpiPowerInfo) {
+        let enabled = info
+            .pm1a_control
+            .read()
+            .map_or(false, |v| v as u16 & Fadt::SCI_ENABLE != 0);
+
+        if enabled || info.smi_command == 0 || info.acpi_enable == 0 {
+            return;
+        }
+
+        outb(info.acpi_enable, info.smi_command as u16);
+
+        for _ in 0..1_000_000 {
+            if info
+                .pm1a_control
+                .read()
+                .map_or(false, |v| v as u16 & Fadt::SCI_ENABLE != 0)
+            {
+                return;
+            }
+            core::hint::spin_loop();
+        }
+
+        warn!("Firmware didn't acknowledge ACPI enable");
+    }
+
+    /// Let the firmware know we're about to enter a sleep state by calling `\_PTS`
+    fn prepare_to_sleep(state: u8) {
+        // Someone else holding the namespace shouldn't keep us from shutting down
+        let mut context = match AML_CONTEXT.try_lock() {
+            Some(v) => v,
+            None => {
+                warn!("ACPI namespace is locked, skipping \\_PTS");
+                return;
+            }
+        };
+
+        if let Some(context) = context.as_mut() {
+            match context.evaluate("\\_PTS", Vec::from([AmlValue::Integer(u64::from(state))])) {
+                Ok(_) | Err(AmlError::ObjectNotFound) => {}
+                Err(e) => warn!("\\_PTS failed: {e:?}"),
+            }
+        }
+    }
+
+    /// Enter S5 through the PM1 control blocks
+    fn acpi_shutdown(info: &AcpiPowerInfo) -> Result<(), PowerManagerError> {
+        let sleep_type = info.s5.ok_or(PowerManagerError::FailedToSwitchState)?;
+
+        Self::prepare_to_sleep(5);
+        Self::enable_acpi_mode(info);
+
+        let write = |reg: &GenericAddress, slp_typ: u8| -> Result<(), PowerManagerError> {
+            let current = reg.read().map_err(PowerManagerError::Acpi)? as u16;
+            let value = (current & !(0b111 << 10)) | (u16::from(slp_typ & 0b111) << 10);
+            reg.write(u64::from(value))
+                .map_err(PowerManagerError::Acpi)?;
+            reg.write(u64::from(value | Fadt::SLEEP_ENABLE))
+                .map_err(PowerManagerError::Acpi)
+        };
+
+        if let Some(pm1b) = &info.pm1b_control {
+            write(pm1b, sleep_type.b)?;
+        }
+        write(&info.pm1a_control, sleep_type.a)?;
+
+        // Writing SLP_EN should never return, give the chipset a moment if it does
+        for _ in 0..1_000_000 {
+            core::hint::spin_loop();
+        }
+
+        Err(PowerManagerError::FailedToSwitchState)
+    }
+
+    /// Reset through the FADT's reset register
+    fn acpi_reset(info: &AcpiPowerInfo) -> Result<(), PowerManagerError> {
+        let (reg, value) = info
+            .reset
+            .ok_or(PowerManagerError::Generic(GenericError::NotSupported))?;
+        reg.write(u64::from(value))
+            .map_err(PowerManagerError::Acpi)?;
+
+        for _ in 0..1_000_000 {
+            core::hint::spin_loop();
+        }
+
+        Err(PowerManagerError::FailedToSwitchState)
+    }
+
+    /// Pulse the reset line through the 8042 keyboard controller
+    fn keyboard_controller_reset() {
+        // Wait for the input buffer to be empty
+        for _ in 0..100_000 {
+            if inb(Self::KBC_STATUS) & 0b10 == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        outb(Self::KBC_RESET, Self::KBC_STATUS);
+
+        for _ in 0..1_000_000 {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Load an empty IDT and raise an exception, which the CPU can't deliver
+    fn triple_fault() -> ! {
+        let empty = SizedDescriptorTable { limit: 0, base: 0 };
+        unsafe {
+            asm!(
+                "lidt [{}]",
+                "int3",
+                in(reg) &empty as *const _,
+                options(noreturn)
+            )
+        }
+    }
+
+    /// Stop the current core forever
+    fn halt() -> ! {
+        loop {
+            unsafe { asm!("cli", "hlt") }
+        }
     }
 }
 
@@ -18,16 +218,70 @@ impl Default for PowerManager {
 }
 
 unsafe impl PowerManagerTrait for PowerManager {
-    fn get_state(&self) -> Result<crate::traits::PowerState, PowerManagerError> {
-        Err(PowerManagerError::Generic(GenericError::NotImplemented))
+    fn get_state(&self) -> Result<PowerState, PowerManagerError> {
+        // If we're running code, we're in S0
+        Ok(PowerState::Working)
     }
 
-    fn switch_state(&self, _new_state: crate::traits::PowerState) -> Result<(), PowerManagerError> {
-        Err(PowerManagerError::Generic(GenericError::NotImplemented))
+    fn switch_state(&self, new_state: PowerState) -> Result<(), PowerManagerError> {
+        match new_state {
+            PowerState::Working => Ok(()),
+            PowerState::Off => self.shutdown(PowerOffKind::Shutdown),
+            // Sleeping requires a waking vector and saving device state
+            PowerState::Standby | PowerState::Suspend | PowerState::Hibernation => {
+                Err(PowerManagerError::Generic(GenericError::NotImplemented))
+            }
+        }
     }
 
-    fn shutdown(&self, _kind: crate::traits::PowerOffKind) -> ! {
-        loop {}
+    fn shutdown(&self, kind: PowerOffKind) -> ! {
+        unsafe { asm!("cli") }
+
+        let info = self.info.read();
+
+        match kind {
+            PowerOffKind::Shutdown | PowerOffKind::Failure => {
+                if matches!(kind, PowerOffKind::Failure) {
+                    // Exits with 3 if the device is there, telling the failure apart from a clean
+                    // shutdown's 0. Without it, this is ignored and it shuts down normally.
+                    // It's written before logging, in case a panic left the logger locked
+                    outb(1, Self::DEBUG_EXIT);
+                    error!("Shutting down after a failure");
+                } else {
+                    info!("Shutting down");
+                }
+                if let Some(info) = &*info {
+                    if let Err(e) = Self::acpi_shutdown(info) {
+                        error!("ACPI shutdown failed: {e:?}");
+                    }
+                }
+
+                for (port, value) in Self::EMULATOR_SHUTDOWN {
+                    outw(value, port);
+                }
+
+                error!("Failed to shut down, halting");
+                Self::halt()
+            }
+            PowerOffKind::Reboot => {
+                info!("Rebooting");
+                let has_8042 = if let Some(info) = &*info {
+                    if let Err(e) = Self::acpi_reset(info) {
+                        warn!("ACPI reset failed: {e:?}");
+                    }
+                    info.has_8042
+                } else {
+                    true
+                };
+
+                if has_8042 {
+                    Self::keyboard_controller_reset();
+                    warn!("Keyboard controller reset failed");
+                }
+
+                Self::triple_fault()
+            }
+        }
     }
 }
 
@@ -35,4 +289,13 @@ impl Init for PowerManager {
     type Error = PowerManagerError;
 
     type Input = ();
+
+    fn init(&self, _: Self::Input) -> Result<(), Self::Error> {
+        match Self::read_acpi() {
+            Ok(info) => *self.info.write() = Some(info),
+            // We can still fall back to legacy methods without ACPI
+            Err(e) => warn!("Failed to read ACPI power management info: {e:?}"),
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,94 @@
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use limine_protocol::{structures::smpinfo::SMPInfo, SMPResponse};
+use log::{debug, info, warn};
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    scheduler,
+    traits::{Platform, TimerManager},
+};
+
+/// The size of each application processor's stack, the same as the bootstrap processor's
+pub const STACK_SIZE: usize = 64 * 1024;
+
+/// How long to wait for the application processors to reach their idle loop
+const START_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The offset of `extra_argument` in [`SMPInfo`], where each core's stack top is passed
+const STACK_TOP_OFFSET: usize = 24;
+
+/// The number of application processors that have finished starting
+static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Start every application processor Limine reported, and wait for them to reach their idle loop
+pub fn start_application_processors(response: &SMPResponse) {
+    let cpus = unsafe {
+        core::slice::from_raw_parts(response.cpus, response.cpu_count.try_into().unwrap_or(0))
+    };
+
+    let mut expected = 0;
+    for &info in cpus {
+        // Limine hands out const pointers, but the structures are in writable memory
+        let info = unsafe { &mut *info.cast_mut() };
+        if info.lapic_id == response.bsp_lapic_id {
+            continue;
+        }
+
+        // Limine's stacks are in bootloader reclaimable memory, so each core gets its own.
+        // It's made of u128s so the top is 16-byte aligned
+        let stack: Vec<u128> = core::iter::repeat(0).take(STACK_SIZE / 16).collect();
+        info.extra_argument = stack.leak().as_ptr_range().end as u64;
+
+        // The core polls for its address, so it's written atomically once its stack is in place
+        let entry: extern "C" fn(*const SMPInfo) -> ! = ap_entry;
+        info.goto_address.store(entry as u64, Ordering::Release);
+        expected += 1;
+    }
+
+    let timers = PLATFORM_MANAGER.get_timer_manager();
+    let deadline = timers.monotonic() + START_TIMEOUT;
+    while STARTED.load(Ordering::Acquire) < expected && timers.monotonic() < deadline {
+        core::hint::spin_loop();
+    }
+
+    let started = STARTED.load(Ordering::Acquire);
+    if started < expected {
+        warn!("Only {started} of {expected} application processors started");
+    } else {
+        info!("Started {started} application processors");
+    }
+}
+
+/// Where application processors start, still on Limine's stack.
+/// This switches to the stack in `extra_argument` before anything is pushed
+#[naked]
+extern "C" fn ap_entry(_info: *const SMPInfo) -> ! {
+    unsafe {
+        asm!(
+            "mov rsp, [rdi + {stack_top}]",
+            "xor ebp, ebp",
+            "call {main}",
+            "ud2",
+            stack_top = const STACK_TOP_OFFSET,
+            main = sym ap_main,
+            options(noreturn)
+        )
+    }
+}
+
+/// Register an application processor and set it up, then become its idle thread
+extern "C" fn ap_main(info: &'static SMPInfo) -> ! {
+    // Its core local data has to be in place before interrupts are enabled
+    crate::CORE_MANAGER.initialize_core(info.lapic_id);
+    PLATFORM_MANAGER.initialize_current_core();
+    scheduler::init_current_core();
+
+    debug!("Core {} started", info.lapic_id);
+    STARTED.fetch_add(1, Ordering::Release);
+
+    scheduler::idle()
+}
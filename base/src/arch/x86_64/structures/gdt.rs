@@ -4,6 +4,7 @@ use core::{
     ops::{Index, IndexMut},
 };
 
+use super::TaskStateSegment;
 use crate::macros::bitflags::bitflags;
 
 /*
@@ -41,7 +42,8 @@ pub fn is_64_bit_mode() -> bool {
     true
 }
 
-/// The kernel's GDT
+/// The template every core's GDT is copied from.
+/// The last two entries are left for that core's TSS descriptor
 #[used]
 pub static mut GDT: [u64; 9] = [
     0,
@@ -424,6 +426,16 @@ impl SystemSegmentDescriptorLongMode {
         }
     }
 
+    /// Create an available descriptor for a 64 bit TSS
+    pub fn new_tss(tss: *const TaskStateSegment) -> Self {
+        let mut descriptor = Self::new_unused();
+        descriptor.set_base(tss as u64);
+        descriptor.set_limit(TaskStateSegment::BASE_LENGTH.into());
+        descriptor.access_byte =
+            SystemSegmentAccessByte::PRESENT.segment_type(SegmentType::Tss64BitAvailable);
+        descriptor
+    }
+
     /// Get the flags
     ///
     /// `Flag Values`
@@ -451,7 +463,7 @@ impl SystemSegmentDescriptorLongMode {
     /// Set the limit
     pub fn set_limit(&mut self, val: u32) {
         let l1u16: u16 = (val & 0xFFFF).try_into().unwrap();
-        let l1u8: u8 = ((val >> 4) & 0xF).try_into().unwrap();
+        let l1u8: u8 = ((val >> 16) & 0xF).try_into().unwrap();
         self.limit = l1u16;
         self.flags_and_limit |= l1u8 & 0xF;
     }
@@ -459,18 +471,18 @@ impl SystemSegmentDescriptorLongMode {
     /// Get the base in the segment
     pub fn get_base(&self) -> u64 {
         let b1: u64 = self.base1.into();
-        let b2: u64 = self.base2.into();
-        let b3: u64 = self.base3.into();
-        let b4: u64 = self.base4.into();
+        let b2: u64 = u64::from(self.base2) << 16;
+        let b3: u64 = u64::from(self.base3) << 24;
+        let b4: u64 = u64::from(self.base4) << 32;
         b1 | b2 | b3 | b4
     }
 
     /// Set the base in the segment
     pub fn set_base(&mut self, base: u64) {
         let b1: u16 = (base & 0xFFFF).try_into().unwrap();
-        let b2: u8 = ((base << 16) & 0xFF).try_into().unwrap();
-        let b3: u8 = ((base << 24) & 0xFF).try_into().unwrap();
-        let b4: u32 = (base << 32) as u32;
+        let b2: u8 = ((base >> 16) & 0xFF).try_into().unwrap();
+        let b3: u8 = ((base >> 24) & 0xFF).try_into().unwrap();
+        let b4: u32 = (base >> 32) as u32;
         self.base1 = b1;
         self.base2 = b2;
         self.base3 = b3;
@@ -554,6 +566,9 @@ impl<'a> GlobalDescriptorTable<'a> {
     /// The 64 bit user data segment
     pub const UDATA64: u16 = 6 << 3;
 
+    /// The core's TSS, which takes up two entries
+    pub const TSS: u16 = 7 << 3;
+
     /// Create a global descriptor table from an exist SizedDescriptorTable
     pub fn from_existing(res: SizedDescriptorTable) -> Self {
         let limit: usize = ((res.limit + 1) / 8).into();
@@ -564,7 +579,8 @@ impl<'a> GlobalDescriptorTable<'a> {
         }
     }
 
-    /// Apply the changes
+    /// Apply the changes.
+    /// FS and GS are left alone, as loading them would clear the per-core data pointer in GS base
     #[naked]
     pub extern "sysv64" fn apply(from: usize) {
         const KCODE: u16 = GlobalDescriptorTable::KCODE;
@@ -575,8 +591,6 @@ impl<'a> GlobalDescriptorTable<'a> {
                 "mov   AX, {0}",
                 "mov   DS, AX",
                 "mov   ES, AX",
-                "mov   FS, AX",
-                "mov   GS, AX",
                 "mov   SS, AX",
                 "pop rax",
                 "push {1}",
@@ -590,6 +604,17 @@ impl<'a> GlobalDescriptorTable<'a> {
     }
 }
 
+impl GlobalDescriptorTable<'_> {
+    /// Load the task register with the TSS descriptor at the given selector.
+    ///
+    /// # Safety
+    /// The selector must refer to an available TSS descriptor in the loaded GDT,
+    /// and the TSS must live as long as it's loaded
+    pub unsafe fn load_tss(selector: u16) {
+        asm!("ltr {0:x}", in(reg) selector, options(nostack, preserves_flags));
+    }
+}
+
 impl<'a> Index<usize> for GlobalDescriptorTable<'a> {
     type Output = SegmentDescriptor;
 
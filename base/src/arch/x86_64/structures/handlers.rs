@@ -1,4 +1,5 @@
 use crate::{
+    arch::syscall::KernelGs,
     interrupts::{
         CheckFailedContext, ControlProtectionContext, DebugBreakpointContext, DivideByZeroContext,
         FloatingPointContext, GenericContext, HypervisorInterferenceContext, IllegalAccessContext,
@@ -14,74 +15,114 @@ macro_rules! invoke_handler {
     ($ctx:expr) => {
         unsafe { INTERRUPT_HANDLER.expect("INTERRUPT HANDLER NOT INSTALLED")($ctx) }
     };
+    // A fault from user mode only kills the process that caused it
+    ($frame:expr, $ctx:expr) => {
+        match $ctx {
+            context if $frame.code_segment & 3 == 3 => crate::process::fault(&context),
+            context => invoke_handler!(context),
+        }
+    };
 }
 
 /// DivideByZero hook
 pub extern "x86-interrupt" fn divide_by_zero(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::DivideByZero(DivideByZeroContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        error_code: None,
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(0);
+    invoke_handler!(
+        frame,
+        InterruptType::DivideByZero(DivideByZeroContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            error_code: None,
+        })
+    );
 }
 
 /// DebugBreakpoint hook
 pub extern "x86-interrupt" fn debug(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::DebugBreakpoint(DebugBreakpointContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        error_code: None,
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(1);
+    invoke_handler!(
+        frame,
+        InterruptType::DebugBreakpoint(DebugBreakpointContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            error_code: None,
+        })
+    );
 }
 
 /// DebugBreakpoint hook
 pub extern "x86-interrupt" fn breakpoint(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::DebugBreakpoint(DebugBreakpointContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        error_code: None,
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(3);
+    invoke_handler!(
+        frame,
+        InterruptType::DebugBreakpoint(DebugBreakpointContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            error_code: None,
+        })
+    );
 }
 
 /// Generic hook
 pub extern "x86-interrupt" fn general_protection(frame: &mut ExceptionStackFrame, error_code: u64) {
-    invoke_handler!(InterruptType::Generic(GenericContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        interrupt_number: 13,
-        error_code: Some(error_code),
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(13);
+    invoke_handler!(
+        frame,
+        InterruptType::Generic(GenericContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            interrupt_number: 13,
+            error_code: Some(error_code),
+        })
+    );
 }
 
 /// Generic hook
 pub extern "x86-interrupt" fn overflow(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::Generic(GenericContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        interrupt_number: 4,
-        error_code: None,
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(4);
+    invoke_handler!(
+        frame,
+        InterruptType::Generic(GenericContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            interrupt_number: 4,
+            error_code: None,
+        })
+    );
 }
 
 /// Generic hook
 pub extern "x86-interrupt" fn bound_range_exceeded(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::Generic(GenericContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        interrupt_number: 5,
-        error_code: None,
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(5);
+    invoke_handler!(
+        frame,
+        InterruptType::Generic(GenericContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            interrupt_number: 5,
+            error_code: None,
+        })
+    );
 }
 
 /// InvalidInstruction hook
 pub extern "x86-interrupt" fn invalid_opcode(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::InvalidInstruction(
-        InvalidInstructionContext {
-            pid: 0,
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(6);
+    invoke_handler!(
+        frame,
+        InterruptType::InvalidInstruction(InvalidInstructionContext {
+            pid: crate::process::current_pid().0,
             iptr: frame.instruction_pointer.try_into().unwrap(),
             error_code: None,
-        }
-    ))
+        })
+    );
 }
 
 bitflags! {
@@ -107,29 +148,41 @@ bitflags! {
 
 /// InvalidAccess hook
 pub extern "x86-interrupt" fn page_fault(frame: &mut ExceptionStackFrame, error_code: u64) {
-    invoke_handler!(InterruptType::IllegalAccess(IllegalAccessContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        page_unmapped: PageFaultErrorCode::from_bits_truncate(error_code)
-            .contains(PageFaultErrorCode::PRESENT),
-        error_code: Some(error_code),
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(14);
+    invoke_handler!(
+        frame,
+        InterruptType::IllegalAccess(IllegalAccessContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            page_unmapped: PageFaultErrorCode::from_bits_truncate(error_code)
+                .contains(PageFaultErrorCode::PRESENT),
+            error_code: Some(error_code),
+        })
+    );
 }
 
 /// CheckFailed hook
 pub extern "x86-interrupt" fn alignment(frame: &mut ExceptionStackFrame, error_code: u64) {
-    invoke_handler!(InterruptType::CheckFailed(CheckFailedContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        message: "FAILED ALIGNMENT CHECK",
-        error_code: Some(error_code),
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(17);
+    invoke_handler!(
+        frame,
+        InterruptType::CheckFailed(CheckFailedContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            message: "FAILED ALIGNMENT CHECK",
+            error_code: Some(error_code),
+        })
+    );
 }
 
 /// CheckFailed hook
 pub extern "x86-interrupt" fn machine(frame: &mut ExceptionStackFrame) {
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(18);
     invoke_handler!(InterruptType::CheckFailed(CheckFailedContext {
-        pid: 0,
+        pid: crate::process::current_pid().0,
         iptr: frame.instruction_pointer.try_into().unwrap(),
         message: "FAILED MACHINE CHECK",
         error_code: None,
@@ -138,22 +191,32 @@ pub extern "x86-interrupt" fn machine(frame: &mut ExceptionStackFrame) {
 
 /// CheckFailed hook
 pub extern "x86-interrupt" fn device_not_available(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::CheckFailed(CheckFailedContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        message: "DEVICE NOT AVAILABLE",
-        error_code: None,
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(7);
+    invoke_handler!(
+        frame,
+        InterruptType::CheckFailed(CheckFailedContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            message: "DEVICE NOT AVAILABLE",
+            error_code: None,
+        })
+    );
 }
 
 /// CheckFailed hook
 pub extern "x86-interrupt" fn invalid_tss(frame: &mut ExceptionStackFrame, error_code: u64) {
-    invoke_handler!(InterruptType::CheckFailed(CheckFailedContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        message: "FAILED TO VERIFY TSS",
-        error_code: Some(error_code),
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(10);
+    invoke_handler!(
+        frame,
+        InterruptType::CheckFailed(CheckFailedContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            message: "FAILED TO VERIFY TSS",
+            error_code: Some(error_code),
+        })
+    );
 }
 
 /// CheckFailed hook
@@ -161,12 +224,17 @@ pub extern "x86-interrupt" fn segment_not_present(
     frame: &mut ExceptionStackFrame,
     error_code: u64,
 ) {
-    invoke_handler!(InterruptType::CheckFailed(CheckFailedContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        message: "FAILED TO SET SEGMENT",
-        error_code: Some(error_code),
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(11);
+    invoke_handler!(
+        frame,
+        InterruptType::CheckFailed(CheckFailedContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            message: "FAILED TO SET SEGMENT",
+            error_code: Some(error_code),
+        })
+    );
 }
 
 /// CheckFailed hook
@@ -174,89 +242,121 @@ pub extern "x86-interrupt" fn stack_segment_fault(
     frame: &mut ExceptionStackFrame,
     error_code: u64,
 ) {
-    invoke_handler!(InterruptType::CheckFailed(CheckFailedContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        message: "FAILED TO SET STACK SEGMENT",
-        error_code: Some(error_code),
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(12);
+    invoke_handler!(
+        frame,
+        InterruptType::CheckFailed(CheckFailedContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            message: "FAILED TO SET STACK SEGMENT",
+            error_code: Some(error_code),
+        })
+    );
 }
 
 /// SimdError hook
 pub extern "x86-interrupt" fn simd_floating_point(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::SIMDError(SIMDErrorContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        error_code: None,
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(19);
+    invoke_handler!(
+        frame,
+        InterruptType::SIMDError(SIMDErrorContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            error_code: None,
+        })
+    );
 }
 
 /// FloatingPoint hook
 pub extern "x86-interrupt" fn floating_point(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::FloatingPoint(FloatingPointContext {
-        pid: 0,
-        iptr: frame.instruction_pointer.try_into().unwrap(),
-        error_code: None,
-    }))
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(16);
+    invoke_handler!(
+        frame,
+        InterruptType::FloatingPoint(FloatingPointContext {
+            pid: crate::process::current_pid().0,
+            iptr: frame.instruction_pointer.try_into().unwrap(),
+            error_code: None,
+        })
+    );
 }
 
 /// VirtualizationError hook
 pub extern "x86-interrupt" fn virtualization(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::VirtualizationError(
-        VirtualizationErrorContext {
-            pid: 0,
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(20);
+    invoke_handler!(
+        frame,
+        InterruptType::VirtualizationError(VirtualizationErrorContext {
+            pid: crate::process::current_pid().0,
             iptr: frame.instruction_pointer.try_into().unwrap(),
             error_code: None,
-        }
-    ))
+        })
+    );
 }
 
 /// VirtalizationError hook
 pub extern "x86-interrupt" fn vmm_communication(frame: &mut ExceptionStackFrame, error_code: u64) {
-    invoke_handler!(InterruptType::VirtualizationError(
-        VirtualizationErrorContext {
-            pid: 0,
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(29);
+    invoke_handler!(
+        frame,
+        InterruptType::VirtualizationError(VirtualizationErrorContext {
+            pid: crate::process::current_pid().0,
             iptr: frame.instruction_pointer.try_into().unwrap(),
             error_code: Some(error_code),
-        }
-    ))
+        })
+    );
 }
 
 /// HypervisorInterference hook
 pub extern "x86-interrupt" fn hypervisor_injection(frame: &mut ExceptionStackFrame) {
-    invoke_handler!(InterruptType::HypervisorInterference(
-        HypervisorInterferenceContext {
-            pid: 0,
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(28);
+    invoke_handler!(
+        frame,
+        InterruptType::HypervisorInterference(HypervisorInterferenceContext {
+            pid: crate::process::current_pid().0,
             iptr: frame.instruction_pointer.try_into().unwrap(),
             error_code: None,
-        }
-    ))
+        })
+    );
 }
 
 /// ControlProtectionViolation hook
 pub extern "x86-interrupt" fn control_protection(frame: &mut ExceptionStackFrame, error_code: u64) {
-    invoke_handler!(InterruptType::ControlProtectionViolation(
-        ControlProtectionContext {
-            pid: 0,
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(21);
+    invoke_handler!(
+        frame,
+        InterruptType::ControlProtectionViolation(ControlProtectionContext {
+            pid: crate::process::current_pid().0,
             iptr: frame.instruction_pointer.try_into().unwrap(),
             error_code: Some(error_code),
-        }
-    ))
+        })
+    );
 }
 
 /// ControlProtectionViolation hook
 pub extern "x86-interrupt" fn security_violation(frame: &mut ExceptionStackFrame, error_code: u64) {
-    invoke_handler!(InterruptType::ControlProtectionViolation(
-        ControlProtectionContext {
-            pid: 0,
+    let _gs = KernelGs::enter(frame);
+    crate::interrupts::record(30);
+    invoke_handler!(
+        frame,
+        InterruptType::ControlProtectionViolation(ControlProtectionContext {
+            pid: crate::process::current_pid().0,
             iptr: frame.instruction_pointer.try_into().unwrap(),
             error_code: Some(error_code),
-        }
-    ))
+        })
+    );
 }
 
-/// NonMaskableInterrupt hook
+/// NonMaskableInterrupt hook. This can arrive between `swapgs` and `sysretq`, where the GS base
+/// doesn't match the frame, so it never touches the core's local data and never returns
 pub extern "x86-interrupt" fn nmi(frame: &mut ExceptionStackFrame) {
+    crate::interrupts::record(2);
     invoke_handler!(InterruptType::NonMaskableInterrupt(
         NonMaskableInterruptContext {
             pid: 0,
@@ -268,6 +368,7 @@ pub extern "x86-interrupt" fn nmi(frame: &mut ExceptionStackFrame) {
 
 /// NonMaskableInterrupt hook
 pub extern "x86-interrupt" fn double_fault(frame: &mut ExceptionStackFrame, error_code: u64) {
+    crate::interrupts::record(8);
     invoke_handler!(InterruptType::NonMaskableInterrupt(
         NonMaskableInterruptContext {
             pid: 0,
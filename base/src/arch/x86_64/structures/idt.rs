@@ -154,6 +154,11 @@ impl InterruptDescriptor {
     pub fn set_segment(self, selector: u16) -> Self {
         Self { selector, ..self }
     }
+
+    /// Set the interrupt stack to switch to, from one, or zero to stay on the current stack
+    pub fn set_interrupt_stack(self, ist: u8) -> Self {
+        Self { ist, ..self }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -216,6 +221,7 @@ pub static mut INTERRUPT_HANDLER: Option<fn(InterruptType)> = None;
 pub unsafe fn install_interrupt_handler() {
     use super::handlers::*;
     use super::GlobalDescriptorTable as GDT;
+    use super::TaskStateSegment;
 
     // DivideByZero
     /// Divide by zero
@@ -294,7 +300,7 @@ pub unsafe fn install_interrupt_handler() {
 
     let idt_o = crate::arch::PLATFORM_MANAGER.get_interrupt_manager().idt();
 
-    let mut idt = { idt_o.inner };
+    let idt = &mut idt_o.inner;
 
     macro_rules! create_interrupt {
         ($idx:expr, $handler:ident, $segment:expr, $priv_level:expr) => {
@@ -325,10 +331,12 @@ pub unsafe fn install_interrupt_handler() {
     macro_rules! create_generic_hook {
         ($idx:expr, $segment:expr, $priv_level:expr) => {{
             extern "x86-interrupt" fn handle_generic(frame: &mut ExceptionStackFrame) {
+                let _gs = crate::arch::syscall::KernelGs::enter(frame);
+                crate::interrupts::record($idx);
                 unsafe {
                     INTERRUPT_HANDLER.expect("INTERRUPT HANDLER NOT INSTALLED")(
                         InterruptType::Generic(crate::interrupts::GenericContext {
-                            pid: 0,
+                            pid: crate::process::current_pid().0,
                             iptr: frame.instruction_pointer.try_into().unwrap(),
                             interrupt_number: $idx,
                             error_code: None,
@@ -371,6 +379,13 @@ pub unsafe fn install_interrupt_handler() {
     create_interrupt!(NMI, nmi, GDT::KCODE, 0);
     create_interrupt_code!(DOUBLE_FAULT, double_fault, GDT::KCODE, 0);
 
+    // These can arrive with a bad stack, or in the middle of switching stacks,
+    // so they always switch to a known good one
+    idt[DOUBLE_FAULT] = idt[DOUBLE_FAULT].set_interrupt_stack(TaskStateSegment::DOUBLE_FAULT_IST);
+    idt[NMI] = idt[NMI].set_interrupt_stack(TaskStateSegment::NMI_IST);
+    idt[MACHINE_CHECK] =
+        idt[MACHINE_CHECK].set_interrupt_stack(TaskStateSegment::MACHINE_CHECK_IST);
+
     create_generic_hook!(32, GDT::KCODE, 0);
     create_generic_hook!(33, GDT::KCODE, 0);
     create_generic_hook!(34, GDT::KCODE, 0);
@@ -11,7 +11,8 @@ pub struct TaskStateSegment {
     /// The RSPs to load when jumping to a specific level
     pub rsp: [Address<Virtual>; 3],
     reserved2: u64,
-    /// What is this, even?
+    /// The Interrupt Stack Table, stacks interrupt gates can ask to switch to.
+    /// Gates refer to them from one, as zero means no switch
     pub ists: [Address<Virtual>; 7],
     reserved3: u64,
     reserved4: u16,
@@ -23,6 +24,15 @@ impl TaskStateSegment {
     /// Base size In bytes?
     pub const BASE_LENGTH: u16 = 103;
 
+    /// The interrupt stack double faults switch to
+    pub const DOUBLE_FAULT_IST: u8 = 1;
+
+    /// The interrupt stack non maskable interrupts switch to
+    pub const NMI_IST: u8 = 2;
+
+    /// The interrupt stack machine checks switch to
+    pub const MACHINE_CHECK_IST: u8 = 3;
+
     /// Create a new blank TSS with no ports
     pub fn new_blank() -> Self {
         Self {
@@ -52,6 +62,22 @@ impl TaskStateSegment {
             iopb_offset: Self::BASE_LENGTH + 1,
         }
     }
+
+    /// Set the top of one of the interrupt stacks
+    ///
+    /// # Arguments
+    /// * `index` - The stack's index, from one, as used in interrupt gates
+    /// * `top` - The top of the stack
+    ///
+    /// # Panics
+    /// This will panic if the index isn't between 1 and 7
+    pub fn set_interrupt_stack(&mut self, index: u8, top: Address<Virtual>) {
+        assert!(
+            (1..=7).contains(&index),
+            "Bad interrupt stack index {index}"
+        );
+        self.ists[usize::from(index - 1)] = top;
+    }
 }
 
 /// Width of the IO port
@@ -0,0 +1,336 @@
+use core::sync::atomic::Ordering;
+
+use crate::{
+    memory::{
+        addresses::{Address, Virtual},
+        USER_SPACE_END,
+    },
+    process::{self, ExitStatus, Fault},
+    scheduler::without_interrupts,
+    smp::this_core,
+    syscalls,
+};
+
+use super::{
+    peripherals::cpu::{read_msr, write_msr},
+    structures::{ExceptionStackFrame, GlobalDescriptorTable},
+};
+
+/// The extended feature enable register, whose bit 0 enables `syscall`
+const EFER_MSR: u32 = 0xC000_0080;
+
+/// The system call extensions bit in EFER
+const EFER_SYSCALL_ENABLE: u64 = 1 << 0;
+
+/// The MSR holding the segments `syscall` and `sysret` load
+const STAR_MSR: u32 = 0xC000_0081;
+
+/// The MSR holding the address `syscall` jumps to in 64-bit mode
+const LSTAR_MSR: u32 = 0xC000_0082;
+
+/// The MSR holding the RFLAGS bits `syscall` clears
+const SFMASK_MSR: u32 = 0xC000_0084;
+
+/// The trap flag in RFLAGS
+const TRAP_FLAG: u64 = 1 << 8;
+
+/// The interrupt enable flag in RFLAGS
+const INTERRUPT_FLAG: u64 = 1 << 9;
+
+/// The direction flag in RFLAGS
+const DIRECTION_FLAG: u64 = 1 << 10;
+
+/// The I/O privilege level in RFLAGS
+const IOPL_MASK: u64 = 3 << 12;
+
+/// The nested task flag in RFLAGS
+const NESTED_TASK_FLAG: u64 = 1 << 14;
+
+/// The alignment check flag in RFLAGS
+const ALIGNMENT_CHECK_FLAG: u64 = 1 << 18;
+
+/// Where the current core's kernel stack top is in its core local data
+const KERNEL_STACK_OFFSET: usize = 8;
+
+/// Where the current core's scratch slot for the user stack pointer is in its core local data
+const USER_STACK_OFFSET: usize = 16;
+
+/// The registers saved by the system call entry, in the order they are on the kernel stack.
+///
+/// The number is passed in rax and the arguments in rdi, rsi, rdx, r10, r8 and r9, as on Linux,
+/// and the result is returned in rax
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct SyscallFrame {
+    /// Callee-saved
+    pub r15: u64,
+    /// Callee-saved
+    pub r14: u64,
+    /// Callee-saved
+    pub r13: u64,
+    /// Callee-saved
+    pub r12: u64,
+    /// The user RFLAGS, as saved by `syscall`
+    pub r11: u64,
+    /// The fourth argument
+    pub r10: u64,
+    /// The sixth argument
+    pub r9: u64,
+    /// The fifth argument
+    pub r8: u64,
+    /// Callee-saved
+    pub rbp: u64,
+    /// The first argument
+    pub rdi: u64,
+    /// The second argument
+    pub rsi: u64,
+    /// The third argument
+    pub rdx: u64,
+    /// The user RIP, as saved by `syscall`
+    pub rcx: u64,
+    /// Callee-saved
+    pub rbx: u64,
+    /// The system call number, and its result
+    pub rax: u64,
+    /// Where `sysretq` returns to
+    pub rip: u64,
+    /// The RFLAGS `sysretq` restores
+    pub rflags: u64,
+    /// The user stack pointer
+    pub rsp: u64,
+}
+
+impl SyscallFrame {
+    /// Make a frame that returns to `entry` in user mode with its stack pointer at `stack`,
+    /// and every other register zeroed, so nothing of the kernel's is left in them
+    #[must_use]
+    pub const fn new_user(entry: usize, stack: usize) -> Self {
+        Self {
+            r15: 0,
+            r14: 0,
+            r13: 0,
+            r12: 0,
+            r11: 0,
+            r10: 0,
+            r9: 0,
+            r8: 0,
+            rbp: 0,
+            rdi: 0,
+            rsi: 0,
+            rdx: 0,
+            rcx: 0,
+            rbx: 0,
+            rax: 0,
+            rip: entry as u64,
+            rflags: INTERRUPT_FLAG | 2,
+            rsp: stack as u64,
+        }
+    }
+}
+
+/// The core's local data in the GS base for the length of an interrupt or exception handler.
+///
+/// It has to be made before anything else in the handler, as nothing can find the core's local data
+/// until it is. If the handler interrupted user mode, `swapgs` swaps the core's local data in,
+/// and dropping it at the end of the handler swaps the user's GS base back before `iretq`.
+/// A handler that ends the thread instead never drops it, which leaves the kernel's in place
+#[must_use = "The user's GS base is swapped back when this is dropped"]
+pub struct KernelGs {
+    from_user: bool,
+}
+
+impl KernelGs {
+    /// Swap the core's local data in if `frame` is from user mode
+    pub fn enter(frame: &ExceptionStackFrame) -> Self {
+        let from_user = frame.code_segment & 3 == 3;
+        if from_user {
+            unsafe { asm!("swapgs", options(nostack, preserves_flags)) }
+        }
+        Self { from_user }
+    }
+}
+
+impl Drop for KernelGs {
+    fn drop(&mut self) {
+        if self.from_user {
+            // Nothing can interrupt between here and `iretq`, which restores the user's flags
+            unsafe { asm!("cli", "swapgs", options(nostack)) }
+        }
+    }
+}
+
+/// Enable `syscall` on the current core, pointing it at the system call entry.
+/// The core's GDT must be loaded, as the selectors are taken from it
+pub fn init_current_core() {
+    // `syscall` loads CS from bits 47:32, and SS from the descriptor after it.
+    // `sysretq` loads CS from 16 past bits 63:48, and SS from 8 past them
+    let star = (u64::from(GlobalDescriptorTable::UCODE32 | 3) << 48)
+        | (u64::from(GlobalDescriptorTable::KCODE) << 32);
+    let entry: unsafe extern "sysv64" fn() -> ! = syscall_entry;
+
+    unsafe {
+        write_msr(STAR_MSR, star);
+        write_msr(LSTAR_MSR, entry as usize as u64);
+        // Interrupts stay off until the entry is on the kernel stack
+        write_msr(
+            SFMASK_MSR,
+            TRAP_FLAG
+                | INTERRUPT_FLAG
+                | DIRECTION_FLAG
+                | IOPL_MASK
+                | NESTED_TASK_FLAG
+                | ALIGNMENT_CHECK_FLAG,
+        );
+        write_msr(EFER_MSR, read_msr(EFER_MSR) | EFER_SYSCALL_ENABLE);
+    }
+}
+
+/// Set the stack the current core enters the kernel on from user mode,
+/// for both system calls and interrupts
+///
+/// # Safety
+/// The stack must belong to the thread that's about to run on this core,
+/// and have nothing on it the thread needs while it's in user mode
+pub unsafe fn set_kernel_stack(top: usize) {
+    let core = this_core();
+    core.kernel_stack.store(top, Ordering::Relaxed);
+    if let Ok(top) = Address::<Virtual>::new(top as *const ()) {
+        super::interrupt_manager::set_privileged_stack(top);
+    }
+}
+
+/// Drop to user mode with the registers in `frame`, the way a system call returns.
+///
+/// Everything left on the kernel stack is abandoned, and system calls and interrupts
+/// from user mode start again from its top
+///
+/// # Safety
+/// The frame's instruction and stack pointers must be mapped for user mode,
+/// and the current thread's kernel stack must have been set with [`set_kernel_stack`]
+pub unsafe fn return_to_user(mut frame: SyscallFrame) -> ! {
+    check_return(&mut frame);
+    asm!(
+        "mov rsp, {frame}",
+        "jmp {exit}",
+        frame = in(reg) core::ptr::addr_of!(frame),
+        exit = sym syscall_exit,
+        options(noreturn)
+    )
+}
+
+/// Get the registers the current thread saved when it made the system call it's in,
+/// which it returns to user mode with
+///
+/// # Safety
+/// The current thread must be in a system call from user mode,
+/// and nothing else may be using its saved registers
+#[must_use]
+pub unsafe fn current_frame<'a>() -> &'a mut SyscallFrame {
+    // They're the first thing pushed to the thread's kernel stack
+    let top = without_interrupts(|| this_core().kernel_stack.load(Ordering::Relaxed));
+    &mut *((top - core::mem::size_of::<SyscallFrame>()) as *mut SyscallFrame)
+}
+
+/// Where `syscall` jumps to, with interrupts disabled, the user's stack, RIP in rcx and RFLAGS in r11.
+///
+/// The GS base is the user's until `swapgs` swaps the core's local data in, as it is for interrupts.
+/// The user stack pointer is parked in the core's local data until the kernel stack is loaded,
+/// then everything is pushed to make a [`SyscallFrame`], which [`syscall_exit`] returns with
+#[naked]
+unsafe extern "sysv64" fn syscall_entry() -> ! {
+    asm!(
+        "swapgs",
+        "mov gs:[{user_stack}], rsp",
+        "mov rsp, gs:[{kernel_stack}]",
+        "push qword ptr gs:[{user_stack}]",
+        "push r11",
+        "push rcx",
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        // Eighteen registers keep the stack 16-byte aligned for the call
+        "sti",
+        "mov rdi, rsp",
+        "call {dispatch}",
+        "jmp {exit}",
+        user_stack = const USER_STACK_OFFSET,
+        kernel_stack = const KERNEL_STACK_OFFSET,
+        dispatch = sym dispatch,
+        exit = sym syscall_exit,
+        options(noreturn)
+    )
+}
+
+/// Return to user mode with the [`SyscallFrame`] the stack pointer is at
+#[naked]
+unsafe extern "sysv64" fn syscall_exit() -> ! {
+    asm!(
+        "cli",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        "pop rcx",
+        "pop r11",
+        "pop rsp",
+        "swapgs",
+        "sysretq",
+        options(noreturn)
+    )
+}
+
+/// Run the system call described by the frame, with interrupts enabled,
+/// and make sure it's safe to return to with `sysretq`.
+/// System calls can change the frame through [`current_frame`], so it isn't borrowed while they run
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+extern "sysv64" fn dispatch(frame: *mut SyscallFrame) {
+    let (number, arguments) = {
+        let frame = unsafe { &*frame };
+        let arguments = [
+            frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9,
+        ];
+        (
+            frame.rax as usize,
+            arguments.map(|argument| argument as usize),
+        )
+    };
+    let result = syscalls::dispatch(number, arguments);
+
+    let frame = unsafe { &mut *frame };
+    frame.rax = result as u64;
+    check_return(frame);
+}
+
+/// Make sure a frame is safe to return to user mode with using `sysretq`,
+/// ending the process if it isn't
+#[allow(clippy::cast_possible_truncation)]
+fn check_return(frame: &mut SyscallFrame) {
+    // `sysretq` to a non-canonical address faults in the kernel, on the user's stack
+    if frame.rip as usize >= USER_SPACE_END {
+        process::exit(ExitStatus::Faulted(Fault::Memory));
+    }
+    frame.rflags = (frame.rflags | INTERRUPT_FLAG) & !(IOPL_MASK | NESTED_TASK_FLAG);
+}
@@ -0,0 +1,381 @@
+use core::{alloc::Layout, mem::size_of};
+
+use crate::{
+    errors::{ElfError, MemoryManagerError},
+    memory::{utilities::align, AddressSpace, USER_SPACE_END, USER_SPACE_START},
+    process::Credentials,
+    random,
+    traits::MemoryFlags,
+};
+
+use super::{read, Elf, FileType, ProgramHeader, SegmentType};
+
+/// Where position independent executables are loaded
+const PIE_BASE: usize = 0x0000_5555_0000_0000;
+
+/// The size of a user program's main stack, which is all allocated up front
+pub const STACK_SIZE: usize = 256 * 1024;
+
+/// The top of a user program's main stack, with an unmapped page above it
+const STACK_TOP: usize = USER_SPACE_END - 4096;
+
+/// The dynamic table entries relocation needs
+const DT_NULL: i64 = 0;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_RELAENT: i64 = 9;
+const DT_RELSZ: i64 = 18;
+const DT_RELRSZ: i64 = 35;
+
+/// The relocation types a static position independent executable uses
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_RELATIVE: u32 = 8;
+
+/// The auxiliary vector entries programs are started with
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_BASE: u64 = 7;
+const AT_FLAGS: u64 = 8;
+const AT_ENTRY: u64 = 9;
+const AT_UID: u64 = 11;
+const AT_EUID: u64 = 12;
+const AT_GID: u64 = 13;
+const AT_EGID: u64 = 14;
+const AT_PLATFORM: u64 = 15;
+const AT_HWCAP: u64 = 16;
+const AT_CLKTCK: u64 = 17;
+const AT_SECURE: u64 = 23;
+const AT_RANDOM: u64 = 25;
+const AT_EXECFN: u64 = 31;
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// An entry in the dynamic table
+struct Dynamic {
+    tag: i64,
+    value: u64,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// A relocation with an addend
+struct Rela {
+    offset: u64,
+    info: u64,
+    addend: i64,
+}
+
+/// A program loaded into an address space
+#[derive(Clone, Copy, Debug)]
+pub struct LoadedImage {
+    /// What its addresses were moved by, which is zero unless it's position independent
+    pub base: usize,
+    /// Where it starts running
+    pub entry: usize,
+    /// Where its program headers are in memory, or zero if they weren't loaded
+    pub program_headers: usize,
+    /// The number of program headers
+    pub program_header_count: usize,
+    /// The end of its highest segment, which its heap can start from
+    pub end: usize,
+}
+
+/// Map a program's loadable segments into an address space, with the permissions they ask for,
+/// and relocate it if it's position independent. Its heap is placed just past them
+///
+/// # Errors
+/// This will return an error if the program needs an interpreter or unsupported relocations,
+/// its segments are invalid, or they can't be mapped
+#[allow(clippy::cast_possible_truncation)]
+pub fn load(address_space: &AddressSpace, elf: &Elf) -> Result<LoadedImage, ElfError> {
+    let base = match elf.file_type() {
+        FileType::Executable => 0,
+        FileType::SharedObject => PIE_BASE,
+    };
+
+    // Segments can share a page, which is then mapped once with both of their permissions
+    let mut regions: Vec<(usize, usize, MemoryFlags)> = Vec::new();
+    let mut end = 0;
+    for segment in elf.program_headers() {
+        match segment.segment_type() {
+            SegmentType::Interpreter => return Err(ElfError::DynamicallyLinked),
+            SegmentType::Load if segment.memory_size > 0 => {}
+            _ => continue,
+        }
+        let (start, segment_end) = segment_range(base, &segment)?;
+        end = end.max(segment_end);
+        regions.push((
+            start & !0xFFF,
+            align(segment_end, 4096),
+            segment_flags(&segment),
+        ));
+    }
+    if regions.is_empty() {
+        return Err(ElfError::NoSegments);
+    }
+    regions.sort_unstable_by_key(|&(start, ..)| start);
+    let mut merged: Vec<(usize, usize, MemoryFlags)> = Vec::with_capacity(regions.len());
+    for (start, end, flags) in regions {
+        match merged.last_mut() {
+            Some(last) if start < last.1 => {
+                last.1 = last.1.max(end);
+                last.2 |= flags;
+            }
+            _ => merged.push((start, end, flags)),
+        }
+    }
+
+    for (start, end, flags) in merged {
+        let layout =
+            Layout::from_size_align(end - start, 4096).map_err(|_| ElfError::InvalidSegment)?;
+        address_space
+            .map(Some(start), layout, flags)
+            .map_err(ElfError::Memory)?;
+    }
+
+    // Whatever isn't in the file was zeroed when it was mapped
+    for segment in elf.program_headers() {
+        if segment.segment_type() == SegmentType::Load && segment.memory_size > 0 {
+            let (start, _) = segment_range(base, &segment)?;
+            address_space
+                .write_bytes(start, elf.segment_data(&segment)?)
+                .map_err(ElfError::Memory)?;
+        }
+    }
+
+    if base != 0 {
+        relocate(address_space, elf, base)?;
+    }
+    address_space.init_program_break(end);
+
+    let entry = base
+        .checked_add(elf.header().entry as usize)
+        .ok_or(ElfError::InvalidSegment)?;
+    Ok(LoadedImage {
+        base,
+        entry,
+        program_headers: program_headers_address(elf, base)?,
+        program_header_count: usize::from(elf.header().program_header_count),
+        end,
+    })
+}
+
+/// Map a main stack into an address space, and put a program's arguments, environment
+/// and auxiliary vector on it the way the System V ABI describes,
+/// then get the stack pointer it should start with
+///
+/// # Errors
+/// This will return an error if the stack can't be mapped, or everything doesn't fit on it
+pub fn build_stack(
+    address_space: &AddressSpace,
+    image: &LoadedImage,
+    arguments: &[&str],
+    environment: &[&str],
+    credentials: Credentials,
+) -> Result<usize, ElfError> {
+    // The strings go at the top, with everything pointing to them below
+    let mut strings = Vec::new();
+    let mut offsets = Vec::with_capacity(arguments.len() + environment.len());
+    for string in arguments.iter().chain(environment) {
+        offsets.push(strings.len());
+        strings.extend_from_slice(string.as_bytes());
+        strings.push(0);
+    }
+    let platform = strings.len();
+    strings.extend_from_slice(b"x86_64\0");
+    let random = strings.len();
+    // C libraries seed stack protectors from what `AT_RANDOM` points to
+    let mut random_bytes = [0; 16];
+    random::fill(&mut random_bytes);
+    strings.extend_from_slice(&random_bytes);
+
+    let strings_start = (STACK_TOP - strings.len()) & !0xF;
+    let pointers = offsets
+        .iter()
+        .map(|offset| (strings_start + offset) as u64)
+        .collect::<Vec<_>>();
+    let (argument_pointers, environment_pointers) = pointers.split_at(arguments.len());
+
+    let execfn = argument_pointers.first().copied().unwrap_or(0);
+    let auxiliary = [
+        (AT_PHDR, image.program_headers as u64),
+        (AT_PHENT, size_of::<ProgramHeader>() as u64),
+        (AT_PHNUM, image.program_header_count as u64),
+        (AT_PAGESZ, 4096),
+        (AT_BASE, 0),
+        (AT_FLAGS, 0),
+        (AT_ENTRY, image.entry as u64),
+        (AT_UID, u64::from(credentials.uid)),
+        (AT_EUID, u64::from(credentials.euid)),
+        (AT_GID, u64::from(credentials.gid)),
+        (AT_EGID, u64::from(credentials.egid)),
+        (AT_PLATFORM, (strings_start + platform) as u64),
+        (AT_HWCAP, 0),
+        (AT_CLKTCK, 100),
+        (AT_SECURE, 0),
+        (AT_RANDOM, (strings_start + random) as u64),
+        (AT_EXECFN, execfn),
+        (AT_NULL, 0),
+    ];
+
+    let mut words = Vec::new();
+    words.push(arguments.len() as u64);
+    words.extend_from_slice(argument_pointers);
+    words.push(0);
+    words.extend_from_slice(environment_pointers);
+    words.push(0);
+    for (key, value) in auxiliary {
+        words.push(key);
+        words.push(value);
+    }
+
+    // The stack pointer has to be 16-byte aligned where the argument count is
+    let stack_pointer = (strings_start - words.len() * 8) & !0xF;
+    // Like Linux, only a quarter of the stack can be taken up by them
+    if STACK_TOP - stack_pointer > STACK_SIZE / 4 {
+        return Err(ElfError::ArgumentsTooLarge);
+    }
+
+    let layout = Layout::from_size_align(STACK_SIZE, 4096)
+        .map_err(|_| ElfError::Memory(MemoryManagerError::OutOfRange))?;
+    address_space
+        .map(
+            Some(STACK_TOP - STACK_SIZE),
+            layout,
+            MemoryFlags::READABLE | MemoryFlags::WRITABLE,
+        )
+        .map_err(ElfError::Memory)?;
+
+    let words = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect::<Vec<_>>();
+    address_space
+        .write_bytes(strings_start, &strings)
+        .and_then(|()| address_space.write_bytes(stack_pointer, &words))
+        .map_err(ElfError::Memory)?;
+    Ok(stack_pointer)
+}
+
+/// Get where a segment starts and ends in memory, once it's moved by `base`
+fn segment_range(base: usize, segment: &ProgramHeader) -> Result<(usize, usize), ElfError> {
+    if segment.file_size > segment.memory_size {
+        return Err(ElfError::InvalidSegment);
+    }
+    let start = usize::try_from(segment.virtual_address)
+        .ok()
+        .and_then(|address| address.checked_add(base))
+        .ok_or(ElfError::InvalidSegment)?;
+    let end = usize::try_from(segment.memory_size)
+        .ok()
+        .and_then(|size| start.checked_add(size))
+        .ok_or(ElfError::InvalidSegment)?;
+    if start < USER_SPACE_START || end > USER_SPACE_END {
+        return Err(ElfError::InvalidSegment);
+    }
+    Ok((start, end))
+}
+
+/// Get the memory flags a segment asks for
+fn segment_flags(segment: &ProgramHeader) -> MemoryFlags {
+    let mut flags = MemoryFlags::none();
+    if segment.flags & ProgramHeader::READABLE != 0 {
+        flags |= MemoryFlags::READABLE;
+    }
+    if segment.flags & ProgramHeader::WRITABLE != 0 {
+        flags |= MemoryFlags::WRITABLE;
+    }
+    if segment.flags & ProgramHeader::EXECUTABLE != 0 {
+        flags |= MemoryFlags::EXECUTABLE;
+    }
+    flags
+}
+
+/// Find where the program headers ended up in memory, which the C library finds
+/// thread-local storage through. They're either described by their own segment,
+/// or are in the first one, along with the file header
+#[allow(clippy::cast_possible_truncation)]
+fn program_headers_address(elf: &Elf, base: usize) -> Result<usize, ElfError> {
+    let offset = elf.header().program_header_offset;
+    let address = elf
+        .program_headers()
+        .find_map(|segment| match segment.segment_type() {
+            SegmentType::ProgramHeaders => Some(Some(segment.virtual_address)),
+            SegmentType::Load
+                if segment.offset <= offset && offset - segment.offset < segment.file_size =>
+            {
+                Some(segment.virtual_address.checked_add(offset - segment.offset))
+            }
+            _ => None,
+        });
+    address.map_or(Ok(0), |address| {
+        address
+            .and_then(|address| base.checked_add(address as usize))
+            .ok_or(ElfError::InvalidSegment)
+    })
+}
+
+/// Apply a position independent executable's relocations, now it's loaded at `base`.
+/// Static ones only have relative relocations, which add the base to what's already there
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn relocate(address_space: &AddressSpace, elf: &Elf, base: usize) -> Result<(), ElfError> {
+    let dynamic = match elf
+        .program_headers()
+        .find(|segment| segment.segment_type() == SegmentType::Dynamic)
+    {
+        Some(dynamic) => elf.segment_data(&dynamic)?,
+        None => return Ok(()),
+    };
+
+    let (mut table, mut size, mut entry_size) = (0, 0, size_of::<Rela>());
+    for index in 0..dynamic.len() / size_of::<Dynamic>() {
+        let entry: Dynamic = read(dynamic, index * size_of::<Dynamic>())?;
+        match { entry.tag } {
+            DT_NULL => break,
+            DT_RELA => table = entry.value as usize,
+            DT_RELASZ => size = entry.value as usize,
+            DT_RELAENT => entry_size = entry.value as usize,
+            // x86_64 only uses relocations with addends, and packed ones aren't supported
+            DT_RELSZ | DT_RELRSZ if entry.value != 0 => {
+                return Err(ElfError::UnsupportedRelocation(entry.tag as u32));
+            }
+            _ => {}
+        }
+    }
+    if size == 0 {
+        return Ok(());
+    }
+    if entry_size != size_of::<Rela>() {
+        return Err(ElfError::InvalidSegment);
+    }
+
+    // The table's size comes from the file, so it has to be mapped before anything's allocated for it
+    let table = base.checked_add(table).ok_or(ElfError::InvalidSegment)?;
+    if table.checked_add(size).is_none() || !address_space.is_accessible(table, size, false) {
+        return Err(ElfError::InvalidSegment);
+    }
+    let mut relocations: Vec<u8> = core::iter::repeat(0).take(size).collect();
+    address_space
+        .read_bytes(table, &mut relocations)
+        .map_err(ElfError::Memory)?;
+    for index in 0..size / entry_size {
+        let relocation: Rela = read(&relocations, index * entry_size)?;
+        match relocation.info as u32 {
+            R_X86_64_NONE => {}
+            R_X86_64_RELATIVE => {
+                let address = base
+                    .checked_add(relocation.offset as usize)
+                    .ok_or(ElfError::InvalidSegment)?;
+                let value = (base as u64).wrapping_add(relocation.addend as u64);
+                address_space
+                    .write_bytes(address, &value.to_le_bytes())
+                    .map_err(ElfError::Memory)?;
+            }
+            other => return Err(ElfError::UnsupportedRelocation(other)),
+        }
+    }
+    Ok(())
+}
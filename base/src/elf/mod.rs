@@ -0,0 +1,327 @@
+use core::{mem::size_of, ptr::read_unaligned};
+
+use crate::errors::ElfError;
+
+mod loader;
+pub use loader::{build_stack, load, LoadedImage, STACK_SIZE};
+
+/// What every ELF file starts with
+pub const MAGIC: [u8; 4] = *b"\x7FELF";
+
+/// The file class for 64-bit files
+const CLASS_64: u8 = 2;
+
+/// The data encoding for little-endian files
+const DATA_LITTLE_ENDIAN: u8 = 1;
+
+/// The only ELF version there is
+const VERSION_CURRENT: u8 = 1;
+
+/// The machine number for `x86_64`
+const MACHINE_X86_64: u16 = 62;
+
+/// What an ELF file is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    /// An executable that runs at the addresses it was linked at
+    Executable,
+    /// A shared object, or a position independent executable, which can run anywhere
+    SharedObject,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// The header at the start of every ELF file
+pub struct FileHeader {
+    /// The magic number, class, data encoding, version and ABI
+    pub ident: [u8; 16],
+    /// The file type
+    pub kind: u16,
+    /// The machine the file is for
+    pub machine: u16,
+    /// The ELF version
+    pub version: u32,
+    /// Where the program starts running
+    pub entry: u64,
+    /// Where the program header table is in the file
+    pub program_header_offset: u64,
+    /// Where the section header table is in the file
+    pub section_header_offset: u64,
+    /// Machine-specific flags
+    pub flags: u32,
+    /// The size of this header
+    pub header_size: u16,
+    /// The size of each program header
+    pub program_header_size: u16,
+    /// The number of program headers
+    pub program_header_count: u16,
+    /// The size of each section header
+    pub section_header_size: u16,
+    /// The number of section headers
+    pub section_header_count: u16,
+    /// The index of the section holding section names
+    pub section_names_index: u16,
+}
+
+/// What a program header describes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentType {
+    /// A segment to be loaded into memory
+    Load,
+    /// The dynamic linking table
+    Dynamic,
+    /// The path of the interpreter that loads the program
+    Interpreter,
+    /// The program header table itself
+    ProgramHeaders,
+    /// The thread-local storage template
+    ThreadLocal,
+    /// Anything else, which loading ignores
+    Other(u32),
+}
+
+impl From<u32> for SegmentType {
+    fn from(kind: u32) -> Self {
+        match kind {
+            1 => Self::Load,
+            2 => Self::Dynamic,
+            3 => Self::Interpreter,
+            6 => Self::ProgramHeaders,
+            7 => Self::ThreadLocal,
+            other => Self::Other(other),
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+/// A program header, describing a segment of the file
+pub struct ProgramHeader {
+    /// The segment type
+    pub kind: u32,
+    /// Whether the segment is executable, writable and readable, in bits 0, 1 and 2
+    pub flags: u32,
+    /// Where the segment is in the file
+    pub offset: u64,
+    /// Where the segment is loaded in memory
+    pub virtual_address: u64,
+    /// Where the segment is loaded in physical memory, which is ignored
+    pub physical_address: u64,
+    /// How much of the segment is in the file
+    pub file_size: u64,
+    /// How big the segment is in memory, with anything past the file's part zeroed
+    pub memory_size: u64,
+    /// What the segment's addresses are aligned to
+    pub alignment: u64,
+}
+
+impl ProgramHeader {
+    /// The segment is executable
+    pub const EXECUTABLE: u32 = 1 << 0;
+    /// The segment is writable
+    pub const WRITABLE: u32 = 1 << 1;
+    /// The segment is readable
+    pub const READABLE: u32 = 1 << 2;
+
+    /// Get the segment's type
+    #[must_use]
+    pub fn segment_type(&self) -> SegmentType {
+        SegmentType::from(self.kind)
+    }
+}
+
+/// A validated ELF64 file for this machine
+#[derive(Clone, Copy, Debug)]
+pub struct Elf<'a> {
+    data: &'a [u8],
+    header: FileHeader,
+    file_type: FileType,
+}
+
+impl<'a> Elf<'a> {
+    /// Check that `data` is an ELF64 executable for this machine, with its program headers in bounds
+    ///
+    /// # Errors
+    /// This will return an error describing the first thing about the file that isn't supported
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.len() < MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+            return Err(ElfError::InvalidMagic);
+        }
+        let header: FileHeader = read(data, 0)?;
+        let ident = header.ident;
+        if ident[4] != CLASS_64 {
+            return Err(ElfError::UnsupportedClass);
+        }
+        if ident[5] != DATA_LITTLE_ENDIAN {
+            return Err(ElfError::UnsupportedEncoding);
+        }
+        if ident[6] != VERSION_CURRENT || header.version != u32::from(VERSION_CURRENT) {
+            return Err(ElfError::UnsupportedVersion);
+        }
+        if header.machine != MACHINE_X86_64 {
+            return Err(ElfError::UnsupportedMachine);
+        }
+        let file_type = match header.kind {
+            2 => FileType::Executable,
+            3 => FileType::SharedObject,
+            _ => return Err(ElfError::UnsupportedType),
+        };
+
+        if header.program_header_count == 0 {
+            return Err(ElfError::NoSegments);
+        }
+        if usize::from(header.program_header_size) != size_of::<ProgramHeader>() {
+            return Err(ElfError::Truncated);
+        }
+        let table_size = usize::from(header.program_header_count) * size_of::<ProgramHeader>();
+        usize::try_from(header.program_header_offset)
+            .ok()
+            .and_then(|offset| offset.checked_add(table_size))
+            .filter(|&end| end <= data.len())
+            .ok_or(ElfError::Truncated)?;
+
+        Ok(Self {
+            data,
+            header,
+            file_type,
+        })
+    }
+
+    /// Get the file's header
+    #[must_use]
+    pub const fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// Get what the file is for
+    #[must_use]
+    pub const fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Get the file's program headers
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + '_ {
+        // They were checked to be in bounds when the file was parsed
+        let offset = self.header.program_header_offset as usize;
+        (0..usize::from(self.header.program_header_count)).filter_map(move |index| {
+            read(self.data, offset + index * size_of::<ProgramHeader>()).ok()
+        })
+    }
+
+    /// Get the part of a segment that's in the file
+    ///
+    /// # Errors
+    /// This will return an error if the segment runs past the end of the file
+    pub fn segment_data(&self, segment: &ProgramHeader) -> Result<&'a [u8], ElfError> {
+        let start = usize::try_from(segment.offset).map_err(|_| ElfError::Truncated)?;
+        let size = usize::try_from(segment.file_size).map_err(|_| ElfError::Truncated)?;
+        start
+            .checked_add(size)
+            .and_then(|end| self.data.get(start..end))
+            .ok_or(ElfError::Truncated)
+    }
+}
+
+/// Read a structure out of a file
+fn read<T: Copy>(data: &[u8], offset: usize) -> Result<T, ElfError> {
+    offset
+        .checked_add(size_of::<T>())
+        .filter(|&end| end <= data.len())
+        .ok_or(ElfError::Truncated)?;
+    Ok(unsafe { read_unaligned(data.as_ptr().add(offset).cast()) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file header for an `x86_64` executable, followed by one program header
+    fn executable() -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"\x7FELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+        file.extend_from_slice(&2u16.to_le_bytes()); // type: executable
+        file.extend_from_slice(&MACHINE_X86_64.to_le_bytes());
+        file.extend_from_slice(&1u32.to_le_bytes()); // version
+        file.extend_from_slice(&0x40_1000u64.to_le_bytes()); // entry
+        file.extend_from_slice(&64u64.to_le_bytes()); // program header offset
+        file.extend_from_slice(&0u64.to_le_bytes()); // section header offset
+        file.extend_from_slice(&0u32.to_le_bytes()); // flags
+        for size in [64u16, 56, 1, 0, 0, 0] {
+            file.extend_from_slice(&size.to_le_bytes());
+        }
+        file.extend_from_slice(&1u32.to_le_bytes()); // type: load
+        file.extend_from_slice(&5u32.to_le_bytes()); // flags: readable and executable
+        for value in [0, 0x40_0000, 0x40_0000, 120, 4096, 4096u64] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        file
+    }
+
+    #[test]
+    fn parses_an_executable() {
+        let file = executable();
+        let elf = Elf::parse(&file).unwrap();
+        assert_eq!(elf.file_type(), FileType::Executable);
+        assert_eq!({ elf.header().entry }, 0x40_1000);
+
+        let segments: Vec<ProgramHeader> = elf.program_headers().collect();
+        assert_eq!(segments.len(), 1);
+        let segment = segments[0];
+        assert_eq!(segment.segment_type(), SegmentType::Load);
+        assert_eq!(
+            { segment.flags },
+            ProgramHeader::READABLE | ProgramHeader::EXECUTABLE
+        );
+        // The segment holds the whole file, headers and all
+        assert_eq!(elf.segment_data(&segment).unwrap(), &file[..]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let check = |offset: usize, value: u8, error: ElfError| {
+            let mut file = executable();
+            file[offset] = value;
+            let result = Elf::parse(&file).map(|_| ()).unwrap_err();
+            assert_eq!(
+                core::mem::discriminant(&result),
+                core::mem::discriminant(&error),
+                "{result:?} with {value} at {offset}"
+            );
+        };
+        check(0, 0, ElfError::InvalidMagic);
+        check(4, 1, ElfError::UnsupportedClass);
+        check(5, 2, ElfError::UnsupportedEncoding);
+        check(6, 0, ElfError::UnsupportedVersion);
+        check(16, 1, ElfError::UnsupportedType);
+        check(18, 3, ElfError::UnsupportedMachine);
+        // No program headers, and the wrong size for them
+        check(56, 0, ElfError::NoSegments);
+        check(54, 32, ElfError::Truncated);
+        assert!(matches!(Elf::parse(b"\x7FEL"), Err(ElfError::InvalidMagic)));
+    }
+
+    #[test]
+    fn bounds_checks_the_file() {
+        let file = executable();
+        // The program header table runs past the end
+        assert!(matches!(Elf::parse(&file[..100]), Err(ElfError::Truncated)));
+        let mut header = executable();
+        header[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(Elf::parse(&header), Err(ElfError::Truncated)));
+
+        let elf = Elf::parse(&file).unwrap();
+        let mut segment = elf.program_headers().next().unwrap();
+        segment.file_size = 121;
+        assert!(matches!(
+            elf.segment_data(&segment),
+            Err(ElfError::Truncated)
+        ));
+        segment.offset = u64::MAX;
+        segment.file_size = 2;
+        assert!(matches!(
+            elf.segment_data(&segment),
+            Err(ElfError::Truncated)
+        ));
+    }
+}
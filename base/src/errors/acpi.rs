@@ -0,0 +1,18 @@
+use super::{AmlError, GenericError};
+
+/// Errors that occur when discovering or parsing ACPI tables
+#[derive(Debug, Clone, Copy)]
+pub enum AcpiError {
+    /// The bootloader didn't provide an RSDP
+    RsdpNotPresent,
+    /// A structure's signature didn't match what was expected
+    InvalidSignature,
+    /// A structure's checksum didn't sum to zero
+    InvalidChecksum,
+    /// The requested table wasn't present
+    TableNotFound,
+    /// An error occurred loading or evaluating AML
+    Aml(AmlError),
+    /// A generic error occurred
+    Generic(GenericError),
+}
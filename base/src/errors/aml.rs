@@ -0,0 +1,43 @@
+use super::GenericError;
+
+/// Errors that occur when parsing or evaluating AML
+#[derive(Debug, Clone, Copy)]
+pub enum AmlError {
+    /// The stream ended in the middle of an object
+    UnexpectedEndOfStream,
+    /// An opcode that isn't supported was encountered
+    UnsupportedOpcode(u16),
+    /// A name string or segment was malformed
+    InvalidName,
+    /// A name couldn't be resolved in the namespace
+    ObjectNotFound,
+    /// An object was declared with a name that is already in use
+    ObjectAlreadyExists,
+    /// An object couldn't be converted to the required type
+    IncompatibleType,
+    /// A method was called with more arguments than it takes
+    TooManyArguments,
+    /// An index was past the end of a buffer, string, or package
+    IndexOutOfBounds,
+    /// An integer was divided by zero
+    DivideByZero,
+    /// A `Break` or `Continue` was encountered outside of a `While`
+    InvalidControlFlow,
+    /// A `While` loop ran for too long
+    LoopLimitExceeded,
+    /// Methods or expressions were nested too deeply to fit on the stack
+    RecursionLimitExceeded,
+    /// An operation region is in an address space that isn't supported
+    UnsupportedRegionSpace(u8),
+    /// The AML executed a `Fatal` opcode
+    Fatal {
+        /// The type of fatal error
+        kind: u8,
+        /// The firmware-defined error code
+        code: u32,
+        /// The firmware-defined argument
+        argument: u64,
+    },
+    /// A generic error occurred
+    Generic(GenericError),
+}
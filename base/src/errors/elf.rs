@@ -0,0 +1,33 @@
+use super::MemoryManagerError;
+
+/// Errors from parsing and loading ELF files
+#[derive(Debug, Clone, Copy)]
+pub enum ElfError {
+    /// The file ended before a structure it describes
+    Truncated,
+    /// The file didn't start with the ELF magic number
+    InvalidMagic,
+    /// The file isn't 64-bit
+    UnsupportedClass,
+    /// The file isn't little-endian
+    UnsupportedEncoding,
+    /// The file's ELF version isn't 1
+    UnsupportedVersion,
+    /// The file isn't for this machine
+    UnsupportedMachine,
+    /// The file isn't an executable or a position independent executable
+    UnsupportedType,
+    /// The file needs an interpreter to load it, which isn't supported
+    DynamicallyLinked,
+    /// A segment's sizes or addresses don't make sense, or it isn't in user space
+    InvalidSegment,
+    /// The file has nothing to load
+    NoSegments,
+    /// The file needs a relocation of this type, or a relocation table with this dynamic tag,
+    /// which isn't supported
+    UnsupportedRelocation(u32),
+    /// The arguments and environment don't fit on the stack
+    ArgumentsTooLarge,
+    /// The program couldn't be mapped into its address space
+    Memory(MemoryManagerError),
+}
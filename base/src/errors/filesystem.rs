@@ -0,0 +1,38 @@
+/// Errors from filesystems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystemError {
+    /// Nothing exists at the path
+    NotFound,
+    /// A path went through something that isn't a directory
+    NotADirectory,
+    /// A file operation was tried on a directory
+    IsADirectory,
+    /// Something already exists at the path
+    AlreadyExists,
+    /// The filesystem can't be changed
+    ReadOnly,
+    /// The operation isn't something the filesystem or file can do
+    Unsupported,
+    /// Something wasn't a symbolic link
+    NotASymlink,
+    /// Resolving a path followed too many symbolic links, which probably loop
+    TooManyLinks,
+    /// A file descriptor isn't open, or wasn't opened for what was tried
+    BadDescriptor,
+    /// A process has as many files open as it's allowed
+    TooManyOpenFiles,
+    /// Something is already mounted at the path, or a filesystem is still mounted
+    Busy,
+    /// An argument was out of range, like a seek before the start of a file
+    InvalidArgument,
+    /// A directory couldn't be removed or replaced as something is in it
+    NotEmpty,
+    /// A name can't be moved or linked to another filesystem
+    CrossDevice,
+    /// The filesystem has no room left
+    NoSpace,
+    /// An archive wasn't in any format that's understood
+    UnknownFormat,
+    /// An archive's structure was broken, or ended early
+    Corrupt,
+}
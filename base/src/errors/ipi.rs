@@ -0,0 +1,6 @@
+/// Errors for inter-processor interrupts
+#[derive(Debug, Clone, Copy)]
+pub enum IpiError {
+    /// The target core isn't running, or can't take interrupts yet
+    CoreNotOnline,
+}
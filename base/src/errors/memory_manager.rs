@@ -7,6 +7,8 @@ pub enum MemoryManagerError {
     AddressUnmapped,
     /// The provided address was mapped
     AddressMapped,
+    /// The address was outside the range it had to be in, or wasn't aligned
+    OutOfRange,
     /// Huge pages cannot be mapped into
     CannotMapToHugePage,
     /// Virtual memory space has been exhausted, immediate panic is encouraged
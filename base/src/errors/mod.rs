@@ -1,9 +1,24 @@
+mod acpi;
+pub use acpi::AcpiError;
+
 mod address;
 pub use address::AddressError;
 
+mod aml;
+pub use aml::AmlError;
+
+mod elf;
+pub use elf::ElfError;
+
+mod filesystem;
+pub use filesystem::FileSystemError;
+
 mod generic;
 pub use generic::GenericError;
 
+mod ipi;
+pub use ipi::IpiError;
+
 mod interrupt_manager;
 pub use interrupt_manager::InterruptManagerError;
 
@@ -13,6 +28,12 @@ pub use memory_manager::MemoryManagerError;
 mod power_manager;
 pub use power_manager::PowerManagerError;
 
+mod process;
+pub use process::ProcessError;
+
+mod scheduler;
+pub use scheduler::SchedulerError;
+
 mod timer_manager;
 pub use timer_manager::TimerManagerError;
 
@@ -1,4 +1,4 @@
-use super::GenericError;
+use super::{AcpiError, GenericError};
 
 /// Errors that occur when switching power state
 #[derive(Debug, Clone, Copy)]
@@ -11,4 +11,6 @@ pub enum PowerManagerError {
     InvalidStateSwitch,
     /// A generic error occurred
     Generic(GenericError),
+    /// An error occurred reading the ACPI tables
+    Acpi(AcpiError),
 }
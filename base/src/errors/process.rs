@@ -0,0 +1,17 @@
+use super::{ElfError, MemoryManagerError, SchedulerError};
+
+/// Errors from creating and running processes
+#[derive(Debug, Clone, Copy)]
+pub enum ProcessError {
+    /// The process's address space couldn't be made or changed
+    Memory(MemoryManagerError),
+    /// A thread couldn't be started in the process
+    Scheduler(SchedulerError),
+    /// The program couldn't be loaded
+    Elf(ElfError),
+    /// The process has no children that can be waited for
+    NoChildren,
+    /// The process has other threads. Threads can't be ended on their own,
+    /// only along with their whole process, so replacing its program isn't supported
+    Threaded,
+}
@@ -0,0 +1,8 @@
+/// Errors from the thread scheduler
+#[derive(Debug, Clone, Copy)]
+pub enum SchedulerError {
+    /// An affinity mask didn't include any core the scheduler is running on
+    NoAllowedCores,
+    /// The process a thread was to be started in is exiting
+    ProcessExiting,
+}
@@ -1,4 +1,4 @@
-use super::GenericError;
+use super::{AcpiError, GenericError};
 
 /// Errors for the timer manager
 #[derive(Debug, Clone, Copy)]
@@ -7,6 +7,14 @@ pub enum TimerManagerError {
     TimerAlreadySet,
     /// The specified timer is not present
     TimerNotPresent,
+    /// A periodic timer was given a period of zero
+    InvalidDuration,
+    /// A timer's frequency couldn't be measured
+    CalibrationFailed,
+    /// The timer hardware doesn't support what was asked of it
+    UnsupportedHardware,
     /// A generic error occurred
     Generic(GenericError),
+    /// An error occurred reading the ACPI tables
+    Acpi(AcpiError),
 }
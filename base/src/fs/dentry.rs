@@ -0,0 +1,128 @@
+use crate::{
+    errors::FileSystemError,
+    traits::{DirectoryEntry, FileKind},
+};
+
+use super::{mount, Inode};
+
+/// A name in the VFS tree, which is an inode and the directory it was found in.
+///
+/// The root of a mounted filesystem takes the place of the directory it's mounted on,
+/// so `..` from it leads out of the filesystem.
+/// Dentries aren't cached, so two for the same path can exist at once
+#[derive(Debug)]
+pub struct Dentry {
+    name: String,
+    parent: Option<Arc<Self>>,
+    inode: Inode,
+}
+
+impl Dentry {
+    /// Make the root of the tree
+    pub(super) fn root(inode: Inode) -> Arc<Self> {
+        Arc::new(Self {
+            name: String::new(),
+            parent: None,
+            inode,
+        })
+    }
+
+    /// Get the dentry's name in its directory, which is empty for the root
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the directory the dentry is in, or `None` for the root
+    #[must_use]
+    pub const fn parent(&self) -> Option<&Arc<Self>> {
+        self.parent.as_ref()
+    }
+
+    /// Get the inode the dentry names
+    #[must_use]
+    pub const fn inode(&self) -> &Inode {
+        &self.inode
+    }
+
+    /// Get the dentry's absolute path, without any symbolic links or `..` in it
+    #[must_use]
+    pub fn path(&self) -> String {
+        let mut names = Vec::new();
+        let mut current = self;
+        while let Some(parent) = current.parent.as_ref() {
+            names.push(current.name.as_str());
+            current = parent;
+        }
+
+        if names.is_empty() {
+            return String::from("/");
+        }
+        let mut path = String::new();
+        for name in names.iter().rev() {
+            path.push('/');
+            path.push_str(name);
+        }
+        path
+    }
+
+    /// Get the absolute path a name in this directory would have
+    pub(super) fn child_path(&self, name: &str) -> String {
+        let mut path = self.path();
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        path.push_str(name);
+        path
+    }
+
+    /// Find a name in this directory, crossing into anything mounted there.
+    /// Symbolic links are returned as they are
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there, or this isn't a directory
+    pub fn lookup(self: &Arc<Self>, name: &str) -> Result<Arc<Self>, FileSystemError> {
+        if self.inode.kind()? != FileKind::Directory {
+            return Err(FileSystemError::NotADirectory);
+        }
+        let inode = match mount::mounted_at(&self.child_path(name)) {
+            Some(filesystem) => Inode::root_of(filesystem),
+            None => self.inode.lookup(name)?,
+        };
+        Ok(self.child(name, inode))
+    }
+
+    /// Make a dentry for an inode in this directory
+    pub(super) fn child(self: &Arc<Self>, name: &str, inode: Inode) -> Arc<Self> {
+        Arc::new(Self {
+            name: String::from(name),
+            parent: Some(self.clone()),
+            inode,
+        })
+    }
+
+    /// Get what's in this directory, including anything mounted in it
+    /// that its filesystem doesn't have a directory for
+    ///
+    /// # Errors
+    /// This will return an error if this isn't a directory
+    pub fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+        let mut entries = self.inode.read_dir()?;
+        let path = self.path();
+        for (name, filesystem) in mount::mounted_in(&path) {
+            let root = filesystem.root();
+            match entries.iter_mut().find(|entry| entry.name == name) {
+                Some(entry) => {
+                    entry.inode = root;
+                    entry.kind = FileKind::Directory;
+                }
+                None => entries.push(DirectoryEntry {
+                    name,
+                    inode: root,
+                    kind: FileKind::Directory,
+                }),
+            }
+        }
+        Ok(entries)
+    }
+}
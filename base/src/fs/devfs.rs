@@ -0,0 +1,199 @@
+use log::info;
+
+use crate::{
+    arch::UART,
+    errors::FileSystemError,
+    platform_logger::LOGGER,
+    random, scheduler,
+    traits::{DirectoryEntry, FileKind, FileSystem, InodeId, Metadata},
+};
+
+/// The root directory's inode number, with each device's after it
+const ROOT: InodeId = InodeId(0);
+
+/// A device in `/dev`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Device {
+    /// The first serial port
+    Serial,
+    /// Reads nothing, and throws away what's written
+    Null,
+    /// Reads zeroes, and throws away what's written
+    Zero,
+    /// Reads the kernel log, and logs what's written
+    Kmsg,
+    /// Reads pseudo-random bytes, and throws away what's written
+    Random,
+}
+
+/// Every device, in inode order, with its name and permission bits
+const DEVICES: [(Device, &str, u32); 5] = [
+    (Device::Serial, "ttyS0", 0o620),
+    (Device::Null, "null", 0o666),
+    (Device::Zero, "zero", 0o666),
+    (Device::Kmsg, "kmsg", 0o644),
+    (Device::Random, "random", 0o666),
+];
+
+/// The device filesystem, usually mounted at `/dev`
+#[derive(Debug, Default)]
+pub struct DevFs;
+
+impl DevFs {
+    /// Make the device filesystem
+    #[must_use]
+    pub const fn new() -> Self {
+        Self
+    }
+
+    /// Get the device an inode is and its permission bits, or `None` for the root
+    fn device(inode: InodeId) -> Result<Option<(Device, u32)>, FileSystemError> {
+        if inode == ROOT {
+            return Ok(None);
+        }
+        usize::try_from(inode.0 - 1)
+            .ok()
+            .and_then(|index| DEVICES.get(index))
+            .map(|&(device, _, mode)| Some((device, mode)))
+            .ok_or(FileSystemError::NotFound)
+    }
+}
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT
+    }
+
+    fn lookup(&self, directory: InodeId, name: &str) -> Result<InodeId, FileSystemError> {
+        if Self::device(directory)?.is_some() {
+            return Err(FileSystemError::NotADirectory);
+        }
+        DEVICES
+            .iter()
+            .position(|&(_, other, _)| other == name)
+            .map(|index| InodeId(index as u64 + 1))
+            .ok_or(FileSystemError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FileSystemError> {
+        let (kind, mode, size) = match Self::device(inode)? {
+            None => (FileKind::Directory, 0o755, 0),
+            Some((device, mode)) => {
+                // The log is the only device with a size, so readers can tell how much there is
+                let size = if device == Device::Kmsg {
+                    LOGGER.logged()
+                } else {
+                    0
+                };
+                (FileKind::CharDevice, mode, size)
+            }
+        };
+        Ok(Metadata {
+            inode,
+            kind,
+            mode,
+            size,
+            links: 1,
+        })
+    }
+
+    fn read(
+        &self,
+        inode: InodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, FileSystemError> {
+        let (device, _) = Self::device(inode)?.ok_or(FileSystemError::IsADirectory)?;
+        Ok(match device {
+            Device::Serial => read_serial(buffer),
+            Device::Null => 0,
+            Device::Zero => {
+                buffer.fill(0);
+                buffer.len()
+            }
+            Device::Kmsg => LOGGER.read_log(offset, buffer),
+            Device::Random => {
+                random::fill(buffer);
+                buffer.len()
+            }
+        })
+    }
+
+    fn write(&self, inode: InodeId, _offset: u64, buffer: &[u8]) -> Result<usize, FileSystemError> {
+        let (device, _) = Self::device(inode)?.ok_or(FileSystemError::IsADirectory)?;
+        match device {
+            // Through the logger, so what's written never lands in the middle of a log line
+            Device::Serial => LOGGER.write_bytes(buffer),
+            Device::Kmsg => {
+                let text = String::from_utf8_lossy(buffer);
+                info!(target: "kmsg", "{}", text.trim_end());
+            }
+            Device::Null | Device::Zero | Device::Random => {}
+        }
+        Ok(buffer.len())
+    }
+
+    fn truncate(&self, inode: InodeId, _size: u64) -> Result<(), FileSystemError> {
+        // Opening a device to truncate it does nothing, as it does elsewhere
+        match Self::device(inode)? {
+            Some(_) => Ok(()),
+            None => Err(FileSystemError::IsADirectory),
+        }
+    }
+
+    fn read_dir(&self, directory: InodeId) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+        if Self::device(directory)?.is_some() {
+            return Err(FileSystemError::NotADirectory);
+        }
+        Ok(DEVICES
+            .iter()
+            .enumerate()
+            .map(|(index, &(_, name, _))| DirectoryEntry {
+                name: String::from(name),
+                inode: InodeId(index as u64 + 1),
+                kind: FileKind::CharDevice,
+            })
+            .collect())
+    }
+
+    fn read_link(&self, inode: InodeId) -> Result<String, FileSystemError> {
+        Self::device(inode)?;
+        Err(FileSystemError::NotASymlink)
+    }
+}
+
+/// Read a line from the serial port, echoing it back, stopping early if the buffer fills.
+/// The thread yields while there's nothing to read, as the port's interrupts aren't used.
+/// A carriage return ends the line as a newline does, as that's what terminals send for enter
+fn read_serial(buffer: &mut [u8]) -> usize {
+    let mut length = 0;
+    while length < buffer.len() {
+        // It isn't locked while waiting, so writers aren't held up by a reader
+        let mut uart = UART.lock_irqsave();
+        let byte = match uart.try_read_byte() {
+            Some(b'\r') => b'\n',
+            Some(byte) => byte,
+            None => {
+                drop(uart);
+                scheduler::yield_now();
+                continue;
+            }
+        };
+        if byte == b'\n' {
+            uart.write_byte(b'\r');
+        }
+        uart.write_byte(byte);
+        drop(uart);
+
+        buffer[length] = byte;
+        length += 1;
+        if byte == b'\n' {
+            break;
+        }
+    }
+    length
+}
@@ -0,0 +1,164 @@
+use crate::{
+    errors::FileSystemError,
+    macros::bitflags::bitflags,
+    sync::SleepMutex,
+    traits::{DirectoryEntry, FileKind, Metadata},
+};
+
+use super::Dentry;
+
+bitflags! {
+    /// How a file is opened
+    #[derive(Debug)]
+    pub struct OpenFlags: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        /// Every write goes to the end of the file
+        const APPEND = 1 << 2;
+        /// Make the file if it doesn't exist
+        const CREATE = 1 << 3;
+        /// With `CREATE`, fail if the file already exists
+        const EXCLUSIVE = 1 << 4;
+        /// Empty the file, if it's opened for writing
+        const TRUNCATE = 1 << 5;
+        /// Fail unless it's a directory
+        const DIRECTORY = 1 << 6;
+        /// Don't follow a symbolic link at the end of the path
+        const NO_FOLLOW = 1 << 7;
+    }
+}
+
+/// Where a seek is from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// From the start of the file
+    Start(u64),
+    /// From the current offset
+    Current(i64),
+    /// From the end of the file
+    End(i64),
+}
+
+/// A file that's been opened, which file descriptors refer to.
+/// Its offset is shared by every descriptor for it
+#[derive(Debug)]
+pub struct OpenFile {
+    dentry: Arc<Dentry>,
+    flags: OpenFlags,
+    /// The byte offset, or for directories, the index of the next entry to read.
+    /// It's held while reading or writing, which can block
+    offset: SleepMutex<u64>,
+}
+
+impl OpenFile {
+    /// Open a dentry that's been resolved, checking the flags make sense for it
+    ///
+    /// # Errors
+    /// This will return an error if a directory is opened for writing,
+    /// or something else is opened with [`OpenFlags::DIRECTORY`]
+    pub(super) fn new(dentry: Arc<Dentry>, flags: OpenFlags) -> Result<Self, FileSystemError> {
+        let kind = dentry.inode().kind()?;
+        if kind == FileKind::Directory && flags.contains(OpenFlags::WRITE) {
+            return Err(FileSystemError::IsADirectory);
+        }
+        if kind != FileKind::Directory && flags.contains(OpenFlags::DIRECTORY) {
+            return Err(FileSystemError::NotADirectory);
+        }
+        if flags.contains(OpenFlags::WRITE | OpenFlags::TRUNCATE) && kind == FileKind::File {
+            dentry.inode().truncate(0)?;
+        }
+        Ok(Self {
+            dentry,
+            flags,
+            offset: SleepMutex::new(0),
+        })
+    }
+
+    /// Get the dentry the file was opened through
+    pub const fn dentry(&self) -> &Arc<Dentry> {
+        &self.dentry
+    }
+
+    /// Get how the file was opened
+    pub const fn flags(&self) -> OpenFlags {
+        self.flags
+    }
+
+    /// Read from the current offset, moving it past what was read
+    ///
+    /// # Errors
+    /// This will return an error if the file wasn't opened for reading, or can't be read
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize, FileSystemError> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(FileSystemError::BadDescriptor);
+        }
+        let mut offset = self.offset.lock();
+        let length = self.dentry.inode().read(*offset, buffer)?;
+        *offset += length as u64;
+        Ok(length)
+    }
+
+    /// Write at the current offset, or the end of the file if it was opened to append,
+    /// moving the offset past what was written
+    ///
+    /// # Errors
+    /// This will return an error if the file wasn't opened for writing, or can't be written
+    pub fn write(&self, buffer: &[u8]) -> Result<usize, FileSystemError> {
+        if !self.flags.contains(OpenFlags::WRITE) {
+            return Err(FileSystemError::BadDescriptor);
+        }
+        let mut offset = self.offset.lock();
+        if self.flags.contains(OpenFlags::APPEND) {
+            *offset = self.dentry.inode().metadata()?.size;
+        }
+        let length = self.dentry.inode().write(*offset, buffer)?;
+        *offset += length as u64;
+        Ok(length)
+    }
+
+    /// Move the offset, and get where it ended up. It can go past the end, but not before the start
+    ///
+    /// # Errors
+    /// This will return an error if it would move before the start of the file
+    pub fn seek(&self, from: SeekFrom) -> Result<u64, FileSystemError> {
+        let mut offset = self.offset.lock();
+        let (base, change) = match from {
+            SeekFrom::Start(position) => (position, 0),
+            SeekFrom::Current(change) => (*offset, change),
+            SeekFrom::End(change) => (self.dentry.inode().metadata()?.size, change),
+        };
+        let moved = if change < 0 {
+            base.checked_sub(change.unsigned_abs())
+        } else {
+            base.checked_add(change.unsigned_abs())
+        };
+        *offset = moved.ok_or(FileSystemError::InvalidArgument)?;
+        Ok(*offset)
+    }
+
+    /// Get what's known about the file
+    ///
+    /// # Errors
+    /// This will return an error if the file no longer exists
+    pub fn metadata(&self) -> Result<Metadata, FileSystemError> {
+        self.dentry.inode().metadata()
+    }
+
+    /// Read up to `count` entries from the current position in a directory,
+    /// moving past them. An empty list means the end was reached
+    ///
+    /// # Errors
+    /// This will return an error if the file isn't a directory
+    pub fn read_dir(&self, count: usize) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+        let mut offset = self.offset.lock();
+        let entries: Vec<DirectoryEntry> = self
+            .dentry
+            .read_dir()?
+            .into_iter()
+            .skip(usize::try_from(*offset).unwrap_or(usize::MAX))
+            .take(count)
+            .collect();
+        *offset += entries.len() as u64;
+        Ok(entries)
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    errors::FileSystemError,
+    sync::Mutex,
+    traits::{DirectoryEntry, FileKind, Metadata},
+};
+
+use super::{mount, Dentry, OpenFile, OpenFlags, SeekFrom};
+
+/// How many files a process can have open at once
+pub const MAX_OPEN_FILES: usize = 1024;
+
+/// A file descriptor, which is an index into a process's file table
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fd(pub usize);
+
+/// A process's open files, and the directory its relative paths start from
+#[derive(Debug)]
+pub struct FileTable {
+    files: Mutex<Vec<Option<Arc<OpenFile>>>>,
+    /// `None` until it's changed, meaning the root
+    working_directory: Mutex<Option<Arc<Dentry>>>,
+}
+
+impl FileTable {
+    /// Make an empty file table, working in the root
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            files: Mutex::new(Vec::new()),
+            working_directory: Mutex::new(None),
+        }
+    }
+
+    /// Make a copy of the table, whose descriptors refer to the same open files,
+    /// so their offsets are shared, and which works in the same directory
+    #[must_use]
+    pub fn duplicate(&self) -> Self {
+        Self {
+            files: Mutex::new(self.files.lock().clone()),
+            working_directory: Mutex::new(self.working_directory.lock().clone()),
+        }
+    }
+
+    /// Get the directory relative paths start from
+    pub fn working_directory(&self) -> Arc<Dentry> {
+        let working_directory = self.working_directory.lock().clone();
+        working_directory.unwrap_or_else(mount::root)
+    }
+
+    /// Change the directory relative paths start from
+    ///
+    /// # Errors
+    /// This will return an error if the path can't be resolved, or isn't a directory
+    pub fn change_directory(&self, path: &str) -> Result<(), FileSystemError> {
+        let directory = super::resolve(Some(&self.working_directory()), path, true)?;
+        if directory.inode().kind()? != FileKind::Directory {
+            return Err(FileSystemError::NotADirectory);
+        }
+        *self.working_directory.lock() = Some(directory);
+        Ok(())
+    }
+
+    /// Open a file, relative to the working directory, and get its descriptor,
+    /// which is the lowest one that's free
+    ///
+    /// # Errors
+    /// This will return an error if the file can't be opened, or too many are open
+    pub fn open(&self, path: &str, flags: OpenFlags, mode: u32) -> Result<Fd, FileSystemError> {
+        let file = super::open(Some(&self.working_directory()), path, flags, mode)?;
+        self.insert(Arc::new(file))
+    }
+
+    /// Open a file for reading and writing as standard input, output and error,
+    /// which are descriptors 0, 1 and 2 if the table is empty
+    ///
+    /// # Errors
+    /// This will return an error if the file can't be opened, or too many are open
+    pub fn open_standard_streams(&self, path: &str) -> Result<(), FileSystemError> {
+        let file = Arc::new(super::open(
+            None,
+            path,
+            OpenFlags::READ | OpenFlags::WRITE,
+            0,
+        )?);
+        for _ in 0..3 {
+            self.insert(file.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Add a file that's already open, and get its descriptor
+    ///
+    /// # Errors
+    /// This will return an error if too many files are open
+    pub fn insert(&self, file: Arc<OpenFile>) -> Result<Fd, FileSystemError> {
+        let mut files = self.files.lock();
+        if let Some(index) = files.iter().position(Option::is_none) {
+            files[index] = Some(file);
+            return Ok(Fd(index));
+        }
+        if files.len() >= MAX_OPEN_FILES {
+            return Err(FileSystemError::TooManyOpenFiles);
+        }
+        files.push(Some(file));
+        Ok(Fd(files.len() - 1))
+    }
+
+    /// Get the file a descriptor refers to
+    ///
+    /// # Errors
+    /// This will return an error if the descriptor isn't open
+    pub fn get(&self, fd: Fd) -> Result<Arc<OpenFile>, FileSystemError> {
+        self.files
+            .lock()
+            .get(fd.0)
+            .cloned()
+            .flatten()
+            .ok_or(FileSystemError::BadDescriptor)
+    }
+
+    /// Close a descriptor. The file stays open while other descriptors refer to it
+    ///
+    /// # Errors
+    /// This will return an error if the descriptor isn't open
+    pub fn close(&self, fd: Fd) -> Result<(), FileSystemError> {
+        let file = self
+            .files
+            .lock()
+            .get_mut(fd.0)
+            .and_then(Option::take)
+            .ok_or(FileSystemError::BadDescriptor)?;
+        // It's dropped here, outside the lock
+        drop(file);
+        Ok(())
+    }
+
+    /// Read from a file at its offset
+    ///
+    /// # Errors
+    /// This will return an error if the descriptor isn't open for reading, or the read fails
+    pub fn read(&self, fd: Fd, buffer: &mut [u8]) -> Result<usize, FileSystemError> {
+        self.get(fd)?.read(buffer)
+    }
+
+    /// Write to a file at its offset
+    ///
+    /// # Errors
+    /// This will return an error if the descriptor isn't open for writing, or the write fails
+    pub fn write(&self, fd: Fd, buffer: &[u8]) -> Result<usize, FileSystemError> {
+        self.get(fd)?.write(buffer)
+    }
+
+    /// Move a file's offset
+    ///
+    /// # Errors
+    /// This will return an error if the descriptor isn't open,
+    /// or it would move before the start of the file
+    pub fn seek(&self, fd: Fd, from: SeekFrom) -> Result<u64, FileSystemError> {
+        self.get(fd)?.seek(from)
+    }
+
+    /// Get what's known about an open file
+    ///
+    /// # Errors
+    /// This will return an error if the descriptor isn't open
+    pub fn stat(&self, fd: Fd) -> Result<Metadata, FileSystemError> {
+        self.get(fd)?.metadata()
+    }
+
+    /// Read up to `count` entries from an open directory
+    ///
+    /// # Errors
+    /// This will return an error if the descriptor isn't open, or isn't a directory
+    pub fn read_dir(&self, fd: Fd, count: usize) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+        self.get(fd)?.read_dir(count)
+    }
+}
+
+impl Default for FileTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
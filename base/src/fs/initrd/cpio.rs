@@ -0,0 +1,199 @@
+use core::str;
+
+use crate::{errors::FileSystemError, memory::utilities::align};
+
+use super::{Entry, EntryKind};
+
+/// The magic numbers of the newc format, without and with checksums
+const MAGICS: [&[u8]; 2] = [b"070701", b"070702"];
+
+/// The size of a header, which is the magic number and thirteen 8-digit hex fields
+const HEADER_SIZE: usize = 110;
+
+/// The name of the entry that ends the archive
+const TRAILER: &str = "TRAILER!!!";
+
+/// The file type bits of a mode, and the types a ramdisk can hold
+const S_IFMT: u32 = 0o170_000;
+const S_IFREG: u32 = 0o100_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFLNK: u32 = 0o120_000;
+
+/// Check whether an archive looks like a newc cpio archive
+pub(super) fn is_cpio(archive: &[u8]) -> bool {
+    archive
+        .get(..6)
+        .map_or(false, |magic| MAGICS.contains(&magic))
+}
+
+/// Read every entry out of a newc cpio archive
+pub(super) fn parse(archive: &'static [u8]) -> Result<Vec<Entry>, FileSystemError> {
+    let mut entries: Vec<Entry> = Vec::new();
+    // Hard links share an inode number, and only the last of them has the data
+    let mut links: Vec<(u32, usize)> = Vec::new();
+
+    let mut offset = 0;
+    loop {
+        let header = archive
+            .get(offset..offset + HEADER_SIZE)
+            .ok_or(FileSystemError::Corrupt)?;
+        if !MAGICS.contains(&&header[..6]) {
+            return Err(FileSystemError::Corrupt);
+        }
+        let field = |index: usize| hex(&header[6 + index * 8..6 + (index + 1) * 8]);
+        let inode = field(0)?;
+        let mode = field(1)?;
+        let link_count = field(4)?;
+        let size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        // The name and the data are each padded to four bytes, counting from the header
+        let name_start = offset + HEADER_SIZE;
+        let name = name_start
+            .checked_add(name_size)
+            .and_then(|end| archive.get(name_start..end))
+            .and_then(|name| name.strip_suffix(&[0]))
+            .and_then(|name| str::from_utf8(name).ok())
+            .ok_or(FileSystemError::Corrupt)?;
+        let data_start = align(name_start + name_size, 4);
+        let data = data_start
+            .checked_add(size)
+            .and_then(|end| archive.get(data_start..end))
+            .ok_or(FileSystemError::Corrupt)?;
+        offset = align(data_start + size, 4);
+
+        if name == TRAILER {
+            return Ok(entries);
+        }
+        let kind = match mode & S_IFMT {
+            S_IFREG => EntryKind::File,
+            S_IFDIR => EntryKind::Directory,
+            S_IFLNK => EntryKind::Symlink,
+            // Devices and FIFOs have nothing to put in a ramdisk
+            _ => continue,
+        };
+        if mode & S_IFMT == S_IFREG && link_count > 1 {
+            if size == 0 {
+                links.push((inode, entries.len()));
+            } else {
+                for &(_, index) in links.iter().filter(|(other, _)| *other == inode) {
+                    entries[index].data = data;
+                }
+            }
+        }
+        entries.push(Entry {
+            path: String::from(name),
+            kind,
+            mode: mode & 0o7777,
+            data,
+        });
+    }
+}
+
+/// Parse one of the header's hex fields
+fn hex(field: &[u8]) -> Result<u32, FileSystemError> {
+    str::from_utf8(field)
+        .ok()
+        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+        .ok_or(FileSystemError::Corrupt)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Add an entry to a newc cpio archive
+    fn entry(archive: &mut Vec<u8>, inode: u32, name: &str, mode: u32, links: u32, data: &[u8]) {
+        let fields = [
+            inode,
+            mode,
+            0,
+            0,
+            links,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            name.len() as u32 + 1,
+            0,
+        ];
+        archive.extend_from_slice(MAGICS[0]);
+        for field in fields {
+            for shift in (0..8).rev() {
+                archive.push(b"0123456789ABCDEF"[(field >> (shift * 4)) as usize & 0xF]);
+            }
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(align(archive.len(), 4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(align(archive.len(), 4), 0);
+    }
+
+    /// Make a newc cpio archive of `(path, mode, contents)` entries, each with its own inode
+    #[must_use]
+    pub fn newc(entries: &[(&str, u32, &[u8])]) -> &'static [u8] {
+        let mut archive = Vec::new();
+        for (index, &(name, mode, data)) in entries.iter().enumerate() {
+            entry(&mut archive, index as u32 + 1, name, mode, 1, data);
+        }
+        entry(&mut archive, 0, TRAILER, 0, 1, &[]);
+        Box::leak(archive.into_boxed_slice())
+    }
+
+    #[test]
+    fn reads_entries() {
+        let archive = newc(&[
+            ("etc", S_IFDIR | 0o755, b""),
+            ("etc/motd", S_IFREG | 0o4644, b"hello"),
+            ("motd", S_IFLNK | 0o777, b"etc/motd"),
+            // A character device, which is skipped
+            ("dev/null", 0o020_666, b""),
+        ]);
+        assert!(is_cpio(archive));
+        let entries = parse(archive).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(entries[0].kind, EntryKind::Directory));
+        assert_eq!(entries[0].path, "etc");
+        assert!(matches!(entries[1].kind, EntryKind::File));
+        assert_eq!((entries[1].mode, entries[1].data), (0o4644, &b"hello"[..]));
+        assert!(matches!(entries[2].kind, EntryKind::Symlink));
+        assert_eq!(entries[2].data, b"etc/motd");
+    }
+
+    #[test]
+    fn hard_links_share_the_last_ones_data() {
+        let mut archive = Vec::new();
+        entry(&mut archive, 7, "a", S_IFREG | 0o644, 2, b"");
+        entry(&mut archive, 7, "b", S_IFREG | 0o644, 2, b"shared");
+        entry(&mut archive, 0, TRAILER, 0, 1, &[]);
+        let entries = parse(Box::leak(archive.into_boxed_slice())).unwrap();
+        assert_eq!(entries[0].data, b"shared");
+        assert_eq!(entries[1].data, b"shared");
+    }
+
+    #[test]
+    fn rejects_corrupt_archives() {
+        let archive = newc(&[("file", S_IFREG | 0o644, b"contents")]);
+        assert!(!is_cpio(b"07070"));
+        // Without its trailer, or cut off in the middle of an entry
+        let end = archive.len() - 120;
+        assert_eq!(
+            parse(&archive[..end]).map(|_| ()),
+            Err(FileSystemError::Corrupt)
+        );
+        assert_eq!(
+            parse(&archive[..HEADER_SIZE + 2]).map(|_| ()),
+            Err(FileSystemError::Corrupt)
+        );
+
+        let mut bad = archive.to_vec();
+        bad[6] = b'G';
+        assert_eq!(
+            parse(Box::leak(bad.into_boxed_slice())).map(|_| ()),
+            Err(FileSystemError::Corrupt)
+        );
+    }
+}
@@ -0,0 +1,450 @@
+use core::str;
+
+use log::{info, warn};
+
+use crate::{
+    errors::FileSystemError,
+    modules,
+    traits::{DirectoryEntry, FileKind, FileSystem, InodeId, Metadata},
+};
+
+mod cpio;
+mod tar;
+
+/// The root directory's inode number
+const ROOT: InodeId = InodeId(0);
+
+/// A file, directory or symbolic link in a ramdisk
+#[derive(Debug)]
+pub struct Node {
+    name: String,
+    kind: FileKind,
+    mode: u32,
+    data: &'static [u8],
+    parent: InodeId,
+    children: Vec<InodeId>,
+}
+
+impl Node {
+    /// Get the node's name in its directory, which is empty for the root
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get what the node is
+    #[must_use]
+    pub const fn kind(&self) -> FileKind {
+        self.kind
+    }
+
+    /// Get the node's permission bits
+    #[must_use]
+    pub const fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// Get the node's contents, or a symbolic link's target
+    #[must_use]
+    pub const fn data(&self) -> &'static [u8] {
+        self.data
+    }
+
+    /// Get the directory the node is in, which is itself for the root
+    #[must_use]
+    pub const fn parent(&self) -> InodeId {
+        self.parent
+    }
+
+    /// Get what's in the node, if it's a directory
+    #[must_use]
+    pub fn children(&self) -> &[InodeId] {
+        &self.children
+    }
+}
+
+/// What an entry in an archive is
+enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// Another name for the file at this path, which came earlier in the archive
+    HardLink(String),
+}
+
+/// An entry read out of an archive
+struct Entry {
+    path: String,
+    kind: EntryKind,
+    mode: u32,
+    data: &'static [u8],
+}
+
+/// A read-only filesystem unpacked from a USTAR or newc cpio archive.
+/// Files' contents are left where they are in the archive, and inode numbers are indices of nodes
+#[derive(Debug)]
+pub struct Initrd {
+    nodes: Vec<Node>,
+}
+
+impl Initrd {
+    /// Make a ramdisk with nothing but an empty root directory
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            nodes: Vec::from([Node {
+                name: String::new(),
+                kind: FileKind::Directory,
+                mode: 0o755,
+                data: &[],
+                parent: ROOT,
+                children: Vec::new(),
+            }]),
+        }
+    }
+
+    /// Unpack an archive, working out which format it's in
+    ///
+    /// # Errors
+    /// This will return an error if it isn't a USTAR or newc cpio archive, or it's corrupt
+    pub fn parse(archive: &'static [u8]) -> Result<Self, FileSystemError> {
+        let entries = if cpio::is_cpio(archive) {
+            cpio::parse(archive)?
+        } else if tar::is_tar(archive) {
+            tar::parse(archive)?
+        } else {
+            return Err(FileSystemError::UnknownFormat);
+        };
+
+        let mut initrd = Self::empty();
+        for entry in entries {
+            initrd.insert(&entry)?;
+        }
+        Ok(initrd)
+    }
+
+    /// Find the node at a path, which is taken from the root whether or not it starts with `/`.
+    /// `.` and `..` are followed, but symbolic links aren't
+    ///
+    /// # Errors
+    /// This will return an error if nothing is at the path,
+    /// or it goes through something that isn't a directory
+    pub fn find(&self, path: &str) -> Result<InodeId, FileSystemError> {
+        let mut current = ROOT;
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => current = self.nodes[index(current)].parent,
+                name => current = self.lookup(current, name)?,
+            }
+        }
+        Ok(current)
+    }
+
+    /// Get a node
+    #[must_use]
+    pub fn node(&self, id: InodeId) -> Option<&Node> {
+        self.nodes.get(index(id))
+    }
+
+    /// Get the number of nodes, including the root
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Check whether there's nothing but the root
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    /// Get a node, or an error if it doesn't exist
+    fn get(&self, id: InodeId) -> Result<&Node, FileSystemError> {
+        self.node(id).ok_or(FileSystemError::NotFound)
+    }
+
+    /// Find a node in a directory by name
+    fn child(&self, directory: InodeId, name: &str) -> Option<InodeId> {
+        self.nodes[index(directory)]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.nodes[index(child)].name == name)
+    }
+
+    /// Add an entry from an archive, making any directories it's in that weren't listed.
+    /// An entry for something that's already there replaces it
+    fn insert(&mut self, entry: &Entry) -> Result<(), FileSystemError> {
+        let mut components = entry
+            .path
+            .split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+            .peekable();
+
+        let mut directory = ROOT;
+        while let Some(name) = components.next() {
+            if name == ".." {
+                return Err(FileSystemError::Corrupt);
+            }
+            let existing = self.child(directory, name);
+            if components.peek().is_some() {
+                directory = match existing {
+                    Some(id) if self.nodes[index(id)].kind == FileKind::Directory => id,
+                    Some(_) => return Err(FileSystemError::NotADirectory),
+                    None => self.add(directory, name, FileKind::Directory, 0o755, &[]),
+                };
+                continue;
+            }
+
+            let (kind, data) = match entry.kind {
+                EntryKind::File => (FileKind::File, entry.data),
+                EntryKind::Directory => (FileKind::Directory, &[][..]),
+                EntryKind::Symlink => (FileKind::Symlink, entry.data),
+                EntryKind::HardLink(ref target) => {
+                    let target = &self.nodes[index(self.find(target)?)];
+                    (target.kind, target.data)
+                }
+            };
+            match existing {
+                Some(id) => {
+                    let node = &mut self.nodes[index(id)];
+                    // A directory listed after what's in it keeps its contents
+                    if node.kind == FileKind::Directory && kind != FileKind::Directory {
+                        return Err(FileSystemError::IsADirectory);
+                    }
+                    node.kind = kind;
+                    node.mode = entry.mode;
+                    node.data = data;
+                }
+                None => {
+                    self.add(directory, name, kind, entry.mode, data);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a node to a directory
+    fn add(
+        &mut self,
+        directory: InodeId,
+        name: &str,
+        kind: FileKind,
+        mode: u32,
+        data: &'static [u8],
+    ) -> InodeId {
+        let id = InodeId(self.nodes.len() as u64);
+        self.nodes.push(Node {
+            name: String::from(name),
+            kind,
+            mode,
+            data,
+            parent: directory,
+            children: Vec::new(),
+        });
+        self.nodes[index(directory)].children.push(id);
+        id
+    }
+}
+
+impl FileSystem for Initrd {
+    fn name(&self) -> &'static str {
+        "initrd"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT
+    }
+
+    fn lookup(&self, directory: InodeId, name: &str) -> Result<InodeId, FileSystemError> {
+        if self.get(directory)?.kind != FileKind::Directory {
+            return Err(FileSystemError::NotADirectory);
+        }
+        self.child(directory, name).ok_or(FileSystemError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FileSystemError> {
+        let node = self.get(inode)?;
+        Ok(Metadata {
+            inode,
+            kind: node.kind,
+            mode: node.mode,
+            size: node.data.len() as u64,
+            links: 1,
+        })
+    }
+
+    fn read(
+        &self,
+        inode: InodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, FileSystemError> {
+        let node = self.get(inode)?;
+        if node.kind == FileKind::Directory {
+            return Err(FileSystemError::IsADirectory);
+        }
+        let data = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| node.data.get(offset..))
+            .unwrap_or(&[]);
+        let length = data.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&data[..length]);
+        Ok(length)
+    }
+
+    fn read_dir(&self, directory: InodeId) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+        let node = self.get(directory)?;
+        if node.kind != FileKind::Directory {
+            return Err(FileSystemError::NotADirectory);
+        }
+        Ok(node
+            .children
+            .iter()
+            .map(|&child| {
+                let child_node = &self.nodes[index(child)];
+                DirectoryEntry {
+                    name: child_node.name.clone(),
+                    inode: child,
+                    kind: child_node.kind,
+                }
+            })
+            .collect())
+    }
+
+    fn read_link(&self, inode: InodeId) -> Result<String, FileSystemError> {
+        let node = self.get(inode)?;
+        if node.kind != FileKind::Symlink {
+            return Err(FileSystemError::NotASymlink);
+        }
+        str::from_utf8(node.data)
+            .map(String::from)
+            .map_err(|_| FileSystemError::Corrupt)
+    }
+}
+
+/// Get the index of a node from its inode number
+#[allow(clippy::cast_possible_truncation)]
+const fn index(id: InodeId) -> usize {
+    id.0 as usize
+}
+
+/// Unpack the first boot module that's an archive, preferring ones with `initrd`
+/// on their command line, as the ramdisk
+#[must_use]
+pub fn load() -> Option<Initrd> {
+    let mut modules = modules::list();
+    modules.sort_by_key(|module| {
+        !module
+            .cmdline
+            .split_whitespace()
+            .any(|word| word == "initrd")
+    });
+
+    for module in modules {
+        match Initrd::parse(module.data) {
+            Ok(initrd) => {
+                info!(
+                    "Loaded the initrd from {} with {} nodes",
+                    module.path,
+                    initrd.len()
+                );
+                return Some(initrd);
+            }
+            Err(FileSystemError::UnknownFormat) => {}
+            Err(e) => warn!("Couldn't unpack {} as an initrd: {e:?}", module.path),
+        }
+    }
+    info!("No initrd was loaded");
+    None
+}
+
+/// Tests, and the archives other filesystems' tests mount
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub use super::cpio::tests::newc;
+
+    const DIRECTORY: u32 = 0o040_755;
+    const FILE: u32 = 0o100_644;
+    const SYMLINK: u32 = 0o120_777;
+
+    #[test]
+    fn builds_a_tree() {
+        // `bin` isn't listed, and `etc` is listed after what's in it
+        let initrd = Initrd::parse(newc(&[
+            ("bin/sh", FILE, b"#!"),
+            ("./etc/motd", FILE, b"Hello from the initrd\n"),
+            ("etc", DIRECTORY, b""),
+            ("motd", SYMLINK, b"etc/motd"),
+        ]))
+        .unwrap();
+        assert_eq!(initrd.len(), 6);
+
+        let motd = initrd.find("/etc/../etc/./motd").unwrap();
+        assert_eq!(initrd.node(motd).unwrap().name(), "motd");
+        let mut buffer = [0; 64];
+        let length = initrd.read(motd, 6, &mut buffer).unwrap();
+        assert_eq!(&buffer[..length], b"from the initrd\n");
+        assert_eq!(initrd.read(motd, 100, &mut buffer), Ok(0));
+        assert_eq!(initrd.metadata(motd).unwrap().size, 22);
+
+        let etc = initrd.find("etc").unwrap();
+        assert_eq!(
+            initrd.read_dir(etc).unwrap(),
+            [DirectoryEntry {
+                name: String::from("motd"),
+                inode: motd,
+                kind: FileKind::File,
+            }]
+        );
+        assert_eq!(initrd.metadata(etc).unwrap().mode, 0o755);
+        let bin = initrd.find("bin").unwrap();
+        assert_eq!(initrd.node(bin).unwrap().kind(), FileKind::Directory);
+        assert_eq!(initrd.node(bin).unwrap().parent(), ROOT);
+
+        let link = initrd.find("motd").unwrap();
+        assert_eq!(initrd.read_link(link).unwrap(), "etc/motd");
+        assert_eq!(initrd.read_link(motd), Err(FileSystemError::NotASymlink));
+    }
+
+    #[test]
+    fn lookups_fail_cleanly() {
+        let initrd = Initrd::parse(newc(&[("file", FILE, b"")])).unwrap();
+        let file = initrd.find("file").unwrap();
+        assert_eq!(initrd.find("missing"), Err(FileSystemError::NotFound));
+        assert!(initrd.find("file/x").is_err());
+        assert_eq!(
+            initrd.lookup(file, "x"),
+            Err(FileSystemError::NotADirectory)
+        );
+        assert_eq!(
+            initrd.read(ROOT, 0, &mut [0; 4]),
+            Err(FileSystemError::IsADirectory)
+        );
+        assert_eq!(initrd.write(file, 0, b"x"), Err(FileSystemError::ReadOnly));
+    }
+
+    #[test]
+    fn rejects_bad_archives() {
+        assert_eq!(
+            Initrd::parse(b"not an archive").map(|_| ()),
+            Err(FileSystemError::UnknownFormat)
+        );
+        // Nothing may escape the root
+        assert_eq!(
+            Initrd::parse(newc(&[("../file", FILE, b"")])).map(|_| ()),
+            Err(FileSystemError::Corrupt)
+        );
+        assert_eq!(
+            Initrd::parse(newc(&[("d", DIRECTORY, b""), ("d", FILE, b"")])).map(|_| ()),
+            Err(FileSystemError::IsADirectory)
+        );
+        assert_eq!(
+            Initrd::parse(newc(&[("f", FILE, b""), ("f/g", FILE, b"")])).map(|_| ()),
+            Err(FileSystemError::NotADirectory)
+        );
+    }
+}
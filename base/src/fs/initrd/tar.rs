@@ -0,0 +1,277 @@
+use core::str;
+
+use crate::{errors::FileSystemError, memory::utilities::align};
+
+use super::{Entry, EntryKind};
+
+/// The size of a header, and what everything in the archive is padded to
+const BLOCK_SIZE: usize = 512;
+
+/// Where the magic number is in a header
+const MAGIC_OFFSET: usize = 257;
+
+/// What the magic number starts with, for both POSIX and GNU archives
+const MAGIC: &[u8] = b"ustar";
+
+/// Check whether an archive looks like a USTAR archive
+pub(super) fn is_tar(archive: &[u8]) -> bool {
+    archive.get(MAGIC_OFFSET..MAGIC_OFFSET + MAGIC.len()) == Some(MAGIC)
+}
+
+/// Read every entry out of a USTAR archive, including GNU long names and PAX paths
+pub(super) fn parse(archive: &'static [u8]) -> Result<Vec<Entry>, FileSystemError> {
+    let mut entries = Vec::new();
+    // Long names come in an entry of their own, before the one they're for
+    let mut long_path: Option<String> = None;
+    let mut long_link: Option<String> = None;
+
+    let mut offset = 0;
+    while let Some(header) = archive.get(offset..offset + BLOCK_SIZE) {
+        // It ends with two zeroed blocks, but one is enough to stop at
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        if header[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC.len()] != *MAGIC {
+            return Err(FileSystemError::Corrupt);
+        }
+        verify_checksum(header)?;
+
+        let size = octal(&header[124..136])?;
+        let data_start = offset + BLOCK_SIZE;
+        let data = data_start
+            .checked_add(size)
+            .and_then(|end| archive.get(data_start..end))
+            .ok_or(FileSystemError::Corrupt)?;
+        offset = data_start + align(size, BLOCK_SIZE);
+
+        let kind = match header[156] {
+            b'0' | 0 | b'7' => EntryKind::File,
+            b'5' => EntryKind::Directory,
+            b'2' => EntryKind::Symlink,
+            b'1' => EntryKind::HardLink(String::new()),
+            b'L' => {
+                long_path = Some(string(data)?);
+                continue;
+            }
+            b'K' => {
+                long_link = Some(string(data)?);
+                continue;
+            }
+            b'x' => {
+                for (key, value) in pax_records(data)? {
+                    match key {
+                        "path" => long_path = Some(String::from(value)),
+                        "linkpath" => long_link = Some(String::from(value)),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            // Devices, FIFOs and global headers have nothing to put in a ramdisk
+            _ => {
+                long_path = None;
+                long_link = None;
+                continue;
+            }
+        };
+
+        let path = if let Some(path) = long_path.take() {
+            path
+        } else {
+            let name = string(&header[..100])?;
+            let prefix = string(&header[345..500])?;
+            if prefix.is_empty() {
+                name
+            } else {
+                prefix + "/" + &name
+            }
+        };
+        let link = match long_link.take() {
+            Some(link) => link,
+            None => string(&header[157..257])?,
+        };
+
+        let (kind, data) = match kind {
+            // The ramdisk is never freed, so the target can live as long as it does
+            EntryKind::Symlink => (
+                EntryKind::Symlink,
+                Box::leak(link.into_boxed_str()).as_bytes(),
+            ),
+            EntryKind::HardLink(_) => (EntryKind::HardLink(link), &[][..]),
+            kind => (kind, data),
+        };
+        entries.push(Entry {
+            path,
+            kind,
+            mode: u32::try_from(octal(&header[100..108])?).map_err(|_| FileSystemError::Corrupt)?
+                & 0o7777,
+            data,
+        });
+    }
+    Ok(entries)
+}
+
+/// Check a header's checksum, which is the sum of its bytes with the checksum field as spaces
+fn verify_checksum(header: &[u8]) -> Result<(), FileSystemError> {
+    let expected = octal(&header[148..156])?;
+    let sum = header
+        .iter()
+        .enumerate()
+        .map(|(index, &byte)| {
+            if (148..156).contains(&index) {
+                usize::from(b' ')
+            } else {
+                usize::from(byte)
+            }
+        })
+        .sum::<usize>();
+    if sum == expected {
+        Ok(())
+    } else {
+        Err(FileSystemError::Corrupt)
+    }
+}
+
+/// Parse an octal number, which is padded with spaces or NULs
+fn octal(field: &[u8]) -> Result<usize, FileSystemError> {
+    let digits = str::from_utf8(field)
+        .map_err(|_| FileSystemError::Corrupt)?
+        .trim_matches(|c| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(digits, 8).map_err(|_| FileSystemError::Corrupt)
+}
+
+/// Read a NUL-terminated string out of a field
+fn string(field: &[u8]) -> Result<String, FileSystemError> {
+    let length = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    str::from_utf8(&field[..length])
+        .map(String::from)
+        .map_err(|_| FileSystemError::Corrupt)
+}
+
+/// Read the `length key=value\n` records of a PAX extended header
+fn pax_records(data: &[u8]) -> Result<Vec<(&str, &str)>, FileSystemError> {
+    let mut records = Vec::new();
+    let mut rest = str::from_utf8(data).map_err(|_| FileSystemError::Corrupt)?;
+    while !rest.is_empty() {
+        let (length, _) = rest.split_once(' ').ok_or(FileSystemError::Corrupt)?;
+        let length: usize = length.parse().map_err(|_| FileSystemError::Corrupt)?;
+        let record = rest.get(..length).ok_or(FileSystemError::Corrupt)?;
+        rest = &rest[length..];
+
+        let (_, record) = record.split_once(' ').ok_or(FileSystemError::Corrupt)?;
+        let (key, value) = record
+            .strip_suffix('\n')
+            .and_then(|record| record.split_once('='))
+            .ok_or(FileSystemError::Corrupt)?;
+        records.push((key, value));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Make a USTAR header block, with its checksum, followed by `data` padded to a block
+    fn block(name: &str, kind: u8, link: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format_octal(data.len(), 11).as_bytes());
+        header[156] = kind;
+        header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        header[MAGIC_OFFSET..MAGIC_OFFSET + 6].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[148..156].fill(b' ');
+        let sum = header.iter().map(|&byte| usize::from(byte)).sum::<usize>();
+        header[148..154].copy_from_slice(format_octal(sum, 6).as_bytes());
+        header[154] = 0;
+
+        let mut out = header.to_vec();
+        out.extend_from_slice(data);
+        out.resize(align(out.len(), BLOCK_SIZE), 0);
+        out
+    }
+
+    /// Write a number as octal, padded with zeroes to `width` digits
+    fn format_octal(mut value: usize, width: usize) -> String {
+        let mut digits = Vec::with_capacity(width);
+        for _ in 0..width {
+            digits.push(b'0' + (value % 8) as u8);
+            value /= 8;
+        }
+        digits.reverse();
+        String::from_utf8(digits).unwrap()
+    }
+
+    /// Make an archive out of blocks, ended with two zeroed ones
+    fn archive(blocks: &[Vec<u8>]) -> &'static [u8] {
+        let mut archive = blocks.concat();
+        archive.resize(archive.len() + 2 * BLOCK_SIZE, 0);
+        Box::leak(archive.into_boxed_slice())
+    }
+
+    #[test]
+    fn reads_entries() {
+        let archive = archive(&[
+            block("etc/", b'5', "", b""),
+            block("etc/motd", b'0', "", b"hello"),
+            block("motd", b'2', "etc/motd", b""),
+            block("copy", b'1', "etc/motd", b""),
+            // A FIFO, which is skipped
+            block("pipe", b'6', "", b""),
+        ]);
+        assert!(is_tar(archive));
+        let entries = parse(archive).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(matches!(entries[0].kind, EntryKind::Directory));
+        assert_eq!(
+            (entries[1].path.as_str(), entries[1].data),
+            ("etc/motd", &b"hello"[..])
+        );
+        assert_eq!(entries[1].mode, 0o644);
+        assert!(matches!(entries[2].kind, EntryKind::Symlink));
+        assert_eq!(entries[2].data, b"etc/motd");
+        assert!(matches!(&entries[3].kind, EntryKind::HardLink(target) if target == "etc/motd"));
+    }
+
+    #[test]
+    fn reads_long_names() {
+        let long = "x".repeat(150);
+        let pax_path = "a/very/long/pax/path";
+        // Each record's length counts itself
+        let record = ["29 path=", pax_path, "\n"].concat();
+        let archive = archive(&[
+            block("././@LongLink", b'L', "", long.as_bytes()),
+            block("short", b'0', "", b"1"),
+            block("PaxHeaders/file", b'x', "", record.as_bytes()),
+            block("file", b'0', "", b"2"),
+        ]);
+        let entries = parse(archive).unwrap();
+        assert_eq!(entries[0].path, long);
+        assert_eq!(entries[1].path, pax_path);
+    }
+
+    #[test]
+    fn rejects_corrupt_archives() {
+        let mut bad = block("file", b'0', "", b"data");
+        bad[0] = b'g';
+        assert_eq!(
+            parse(archive(&[bad])).map(|_| ()),
+            Err(FileSystemError::Corrupt)
+        );
+
+        // The data runs past the end
+        let whole = block("file", b'0', "", &[1; 1000]);
+        let cut: &'static [u8] = Box::leak(whole[..BLOCK_SIZE + 10].to_vec().into_boxed_slice());
+        assert_eq!(parse(cut).map(|_| ()), Err(FileSystemError::Corrupt));
+
+        assert!(!is_tar(&[0; 300]));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{
+    errors::FileSystemError,
+    traits::{DirectoryEntry, FileKind, FileSystem, InodeId, Metadata},
+};
+
+/// A file, directory or symbolic link in a mounted filesystem.
+/// This keeps the filesystem alive, even if it's unmounted
+#[derive(Clone)]
+pub struct Inode {
+    filesystem: Arc<dyn FileSystem>,
+    id: InodeId,
+}
+
+impl Inode {
+    /// Refer to an inode in a filesystem
+    #[must_use]
+    pub fn new(filesystem: Arc<dyn FileSystem>, id: InodeId) -> Self {
+        Self { filesystem, id }
+    }
+
+    /// Refer to a filesystem's root directory
+    #[must_use]
+    pub fn root_of(filesystem: Arc<dyn FileSystem>) -> Self {
+        let id = filesystem.root();
+        Self { filesystem, id }
+    }
+
+    /// Get the inode's number in its filesystem
+    #[must_use]
+    pub const fn id(&self) -> InodeId {
+        self.id
+    }
+
+    /// Get the filesystem the inode is in
+    #[must_use]
+    pub const fn filesystem(&self) -> &Arc<dyn FileSystem> {
+        &self.filesystem
+    }
+
+    /// Check whether two handles refer to the same inode
+    #[must_use]
+    pub fn same_as(&self, other: &Self) -> bool {
+        self.id == other.id && self.same_filesystem(other)
+    }
+
+    /// Check whether two handles are in the same filesystem.
+    /// Only the data pointers are compared, as one type can have several vtables
+    fn same_filesystem(&self, other: &Self) -> bool {
+        core::ptr::eq(
+            Arc::as_ptr(&self.filesystem).cast::<()>(),
+            Arc::as_ptr(&other.filesystem).cast::<()>(),
+        )
+    }
+
+    /// Get what's known about the inode
+    ///
+    /// # Errors
+    /// This will return an error if the inode no longer exists
+    pub fn metadata(&self) -> Result<Metadata, FileSystemError> {
+        self.filesystem.metadata(self.id)
+    }
+
+    /// Get what the inode is
+    ///
+    /// # Errors
+    /// This will return an error if the inode no longer exists
+    pub fn kind(&self) -> Result<FileKind, FileSystemError> {
+        self.metadata().map(|metadata| metadata.kind)
+    }
+
+    /// Find a name in the inode, which must be a directory
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there, or this isn't a directory
+    pub fn lookup(&self, name: &str) -> Result<Self, FileSystemError> {
+        let id = self.filesystem.lookup(self.id, name)?;
+        Ok(Self::new(self.filesystem.clone(), id))
+    }
+
+    /// Read the inode's contents from `offset`
+    ///
+    /// # Errors
+    /// This will return an error if the inode can't be read
+    pub fn read(&self, offset: u64, buffer: &mut [u8]) -> Result<usize, FileSystemError> {
+        self.filesystem.read(self.id, offset, buffer)
+    }
+
+    /// Write to the inode's contents at `offset`
+    ///
+    /// # Errors
+    /// This will return an error if the inode can't be written
+    pub fn write(&self, offset: u64, buffer: &[u8]) -> Result<usize, FileSystemError> {
+        self.filesystem.write(self.id, offset, buffer)
+    }
+
+    /// Set the size of the inode's contents
+    ///
+    /// # Errors
+    /// This will return an error if the inode can't be written
+    pub fn truncate(&self, size: u64) -> Result<(), FileSystemError> {
+        self.filesystem.truncate(self.id, size)
+    }
+
+    /// Get what's in the inode, which must be a directory
+    ///
+    /// # Errors
+    /// This will return an error if this isn't a directory
+    pub fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+        self.filesystem.read_dir(self.id)
+    }
+
+    /// Get the inode's target, if it's a symbolic link
+    ///
+    /// # Errors
+    /// This will return an error if this isn't a symbolic link
+    pub fn read_link(&self) -> Result<String, FileSystemError> {
+        self.filesystem.read_link(self.id)
+    }
+
+    /// Make an empty file or directory in the inode, which must be a directory
+    ///
+    /// # Errors
+    /// This will return an error if the name is taken, or the filesystem is read-only
+    pub fn create(&self, name: &str, kind: FileKind, mode: u32) -> Result<Self, FileSystemError> {
+        let id = self.filesystem.create(self.id, name, kind, mode)?;
+        Ok(Self::new(self.filesystem.clone(), id))
+    }
+
+    /// Give the inode another name in `directory`, which must be in the same filesystem
+    ///
+    /// # Errors
+    /// This will return an error if the name is taken, this is a directory,
+    /// or the filesystems differ or are read-only
+    pub fn link(&self, directory: &Self, name: &str) -> Result<(), FileSystemError> {
+        if !self.same_filesystem(directory) {
+            return Err(FileSystemError::CrossDevice);
+        }
+        self.filesystem.link(self.id, directory.id, name)
+    }
+
+    /// Remove a name that isn't a directory from the inode, which must be a directory
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there or is a directory,
+    /// or the filesystem is read-only
+    pub fn unlink(&self, name: &str) -> Result<(), FileSystemError> {
+        self.filesystem.unlink(self.id, name)
+    }
+
+    /// Remove an empty directory from the inode, which must be a directory
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there, isn't an empty directory,
+    /// or the filesystem is read-only
+    pub fn remove_directory(&self, name: &str) -> Result<(), FileSystemError> {
+        self.filesystem.remove_directory(self.id, name)
+    }
+
+    /// Move a name in the inode, which must be a directory, to a name in `to_directory`,
+    /// which must be in the same filesystem
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there, can't replace what's at the new name,
+    /// or the filesystems differ or are read-only
+    pub fn rename(
+        &self,
+        from_name: &str,
+        to_directory: &Self,
+        to_name: &str,
+    ) -> Result<(), FileSystemError> {
+        if !self.same_filesystem(to_directory) {
+            return Err(FileSystemError::CrossDevice);
+        }
+        self.filesystem
+            .rename(self.id, from_name, to_directory.id, to_name)
+    }
+}
+
+impl core::fmt::Debug for Inode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Inode")
+            .field("filesystem", &self.filesystem.name())
+            .field("id", &self.id)
+            .finish()
+    }
+}
@@ -0,0 +1,192 @@
+use log::warn;
+
+use crate::{
+    errors::FileSystemError,
+    memory::MEGABYTE,
+    traits::{DirectoryEntry, FileKind, Metadata},
+};
+
+/// The initial ramdisk, unpacked from a boot module
+pub mod initrd;
+
+/// Devices, like the serial port
+pub mod devfs;
+
+/// The kernel's state, as files
+pub mod procfs;
+
+/// Writable files, kept in memory
+pub mod tmpfs;
+
+mod dentry;
+pub use dentry::Dentry;
+
+mod file;
+pub use file::{OpenFile, OpenFlags, SeekFrom};
+
+mod file_table;
+pub use file_table::{Fd, FileTable, MAX_OPEN_FILES};
+
+mod inode;
+pub use inode::Inode;
+
+/// The mount table
+pub mod mount;
+
+mod path;
+pub use path::{resolve, resolve_parent};
+
+/// The most the files in `/tmp` can hold between them
+const TMP_SIZE: usize = 16 * MEGABYTE;
+
+/// Mount everything the kernel starts with. This must be called once, before any paths are resolved.
+///
+/// The initrd goes at the root, or an empty one if no module was an archive,
+/// then the devices at `/dev`, the kernel's state at `/proc`, and scratch space at `/tmp`
+///
+/// # Panics
+/// This will panic if something is already mounted at the root
+pub fn init() {
+    let root = initrd::load().unwrap_or_else(initrd::Initrd::empty);
+    mount::mount("/", Arc::new(root)).expect("Something is already mounted at the root");
+    if let Err(e) = mount::mount("/dev", Arc::new(devfs::DevFs::new())) {
+        warn!("Couldn't mount the devices at /dev: {e:?}");
+    }
+    if let Err(e) = mount::mount("/proc", Arc::new(procfs::ProcFs::new())) {
+        warn!("Couldn't mount the kernel's state at /proc: {e:?}");
+    }
+    if let Err(e) = mount::mount("/tmp", Arc::new(tmpfs::TmpFs::new(TMP_SIZE))) {
+        warn!("Couldn't mount scratch space at /tmp: {e:?}");
+    }
+}
+
+/// Open a file, with relative paths starting from `start`, or the root if there isn't one.
+/// With [`OpenFlags::CREATE`], a file that doesn't exist is made with the permission bits in `mode`
+///
+/// # Errors
+/// This will return an error if the path can't be resolved,
+/// the file can't be made, or the flags don't make sense for it
+pub fn open(
+    start: Option<&Arc<Dentry>>,
+    path: &str,
+    flags: OpenFlags,
+    mode: u32,
+) -> Result<OpenFile, FileSystemError> {
+    let follow = !flags.contains(OpenFlags::NO_FOLLOW);
+    let dentry = match resolve(start, path, follow) {
+        Ok(_) if flags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) => {
+            return Err(FileSystemError::AlreadyExists)
+        }
+        Ok(dentry) => dentry,
+        Err(FileSystemError::NotFound) if flags.contains(OpenFlags::CREATE) => {
+            let (directory, name) = resolve_parent(start, path)?;
+            let inode = directory
+                .inode()
+                .create(name, FileKind::File, mode & 0o7777)?;
+            directory.child(name, inode)
+        }
+        Err(e) => return Err(e),
+    };
+    OpenFile::new(dentry, flags)
+}
+
+/// Get what's known about whatever's at a path, following a symbolic link at the end of it
+///
+/// # Errors
+/// This will return an error if the path can't be resolved
+pub fn stat(start: Option<&Arc<Dentry>>, path: &str) -> Result<Metadata, FileSystemError> {
+    resolve(start, path, true)?.inode().metadata()
+}
+
+/// Get what's in the directory at a path
+///
+/// # Errors
+/// This will return an error if the path can't be resolved, or isn't a directory
+pub fn read_dir(
+    start: Option<&Arc<Dentry>>,
+    path: &str,
+) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+    resolve(start, path, true)?.read_dir()
+}
+
+/// Make an empty directory at a path, with the permission bits in `mode`
+///
+/// # Errors
+/// This will return an error if the directory it's in can't be resolved,
+/// the name is taken, or the filesystem is read-only
+pub fn make_directory(
+    start: Option<&Arc<Dentry>>,
+    path: &str,
+    mode: u32,
+) -> Result<(), FileSystemError> {
+    let (directory, name) = resolve_parent(start, path)?;
+    directory
+        .inode()
+        .create(name, FileKind::Directory, mode & 0o7777)
+        .map(drop)
+}
+
+/// Give what's at `existing` another name at `new`. A symbolic link at the end of `existing`
+/// is linked itself, rather than what it points to
+///
+/// # Errors
+/// This will return an error if either path can't be resolved, `new` is taken,
+/// `existing` is a directory, or they're in different filesystems
+pub fn link(start: Option<&Arc<Dentry>>, existing: &str, new: &str) -> Result<(), FileSystemError> {
+    let existing = resolve(start, existing, false)?;
+    let (directory, name) = resolve_parent(start, new)?;
+    existing.inode().link(directory.inode(), name)
+}
+
+/// Remove the name at a path, which mustn't be a directory
+///
+/// # Errors
+/// This will return an error if the path can't be resolved, is a directory or has something mounted on it,
+/// or the filesystem is read-only
+pub fn unlink(start: Option<&Arc<Dentry>>, path: &str) -> Result<(), FileSystemError> {
+    let (directory, name) = resolve_parent(start, path)?;
+    check_not_mounted(&directory, name)?;
+    directory.inode().unlink(name)
+}
+
+/// Remove the empty directory at a path
+///
+/// # Errors
+/// This will return an error if the path can't be resolved, isn't an empty directory,
+/// has something mounted on it, or the filesystem is read-only
+pub fn remove_directory(start: Option<&Arc<Dentry>>, path: &str) -> Result<(), FileSystemError> {
+    let (directory, name) = resolve_parent(start, path)?;
+    check_not_mounted(&directory, name)?;
+    directory.inode().remove_directory(name)
+}
+
+/// Move whatever's at `from` to `to`, replacing what was there
+///
+/// # Errors
+/// This will return an error if either path can't be resolved, something is mounted on either,
+/// they're in different filesystems, or what's at `from` can't replace what's at `to`
+pub fn rename(start: Option<&Arc<Dentry>>, from: &str, to: &str) -> Result<(), FileSystemError> {
+    let (from_directory, from_name) = resolve_parent(start, from)?;
+    let (to_directory, to_name) = resolve_parent(start, to)?;
+    check_not_mounted(&from_directory, from_name)?;
+    check_not_mounted(&to_directory, to_name)?;
+    from_directory
+        .inode()
+        .rename(from_name, to_directory.inode(), to_name)
+}
+
+/// Check nothing is mounted on a name in a directory, so it can be removed or replaced
+fn check_not_mounted(directory: &Dentry, name: &str) -> Result<(), FileSystemError> {
+    match mount::mounted_at(&directory.child_path(name)) {
+        Some(_) => Err(FileSystemError::Busy),
+        None => Ok(()),
+    }
+}
+
+/// Get the target of the symbolic link at a path
+///
+/// # Errors
+/// This will return an error if the path can't be resolved, or isn't a symbolic link
+pub fn read_link(start: Option<&Arc<Dentry>>, path: &str) -> Result<String, FileSystemError> {
+    resolve(start, path, false)?.inode().read_link()
+}
@@ -0,0 +1,133 @@
+use log::debug;
+
+use crate::{
+    errors::FileSystemError,
+    sync::RwLock,
+    traits::{FileKind, FileSystem},
+};
+
+use super::{path, Dentry, Inode};
+
+/// Everything that's mounted, by the absolute path it's mounted at
+static MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+
+/// A filesystem mounted in the VFS tree
+#[derive(Clone)]
+pub struct Mount {
+    path: String,
+    filesystem: Arc<dyn FileSystem>,
+}
+
+impl Mount {
+    /// Get the absolute path the filesystem is mounted at
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the filesystem
+    #[must_use]
+    pub const fn filesystem(&self) -> &Arc<dyn FileSystem> {
+        &self.filesystem
+    }
+}
+
+impl core::fmt::Debug for Mount {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Mount")
+            .field("path", &self.path)
+            .field("filesystem", &self.filesystem.name())
+            .finish()
+    }
+}
+
+/// Mount a filesystem at a path. The root must be mounted first.
+///
+/// Anything else is mounted on a directory, or a name that doesn't exist in a directory,
+/// which lets read-only filesystems be mounted on without having a directory for it
+///
+/// # Errors
+/// This will return an error if something is already mounted there,
+/// the directory it's in doesn't exist, or the path names something other than a directory
+pub fn mount(path: &str, filesystem: Arc<dyn FileSystem>) -> Result<(), FileSystemError> {
+    let path = if path.split('/').all(str::is_empty) {
+        String::from("/")
+    } else {
+        let (directory, name) = path::resolve_parent(None, path)?;
+        match directory.lookup(name) {
+            Ok(existing) if existing.inode().kind()? != FileKind::Directory => {
+                return Err(FileSystemError::NotADirectory)
+            }
+            Ok(_) | Err(FileSystemError::NotFound) => directory.child_path(name),
+            Err(e) => return Err(e),
+        }
+    };
+
+    let mut mounts = MOUNTS.write();
+    if mounts.iter().any(|mount| mount.path == path) {
+        return Err(FileSystemError::Busy);
+    }
+    debug!("Mounted {} at {path}", filesystem.name());
+    mounts.push(Mount { path, filesystem });
+    Ok(())
+}
+
+/// Unmount whatever is mounted at an absolute path, and get it back.
+/// Files that are open in it keep it alive until they're closed
+///
+/// # Errors
+/// This will return an error if nothing is mounted there,
+/// or something else is mounted inside it
+pub fn unmount(path: &str) -> Result<Arc<dyn FileSystem>, FileSystemError> {
+    let mut mounts = MOUNTS.write();
+    let index = mounts
+        .iter()
+        .position(|mount| mount.path == path)
+        .ok_or(FileSystemError::NotFound)?;
+    let inside = |other: &Mount| {
+        other.path != path && (path == "/" || other.path.starts_with(&(String::from(path) + "/")))
+    };
+    if mounts.iter().any(inside) {
+        return Err(FileSystemError::Busy);
+    }
+    Ok(mounts.remove(index).filesystem)
+}
+
+/// Get everything that's mounted, in the order it was mounted
+#[must_use]
+pub fn list() -> Vec<Mount> {
+    MOUNTS.read().clone()
+}
+
+/// Get the root of the VFS tree
+///
+/// # Panics
+/// This will panic if nothing is mounted at the root, which [`super::init`] does
+#[must_use]
+pub fn root() -> Arc<Dentry> {
+    let filesystem = mounted_at("/").expect("Nothing is mounted at the root");
+    Dentry::root(Inode::root_of(filesystem))
+}
+
+/// Get what's mounted at an absolute path
+pub(super) fn mounted_at(path: &str) -> Option<Arc<dyn FileSystem>> {
+    MOUNTS
+        .read()
+        .iter()
+        .find(|mount| mount.path == path)
+        .map(|mount| mount.filesystem.clone())
+}
+
+/// Get the names and filesystems mounted directly in the directory at an absolute path
+pub(super) fn mounted_in(directory: &str) -> Vec<(String, Arc<dyn FileSystem>)> {
+    MOUNTS
+        .read()
+        .iter()
+        .filter_map(|mount| {
+            let (parent, name) = mount.path.rsplit_once('/')?;
+            let parent = if parent.is_empty() { "/" } else { parent };
+            (parent == directory && !name.is_empty())
+                .then(|| (String::from(name), mount.filesystem.clone()))
+        })
+        .collect()
+}
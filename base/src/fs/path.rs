@@ -0,0 +1,184 @@
+use crate::{errors::FileSystemError, traits::FileKind};
+
+use super::{mount, Dentry};
+
+/// How many symbolic links resolving one path can follow before it's taken to be a loop
+const MAX_LINKS: usize = 40;
+
+/// Resolve a path to a dentry, following `..` and symbolic links.
+///
+/// Relative paths start from `start`, or the root if there isn't one.
+/// A symbolic link at the end of the path is only followed if `follow` is set
+///
+/// # Errors
+/// This will return an error if the path is empty, something in it doesn't exist
+/// or isn't a directory, or it follows too many symbolic links
+pub fn resolve(
+    start: Option<&Arc<Dentry>>,
+    path: &str,
+    follow: bool,
+) -> Result<Arc<Dentry>, FileSystemError> {
+    if path.is_empty() {
+        return Err(FileSystemError::NotFound);
+    }
+    let mut current = starting_point(start, path);
+    // What's left of the path, last component first, which symbolic links are spliced into
+    let mut pending: Vec<String> = path.rsplit('/').map(String::from).collect();
+    let mut links = 0;
+
+    while let Some(component) = pending.pop() {
+        match component.as_str() {
+            "" | "." => {}
+            ".." => {
+                if let Some(parent) = current.parent() {
+                    current = parent.clone();
+                }
+            }
+            name => {
+                let child = current.lookup(name)?;
+                let last = pending.iter().all(String::is_empty);
+                // A trailing slash means the link's target is wanted
+                let trailing_slash = !pending.is_empty();
+                if child.inode().kind()? != FileKind::Symlink
+                    || (last && !follow && !trailing_slash)
+                {
+                    current = child;
+                    continue;
+                }
+
+                links += 1;
+                if links > MAX_LINKS {
+                    return Err(FileSystemError::TooManyLinks);
+                }
+                let target = child.inode().read_link()?;
+                if target.starts_with('/') {
+                    current = mount::root();
+                }
+                pending.extend(target.rsplit('/').map(String::from));
+            }
+        }
+    }
+    Ok(current)
+}
+
+/// Resolve everything but the last component of a path, for making or mounting something there.
+/// Get the directory and the last component, which is never `.` or `..`
+///
+/// # Errors
+/// This will return an error if the directory can't be resolved, or it isn't a directory,
+/// or the path ends in `.` or `..` or names the root
+pub fn resolve_parent<'a>(
+    start: Option<&Arc<Dentry>>,
+    path: &'a str,
+) -> Result<(Arc<Dentry>, &'a str), FileSystemError> {
+    let trimmed = path.trim_end_matches('/');
+    let (directory, name) = match trimmed.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((directory, name)) => (directory, name),
+        None => (".", trimmed),
+    };
+    if matches!(name, "" | "." | "..") {
+        return Err(FileSystemError::InvalidArgument);
+    }
+
+    let directory = if path.starts_with('/') || directory != "." {
+        resolve(start, directory, true)?
+    } else {
+        starting_point(start, path)
+    };
+    if directory.inode().kind()? != FileKind::Directory {
+        return Err(FileSystemError::NotADirectory);
+    }
+    Ok((directory, name))
+}
+
+/// Get where resolving a path starts
+fn starting_point(start: Option<&Arc<Dentry>>, path: &str) -> Arc<Dentry> {
+    match start {
+        Some(start) if !path.starts_with('/') => start.clone(),
+        _ => mount::root(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::initrd::{tests::newc, Initrd};
+
+    /// Mount the same tree at the root for every test, whichever gets there first
+    fn mount_root() {
+        let archive = newc(&[
+            ("etc", 0o040_755, b""),
+            ("etc/motd", 0o100_644, b"hello"),
+            ("relative", 0o120_777, b"etc/motd"),
+            ("absolute", 0o120_777, b"/etc/../etc/motd"),
+            ("directory", 0o120_777, b"etc"),
+            ("loop", 0o120_777, b"loop"),
+            ("dangling", 0o120_777, b"missing"),
+        ]);
+        let _ = mount::mount("/", Arc::new(Initrd::parse(archive).unwrap()));
+    }
+
+    fn path(path: &str, follow: bool) -> Result<String, FileSystemError> {
+        resolve(None, path, follow).map(|dentry| dentry.path())
+    }
+
+    #[test]
+    fn resolves_dots_and_links() {
+        mount_root();
+        assert_eq!(path("/", true).unwrap(), "/");
+        assert_eq!(path("/etc/./motd", true).unwrap(), "/etc/motd");
+        // `..` at the root stays there
+        assert_eq!(path("/../../etc//motd", true).unwrap(), "/etc/motd");
+        assert_eq!(path("/relative", true).unwrap(), "/etc/motd");
+        assert_eq!(path("/absolute", true).unwrap(), "/etc/motd");
+        assert_eq!(path("/directory/motd", false).unwrap(), "/etc/motd");
+
+        // The last link is only followed if asked, or if there's a trailing slash
+        assert_eq!(path("/relative", false).unwrap(), "/relative");
+        assert_eq!(path("/directory/", false).unwrap(), "/etc");
+
+        let etc = resolve(None, "/etc", true).unwrap();
+        assert_eq!(
+            resolve(Some(&etc), "../relative", true).unwrap().path(),
+            "/etc/motd"
+        );
+        assert_eq!(resolve(Some(&etc), "/etc", true).unwrap().path(), "/etc");
+    }
+
+    #[test]
+    fn reports_what_went_wrong() {
+        mount_root();
+        assert_eq!(path("", true), Err(FileSystemError::NotFound));
+        assert_eq!(path("/missing", true), Err(FileSystemError::NotFound));
+        assert_eq!(path("/dangling", true), Err(FileSystemError::NotFound));
+        assert_eq!(path("/loop", true), Err(FileSystemError::TooManyLinks));
+        assert_eq!(
+            path("/etc/motd/more", true),
+            Err(FileSystemError::NotADirectory)
+        );
+    }
+
+    #[test]
+    fn splits_off_the_last_component() {
+        mount_root();
+        let (directory, name) = resolve_parent(None, "/etc/new/").unwrap();
+        assert_eq!((directory.path().as_str(), name), ("/etc", "new"));
+        let (directory, name) = resolve_parent(None, "/top").unwrap();
+        assert_eq!((directory.path().as_str(), name), ("/", "top"));
+        let etc = resolve(None, "/etc", true).unwrap();
+        let (directory, name) = resolve_parent(Some(&etc), "motd").unwrap();
+        assert_eq!((directory.path().as_str(), name), ("/etc", "motd"));
+
+        for path in ["/", "/etc/..", "/etc/."] {
+            assert_eq!(
+                resolve_parent(None, path).map(|_| ()),
+                Err(FileSystemError::InvalidArgument)
+            );
+        }
+        assert_eq!(
+            resolve_parent(None, "/etc/motd/x").map(|_| ()),
+            Err(FileSystemError::NotADirectory)
+        );
+    }
+}
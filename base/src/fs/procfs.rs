@@ -0,0 +1,515 @@
+use core::fmt::Write;
+
+use crate::{
+    arch::{acpi::Acpi, PLATFORM_MANAGER},
+    errors::FileSystemError,
+    interrupts,
+    memory::allocators::PageAllocator,
+    process::{self, ExitStatus, Pid, Process},
+    scheduler,
+    traits::{DirectoryEntry, FileKind, FileSystem, InodeId, Metadata, Platform, TimerManager},
+};
+
+use super::mount;
+
+/// Where the kind of node is kept in an inode number, above whatever identifies it
+const TAG_SHIFT: u32 = 56;
+/// Where a process's file is kept in its inode number, above the process ID
+const FILE_SHIFT: u32 = 48;
+/// The bits of an inode number that hold a process ID or table index
+const PAYLOAD_MASK: u64 = (1 << FILE_SHIFT) - 1;
+
+/// A file in the root of the filesystem
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KernelFile {
+    /// Physical memory, in pages
+    Meminfo,
+    /// The kernel heap
+    Heap,
+    /// Each core, and how busy its scheduler is
+    Cpus,
+    /// The memory map the bootloader handed over
+    Memmap,
+    /// How many times each interrupt vector has been raised
+    Interrupts,
+    /// Everything that's mounted
+    Mounts,
+    /// Every process, and its threads
+    Processes,
+    /// How long it's been since the timers started
+    Uptime,
+}
+
+/// Every file in the root, in inode order, with its name
+const KERNEL_FILES: [(KernelFile, &str); 8] = [
+    (KernelFile::Meminfo, "meminfo"),
+    (KernelFile::Heap, "heap"),
+    (KernelFile::Cpus, "cpus"),
+    (KernelFile::Memmap, "memmap"),
+    (KernelFile::Interrupts, "interrupts"),
+    (KernelFile::Mounts, "mounts"),
+    (KernelFile::Processes, "processes"),
+    (KernelFile::Uptime, "uptime"),
+];
+
+/// A file in a process's directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ProcessFile {
+    /// Who it is, who it runs as, and whether it's exited
+    Status,
+    /// The IDs of its threads
+    Threads,
+}
+
+/// Every file in a process's directory, in inode order, with its name
+const PROCESS_FILES: [(ProcessFile, &str); 2] = [
+    (ProcessFile::Status, "status"),
+    (ProcessFile::Threads, "threads"),
+];
+
+/// Something in the filesystem, which is what its inode number encodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Node {
+    /// The root directory
+    Root,
+    /// A file in the root
+    Kernel(KernelFile),
+    /// A link to the directory of the process reading it
+    SelfLink,
+    /// The directory with the ACPI tables in it
+    AcpiDirectory,
+    /// An ACPI table, by its index in [`acpi_tables`]
+    AcpiTable(usize),
+    /// A process's directory
+    Process(Pid),
+    /// A file in a process's directory
+    ProcessFile(Pid, ProcessFile),
+}
+
+impl Node {
+    /// The tags for each kind of node, which are the top byte of its inode number
+    const ROOT: u64 = 0;
+    const KERNEL: u64 = 1;
+    const SELF_LINK: u64 = 2;
+    const ACPI_DIRECTORY: u64 = 3;
+    const ACPI_TABLE: u64 = 4;
+    const PROCESS: u64 = 5;
+    const PROCESS_FILE: u64 = 6;
+
+    /// Get the node's inode number
+    fn inode(self) -> InodeId {
+        let (tag, payload) = match self {
+            Self::Root => (Self::ROOT, 0),
+            Self::Kernel(file) => (Self::KERNEL, index_of(&KERNEL_FILES, file)),
+            Self::SelfLink => (Self::SELF_LINK, 0),
+            Self::AcpiDirectory => (Self::ACPI_DIRECTORY, 0),
+            Self::AcpiTable(index) => (Self::ACPI_TABLE, index as u64),
+            Self::Process(pid) => (Self::PROCESS, pid.0),
+            Self::ProcessFile(pid, file) => (
+                Self::PROCESS_FILE,
+                index_of(&PROCESS_FILES, file) << FILE_SHIFT | pid.0,
+            ),
+        };
+        InodeId(tag << TAG_SHIFT | payload)
+    }
+
+    /// Find the node an inode number refers to, if it still exists.
+    /// Processes that have been reaped don't
+    fn from_inode(inode: InodeId) -> Result<Self, FileSystemError> {
+        let payload = inode.0 & ((1 << TAG_SHIFT) - 1);
+        let index = usize::try_from(payload).map_err(|_| FileSystemError::NotFound)?;
+        let node = match inode.0 >> TAG_SHIFT {
+            Self::ROOT if payload == 0 => Self::Root,
+            Self::KERNEL => {
+                Self::Kernel(KERNEL_FILES.get(index).ok_or(FileSystemError::NotFound)?.0)
+            }
+            Self::SELF_LINK if payload == 0 => Self::SelfLink,
+            Self::ACPI_DIRECTORY if payload == 0 => Self::AcpiDirectory,
+            Self::ACPI_TABLE => Self::AcpiTable(index),
+            Self::PROCESS => Self::Process(Pid(payload)),
+            Self::PROCESS_FILE => {
+                let file = usize::try_from(payload >> FILE_SHIFT)
+                    .ok()
+                    .and_then(|file| PROCESS_FILES.get(file))
+                    .ok_or(FileSystemError::NotFound)?;
+                Self::ProcessFile(Pid(payload & PAYLOAD_MASK), file.0)
+            }
+            _ => return Err(FileSystemError::NotFound),
+        };
+
+        let exists = match node {
+            Self::AcpiTable(index) => index < acpi_tables().len(),
+            Self::Process(pid) | Self::ProcessFile(pid, _) => process::get(pid).is_some(),
+            _ => true,
+        };
+        if exists {
+            Ok(node)
+        } else {
+            Err(FileSystemError::NotFound)
+        }
+    }
+
+    /// Get what the node is
+    const fn kind(self) -> FileKind {
+        match self {
+            Self::Root | Self::AcpiDirectory | Self::Process(_) => FileKind::Directory,
+            Self::SelfLink => FileKind::Symlink,
+            Self::Kernel(_) | Self::AcpiTable(_) | Self::ProcessFile(..) => FileKind::File,
+        }
+    }
+
+    /// Make an entry for the node in its directory
+    fn entry(self, name: String) -> DirectoryEntry {
+        DirectoryEntry {
+            name,
+            inode: self.inode(),
+            kind: self.kind(),
+        }
+    }
+}
+
+/// Get where something is in one of the file lists, as part of an inode number
+fn index_of<T: Copy + PartialEq>(files: &[(T, &str)], file: T) -> u64 {
+    files
+        .iter()
+        .position(|(other, _)| *other == file)
+        .unwrap_or_default() as u64
+}
+
+/// A read-only view of the kernel's state, usually mounted at `/proc`.
+///
+/// Its files are generated each time they're read, so they always have a size of zero,
+/// and a file read in pieces can change between them
+#[derive(Debug, Default)]
+pub struct ProcFs;
+
+impl ProcFs {
+    /// Make the filesystem
+    #[must_use]
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&self) -> InodeId {
+        Node::Root.inode()
+    }
+
+    fn lookup(&self, directory: InodeId, name: &str) -> Result<InodeId, FileSystemError> {
+        let node = match Node::from_inode(directory)? {
+            Node::Root => match name {
+                "self" => Node::SelfLink,
+                "acpi" => Node::AcpiDirectory,
+                _ => KERNEL_FILES
+                    .iter()
+                    .find(|&&(_, other)| other == name)
+                    .map(|&(file, _)| Node::Kernel(file))
+                    .or_else(|| {
+                        name.parse()
+                            .ok()
+                            .filter(|&pid| pid <= PAYLOAD_MASK)
+                            .map(|pid| Node::Process(Pid(pid)))
+                    })
+                    .ok_or(FileSystemError::NotFound)?,
+            },
+            Node::AcpiDirectory => acpi_tables()
+                .iter()
+                .position(|(other, _)| other == name)
+                .map(Node::AcpiTable)
+                .ok_or(FileSystemError::NotFound)?,
+            Node::Process(pid) => PROCESS_FILES
+                .iter()
+                .find(|&&(_, other)| other == name)
+                .map(|&(file, _)| Node::ProcessFile(pid, file))
+                .ok_or(FileSystemError::NotFound)?,
+            _ => return Err(FileSystemError::NotADirectory),
+        };
+        // Check a process with that ID exists
+        Node::from_inode(node.inode()).map(Node::inode)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FileSystemError> {
+        let node = Node::from_inode(inode)?;
+        let kind = node.kind();
+        let (mode, size) = match node {
+            // Tables are copied from firmware as they are, so they have a size
+            Node::AcpiTable(index) => (0o400, acpi_tables()[index].1.len() as u64),
+            Node::SelfLink => (0o777, 0),
+            _ if kind == FileKind::Directory => (0o555, 0),
+            _ => (0o444, 0),
+        };
+        Ok(Metadata {
+            inode,
+            kind,
+            mode,
+            size,
+            links: 1,
+        })
+    }
+
+    fn read(
+        &self,
+        inode: InodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, FileSystemError> {
+        let text = match Node::from_inode(inode)? {
+            Node::Kernel(file) => generate(file),
+            Node::ProcessFile(pid, file) => {
+                let process = process::get(pid).ok_or(FileSystemError::NotFound)?;
+                generate_for_process(&process, file)
+            }
+            Node::AcpiTable(index) => return Ok(copy_from(acpi_tables()[index].1, offset, buffer)),
+            Node::SelfLink => return Err(FileSystemError::InvalidArgument),
+            Node::Root | Node::AcpiDirectory | Node::Process(_) => {
+                return Err(FileSystemError::IsADirectory)
+            }
+        };
+        Ok(copy_from(text.as_bytes(), offset, buffer))
+    }
+
+    fn read_dir(&self, directory: InodeId) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+        match Node::from_inode(directory)? {
+            Node::Root => {
+                let mut entries: Vec<DirectoryEntry> = KERNEL_FILES
+                    .iter()
+                    .map(|&(file, name)| Node::Kernel(file).entry(String::from(name)))
+                    .collect();
+                entries.push(Node::SelfLink.entry(String::from("self")));
+                entries.push(Node::AcpiDirectory.entry(String::from("acpi")));
+                for process in process::list() {
+                    let mut name = String::new();
+                    let _ = write!(name, "{}", process.pid().0);
+                    entries.push(Node::Process(process.pid()).entry(name));
+                }
+                Ok(entries)
+            }
+            Node::AcpiDirectory => Ok(acpi_tables()
+                .into_iter()
+                .enumerate()
+                .map(|(index, (name, _))| Node::AcpiTable(index).entry(name))
+                .collect()),
+            Node::Process(pid) => Ok(PROCESS_FILES
+                .iter()
+                .map(|&(file, name)| Node::ProcessFile(pid, file).entry(String::from(name)))
+                .collect()),
+            _ => Err(FileSystemError::NotADirectory),
+        }
+    }
+
+    fn read_link(&self, inode: InodeId) -> Result<String, FileSystemError> {
+        if Node::from_inode(inode)? != Node::SelfLink {
+            return Err(FileSystemError::NotASymlink);
+        }
+        // The kernel's own threads aren't in a process, so theirs leads nowhere
+        let mut target = String::new();
+        let _ = write!(target, "{}", process::current_pid().0);
+        Ok(target)
+    }
+}
+
+/// Copy as much of `data` from `offset` as fits in the buffer
+fn copy_from(data: &[u8], offset: u64, buffer: &mut [u8]) -> usize {
+    let start = usize::try_from(offset).map_or(data.len(), |offset| offset.min(data.len()));
+    let length = buffer.len().min(data.len() - start);
+    buffer[..length].copy_from_slice(&data[start..start + length]);
+    length
+}
+
+/// Get every ACPI table by the name of its file, which is its signature,
+/// with a number after it for the second table with a signature onwards.
+/// The DSDT is found through the FADT, so it's added on the end
+fn acpi_tables() -> Vec<(String, &'static [u8])> {
+    let mut tables: Vec<(String, &'static [u8])> = Vec::new();
+    let acpi = Acpi::new().ok();
+    let found = acpi
+        .iter()
+        .flat_map(|acpi| acpi.tables().chain(acpi.dsdt().ok()));
+    for table in found {
+        let signature = table.signature();
+        let signature = String::from_utf8_lossy(&signature);
+        let mut name = String::from(signature.as_ref());
+        let mut duplicates = 0;
+        while tables.iter().any(|(other, _)| *other == name) {
+            duplicates += 1;
+            name.clear();
+            let _ = write!(name, "{signature}{duplicates}");
+        }
+        tables.push((name, table.data()));
+    }
+    tables
+}
+
+/// Generate the contents of a file in the root
+fn generate(file: KernelFile) -> String {
+    let mut text = String::new();
+    // Writing to a `String` can't fail
+    let _ = match file {
+        KernelFile::Meminfo => write_meminfo(&mut text),
+        KernelFile::Heap => write_heap(&mut text),
+        KernelFile::Cpus => write_cpus(&mut text),
+        KernelFile::Memmap => write_memmap(&mut text),
+        KernelFile::Interrupts => write_interrupts(&mut text),
+        KernelFile::Mounts => write_mounts(&mut text),
+        KernelFile::Processes => write_processes(&mut text),
+        KernelFile::Uptime => write_uptime(&mut text),
+    };
+    text
+}
+
+/// Write how many physical pages there are, and how many are used
+fn write_meminfo(text: &mut String) -> core::fmt::Result {
+    let total = crate::PHYSICAL_ALLOCATOR.get_total();
+    let used = crate::PHYSICAL_ALLOCATOR.get_used();
+    writeln!(text, "page_size {}", PageAllocator::BLOCK_SIZE)?;
+    writeln!(text, "pages_total {total}")?;
+    writeln!(text, "pages_used {used}")?;
+    writeln!(text, "pages_free {}", total.saturating_sub(used))
+}
+
+/// Write how much of the kernel heap is used, and how it's split up
+fn write_heap(text: &mut String) -> core::fmt::Result {
+    let stats = crate::ALLOCATOR.stats();
+    writeln!(text, "allocations {}", stats.allocations)?;
+    writeln!(text, "allocated_bytes {}", stats.allocated_bytes)?;
+    writeln!(text, "free_bytes {}", stats.free_bytes)?;
+    writeln!(text, "free_regions {}", stats.free_regions)?;
+    writeln!(text, "largest_free_region {}", stats.largest_free_region)
+}
+
+/// Write each core, and what its scheduler has to run
+fn write_cpus(text: &mut String) -> core::fmt::Result {
+    writeln!(text, "index id started runnable load")?;
+    let cores = (0..crate::CORE_MANAGER.core_count())
+        .filter_map(|index| crate::CORE_MANAGER.get_core_by_index(index));
+    for core in cores {
+        let scheduler = &core.scheduler;
+        let (runnable, load) =
+            scheduler::without_interrupts(|| (scheduler.runnable(), scheduler.load()));
+        writeln!(
+            text,
+            "{} {} {} {runnable} {load}",
+            core.index,
+            core.id,
+            if scheduler.is_started() { "yes" } else { "no" },
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the bootloader's memory map, one region per line
+fn write_memmap(text: &mut String) -> core::fmt::Result {
+    writeln!(text, "base end kind")?;
+    let map = crate::MEMORY_MAP
+        .response
+        .and_then(|response| unsafe { response.as_ref().get_memory_map() });
+    for entry in map.unwrap_or_default() {
+        writeln!(
+            text,
+            "{:#x} {:#x} {:?}",
+            entry.base,
+            entry.end(),
+            entry.kind
+        )?;
+    }
+    Ok(())
+}
+
+/// Write each interrupt vector that has been raised, and how many times
+fn write_interrupts(text: &mut String) -> core::fmt::Result {
+    writeln!(text, "vector count")?;
+    for (vector, count) in interrupts::counts() {
+        writeln!(text, "{vector} {count}")?;
+    }
+    Ok(())
+}
+
+/// Write each mount, in the order they were mounted
+fn write_mounts(text: &mut String) -> core::fmt::Result {
+    for mount in mount::list() {
+        writeln!(text, "{} {}", mount.path(), mount.filesystem().name())?;
+    }
+    Ok(())
+}
+
+/// Write each process, with its parent and threads
+fn write_processes(text: &mut String) -> core::fmt::Result {
+    writeln!(text, "pid ppid state threads name")?;
+    for process in process::list() {
+        let parent = process.parent().map_or(0, |parent| parent.pid().0);
+        let threads = process.threads();
+        write!(text, "{} {parent} {} ", process.pid().0, state(&process))?;
+        if threads.is_empty() {
+            write!(text, "-")?;
+        }
+        for (index, thread) in threads.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            write!(text, "{separator}{}", thread.0)?;
+        }
+        writeln!(text, " {}", process.name())?;
+    }
+    Ok(())
+}
+
+/// Write how long it's been since the timers started, in seconds
+fn write_uptime(text: &mut String) -> core::fmt::Result {
+    let uptime = PLATFORM_MANAGER.get_timer_manager().monotonic();
+    writeln!(text, "{}.{:03}", uptime.as_secs(), uptime.subsec_millis())
+}
+
+/// Generate the contents of a file in a process's directory
+fn generate_for_process(process: &Process, file: ProcessFile) -> String {
+    let mut text = String::new();
+    // Writing to a `String` can't fail
+    let _ = match file {
+        ProcessFile::Status => write_status(&mut text, process),
+        ProcessFile::Threads => write_threads(&mut text, process),
+    };
+    text
+}
+
+/// Write who a process is, who it runs as, and how it ended
+fn write_status(text: &mut String, process: &Process) -> core::fmt::Result {
+    let credentials = process.credentials();
+    writeln!(text, "name {}", process.name())?;
+    writeln!(text, "pid {}", process.pid().0)?;
+    writeln!(
+        text,
+        "ppid {}",
+        process.parent().map_or(0, |parent| parent.pid().0)
+    )?;
+    writeln!(text, "state {}", state(process))?;
+    match process.exit_status() {
+        Some(ExitStatus::Exited(code)) => writeln!(text, "exit_status {code}")?,
+        Some(ExitStatus::Faulted(fault)) => writeln!(text, "exit_status {fault:?}")?,
+        None => {}
+    }
+    writeln!(text, "uid {} {}", credentials.uid, credentials.euid)?;
+    writeln!(text, "gid {} {}", credentials.gid, credentials.egid)?;
+    writeln!(text, "threads {}", process.threads().len())?;
+    writeln!(text, "children {}", process.children().len())
+}
+
+/// Write the IDs of a process's threads, one per line
+fn write_threads(text: &mut String, process: &Process) -> core::fmt::Result {
+    for thread in process.threads() {
+        writeln!(text, "{}", thread.0)?;
+    }
+    Ok(())
+}
+
+/// Get a word for where a process is in its life
+fn state(process: &Process) -> &'static str {
+    if process.has_exited() {
+        "exited"
+    } else if process.is_exiting() {
+        "exiting"
+    } else {
+        "running"
+    }
+}
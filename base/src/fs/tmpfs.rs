@@ -0,0 +1,694 @@
+use core::{alloc::Layout, ptr};
+
+use crate::{
+    errors::FileSystemError,
+    memory::{
+        addresses::{AlignedAddress, Physical},
+        allocators::PageAllocator,
+    },
+    sync::SleepMutex,
+    traits::{DirectoryEntry, FileKind, FileSystem, InodeId, Metadata, PhysicalAllocator},
+};
+
+/// The size of each piece of a file's contents
+const PAGE_SIZE: usize = PageAllocator::BLOCK_SIZE;
+
+/// What each piece of a file's contents is allocated with
+const PAGE_LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE) };
+
+/// The root directory's inode number
+const ROOT: InodeId = InodeId(1);
+
+/// A page of a file's contents, straight from the page allocator, which is freed when it's dropped
+struct Page(AlignedAddress<Physical>);
+
+impl Page {
+    /// Allocate a page of zeroes
+    fn new() -> Result<Self, FileSystemError> {
+        let frame = crate::PHYSICAL_ALLOCATOR
+            .allocate(PAGE_LAYOUT)
+            .map_err(|_| FileSystemError::NoSpace)?;
+        let page = Self(frame);
+        // Whatever the frame held before mustn't be readable through a hole
+        unsafe { ptr::write_bytes(page.pointer(), 0, PAGE_SIZE) };
+        Ok(page)
+    }
+
+    /// Get where the page is in the direct map
+    fn pointer(&self) -> *mut u8 {
+        (usize::from(self.0) + crate::HHDM_RANGE.start) as *mut u8
+    }
+
+    /// Get what's in the page
+    fn bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.pointer(), PAGE_SIZE) }
+    }
+
+    /// Get what's in the page, to change it
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.pointer(), PAGE_SIZE) }
+    }
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        unsafe { crate::PHYSICAL_ALLOCATOR.deallocate(self.0, PAGE_LAYOUT) }
+    }
+}
+
+/// What an inode holds
+enum Contents {
+    /// A file, with only the pages that have been written to.
+    /// Anything between them reads as zeroes
+    File {
+        pages: BTreeMap<u64, Page>,
+        size: u64,
+    },
+    /// A directory, and the directory it's in, which is itself for the root
+    Directory {
+        entries: BTreeMap<String, InodeId>,
+        parent: InodeId,
+    },
+}
+
+/// An inode in the filesystem
+struct Node {
+    contents: Contents,
+    mode: u32,
+    /// How many names it has, which for a directory is its own `.`,
+    /// its name in its parent, and each of its subdirectories' `..`
+    links: u32,
+}
+
+impl Node {
+    /// Get what the inode is
+    const fn kind(&self) -> FileKind {
+        match self.contents {
+            Contents::File { .. } => FileKind::File,
+            Contents::Directory { .. } => FileKind::Directory,
+        }
+    }
+}
+
+/// Everything in the filesystem
+struct State {
+    nodes: BTreeMap<InodeId, Node>,
+    /// The next inode number to hand out
+    next: u64,
+    /// How many pages the files hold between them
+    pages: usize,
+}
+
+impl State {
+    /// Get an inode
+    fn node(&self, inode: InodeId) -> Result<&Node, FileSystemError> {
+        self.nodes.get(&inode).ok_or(FileSystemError::NotFound)
+    }
+
+    /// Get an inode, to change it
+    fn node_mut(&mut self, inode: InodeId) -> Result<&mut Node, FileSystemError> {
+        self.nodes.get_mut(&inode).ok_or(FileSystemError::NotFound)
+    }
+
+    /// Get what's in a directory
+    fn entries(&self, directory: InodeId) -> Result<&BTreeMap<String, InodeId>, FileSystemError> {
+        match &self.node(directory)?.contents {
+            Contents::Directory { entries, .. } => Ok(entries),
+            Contents::File { .. } => Err(FileSystemError::NotADirectory),
+        }
+    }
+
+    /// Get what's in a directory, to change it
+    fn entries_mut(
+        &mut self,
+        directory: InodeId,
+    ) -> Result<&mut BTreeMap<String, InodeId>, FileSystemError> {
+        match &mut self.node_mut(directory)?.contents {
+            Contents::Directory { entries, .. } => Ok(entries),
+            Contents::File { .. } => Err(FileSystemError::NotADirectory),
+        }
+    }
+
+    /// Find a name in a directory
+    fn lookup(&self, directory: InodeId, name: &str) -> Result<InodeId, FileSystemError> {
+        self.entries(directory)?
+            .get(name)
+            .copied()
+            .ok_or(FileSystemError::NotFound)
+    }
+
+    /// Add an inode, without giving it a name
+    fn insert(&mut self, contents: Contents, mode: u32, links: u32) -> InodeId {
+        let inode = InodeId(self.next);
+        self.next += 1;
+        self.nodes.insert(
+            inode,
+            Node {
+                contents,
+                mode,
+                links,
+            },
+        );
+        inode
+    }
+
+    /// Take a name away from an inode that isn't a directory, freeing it if it was the last
+    fn release(&mut self, inode: InodeId) -> Result<(), FileSystemError> {
+        let node = self.node_mut(inode)?;
+        node.links -= 1;
+        if node.links == 0 {
+            if let Some(Node {
+                contents: Contents::File { pages, .. },
+                ..
+            }) = self.nodes.remove(&inode)
+            {
+                self.pages -= pages.len();
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove an empty directory from its parent, and free it
+    fn remove_directory(&mut self, parent: InodeId, inode: InodeId) -> Result<(), FileSystemError> {
+        if !self.entries(inode)?.is_empty() {
+            return Err(FileSystemError::NotEmpty);
+        }
+        self.nodes.remove(&inode);
+        self.node_mut(parent)?.links -= 1;
+        Ok(())
+    }
+
+    /// Check whether `directory` is `ancestor` or anywhere inside it
+    fn is_inside(&self, directory: InodeId, ancestor: InodeId) -> Result<bool, FileSystemError> {
+        let mut current = directory;
+        loop {
+            if current == ancestor {
+                return Ok(true);
+            }
+            match self.node(current)?.contents {
+                Contents::Directory { parent, .. } if parent != current => current = parent,
+                _ => return Ok(false),
+            }
+        }
+    }
+}
+
+/// A writable filesystem kept in memory, usually mounted at `/tmp`.
+///
+/// Files are stored in pages from the page allocator, which are only allocated once they're
+/// written to, so files can have holes in them. Everything is freed when it's dropped.
+/// A file is freed as soon as its last name is removed, even if it's still open
+pub struct TmpFs {
+    state: SleepMutex<State>,
+    /// The most pages the files can hold between them
+    limit: usize,
+}
+
+impl TmpFs {
+    /// Make an empty filesystem that can hold up to `limit` bytes, rounded down to a page
+    #[must_use]
+    pub fn new(limit: usize) -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(
+            ROOT,
+            Node {
+                contents: Contents::Directory {
+                    entries: BTreeMap::new(),
+                    parent: ROOT,
+                },
+                mode: 0o1777,
+                links: 2,
+            },
+        );
+        Self {
+            state: SleepMutex::new(State {
+                nodes,
+                next: ROOT.0 + 1,
+                pages: 0,
+            }),
+            limit: limit / PAGE_SIZE,
+        }
+    }
+
+    /// Get the most bytes the files can hold between them
+    #[must_use]
+    pub const fn limit(&self) -> usize {
+        self.limit * PAGE_SIZE
+    }
+
+    /// Get how many bytes the files hold between them, which counts whole pages
+    #[must_use]
+    pub fn used(&self) -> usize {
+        self.state.lock().pages * PAGE_SIZE
+    }
+}
+
+impl core::fmt::Debug for TmpFs {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TmpFs")
+            .field("limit", &self.limit())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT
+    }
+
+    fn lookup(&self, directory: InodeId, name: &str) -> Result<InodeId, FileSystemError> {
+        self.state.lock().lookup(directory, name)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FileSystemError> {
+        let state = self.state.lock();
+        let node = state.node(inode)?;
+        let size = match node.contents {
+            Contents::File { size, .. } => size,
+            Contents::Directory { .. } => 0,
+        };
+        Ok(Metadata {
+            inode,
+            kind: node.kind(),
+            mode: node.mode,
+            size,
+            links: node.links,
+        })
+    }
+
+    fn read(
+        &self,
+        inode: InodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, FileSystemError> {
+        let state = self.state.lock();
+        let (pages, size) = match &state.node(inode)?.contents {
+            Contents::File { pages, size } => (pages, *size),
+            Contents::Directory { .. } => return Err(FileSystemError::IsADirectory),
+        };
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let length =
+            usize::try_from(size - offset).map_or(buffer.len(), |left| left.min(buffer.len()));
+        let mut done = 0;
+        while done < length {
+            let (index, within) = split(offset + done as u64);
+            let count = (PAGE_SIZE - within).min(length - done);
+            let target = &mut buffer[done..done + count];
+            match pages.get(&index) {
+                Some(page) => target.copy_from_slice(&page.bytes()[within..within + count]),
+                None => target.fill(0),
+            }
+            done += count;
+        }
+        Ok(length)
+    }
+
+    fn write(&self, inode: InodeId, offset: u64, buffer: &[u8]) -> Result<usize, FileSystemError> {
+        let mut state = self.state.lock();
+        let State {
+            nodes, pages: used, ..
+        } = &mut *state;
+        let node = nodes.get_mut(&inode).ok_or(FileSystemError::NotFound)?;
+        let (pages, size) = match &mut node.contents {
+            Contents::File { pages, size } => (pages, size),
+            Contents::Directory { .. } => return Err(FileSystemError::IsADirectory),
+        };
+        offset
+            .checked_add(buffer.len() as u64)
+            .ok_or(FileSystemError::InvalidArgument)?;
+
+        // As much as fits is written, and it's only an error if nothing did
+        let mut done = 0;
+        while done < buffer.len() {
+            let (index, within) = split(offset + done as u64);
+            let count = (PAGE_SIZE - within).min(buffer.len() - done);
+            let page = match pages.entry(index) {
+                btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                btree_map::Entry::Vacant(entry) => {
+                    if *used >= self.limit {
+                        break;
+                    }
+                    match Page::new() {
+                        Ok(page) => {
+                            *used += 1;
+                            entry.insert(page)
+                        }
+                        Err(_) => break,
+                    }
+                }
+            };
+            page.bytes_mut()[within..within + count].copy_from_slice(&buffer[done..done + count]);
+            done += count;
+        }
+        if done == 0 && !buffer.is_empty() {
+            return Err(FileSystemError::NoSpace);
+        }
+        *size = (*size).max(offset + done as u64);
+        Ok(done)
+    }
+
+    fn truncate(&self, inode: InodeId, size: u64) -> Result<(), FileSystemError> {
+        let mut state = self.state.lock();
+        let State {
+            nodes, pages: used, ..
+        } = &mut *state;
+        let node = nodes.get_mut(&inode).ok_or(FileSystemError::NotFound)?;
+        let (pages, old_size) = match &mut node.contents {
+            Contents::File { pages, size } => (pages, size),
+            Contents::Directory { .. } => return Err(FileSystemError::IsADirectory),
+        };
+
+        if size < *old_size {
+            // Pages past the end are freed, and the end of the last one is zeroed,
+            // so growing it again reads zeroes
+            let (index, within) = split(size);
+            let first_removed = if within == 0 { index } else { index + 1 };
+            *used -= pages.split_off(&first_removed).len();
+            if within != 0 {
+                if let Some(page) = pages.get_mut(&index) {
+                    page.bytes_mut()[within..].fill(0);
+                }
+            }
+        }
+        *old_size = size;
+        Ok(())
+    }
+
+    fn read_dir(&self, directory: InodeId) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+        let state = self.state.lock();
+        state
+            .entries(directory)?
+            .iter()
+            .map(|(name, &inode)| {
+                Ok(DirectoryEntry {
+                    name: name.clone(),
+                    inode,
+                    kind: state.node(inode)?.kind(),
+                })
+            })
+            .collect()
+    }
+
+    fn read_link(&self, inode: InodeId) -> Result<String, FileSystemError> {
+        self.state.lock().node(inode)?;
+        Err(FileSystemError::NotASymlink)
+    }
+
+    fn create(
+        &self,
+        directory: InodeId,
+        name: &str,
+        kind: FileKind,
+        mode: u32,
+    ) -> Result<InodeId, FileSystemError> {
+        check_name(name)?;
+        let mut state = self.state.lock();
+        if state.entries(directory)?.contains_key(name) {
+            return Err(FileSystemError::AlreadyExists);
+        }
+
+        let inode = match kind {
+            FileKind::File => state.insert(
+                Contents::File {
+                    pages: BTreeMap::new(),
+                    size: 0,
+                },
+                mode,
+                1,
+            ),
+            FileKind::Directory => {
+                let inode = state.insert(
+                    Contents::Directory {
+                        entries: BTreeMap::new(),
+                        parent: directory,
+                    },
+                    mode,
+                    2,
+                );
+                state.node_mut(directory)?.links += 1;
+                inode
+            }
+            FileKind::Symlink | FileKind::CharDevice => return Err(FileSystemError::Unsupported),
+        };
+        state
+            .entries_mut(directory)?
+            .insert(String::from(name), inode);
+        Ok(inode)
+    }
+
+    fn link(&self, inode: InodeId, directory: InodeId, name: &str) -> Result<(), FileSystemError> {
+        check_name(name)?;
+        let mut state = self.state.lock();
+        if state.node(inode)?.kind() == FileKind::Directory {
+            return Err(FileSystemError::IsADirectory);
+        }
+        if state.entries(directory)?.contains_key(name) {
+            return Err(FileSystemError::AlreadyExists);
+        }
+        state.node_mut(inode)?.links += 1;
+        state
+            .entries_mut(directory)?
+            .insert(String::from(name), inode);
+        Ok(())
+    }
+
+    fn unlink(&self, directory: InodeId, name: &str) -> Result<(), FileSystemError> {
+        let mut state = self.state.lock();
+        let inode = state.lookup(directory, name)?;
+        if state.node(inode)?.kind() == FileKind::Directory {
+            return Err(FileSystemError::IsADirectory);
+        }
+        state.entries_mut(directory)?.remove(name);
+        state.release(inode)
+    }
+
+    fn remove_directory(&self, directory: InodeId, name: &str) -> Result<(), FileSystemError> {
+        let mut state = self.state.lock();
+        let inode = state.lookup(directory, name)?;
+        state.remove_directory(directory, inode)?;
+        state.entries_mut(directory)?.remove(name);
+        Ok(())
+    }
+
+    fn rename(
+        &self,
+        from_directory: InodeId,
+        from_name: &str,
+        to_directory: InodeId,
+        to_name: &str,
+    ) -> Result<(), FileSystemError> {
+        check_name(to_name)?;
+        let mut state = self.state.lock();
+        let inode = state.lookup(from_directory, from_name)?;
+        let replaced = match state.lookup(to_directory, to_name) {
+            Ok(replaced) => Some(replaced),
+            Err(FileSystemError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+        // Two names for the same file are left as they are
+        if replaced == Some(inode) {
+            return Ok(());
+        }
+
+        let is_directory = state.node(inode)?.kind() == FileKind::Directory;
+        if is_directory && state.is_inside(to_directory, inode)? {
+            return Err(FileSystemError::InvalidArgument);
+        }
+        if let Some(replaced) = replaced {
+            match (
+                is_directory,
+                state.node(replaced)?.kind() == FileKind::Directory,
+            ) {
+                (true, true) => state.remove_directory(to_directory, replaced)?,
+                (true, false) => return Err(FileSystemError::NotADirectory),
+                (false, true) => return Err(FileSystemError::IsADirectory),
+                (false, false) => state.release(replaced)?,
+            }
+        }
+
+        state.entries_mut(from_directory)?.remove(from_name);
+        state
+            .entries_mut(to_directory)?
+            .insert(String::from(to_name), inode);
+        if is_directory && from_directory != to_directory {
+            state.node_mut(from_directory)?.links -= 1;
+            state.node_mut(to_directory)?.links += 1;
+            if let Contents::Directory { parent, .. } = &mut state.node_mut(inode)?.contents {
+                *parent = to_directory;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check a name can be put in a directory
+fn check_name(name: &str) -> Result<(), FileSystemError> {
+    if matches!(name, "" | "." | "..") || name.contains('/') {
+        Err(FileSystemError::InvalidArgument)
+    } else {
+        Ok(())
+    }
+}
+
+/// Split a position in a file into the index of the page it's in, and where it is in that page
+#[allow(clippy::cast_possible_truncation)]
+const fn split(position: u64) -> (u64, usize) {
+    // The remainder is less than a page, so it always fits
+    (
+        position / PAGE_SIZE as u64,
+        (position % PAGE_SIZE as u64) as usize,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A filesystem with no room for pages, so nothing here touches the page allocator
+    fn tmpfs() -> TmpFs {
+        TmpFs::new(0)
+    }
+
+    fn names(tmpfs: &TmpFs, directory: InodeId) -> Vec<String> {
+        let entries = tmpfs.read_dir(directory).unwrap();
+        entries.into_iter().map(|entry| entry.name).collect()
+    }
+
+    #[test]
+    fn makes_files_and_directories() {
+        let tmpfs = tmpfs();
+        let directory = tmpfs.create(ROOT, "d", FileKind::Directory, 0o755).unwrap();
+        let file = tmpfs.create(directory, "f", FileKind::File, 0o644).unwrap();
+        assert_eq!(tmpfs.lookup(directory, "f"), Ok(file));
+        assert_eq!(names(&tmpfs, ROOT), ["d"]);
+        // The root's links are its `.`, its `..`, and the new directory's `..`
+        assert_eq!(tmpfs.metadata(ROOT).unwrap().links, 3);
+        assert_eq!(tmpfs.metadata(directory).unwrap().links, 2);
+        assert_eq!(tmpfs.metadata(file).unwrap().mode, 0o644);
+
+        assert_eq!(
+            tmpfs.create(directory, "f", FileKind::File, 0),
+            Err(FileSystemError::AlreadyExists)
+        );
+        for name in ["", ".", "..", "a/b"] {
+            assert_eq!(
+                tmpfs.create(ROOT, name, FileKind::File, 0),
+                Err(FileSystemError::InvalidArgument)
+            );
+        }
+        assert_eq!(
+            tmpfs.create(ROOT, "link", FileKind::Symlink, 0),
+            Err(FileSystemError::Unsupported)
+        );
+        assert_eq!(
+            tmpfs.create(file, "x", FileKind::File, 0),
+            Err(FileSystemError::NotADirectory)
+        );
+    }
+
+    #[test]
+    fn holes_read_as_zeroes() {
+        let tmpfs = tmpfs();
+        let file = tmpfs.create(ROOT, "f", FileKind::File, 0o644).unwrap();
+        // Growing a file doesn't take any pages
+        tmpfs.truncate(file, 5000).unwrap();
+        assert_eq!(tmpfs.metadata(file).unwrap().size, 5000);
+        assert_eq!(tmpfs.used(), 0);
+        let mut buffer = [0xFF; 64];
+        assert_eq!(tmpfs.read(file, 4990, &mut buffer), Ok(10));
+        assert!(buffer[..10].iter().all(|&byte| byte == 0));
+        assert_eq!(tmpfs.read(file, 5000, &mut buffer), Ok(0));
+
+        // Writing does, and there aren't any
+        assert_eq!(tmpfs.write(file, 0, b"x"), Err(FileSystemError::NoSpace));
+        assert_eq!(tmpfs.write(file, 0, b""), Ok(0));
+        assert_eq!(
+            tmpfs.write(file, u64::MAX, b"x"),
+            Err(FileSystemError::InvalidArgument)
+        );
+        assert_eq!(
+            tmpfs.read(ROOT, 0, &mut buffer),
+            Err(FileSystemError::IsADirectory)
+        );
+    }
+
+    #[test]
+    fn links_and_unlinks() {
+        let tmpfs = tmpfs();
+        let file = tmpfs.create(ROOT, "f", FileKind::File, 0o644).unwrap();
+        tmpfs.link(file, ROOT, "g").unwrap();
+        assert_eq!(tmpfs.metadata(file).unwrap().links, 2);
+        tmpfs.unlink(ROOT, "f").unwrap();
+        assert_eq!(tmpfs.metadata(file).unwrap().links, 1);
+        assert_eq!(tmpfs.lookup(ROOT, "f"), Err(FileSystemError::NotFound));
+        // The file goes with its last name
+        tmpfs.unlink(ROOT, "g").unwrap();
+        assert_eq!(
+            tmpfs.metadata(file).map(|_| ()),
+            Err(FileSystemError::NotFound)
+        );
+
+        let directory = tmpfs.create(ROOT, "d", FileKind::Directory, 0o755).unwrap();
+        assert_eq!(
+            tmpfs.link(directory, ROOT, "e"),
+            Err(FileSystemError::IsADirectory)
+        );
+        assert_eq!(tmpfs.unlink(ROOT, "d"), Err(FileSystemError::IsADirectory));
+        tmpfs.create(directory, "f", FileKind::File, 0).unwrap();
+        assert_eq!(
+            tmpfs.remove_directory(ROOT, "d"),
+            Err(FileSystemError::NotEmpty)
+        );
+        tmpfs.unlink(directory, "f").unwrap();
+        tmpfs.remove_directory(ROOT, "d").unwrap();
+        assert!(names(&tmpfs, ROOT).is_empty());
+        assert_eq!(tmpfs.metadata(ROOT).unwrap().links, 2);
+    }
+
+    #[test]
+    fn renames() {
+        let tmpfs = tmpfs();
+        let a = tmpfs.create(ROOT, "a", FileKind::Directory, 0o755).unwrap();
+        let b = tmpfs.create(ROOT, "b", FileKind::Directory, 0o755).unwrap();
+        let file = tmpfs.create(a, "f", FileKind::File, 0o644).unwrap();
+        let other = tmpfs.create(ROOT, "g", FileKind::File, 0o644).unwrap();
+
+        // Replacing a file frees it
+        tmpfs.rename(a, "f", ROOT, "g").unwrap();
+        assert_eq!(tmpfs.lookup(ROOT, "g"), Ok(file));
+        assert_eq!(
+            tmpfs.metadata(other).map(|_| ()),
+            Err(FileSystemError::NotFound)
+        );
+        assert!(names(&tmpfs, a).is_empty());
+
+        // A directory moves its `..` with it
+        tmpfs.rename(ROOT, "a", b, "a").unwrap();
+        assert_eq!(tmpfs.metadata(b).unwrap().links, 3);
+        assert_eq!(tmpfs.metadata(ROOT).unwrap().links, 3);
+        assert_eq!(
+            tmpfs.rename(ROOT, "b", a, "b"),
+            Err(FileSystemError::InvalidArgument)
+        );
+        assert_eq!(
+            tmpfs.rename(ROOT, "g", ROOT, "b"),
+            Err(FileSystemError::IsADirectory)
+        );
+        assert_eq!(
+            tmpfs.rename(ROOT, "b", ROOT, "g"),
+            Err(FileSystemError::NotADirectory)
+        );
+        assert_eq!(
+            tmpfs.rename(ROOT, "missing", ROOT, "x"),
+            Err(FileSystemError::NotFound)
+        );
+    }
+}
@@ -1,5 +1,34 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use crate::memory::addresses::{Address, Virtual};
 
+#[allow(clippy::declare_interior_mutable_const)]
+const NEVER_RAISED: AtomicU64 = AtomicU64::new(0);
+
+/// How many times each vector has been raised, across every core
+static COUNTS: [AtomicU64; 256] = [NEVER_RAISED; 256];
+
+/// Count a vector being raised. This doesn't lock or allocate,
+/// so it's safe to call from any handler, including NMIs
+pub fn record(vector: u8) {
+    COUNTS[usize::from(vector)].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Get how many times a vector has been raised, across every core
+#[must_use]
+pub fn count(vector: u8) -> u64 {
+    COUNTS[usize::from(vector)].load(Ordering::Relaxed)
+}
+
+/// Get every vector that has been raised, and how many times
+#[must_use]
+pub fn counts() -> Vec<(u8, u64)> {
+    (0..=u8::MAX)
+        .map(|vector| (vector, count(vector)))
+        .filter(|&(_, count)| count != 0)
+        .collect()
+}
+
 /// Possible types of interrupts
 #[repr(C)]
 #[derive(Debug)]
@@ -1,5 +1,5 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 #![feature(
     panic_info_message,
     lang_items,
@@ -30,7 +30,9 @@
     unused_features,
     missing_docs
 )]
+#![cfg_attr(test, allow(clippy::unwrap_used, clippy::cast_possible_truncation))]
 #![feature(default_alloc_error_handler)]
+#![cfg_attr(feature = "lockdep", feature(const_caller_location))]
 
 //! This is the Lotus kernel
 
@@ -82,6 +84,38 @@ pub mod traits;
 /// Multi-core related objects
 pub mod smp;
 
+/// Wall-clock time
+pub mod time;
+
+/// Per-core timer queues, on top of the platform's timer manager
+pub mod timer;
+
+/// Kernel threads and scheduling
+pub mod scheduler;
+
+/// The system calls user mode makes
+pub mod syscalls;
+
+/// User processes
+pub mod process;
+
+/// Parsing and loading ELF programs
+pub mod elf;
+
+/// Files the bootloader loaded alongside the kernel
+pub mod modules;
+
+/// Filesystems
+pub mod fs;
+
+/// Pseudo-random numbers
+pub mod random;
+
+/// Checks run at boot, for testing in an emulator. A failed check panics
+#[cfg(feature = "selftest")]
+#[allow(clippy::unwrap_used)]
+mod selftest;
+
 mod prelude {
     pub mod rust_2021 {
         mod print_macros {
@@ -123,18 +157,19 @@ pub use prelude::rust_2021::*;
 // END: Modules, Macros, and Prelude
 // Actual code begins here
 
-use log::{debug, error, info, trace};
+use log::{debug, info, trace};
 
-use memory::allocators::{NeverAllocator, PageAllocator};
+use memory::allocators::{HeapAllocator, PageAllocator};
 
 use smp::CoreManager;
 use sync::lazy_static;
-use traits::Platform;
+use traits::{MemoryFlags, MemoryManager, Platform};
 
 use crate::{arch::PLATFORM_MANAGER, traits::Init};
 
 use limine_protocol::{
-    HHDMRequest, KernelAddressRequest, MemoryMapRequest, Request, SMPRequest, StackSizeRequest,
+    HHDMRequest, KernelAddressRequest, MemoryMapRequest, ModuleRequest, RSDPRequest, Request,
+    SMPRequest, StackSizeRequest,
 };
 
 static MEMORY_MAP: Request<MemoryMapRequest> = MemoryMapRequest::default().into();
@@ -155,6 +190,10 @@ static SMP_REQUEST: Request<SMPRequest> = SMPRequest {
 
 static HHDM_REQUEST: Request<HHDMRequest> = HHDMRequest::default().into();
 
+static RSDP_REQUEST: Request<RSDPRequest> = RSDPRequest::default().into();
+
+static MODULE_REQUEST: Request<ModuleRequest> = ModuleRequest::default().into();
+
 lazy_static! {
     /// The Range for the Higher Half Direct Map
     pub lazy static HHDM_RANGE: core::ops::Range<usize> = {
@@ -184,9 +223,12 @@ lazy_static! {
     };
 }
 
-/// This shouldn't be used, it immediately errors out. This is intentional
-#[global_allocator]
-static ALLOCATOR: NeverAllocator = NeverAllocator;
+/// The kernel heap. Host tests use the standard library's
+#[cfg_attr(not(test), global_allocator)]
+static ALLOCATOR: HeapAllocator = HeapAllocator::new();
+
+/// The size of the kernel heap
+const KERNEL_HEAP_SIZE: usize = 16 * memory::MEGABYTE;
 
 /// The Physical Allocator
 static PHYSICAL_ALLOCATOR: PageAllocator = PageAllocator::new();
@@ -199,6 +241,7 @@ pub fn get_memory_manager() -> &'static <arch::PlatformType as Platform>::Memory
 static CORE_MANAGER: CoreManager = CoreManager::new();
 
 /// The kernel entrypoint
+#[cfg(not(test))]
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     kentry()
@@ -244,84 +287,55 @@ fn kentry() -> ! {
 
     info!("Initialized page allocator");
 
+    unsafe {
+        let mut heap = get_memory_manager()
+            .allocate_and_map(
+                get_memory_manager().get_current_table().unwrap(),
+                (*SAFE_UPPER_HALF_RANGE).clone(),
+                MemoryFlags::CACHABLE
+                    | MemoryFlags::KERNEL_ONLY
+                    | MemoryFlags::READABLE
+                    | MemoryFlags::WRITABLE,
+                core::alloc::Layout::from_size_align(KERNEL_HEAP_SIZE, 4096).unwrap(),
+            )
+            .unwrap();
+        ALLOCATOR
+            .init(heap.get_inner_ptr_mut().cast(), KERNEL_HEAP_SIZE)
+            .unwrap();
+    }
+
+    info!("Initialized kernel heap");
+
     CORE_MANAGER
         .init(smp.cpu_count.try_into().unwrap())
         .unwrap();
 
+    CORE_MANAGER.initialize_core(smp.bsp_lapic_id);
+
     info!("Initialized Core Manager");
 
     PLATFORM_MANAGER.init(()).unwrap();
 
-    {
-        let mutex = sync::Mutex::new(9);
-        {
-            debug!("Locking mutex!");
-            let lock = mutex.lock();
-            debug!("Locked mutex! Got value {}", *lock);
-            assert!(*lock == 9)
-        }
-        debug!("Dropped mutex!");
-    }
-
-    /*
-    // let rsp = RSP::get();
-    let rsp = RSP(core::ptr::null_mut());
-
-    trace!("Got rsp");
-
-    let tss = TaskStateSegment::new_no_ports(
-        (rsp.0).try_into().unwrap(),
-        core::ptr::null_mut::<u8>().try_into().unwrap(),
-        core::ptr::null_mut::<u8>().try_into().unwrap(),
-    );
-
-    let mut sys_descriptor = SystemSegmentDescriptorLongMode::new_unused();
-    sys_descriptor.set_flags(0x40);
-    sys_descriptor.set_base(&tss as *const _ as u64);
-    sys_descriptor.set_limit(core::mem::size_of_val(&tss) as u32);
-    sys_descriptor.access_byte = SystemSegmentAccessByte::PRESENT
-        .descriptor_privilege_level(0)
-        .segment_type(SegmentType {
-            long: SegmentType64Bit::Tss64BitAvailable,
-        });
-
-    trace!("Made tss and system descriptor");
-
-    let as_segd = unsafe { &*(arch::structures::GDT.as_ptr() as *const [SegmentDescriptor; 9]) };
-    debug!("{:#?}", as_segd);
-
-    /*
-
-    unsafe {
-        (arch::structures::GDT.as_ptr().offset(7) as *mut SystemSegmentDescriptorLongMode)
-            .write(sys_descriptor);
-    }
-
-    */
-
-    info!("Loading GDT");
-
-    let gdt_ldr = SizedDescriptorTable {
-        limit: { 7 * 8 } - 1,
-        base: unsafe { arch::structures::GDT.as_ptr() as usize as u64 },
-    };
-
-    let gdt_ldr_ptr = &gdt_ldr as *const _ as usize;
-
+    arch::start_application_processors(smp);
     debug!(
-        "Is address canonical: {}",
-        memory::utilities::is_address_canonical(gdt_ldr.base as usize, 48)
+        "{} of {} cores online",
+        CORE_MANAGER.core_count(),
+        smp.cpu_count
     );
 
-    debug!("Made object: {:#?}", gdt_ldr);
-
-    GlobalDescriptorTable::apply(gdt_ldr_ptr);
+    scheduler::init_current_core();
+    memory::AddressSpace::init_kernel().unwrap();
+    fs::init();
 
-    let gdt_res = SizedDescriptorTable::get_gdt();
-    debug!("{:#?}", gdt_res);
+    #[cfg(feature = "selftest")]
+    selftest::run();
 
-    // debug!("{:#?}", gdt);
+    scheduler::spawn(|| loop {
+        debug!("Heartbeat <3");
+        scheduler::sleep(core::time::Duration::from_secs(1));
+    });
 
+    /*
     unsafe { install_interrupt_handler() };
 
     fn handler(it: InterruptType) {
@@ -365,27 +379,30 @@ fn kentry() -> ! {
         uart.write_byte(byte);
     }
     */
-    let mut num = 0u64;
-    loop {
-        if num % 13 == 0 {
-            debug!("Heartbeat <3");
-        }
-        if num == u64::MAX {
-            num = 0;
-        } else {
-            num += 1;
-        }
-    }
+
+    // This becomes the bootstrap core's idle thread
+    scheduler::idle()
 }
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    error!("KERNEL PANIC");
+    use platform_logger::LOGGER;
+
+    LOGGER.log_panic(format_args!("KERNEL PANIC"));
     if let Some(reason) = info.message() {
-        error!("REASON: {}", reason);
+        LOGGER.log_panic(format_args!("REASON: {}", reason));
     }
     if let Some(loc) = info.location() {
-        error!("IN: {}:{}", loc.file(), loc.line());
+        LOGGER.log_panic(format_args!("IN: {}:{}", loc.file(), loc.line()));
+    }
+
+    // A failed check ends a self-test run
+    if cfg!(feature = "selftest") {
+        use traits::{PowerManager, PowerOffKind};
+        PLATFORM_MANAGER
+            .get_power_manager()
+            .shutdown(PowerOffKind::Failure);
     }
 
     loop {
@@ -0,0 +1,507 @@
+use core::{
+    alloc::Layout,
+    ops::Range,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use log::warn;
+
+use crate::{
+    arch::PlatformType,
+    errors::{GenericError, MemoryManagerError},
+    get_memory_manager,
+    sync::Mutex,
+    traits::{MemoryFlags, MemoryManager, Platform, PlatformAddress},
+};
+
+use super::{
+    addresses::{AlignedAddress, Virtual},
+    utilities::align,
+    USER_SPACE_END, USER_SPACE_START,
+};
+
+/// The platform's root page table
+type RootTable = <<PlatformType as Platform>::MemoryManager as MemoryManager>::RootTable;
+
+/// The root table the kernel booted with, which kernel threads run on
+static KERNEL_TABLE: AtomicPtr<RootTable> = AtomicPtr::new(ptr::null_mut());
+
+/// Memory mapped into an address space
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    /// Where it starts, which is page aligned
+    pub start: usize,
+    /// Its size and alignment
+    pub layout: Layout,
+    /// What it's mapped with
+    pub flags: MemoryFlags,
+}
+
+impl Region {
+    /// Get where the region ends, which is page aligned
+    #[must_use]
+    pub fn end(&self) -> usize {
+        self.start + align(self.layout.size(), 4096)
+    }
+}
+
+/// Where a program's heap is, which `brk` grows and shrinks
+#[derive(Debug, Default, Clone, Copy)]
+struct ProgramBreak {
+    /// Where the heap starts, which is page aligned, or zero if it hasn't been placed
+    start: usize,
+    /// Where the heap ends
+    end: usize,
+    /// Where the memory mapped for the heap ends, which is page aligned
+    mapped: usize,
+}
+
+/// A user address space: its own mappings in user space, and the kernel's everywhere else.
+/// Every region mapped into it is freed along with it
+pub struct AddressSpace {
+    table: *mut RootTable,
+    /// Only locked from thread context
+    regions: Mutex<Vec<Region>>,
+    /// Only locked from thread context, and before `regions`
+    program_break: Mutex<ProgramBreak>,
+}
+
+unsafe impl Send for AddressSpace {}
+unsafe impl Sync for AddressSpace {}
+
+impl AddressSpace {
+    /// Remember the current root table as the kernel's, which address spaces share the kernel half of.
+    /// This must be called once, before any address space is made
+    ///
+    /// # Errors
+    /// This will return an error if there's no root table loaded
+    pub fn init_kernel() -> Result<(), MemoryManagerError> {
+        let table: *mut RootTable = unsafe { get_memory_manager().get_current_table() }?;
+        KERNEL_TABLE.store(table, Ordering::Release);
+        Ok(())
+    }
+
+    /// Make an empty address space
+    ///
+    /// # Errors
+    /// This will return an error if the kernel's table wasn't remembered,
+    /// or a new table couldn't be allocated
+    pub fn new() -> Result<Self, MemoryManagerError> {
+        let kernel = KERNEL_TABLE.load(Ordering::Acquire);
+        if kernel.is_null() {
+            return Err(MemoryManagerError::Generic(GenericError::NotInitialized));
+        }
+        let table = unsafe { get_memory_manager().new_user_table(&mut *kernel) }?;
+
+        Ok(Self {
+            table,
+            regions: Mutex::new(Vec::new()),
+            program_break: Mutex::new(ProgramBreak::default()),
+        })
+    }
+
+    /// Make a new address space with a copy of everything mapped into this one, and the same heap
+    ///
+    /// # Errors
+    /// This will return an error if a new table or the memory can't be allocated
+    pub fn duplicate(&self) -> Result<Self, MemoryManagerError> {
+        let copy = Self::new()?;
+        let mut page = [0; 4096];
+        for region in self.regions() {
+            let layout = Layout::from_size_align(region.end() - region.start, 4096)
+                .map_err(|_| MemoryManagerError::Generic(GenericError::IntConversionError))?;
+            copy.map(Some(region.start), layout, region.flags)?;
+            for address in (region.start..region.end()).step_by(4096) {
+                self.read_bytes(address, &mut page)?;
+                copy.write_bytes(address, &page)?;
+            }
+        }
+        *copy.program_break.lock() = *self.program_break.lock();
+        Ok(copy)
+    }
+
+    /// Load the address space on the current core, if it isn't already
+    pub fn activate(&self) {
+        unsafe { activate(self.table) };
+    }
+
+    /// Load the kernel's root table on the current core, if it isn't already
+    pub fn activate_kernel() {
+        let kernel = KERNEL_TABLE.load(Ordering::Acquire);
+        if !kernel.is_null() {
+            unsafe { activate(kernel) };
+        }
+    }
+
+    /// Map zeroed memory into user space, at `address` if one is given,
+    /// or otherwise wherever there's room, and get where it was mapped.
+    /// It's always accessible to user mode
+    ///
+    /// # Errors
+    /// This will return an error if the address isn't page aligned and in user space,
+    /// there's no room, or the memory can't be allocated or mapped
+    pub fn map(
+        &self,
+        address: Option<usize>,
+        layout: Layout,
+        flags: MemoryFlags,
+    ) -> Result<usize, MemoryManagerError> {
+        let size = align(layout.size().max(1), 4096);
+        // A fixed address only has to be page aligned
+        let (range, alignment) = match address {
+            Some(address) if address % 4096 != 0 || !is_user_range(address, size) => {
+                return Err(MemoryManagerError::OutOfRange);
+            }
+            // The search only stops once it's past a free area, so it's given one page more
+            Some(address) => (address..=address + size, 4096),
+            None => (USER_SPACE_START..=USER_SPACE_END, layout.align().max(4096)),
+        };
+        let layout = Layout::from_size_align(size, alignment)
+            .map_err(|_| MemoryManagerError::Generic(GenericError::IntConversionError))?;
+        let flags = flags - MemoryFlags::KERNEL_ONLY;
+
+        let mut regions = self.regions.lock();
+        let start = unsafe {
+            get_memory_manager().allocate_and_map(
+                &mut *self.table,
+                range.step_by(4096),
+                flags,
+                layout,
+            )
+        }
+        .map_err(|e| match (e, address) {
+            (MemoryManagerError::VirtualMemoryExhausted, Some(_)) => {
+                MemoryManagerError::AddressMapped
+            }
+            (e, _) => e,
+        })?;
+        let start = usize::try_from(start.inner().into_raw())
+            .map_err(|_| MemoryManagerError::Generic(GenericError::IntConversionError))?;
+
+        let region = Region {
+            start,
+            layout,
+            flags,
+        };
+        regions.push(region);
+        drop(regions);
+
+        // Whatever the frames held before mustn't leak into user space
+        let offset = crate::HHDM_RANGE.start;
+        self.for_each_page(start, size, |_, phys, len| unsafe {
+            ptr::write_bytes((phys + offset) as *mut u8, 0, len);
+        })?;
+        Ok(start)
+    }
+
+    /// Unmap and free the region starting at `address`
+    ///
+    /// # Errors
+    /// This will return an error if no region starts there, or it can't be unmapped
+    pub fn unmap(&self, address: usize) -> Result<(), MemoryManagerError> {
+        let mut regions = self.regions.lock();
+        let index = regions
+            .iter()
+            .position(|region| region.start == address)
+            .ok_or(MemoryManagerError::AddressUnmapped)?;
+        let region = regions.swap_remove(index);
+        drop(regions);
+
+        unsafe { self.free(region) }
+    }
+
+    /// Unmap and free every region within `length` bytes from `address`.
+    /// Nothing has to be mapped there, but regions can't be split
+    ///
+    /// # Errors
+    /// This will return an error if the range isn't page aligned and in user space,
+    /// or a region is only partly in it, in which case nothing is unmapped
+    pub fn unmap_range(&self, address: usize, length: usize) -> Result<(), MemoryManagerError> {
+        let range = page_range(address, length)?;
+        let mut regions = self.regions.lock();
+        if regions.iter().any(|region| is_split(region, &range)) {
+            return Err(MemoryManagerError::OutOfRange);
+        }
+        let removed = take_regions(&mut regions, &range);
+        drop(regions);
+
+        removed
+            .into_iter()
+            .try_for_each(|region| unsafe { self.free(region) })
+    }
+
+    /// Change what every region within `length` bytes from `address` is mapped with.
+    /// All of the range has to be mapped, and regions can't be split
+    ///
+    /// # Errors
+    /// This will return an error if the range isn't page aligned and in user space,
+    /// a region is only partly in it, or any of it isn't mapped
+    pub fn protect(
+        &self,
+        address: usize,
+        length: usize,
+        flags: MemoryFlags,
+    ) -> Result<(), MemoryManagerError> {
+        let range = page_range(address, length)?;
+        let flags = flags - MemoryFlags::KERNEL_ONLY;
+
+        let mut regions = self.regions.lock();
+        let mut next = range.start;
+        while next < range.end {
+            let region = regions
+                .iter()
+                .find(|region| region.start <= next && next < region.end())
+                .ok_or(MemoryManagerError::AddressUnmapped)?;
+            if is_split(region, &range) {
+                return Err(MemoryManagerError::OutOfRange);
+            }
+            next = region.end();
+        }
+
+        let memory_manager = get_memory_manager();
+        for region in regions
+            .iter_mut()
+            .filter(|region| range.start <= region.start && region.end() <= range.end)
+        {
+            let start = AlignedAddress::<Virtual>::new(region.start as *const ())
+                .map_err(MemoryManagerError::Address)?;
+            let pages = (region.end() - region.start) / 4096;
+            unsafe { memory_manager.protect_range(&mut *self.table, start, pages, flags) }?;
+            region.flags = flags;
+        }
+        Ok(())
+    }
+
+    /// Place the heap, which starts out empty at `address` rounded up to a page.
+    /// Programs are given one just past their highest segment when they're loaded
+    pub fn init_program_break(&self, address: usize) {
+        let start = align(address, 4096);
+        *self.program_break.lock() = ProgramBreak {
+            start,
+            end: start,
+            mapped: start,
+        };
+    }
+
+    /// Get where the heap ends, or zero if it hasn't been placed
+    pub fn program_break(&self) -> usize {
+        self.program_break.lock().end
+    }
+
+    /// Move the end of the heap to `address`, and get where it now ends.
+    /// Zeroed memory is mapped as it grows, and freed as it shrinks by whole regions
+    ///
+    /// # Errors
+    /// This will return an error if the heap hasn't been placed, `address` is before its start,
+    /// or the memory can't be mapped
+    pub fn set_program_break(&self, address: usize) -> Result<usize, MemoryManagerError> {
+        let mut program_break = self.program_break.lock();
+        if program_break.start == 0
+            || address < program_break.start
+            || !is_user_range(program_break.start, address - program_break.start)
+        {
+            return Err(MemoryManagerError::OutOfRange);
+        }
+
+        let wanted = align(address, 4096);
+        if wanted > program_break.mapped {
+            let layout = Layout::from_size_align(wanted - program_break.mapped, 4096)
+                .map_err(|_| MemoryManagerError::Generic(GenericError::IntConversionError))?;
+            self.map(
+                Some(program_break.mapped),
+                layout,
+                MemoryFlags::READABLE | MemoryFlags::WRITABLE,
+            )?;
+            program_break.mapped = wanted;
+        } else {
+            // The heap's regions are contiguous, so whichever start past the new end go
+            let removed = take_regions(&mut self.regions.lock(), &(wanted..program_break.mapped));
+            if let Some(start) = removed.iter().map(|region| region.start).min() {
+                program_break.mapped = start;
+            }
+            for region in removed {
+                unsafe { self.free(region) }?;
+            }
+        }
+        program_break.end = address;
+        Ok(address)
+    }
+
+    /// Get the regions mapped into the address space
+    pub fn regions(&self) -> Vec<Region> {
+        self.regions.lock().clone()
+    }
+
+    /// Check whether user mode can access all of `length` bytes from `address`,
+    /// and write to them if `write` is set
+    pub fn is_accessible(&self, address: usize, length: usize, write: bool) -> bool {
+        if length == 0 {
+            return true;
+        }
+        if !is_user_range(address, length) {
+            return false;
+        }
+
+        // Regions can be next to each other, so the range is followed through each one it touches
+        let regions = self.regions.lock();
+        let end = address + length;
+        let mut next = address;
+        while next < end {
+            let region = regions
+                .iter()
+                .find(|region| region.start <= next && next < region.end());
+            match region {
+                Some(region) if !write || region.flags.contains(MemoryFlags::WRITABLE) => {
+                    next = region.end();
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Copy memory out of the address space, from `address` into `buffer`.
+    /// This goes through the direct map, so it works whether or not the address space is loaded
+    ///
+    /// # Errors
+    /// This will return an error if any of the range wasn't mapped by the address space
+    pub fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<(), MemoryManagerError> {
+        if !self.is_accessible(address, buffer.len(), false) {
+            return Err(MemoryManagerError::AddressUnmapped);
+        }
+
+        let offset = crate::HHDM_RANGE.start;
+        self.for_each_page(address, buffer.len(), |virt, phys, len| unsafe {
+            let source = (phys + offset) as *const u8;
+            ptr::copy_nonoverlapping(source, buffer.as_mut_ptr().add(virt - address), len);
+        })
+    }
+
+    /// Copy `bytes` into the address space at `address`, whatever the memory's mapped with.
+    /// This goes through the direct map, so it works whether or not the address space is loaded
+    ///
+    /// # Errors
+    /// This will return an error if any of the range wasn't mapped by the address space
+    pub fn write_bytes(&self, address: usize, bytes: &[u8]) -> Result<(), MemoryManagerError> {
+        if !self.is_accessible(address, bytes.len(), false) {
+            return Err(MemoryManagerError::AddressUnmapped);
+        }
+
+        let offset = crate::HHDM_RANGE.start;
+        self.for_each_page(address, bytes.len(), |virt, phys, len| unsafe {
+            let destination = (phys + offset) as *mut u8;
+            ptr::copy_nonoverlapping(bytes.as_ptr().add(virt - address), destination, len);
+        })
+    }
+
+    /// Call `f` with each piece of the range that's within one page,
+    /// with its virtual address, physical address and length
+    fn for_each_page(
+        &self,
+        address: usize,
+        length: usize,
+        mut f: impl FnMut(usize, usize, usize),
+    ) -> Result<(), MemoryManagerError> {
+        let memory_manager = get_memory_manager();
+        let table = unsafe { &*self.table };
+        let end = address + length;
+        let mut virt = address;
+        while virt < end {
+            let len = (align(virt + 1, 4096)).min(end) - virt;
+            let phys = super::addresses::Address::<Virtual>::new(virt as *const ())
+                .ok()
+                .and_then(|page| memory_manager.virtual_to_physical(table, page))
+                .ok_or(MemoryManagerError::AddressUnmapped)?;
+            let phys = usize::try_from(phys.inner().into_raw())
+                .map_err(|_| MemoryManagerError::Generic(GenericError::IntConversionError))?;
+            f(virt, phys, len);
+            virt += len;
+        }
+        Ok(())
+    }
+
+    /// Unmap and free a region
+    ///
+    /// # Safety
+    /// Nothing may be using the region any more
+    unsafe fn free(&self, region: Region) -> Result<(), MemoryManagerError> {
+        let start = AlignedAddress::<Virtual>::new(region.start as *const ())
+            .map_err(MemoryManagerError::Address)?;
+        get_memory_manager().deallocate_and_unmap(&mut *self.table, start, region.layout)
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        // It's only dropped once nothing's running in it, but this core might still have it loaded
+        if is_loaded(self.table) {
+            Self::activate_kernel();
+        }
+
+        for region in core::mem::take(&mut *self.regions.lock()) {
+            if let Err(e) = unsafe { self.free(region) } {
+                warn!("Couldn't free {region:?}: {e:?}");
+            }
+        }
+        if let Err(e) = unsafe { get_memory_manager().free_user_table(&mut *self.table) } {
+            warn!("Couldn't free an address space's tables: {e:?}");
+        }
+    }
+}
+
+impl core::fmt::Debug for AddressSpace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AddressSpace")
+            .field("regions", &self.regions.lock().len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Check that a range is entirely in user space
+fn is_user_range(address: usize, length: usize) -> bool {
+    address >= USER_SPACE_START
+        && address
+            .checked_add(length)
+            .map_or(false, |end| end <= USER_SPACE_END)
+}
+
+/// Get the pages `length` bytes from `address` cover, which must be page aligned and in user space
+fn page_range(address: usize, length: usize) -> Result<Range<usize>, MemoryManagerError> {
+    if address % 4096 != 0 || !is_user_range(address, length) {
+        return Err(MemoryManagerError::OutOfRange);
+    }
+    Ok(address..align(address + length, 4096))
+}
+
+/// Check whether a region is partly in a range, but not all of it
+fn is_split(region: &Region, range: &Range<usize>) -> bool {
+    let overlaps = region.start < range.end && range.start < region.end();
+    overlaps && (region.start < range.start || range.end < region.end())
+}
+
+/// Remove the regions that are entirely within a range, and get them
+fn take_regions(regions: &mut Vec<Region>, range: &Range<usize>) -> Vec<Region> {
+    let (removed, kept) = core::mem::take(regions)
+        .into_iter()
+        .partition(|region| range.start <= region.start && region.end() <= range.end);
+    *regions = kept;
+    removed
+}
+
+/// Check whether a root table is loaded on the current core
+fn is_loaded(table: *mut RootTable) -> bool {
+    let current: *mut RootTable = unsafe { get_memory_manager().get_current_table() }
+        .map_or(ptr::null_mut(), |current| current);
+    current == table
+}
+
+/// Load a root table on the current core, if it isn't already
+///
+/// # Safety
+/// The table must stay alive while it's loaded
+unsafe fn activate(table: *mut RootTable) {
+    if !is_loaded(table) {
+        let _ = get_memory_manager().current_table(&mut *table);
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
         addresses::{Address, AlignedAddress, Virtual},
         utilities::align,
     },
-    sync::RwLock,
+    sync::{IrqSpinlock, RwLock},
     traits::{MemoryFlags, MemoryManager},
 };
 
@@ -67,10 +67,32 @@ impl core::cmp::Ord for FreeRegion {
     }
 }
 
+/// A snapshot of how the heap is being used
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// How many allocations haven't been freed
+    pub allocations: usize,
+    /// How many bytes those allocations asked for
+    pub allocated_bytes: usize,
+    /// How many bytes are in free regions
+    pub free_bytes: usize,
+    /// How many free regions there are
+    pub free_regions: usize,
+    /// The size of the largest free region, which bounds the largest allocation that can succeed
+    pub largest_free_region: usize,
+}
+
 /// The Lotus OS Heap Allocator
 pub struct Allocator {
     allocated_item_count: AtomicUsize,
+    /// How many allocations haven't been freed
+    allocations: AtomicUsize,
+    /// How many bytes those allocations asked for
+    allocated_bytes: AtomicUsize,
     storage: RwLock<Vec<FreeRegion, NeverAllocator>>,
+    /// Held for a whole allocation or deallocation, so they don't interleave between cores.
+    /// Interrupts are disabled while it's held, so a handler can't deadlock its core on it
+    lock: IrqSpinlock<()>,
 }
 
 static DIV: &str = "================================================================";
@@ -90,7 +112,27 @@ impl Allocator {
     pub const fn new() -> Self {
         Self {
             allocated_item_count: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            allocated_bytes: AtomicUsize::new(0),
             storage: RwLock::new(Vec::new_in(NeverAllocator)),
+            lock: IrqSpinlock::new(()),
+        }
+    }
+
+    /// Get how the heap is being used. This doesn't allocate,
+    /// and holds the allocator's lock so the numbers agree with each other
+    #[must_use]
+    pub fn stats(&self) -> HeapStats {
+        let _lock = self.lock.lock_irqsave();
+        let items = self.storage.read();
+        HeapStats {
+            allocations: self.allocations.load(core::sync::atomic::Ordering::Relaxed),
+            allocated_bytes: self
+                .allocated_bytes
+                .load(core::sync::atomic::Ordering::Relaxed),
+            free_bytes: items.iter().map(|item| item.size).sum(),
+            free_regions: items.len(),
+            largest_free_region: items.iter().map(|item| item.size).max().unwrap_or(0),
         }
     }
 
@@ -141,13 +183,13 @@ impl Allocator {
                         .get_inner_ptr_mut()
                         .cast::<FreeRegion>(),
                     0,
-                    32,
+                    new_size,
                     NeverAllocator,
                 )
             };
 
             {
-                new_vec.clone_from_slice(&data[..]);
+                new_vec.extend_from_slice(&data[..]);
 
                 let old_data = core::mem::replace(&mut *data, new_vec);
 
@@ -376,6 +418,7 @@ unsafe impl GlobalAlloc for Allocator {
     /// * Sorts the regions based on ascending base
     /// * Returns a pointer to the region and then pops it
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _lock = self.lock.lock_irqsave();
         let (size, align) = (layout.size(), layout.align());
         if let Some((region_ptr, alloc_start, region_idx)) = self.find_region(size, align) {
             let region = &mut *region_ptr;
@@ -400,6 +443,10 @@ unsafe impl GlobalAlloc for Allocator {
                 storage.sort_by(Self::sort_ascending_base);
             }
 
+            self.allocations
+                .fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+            self.allocated_bytes
+                .fetch_add(size, core::sync::atomic::Ordering::Relaxed);
             alloc_start as *mut u8
         } else {
             ptr::null_mut()
@@ -410,9 +457,15 @@ unsafe impl GlobalAlloc for Allocator {
     /// * Aligns the layout
     /// * Adds it to the free region list
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _lock = self.lock.lock_irqsave();
         let size = layout.size();
 
         let _ = self.add_free_region(ptr, size).is_ok();
         self.join_nearby();
+
+        self.allocations
+            .fetch_sub(1, core::sync::atomic::Ordering::Relaxed);
+        self.allocated_bytes
+            .fetch_sub(size, core::sync::atomic::Ordering::Relaxed);
     }
 }
@@ -1,5 +1,5 @@
 mod heap;
-pub use heap::Allocator as HeapAllocator;
+pub use heap::{Allocator as HeapAllocator, HeapStats};
 
 mod physical_allocator;
 pub use physical_allocator::PageAllocator;
@@ -37,7 +37,8 @@ impl<'a> core::fmt::Display for PageAllocator<'a> {
 }
 
 impl<'a> PageAllocator<'a> {
-    const BLOCK_SIZE: usize = 4096;
+    /// The size of a page, which is the smallest amount it hands out
+    pub const BLOCK_SIZE: usize = 4096;
     /// Return a new page allocator
     ///
     /// # Example
@@ -55,6 +56,12 @@ impl<'a> PageAllocator<'a> {
         }
     }
 
+    /// Get the amount of pages the memory map covers, including reserved ones
+    #[must_use]
+    pub fn get_total(&self) -> usize {
+        self.pages.load(Ordering::SeqCst)
+    }
+
     /// Get the amount of used pages
     pub fn get_used(&self) -> usize {
         let mut total = 0;
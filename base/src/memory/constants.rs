@@ -7,5 +7,12 @@ pub const MEGABYTE: usize = KILOBYTE * 1024;
 /// A Gigabyte
 pub const GIGABYTE: usize = MEGABYTE * 1024;
 
+/// The start of user space. Below it, the lower half holds the bootloader's identity map,
+/// which page tables are reached through
+pub const USER_SPACE_START: usize = 0x0000_0080_0000_0000;
+
+/// The end of the lower half of the address space, which user programs live in
+pub const USER_SPACE_END: usize = 0x0000_8000_0000_0000;
+
 /// A Petabyte
 pub const PETABYTE: usize = GIGABYTE * 1024;
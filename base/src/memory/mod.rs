@@ -7,6 +7,10 @@ pub mod addresses;
 /// Memory utilities
 pub mod utilities;
 
+/// User address spaces
+mod address_space;
+pub use address_space::{AddressSpace, Region};
+
 /// Memory related constants
 mod constants;
 
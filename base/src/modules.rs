@@ -0,0 +1,66 @@
+use core::{ffi::CStr, slice};
+
+/// A file the bootloader loaded alongside the kernel
+#[derive(Clone, Copy, Debug)]
+pub struct BootModule {
+    /// The path it was loaded from
+    pub path: &'static str,
+    /// The command line it was given in the bootloader's configuration
+    pub cmdline: &'static str,
+    /// Its contents
+    pub data: &'static [u8],
+}
+
+impl BootModule {
+    /// Get the last component of its path
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.path.rsplit('/').next().unwrap_or(self.path)
+    }
+}
+
+/// Get every module the bootloader loaded
+#[must_use]
+pub fn list() -> Vec<BootModule> {
+    let response = match crate::MODULE_REQUEST.response {
+        Some(response) => unsafe { response.as_ref() },
+        None => return Vec::new(),
+    };
+    if response.modules.is_null() {
+        return Vec::new();
+    }
+
+    let count = response.module_count.try_into().unwrap_or(0);
+    let files = unsafe { slice::from_raw_parts(response.modules, count) };
+
+    // They're in memory the page allocator never hands out, and reached through the HHDM
+    #[allow(clippy::cast_possible_truncation)]
+    files
+        .iter()
+        .map(|&file| unsafe { &*file })
+        .map(|file| BootModule {
+            path: unsafe { c_str(file.path.as_ptr().cast()) },
+            cmdline: unsafe { c_str(file.cmdline.as_ptr().cast()) },
+            data: unsafe { slice::from_raw_parts(file.address, file.size as usize) },
+        })
+        .collect()
+}
+
+/// Find a module by its path, or the last component of its path
+#[must_use]
+pub fn find(name: &str) -> Option<BootModule> {
+    list()
+        .into_iter()
+        .find(|module| module.path == name || module.name() == name)
+}
+
+/// Read a string the bootloader gave, which isn't always UTF-8
+///
+/// # Safety
+/// The pointer must be null, or point to a NUL-terminated string that's never freed
+unsafe fn c_str(pointer: *const u8) -> &'static str {
+    if pointer.is_null() {
+        return "";
+    }
+    CStr::from_ptr(pointer.cast()).to_str().unwrap_or("")
+}
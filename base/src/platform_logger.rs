@@ -1,11 +1,73 @@
-use core::fmt::Write;
+use core::fmt::{self, Display, Write};
 
 use log::{error, Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
 
-use crate::{arch::PLATFORM_MANAGER, traits::Platform};
+use crate::{arch::PLATFORM_MANAGER, sync::IrqSpinlock, time::DateTime, traits::Platform};
 
-/// `Log` implementation for serial
-pub struct PlatformLogger;
+/// The time a line was logged at, which is left out before the wall clock is read
+struct Timestamp(Option<DateTime>);
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.map_or(Ok(()), |time| write!(f, "{time} "))
+    }
+}
+
+/// How much of the log is kept to be read back
+const LOG_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How many times the panic handler tries the lock before writing without it
+const PANIC_LOCK_ATTEMPTS: usize = 1_000_000;
+
+/// The most recent part of the log, which wraps around as it fills
+struct LogBuffer {
+    data: [u8; LOG_BUFFER_SIZE],
+    /// How much has ever been logged, which is where the next byte goes before wrapping
+    written: u64,
+}
+
+impl LogBuffer {
+    /// Add to the end of the log, overwriting the oldest part once it's full
+    #[allow(clippy::cast_possible_truncation)]
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.data[(self.written % LOG_BUFFER_SIZE as u64) as usize] = byte;
+            self.written += 1;
+        }
+    }
+
+    /// Copy what was logged from `offset` into `buffer`, starting from the oldest part
+    /// that's kept if `offset` has been overwritten, and get how much was copied
+    #[allow(clippy::cast_possible_truncation)]
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> usize {
+        let oldest = self.written.saturating_sub(LOG_BUFFER_SIZE as u64);
+        let mut position = offset.max(oldest);
+        let mut length = 0;
+        while position < self.written && length < buffer.len() {
+            buffer[length] = self.data[(position % LOG_BUFFER_SIZE as u64) as usize];
+            position += 1;
+            length += 1;
+        }
+        length
+    }
+}
+
+/// Writes a line both to the output and to the log buffer
+struct Tee<'a>(&'a mut LogBuffer);
+
+impl Write for Tee<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.push(s.as_bytes());
+        PLATFORM_MANAGER.get_text_output().write_str(s)
+    }
+}
+
+/// `Log` implementation for serial, which also keeps the most recent part of the log
+pub struct PlatformLogger {
+    /// Held while a line is written, so lines from different cores don't interleave.
+    /// Interrupts are disabled while it's held, so a handler logging can't deadlock its core
+    lock: IrqSpinlock<LogBuffer>,
+}
 
 impl Log for PlatformLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
@@ -13,36 +75,93 @@ impl Log for PlatformLogger {
     }
 
     fn log(&self, record: &Record) {
-        writeln!(
-            PLATFORM_MANAGER.get_text_output(),
-            "[{}:{}] {}: {}",
-            match record.file() {
-                Some(v) => v,
-                None => {
-                    error!("FILE NAME NOT PRESENT");
-                    "???"
-                }
-            },
-            match record.line() {
-                Some(v) => v,
-                None => {
-                    error!("FILE LINE NOT PRESENT");
-                    0
-                }
-            },
+        // These log themselves, so they're worked out before the lock is taken
+        let file = record.file().unwrap_or_else(|| {
+            error!("FILE NAME NOT PRESENT");
+            "???"
+        });
+        let line = record.line().unwrap_or_else(|| {
+            error!("FILE LINE NOT PRESENT");
+            0
+        });
+        let timestamp = Timestamp(crate::time::now().map(DateTime::from_unix));
+
+        // Nothing can panic while the lock is held, as the panic handler logs too,
+        // and there's nowhere to report a failed write
+        let mut buffer = self.lock.lock_irqsave();
+        let _ = writeln!(
+            Tee(&mut buffer),
+            "{}[{}:{}] {}: {}",
+            timestamp,
+            file,
+            line,
             record.level(),
             record.args()
-        )
-        .unwrap();
+        );
     }
 
     fn flush(&self) {}
 }
 
+impl Default for PlatformLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// The static logger
-pub static LOGGER: PlatformLogger = PlatformLogger;
+pub static LOGGER: PlatformLogger = PlatformLogger::new();
 
 impl PlatformLogger {
+    /// Make a logger. It's only meant to be made as a static, as its log buffer is large
+    #[must_use]
+    #[allow(clippy::large_stack_arrays)]
+    pub const fn new() -> Self {
+        Self {
+            lock: IrqSpinlock::new(LogBuffer {
+                data: [0; LOG_BUFFER_SIZE],
+                written: 0,
+            }),
+        }
+    }
+
+    /// Write bytes straight to the output, between whole lines of the log.
+    /// Anything that isn't UTF-8 is replaced
+    pub fn write_bytes(&self, bytes: &[u8]) {
+        let text = String::from_utf8_lossy(bytes);
+        let _lock = self.lock.lock_irqsave();
+        let _ = PLATFORM_MANAGER.get_text_output().write_str(&text);
+    }
+
+    /// Log an error line for the panic handler. If the lock isn't free after a while,
+    /// this core may have panicked while holding it, so the line is written straight to the output
+    pub fn log_panic(&self, args: fmt::Arguments) {
+        for _ in 0..PANIC_LOCK_ATTEMPTS {
+            if let Some(mut buffer) = self.lock.try_lock_irqsave() {
+                let _ = writeln!(Tee(&mut buffer), "{}: {}", Level::Error, args);
+                return;
+            }
+            core::hint::spin_loop();
+        }
+        let _ = writeln!(
+            PLATFORM_MANAGER.get_text_output(),
+            "{}: {}",
+            Level::Error,
+            args
+        );
+    }
+
+    /// Copy what was logged from `offset` into `buffer`, and get how much was copied.
+    /// Only the most recent part of the log is kept, so reading from before it starts there
+    pub fn read_log(&self, offset: u64, buffer: &mut [u8]) -> usize {
+        self.lock.lock_irqsave().read(offset, buffer)
+    }
+
+    /// Get how much has ever been logged
+    pub fn logged(&self) -> u64 {
+        self.lock.lock_irqsave().written
+    }
+
     #[cfg(debug_assertions)]
     const LEVEL: Level = Level::Trace;
     #[cfg(debug_assertions)]
@@ -0,0 +1,28 @@
+/// Who a process runs as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// The real user ID
+    pub uid: u32,
+    /// The real group ID
+    pub gid: u32,
+    /// The effective user ID, which permissions are checked against
+    pub euid: u32,
+    /// The effective group ID, which permissions are checked against
+    pub egid: u32,
+}
+
+impl Credentials {
+    /// The superuser's credentials
+    pub const ROOT: Self = Self::new(0, 0);
+
+    /// Make credentials for a user and group, with the same real and effective IDs
+    #[must_use]
+    pub const fn new(uid: u32, gid: u32) -> Self {
+        Self {
+            uid,
+            gid,
+            euid: uid,
+            egid: gid,
+        }
+    }
+}
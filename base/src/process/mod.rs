@@ -0,0 +1,509 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use log::{debug, error, warn};
+
+use crate::{
+    arch::syscall::SyscallFrame,
+    elf::{self, Elf},
+    errors::ProcessError,
+    fs::FileTable,
+    interrupts::InterruptType,
+    memory::AddressSpace,
+    scheduler::{self, without_interrupts, Thread, ThreadId},
+    sync::{Mutex, RwLock, WaitQueue},
+};
+
+mod credentials;
+pub use credentials::Credentials;
+
+mod status;
+pub use status::{ExitStatus, Fault};
+
+/// The ID of a process, unique while it's in the process table
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pid(pub u64);
+
+impl Pid {
+    /// The ID reported for the kernel's own threads, which aren't in any process
+    pub const KERNEL: Self = Self(0);
+    /// The ID of the first process, which is given the children of processes that exit
+    pub const INIT: Self = Self(1);
+}
+
+/// The next process ID to hand out
+static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+
+/// What processes without a parent use as their standard streams
+const CONSOLE: &str = "/dev/ttyS0";
+
+/// Every process that hasn't been reaped
+static PROCESSES: RwLock<Vec<Arc<Process>>> = RwLock::new(Vec::new());
+
+/// The threads waiting for any process to exit, which check whether it was one of their children
+static EXITS: WaitQueue = WaitQueue::new();
+
+/// A user program, with its own address space, and the threads running in it
+pub struct Process {
+    pid: Pid,
+    name: Mutex<String>,
+    /// Only locked with interrupts disabled, as it's changed when the parent exits
+    parent: Mutex<Option<Weak<Self>>>,
+    /// Only locked with interrupts disabled, as it's loaded while switching threads
+    address_space: Mutex<Arc<AddressSpace>>,
+    /// The threads that haven't exited.
+    /// Only locked with interrupts disabled, as a fault can end a thread
+    threads: Mutex<Vec<Arc<Thread>>>,
+    credentials: Mutex<Credentials>,
+    files: FileTable,
+    /// Set once the process has been told to exit, so its threads stop when they next enter the kernel.
+    /// Only locked with interrupts disabled
+    exit_status: Mutex<Option<ExitStatus>>,
+    /// Set once its last thread has exited
+    exited: AtomicBool,
+    /// The threads waiting for it to exit
+    waiters: WaitQueue,
+}
+
+impl Process {
+    /// Make a process with an empty address space and no threads, and add it to the process table.
+    /// If it has no parent, its standard streams are the console
+    ///
+    /// # Errors
+    /// This will return an error if its address space can't be made
+    pub fn new(
+        name: &str,
+        parent: Option<&Arc<Self>>,
+        credentials: Credentials,
+    ) -> Result<Arc<Self>, ProcessError> {
+        let address_space = AddressSpace::new().map_err(ProcessError::Memory)?;
+        Ok(Self::create(
+            name,
+            parent,
+            credentials,
+            address_space,
+            FileTable::new(),
+        ))
+    }
+
+    /// Make a process with no threads out of its parts, and add it to the process table.
+    /// If it has no parent, its standard streams are the console
+    fn create(
+        name: &str,
+        parent: Option<&Arc<Self>>,
+        credentials: Credentials,
+        address_space: AddressSpace,
+        files: FileTable,
+    ) -> Arc<Self> {
+        let process = Arc::new(Self {
+            pid: Pid(NEXT_PID.fetch_add(1, Ordering::Relaxed)),
+            name: Mutex::new(String::from(name)),
+            parent: Mutex::new(parent.map(Arc::downgrade)),
+            address_space: Mutex::new(Arc::new(address_space)),
+            threads: Mutex::new(Vec::new()),
+            credentials: Mutex::new(credentials),
+            files,
+            exit_status: Mutex::new(None),
+            exited: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+        });
+
+        if parent.is_none() {
+            if let Err(e) = process.files.open_standard_streams(CONSOLE) {
+                warn!("Process {} has no standard streams: {e:?}", process.pid.0);
+            }
+        }
+        PROCESSES.write().push(process.clone());
+        debug!("Created process {} ({name})", process.pid.0);
+        process
+    }
+
+    /// Make a process running an ELF program, with the given arguments and environment,
+    /// and start its main thread
+    ///
+    /// # Errors
+    /// This will return an error if the program can't be loaded, or its thread can't be started
+    ///
+    /// # Panics
+    /// This will panic if the scheduler isn't running on this core
+    pub fn spawn_program(
+        name: &str,
+        parent: Option<&Arc<Self>>,
+        credentials: Credentials,
+        program: &[u8],
+        arguments: &[&str],
+        environment: &[&str],
+    ) -> Result<Arc<Self>, ProcessError> {
+        let (address_space, entry, stack) =
+            load_program(program, arguments, environment, credentials)?;
+        let process = Self::create(name, parent, credentials, address_space, FileTable::new());
+
+        if let Err(e) = process.spawn_thread(entry, stack) {
+            // It never ran, so there's nothing to wait for
+            process.reap();
+            return Err(e);
+        }
+        Ok(process)
+    }
+
+    /// Make a child of the process with a copy of its memory, and the same open files
+    /// and working directory, and start a thread in it which returns to user mode with `frame`
+    ///
+    /// # Errors
+    /// This will return an error if the memory can't be copied, or the thread can't be started
+    ///
+    /// # Panics
+    /// This will panic if the scheduler isn't running on this core
+    pub fn fork(self: &Arc<Self>, frame: SyscallFrame) -> Result<Arc<Self>, ProcessError> {
+        let address_space = self
+            .address_space()
+            .duplicate()
+            .map_err(ProcessError::Memory)?;
+        let child = Self::create(
+            &self.name(),
+            Some(self),
+            self.credentials(),
+            address_space,
+            self.files.duplicate(),
+        );
+
+        if let Err(e) = scheduler::spawn_user(child.clone(), frame) {
+            child.reap();
+            return Err(ProcessError::Scheduler(e));
+        }
+        Ok(child)
+    }
+
+    /// Replace the process's program with an ELF one, with the given arguments and environment,
+    /// and get where the calling thread enters it and its stack pointer.
+    /// The old program's memory is freed, and its open files are kept.
+    /// Unlike Linux, this doesn't end the process's other threads, as there's no way to end
+    /// a thread other than the calling one without ending the process, so it has to be the only one
+    ///
+    /// # Errors
+    /// This will return an error if the process has other threads, or the program can't be loaded,
+    /// in which case the old one is left as it was
+    pub fn exec(
+        &self,
+        name: &str,
+        program: &[u8],
+        arguments: &[&str],
+        environment: &[&str],
+    ) -> Result<(usize, usize), ProcessError> {
+        if self.threads().len() > 1 {
+            return Err(ProcessError::Threaded);
+        }
+        let (address_space, entry, stack) =
+            load_program(program, arguments, environment, self.credentials())?;
+
+        let address_space = Arc::new(address_space);
+        let old = without_interrupts(|| {
+            let mut current = self.address_space.lock();
+            address_space.activate();
+            core::mem::replace(&mut *current, address_space)
+        });
+        drop(old);
+        *self.name.lock() = String::from(name);
+        debug!("Process {} is now running {name}", self.pid.0);
+        Ok((entry, stack))
+    }
+
+    /// Get the process's ID
+    pub const fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Get the process's name
+    pub fn name(&self) -> String {
+        self.name.lock().clone()
+    }
+
+    /// Get the process's parent, if it has one that hasn't been reaped
+    pub fn parent(&self) -> Option<Arc<Self>> {
+        without_interrupts(|| self.parent.lock().as_ref().and_then(Weak::upgrade))
+    }
+
+    /// Get the process's children that haven't been reaped
+    pub fn children(&self) -> Vec<Arc<Self>> {
+        PROCESSES
+            .read()
+            .iter()
+            .filter(|process| process.is_child_of(self))
+            .cloned()
+            .collect()
+    }
+
+    /// Get who the process runs as
+    pub fn credentials(&self) -> Credentials {
+        *self.credentials.lock()
+    }
+
+    /// Get the process's open files and working directory
+    pub const fn files(&self) -> &FileTable {
+        &self.files
+    }
+
+    /// Get the process's address space
+    pub fn address_space(&self) -> Arc<AddressSpace> {
+        without_interrupts(|| self.address_space.lock().clone())
+    }
+
+    /// Get the IDs of the process's threads that haven't exited
+    pub fn threads(&self) -> Vec<ThreadId> {
+        let threads = without_interrupts(|| self.threads.lock().clone());
+        threads.iter().map(|thread| thread.id()).collect()
+    }
+
+    /// Get how the process ended, or is ending, if it's been told to exit
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        without_interrupts(|| *self.exit_status.lock())
+    }
+
+    /// Check whether the process has been told to exit
+    pub fn is_exiting(&self) -> bool {
+        self.exit_status().is_some()
+    }
+
+    /// Check whether all of the process's threads have exited
+    pub fn has_exited(&self) -> bool {
+        self.exited.load(Ordering::Acquire)
+    }
+
+    /// Start a thread in the process, which drops straight to user mode
+    /// at `entry` with its stack pointer at `stack`
+    ///
+    /// # Errors
+    /// This will return an error if the process is exiting, or the thread can't be started
+    ///
+    /// # Panics
+    /// This will panic if the scheduler isn't running on this core
+    pub fn spawn_thread(
+        self: &Arc<Self>,
+        entry: usize,
+        stack: usize,
+    ) -> Result<Arc<Thread>, ProcessError> {
+        scheduler::spawn_user(self.clone(), SyscallFrame::new_user(entry, stack))
+            .map_err(ProcessError::Scheduler)
+    }
+
+    /// Add a thread that's about to start, unless the process is exiting.
+    /// Returns whether it was added
+    pub(crate) fn add_thread(&self, thread: &Arc<Thread>) -> bool {
+        without_interrupts(|| {
+            if self.exit_status.lock().is_some() {
+                return false;
+            }
+            self.threads.lock().push(thread.clone());
+            true
+        })
+    }
+
+    /// Tell the process to exit with the given status, unless it's already been told to.
+    /// Its threads stop the next time they enter the kernel
+    pub fn kill(&self, status: ExitStatus) {
+        without_interrupts(|| {
+            let mut exit_status = self.exit_status.lock();
+            if exit_status.is_none() {
+                *exit_status = Some(status);
+            }
+        });
+    }
+
+    /// Block until all of the process's threads have exited, and get how it ended
+    pub fn wait(&self) -> ExitStatus {
+        self.waiters.wait_until(|| self.has_exited());
+        self.exit_status()
+            .unwrap_or_else(|| unreachable!("A process exited without a status"))
+    }
+
+    /// Block until one of the process's children has exited, or the one with ID `pid` if it's given,
+    /// then reap it and get its ID and how it ended.
+    /// Without `block`, `None` is returned straight away if none has exited
+    ///
+    /// # Errors
+    /// This will return an error if the process has no such children
+    pub fn wait_child(
+        &self,
+        pid: Option<Pid>,
+        block: bool,
+    ) -> Result<Option<(Pid, ExitStatus)>, ProcessError> {
+        let mut found = Ok(None);
+        EXITS.wait_until(|| {
+            let processes = PROCESSES.read();
+            let mut children = processes
+                .iter()
+                .filter(|process| process.is_child_of(self))
+                .filter(|process| pid.map_or(true, |pid| process.pid == pid))
+                .peekable();
+            if children.peek().is_none() {
+                found = Err(ProcessError::NoChildren);
+                return true;
+            }
+            found = Ok(children.find(|child| child.has_exited()).cloned());
+            !block || matches!(found, Ok(Some(_)))
+        });
+
+        Ok(found?.map(|child| {
+            let status = child.wait();
+            child.reap();
+            (child.pid, status)
+        }))
+    }
+
+    /// Remove an exited process from the process table,
+    /// so its ID is gone and its memory is freed once nothing else refers to it
+    pub fn reap(&self) {
+        PROCESSES.write().retain(|process| process.pid != self.pid);
+    }
+
+    /// Check whether `parent` is the process's parent
+    fn is_child_of(&self, parent: &Self) -> bool {
+        without_interrupts(|| {
+            self.parent
+                .lock()
+                .as_ref()
+                .map_or(false, |weak| core::ptr::eq(weak.as_ptr(), parent))
+        })
+    }
+
+    /// Give the process's children to [`Pid::INIT`], or leave them without a parent if it's gone.
+    /// This never allocates or frees, as the process is still alive to be pointed at
+    fn orphan_children(&self) {
+        let processes = PROCESSES.read();
+        let init = processes
+            .iter()
+            .find(|process| process.pid == Pid::INIT && process.pid != self.pid)
+            .map(Arc::downgrade);
+        for child in processes.iter().filter(|process| process.is_child_of(self)) {
+            without_interrupts(|| child.parent.lock().clone_from(&init));
+        }
+    }
+
+    /// Load the process's address space on the current core. Interrupts must be disabled
+    pub(crate) fn activate(&self) {
+        self.address_space.lock().activate();
+    }
+
+    /// Remove a thread that's exiting, and if it was the last one,
+    /// mark the process as exited with `status`, unless it was told to exit with another.
+    /// This never allocates or frees, so it can be called from an interrupt handler
+    fn thread_exiting(&self, thread: &Thread, status: ExitStatus) {
+        let last = without_interrupts(|| {
+            let mut threads = self.threads.lock();
+            // The scheduler still refers to the thread, so removing it doesn't free it
+            threads.retain(|other| other.id() != thread.id());
+            if !threads.is_empty() {
+                return false;
+            }
+
+            let mut exit_status = self.exit_status.lock();
+            if exit_status.is_none() {
+                *exit_status = Some(status);
+            }
+            true
+        });
+
+        if last {
+            self.orphan_children();
+            self.exited.store(true, Ordering::Release);
+            self.waiters.wake_all();
+            EXITS.wake_all();
+        }
+    }
+}
+
+impl core::fmt::Debug for Process {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Process")
+            .field("pid", &self.pid)
+            .field("name", &*self.name.lock())
+            .field("exited", &self.has_exited())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Load an ELF program into a new address space, with its arguments and environment on its stack,
+/// and get the address space, where the program starts, and its stack pointer
+fn load_program(
+    program: &[u8],
+    arguments: &[&str],
+    environment: &[&str],
+    credentials: Credentials,
+) -> Result<(AddressSpace, usize, usize), ProcessError> {
+    let elf = Elf::parse(program).map_err(ProcessError::Elf)?;
+    let address_space = AddressSpace::new().map_err(ProcessError::Memory)?;
+    let image = elf::load(&address_space, &elf).map_err(ProcessError::Elf)?;
+    let stack = elf::build_stack(&address_space, &image, arguments, environment, credentials)
+        .map_err(ProcessError::Elf)?;
+    Ok((address_space, image.entry, stack))
+}
+
+/// Get the process the current thread belongs to, or `None` for the kernel's own threads
+pub fn current() -> Option<Arc<Process>> {
+    scheduler::try_current()?.process().cloned()
+}
+
+/// Get the ID of the process the current thread belongs to, or [`Pid::KERNEL`].
+/// This never allocates, so it can be called from an interrupt handler
+pub fn current_pid() -> Pid {
+    scheduler::try_current()
+        .and_then(|thread| thread.process().map(|process| process.pid))
+        .unwrap_or(Pid::KERNEL)
+}
+
+/// Find a process in the process table
+pub fn get(pid: Pid) -> Option<Arc<Process>> {
+    PROCESSES
+        .read()
+        .iter()
+        .find(|process| process.pid == pid)
+        .cloned()
+}
+
+/// Get every process in the process table
+pub fn list() -> Vec<Arc<Process>> {
+    PROCESSES.read().clone()
+}
+
+/// End the current thread. If it's the last one in its process,
+/// the process exits with `status`, unless it was already told to exit with another
+///
+/// # Panics
+/// This will panic if the scheduler isn't running on this core, or if it's the idle thread
+pub fn exit_thread(status: ExitStatus) -> ! {
+    // Nothing can be left on this stack that needs dropping, as it's never returned to
+    {
+        let thread = scheduler::current();
+        if let Some(process) = thread.process() {
+            process.thread_exiting(&thread, status);
+        }
+    }
+    scheduler::exit()
+}
+
+/// End the current process with `status`, unless it was already told to exit with another.
+/// The other threads stop the next time they enter the kernel
+///
+/// # Panics
+/// This will panic if the scheduler isn't running on this core, or if it's the idle thread
+pub fn exit(status: ExitStatus) -> ! {
+    if let Some(process) = current() {
+        process.kill(status);
+    }
+    exit_thread(status)
+}
+
+/// End the current thread if its process has been told to exit.
+/// This is called whenever a thread is about to return to user mode
+pub fn check_exiting() {
+    let status = current().and_then(|process| process.exit_status());
+    if let Some(status) = status {
+        exit_thread(status);
+    }
+}
+
+/// Kill the current process for a fault it caused in user mode, reporting what happened.
+/// This is called from the fault's handler, and never allocates
+pub(crate) fn fault(interrupt: &InterruptType) -> ! {
+    error!("Process {} was killed by {interrupt:?}", current_pid().0);
+    exit(ExitStatus::Faulted(Fault::from(interrupt)))
+}
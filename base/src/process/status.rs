@@ -0,0 +1,38 @@
+use crate::interrupts::InterruptType;
+
+/// How a process ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// It exited with this code
+    Exited(i32),
+    /// It was killed for a fault in user mode
+    Faulted(Fault),
+}
+
+/// What kind of fault a process was killed for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// An arithmetic error, like dividing by zero
+    Arithmetic,
+    /// An invalid or privileged instruction
+    Instruction,
+    /// An access to memory it isn't allowed to make
+    Memory,
+    /// Anything else
+    Other,
+}
+
+impl From<&InterruptType> for Fault {
+    fn from(interrupt: &InterruptType) -> Self {
+        match interrupt {
+            InterruptType::DivideByZero(_)
+            | InterruptType::FloatingPoint(_)
+            | InterruptType::SIMDError(_) => Self::Arithmetic,
+            InterruptType::InvalidInstruction(_) => Self::Instruction,
+            InterruptType::IllegalAccess(_) => Self::Memory,
+            // General protection faults are mostly bad addresses
+            InterruptType::Generic(context) if context.interrupt_number == 13 => Self::Memory,
+            _ => Self::Other,
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    traits::{Platform, TimerManager},
+};
+
+/// The `SplitMix64` increment
+const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// The generator's state, shared by every core
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Get a pseudo-random number. This never allocates or blocks.
+///
+/// There's no entropy source yet, so each one is only as unpredictable as the monotonic clock
+/// when it's asked for, and none are fit for cryptography
+#[allow(clippy::cast_possible_truncation)]
+pub fn next_u64() -> u64 {
+    let time = PLATFORM_MANAGER.get_timer_manager().monotonic().as_nanos() as u64;
+    // SplitMix64, with the clock mixed into its state
+    let mut z = STATE
+        .fetch_add(GAMMA, Ordering::Relaxed)
+        .wrapping_add(GAMMA)
+        ^ time;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Fill a buffer with pseudo-random bytes, from [`next_u64`]
+pub fn fill(buffer: &mut [u8]) {
+    for chunk in buffer.chunks_mut(8) {
+        chunk.copy_from_slice(&next_u64().to_le_bytes()[..chunk.len()]);
+    }
+}
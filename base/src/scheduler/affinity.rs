@@ -0,0 +1,67 @@
+use crate::smp::MAX_CORES;
+
+/// The number of words in an affinity mask
+const WORDS: usize = MAX_CORES / 64;
+
+/// The set of cores a thread is allowed to run on, by core index
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Affinity([u64; WORDS]);
+
+impl Affinity {
+    /// Allow every core
+    #[must_use]
+    pub const fn all() -> Self {
+        Self([u64::MAX; WORDS])
+    }
+
+    /// Allow no cores, to be filled in with [`Affinity::insert`]
+    #[must_use]
+    pub const fn none() -> Self {
+        Self([0; WORDS])
+    }
+
+    /// Allow only the core with the given index
+    ///
+    /// # Panics
+    /// This will panic if the index is at least [`MAX_CORES`]
+    #[must_use]
+    pub fn only(index: usize) -> Self {
+        let mut affinity = Self::none();
+        affinity.insert(index);
+        affinity
+    }
+
+    /// Allow the core with the given index
+    ///
+    /// # Panics
+    /// This will panic if the index is at least [`MAX_CORES`]
+    pub fn insert(&mut self, index: usize) {
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    /// Disallow the core with the given index
+    ///
+    /// # Panics
+    /// This will panic if the index is at least [`MAX_CORES`]
+    pub fn remove(&mut self, index: usize) {
+        self.0[index / 64] &= !(1 << (index % 64));
+    }
+
+    /// Check whether the core with the given index is allowed
+    #[must_use]
+    pub const fn contains(&self, index: usize) -> bool {
+        index < MAX_CORES && self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Check whether no cores are allowed
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&word| word == 0)
+    }
+}
+
+impl Default for Affinity {
+    fn default() -> Self {
+        Self::all()
+    }
+}
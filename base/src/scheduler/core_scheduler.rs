@@ -0,0 +1,186 @@
+use core::{
+    cell::UnsafeCell,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::sync::Mutex;
+
+use super::Thread;
+
+/// A core's scheduler, which runs the threads on its run queue round-robin,
+/// falling back to its idle thread
+///
+/// Everything but the run queue and load is only touched by the core itself, with interrupts disabled
+pub struct CoreScheduler {
+    /// The core's runnable threads, in the order they'll run.
+    /// It always has room for every thread, so threads can be queued from interrupt handlers
+    /// and moved between cores without allocating.
+    /// Only locked with interrupts disabled, as threads are queued from interrupt handlers
+    run_queue: Mutex<VecDeque<Arc<Thread>>>,
+    current: UnsafeCell<Option<Arc<Thread>>>,
+    idle: UnsafeCell<Option<Arc<Thread>>>,
+    /// The thread that was switched away from, until the core is off its stack
+    previous: UnsafeCell<*const Thread>,
+    /// A thread that exited on this core, kept until the core has switched off its stack
+    exited: UnsafeCell<Option<Arc<Thread>>>,
+    /// Set once the scheduler has started
+    started: AtomicBool,
+    /// Set while the core is running something other than its idle thread
+    busy: AtomicBool,
+    /// Set when the current thread should be switched out at the end of the next interrupt
+    need_reschedule: AtomicBool,
+}
+
+impl CoreScheduler {
+    /// Make a scheduler with nothing to run, which does nothing until it's started
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            run_queue: Mutex::new(VecDeque::new()),
+            current: UnsafeCell::new(None),
+            idle: UnsafeCell::new(None),
+            previous: UnsafeCell::new(ptr::null()),
+            exited: UnsafeCell::new(None),
+            started: AtomicBool::new(false),
+            busy: AtomicBool::new(false),
+            need_reschedule: AtomicBool::new(false),
+        }
+    }
+
+    /// Start the scheduler, with the given thread as the idle thread and the one running now.
+    ///
+    /// # Safety
+    /// This must be called on the scheduler's own core with interrupts disabled
+    pub(super) unsafe fn start(&self, idle: Arc<Thread>) {
+        *self.current.get() = Some(idle.clone());
+        *self.idle.get() = Some(idle);
+        self.started.store(true, Ordering::Release);
+    }
+
+    /// Check whether the scheduler has started, so threads can be put on this core
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Acquire)
+    }
+
+    /// Get the thread running on this core, if the scheduler has started
+    ///
+    /// # Safety
+    /// This must be called on the scheduler's own core with interrupts disabled
+    pub(super) unsafe fn current(&self) -> Option<&Arc<Thread>> {
+        (*self.current.get()).as_ref()
+    }
+
+    /// Set the thread running on this core
+    ///
+    /// # Safety
+    /// This must be called on the scheduler's own core with interrupts disabled
+    pub(super) unsafe fn set_current(&self, thread: Arc<Thread>) {
+        let busy = self.idle().map_or(true, |idle| !Arc::ptr_eq(idle, &thread));
+        self.busy.store(busy, Ordering::Relaxed);
+        *self.current.get() = Some(thread);
+    }
+
+    /// Get this core's idle thread, if the scheduler has started
+    ///
+    /// # Safety
+    /// This must be called on the scheduler's own core with interrupts disabled
+    pub(super) unsafe fn idle(&self) -> Option<&Arc<Thread>> {
+        (*self.idle.get()).as_ref()
+    }
+
+    /// Remember the thread being switched away from, until the core is off its stack
+    ///
+    /// # Safety
+    /// This must be called on the scheduler's own core with interrupts disabled,
+    /// and the thread must be kept alive until it's taken again
+    pub(super) unsafe fn set_previous(&self, thread: *const Thread) {
+        *self.previous.get() = thread;
+    }
+
+    /// Take the thread that was switched away from, if there was one
+    ///
+    /// # Safety
+    /// This must be called on the scheduler's own core with interrupts disabled
+    pub(super) unsafe fn take_previous(&self) -> *const Thread {
+        core::mem::replace(&mut *self.previous.get(), ptr::null())
+    }
+
+    /// Keep a thread that's exiting until the core has switched off its stack
+    ///
+    /// # Safety
+    /// This must be called on the scheduler's own core with interrupts disabled
+    pub(super) unsafe fn set_exited(&self, thread: Arc<Thread>) {
+        *self.exited.get() = Some(thread);
+    }
+
+    /// Take the thread that exited before the last switch, if there was one
+    ///
+    /// # Safety
+    /// This must be called on the scheduler's own core with interrupts disabled
+    pub(super) unsafe fn take_exited(&self) -> Option<Arc<Thread>> {
+        (*self.exited.get()).take()
+    }
+
+    /// Make sure the run queue has room for the given number of threads.
+    /// Interrupts must be disabled
+    pub(super) fn reserve(&self, threads: usize) {
+        let mut queue = self.run_queue.lock();
+        let additional = threads.saturating_sub(queue.len());
+        queue.reserve(additional);
+    }
+
+    /// Put a thread on the back of the run queue. Interrupts must be disabled
+    pub(super) fn enqueue(&self, thread: Arc<Thread>) {
+        self.run_queue.lock().push_back(thread);
+    }
+
+    /// Take the thread at the front of the run queue, putting `requeue` on the back if it's given.
+    /// Interrupts must be disabled
+    pub(super) fn rotate(&self, requeue: Option<&Arc<Thread>>) -> Option<Arc<Thread>> {
+        let mut queue = self.run_queue.lock();
+        let next = queue.pop_front()?;
+        if let Some(thread) = requeue {
+            queue.push_back(thread.clone());
+        }
+        Some(next)
+    }
+
+    /// Take a thread for the core with the given index to run, if there's one that's allowed to.
+    /// The most recently queued threads are taken first, as they're the least likely
+    /// to still be in this core's caches. Interrupts must be disabled
+    pub(super) fn steal(&self, index: usize) -> Option<Arc<Thread>> {
+        let mut queue = self.run_queue.lock();
+        let position = queue.iter().rposition(|thread| {
+            // A thread still being switched away from can't be run anywhere else yet
+            !thread.on_cpu.load(Ordering::Acquire) && thread.affinity().contains(index)
+        })?;
+        queue.remove(position)
+    }
+
+    /// Get the number of threads waiting to run. Interrupts must be disabled
+    pub fn runnable(&self) -> usize {
+        self.run_queue.lock().len()
+    }
+
+    /// Get the number of threads running or waiting to run. Interrupts must be disabled
+    pub fn load(&self) -> usize {
+        self.runnable() + usize::from(self.busy.load(Ordering::Relaxed))
+    }
+
+    /// Ask for the current thread to be switched out at the end of the next interrupt
+    pub fn request_reschedule(&self) {
+        self.need_reschedule.store(true, Ordering::Release);
+    }
+
+    /// Check whether a reschedule was asked for, clearing the request
+    pub(super) fn take_reschedule(&self) -> bool {
+        self.need_reschedule.swap(false, Ordering::AcqRel)
+    }
+}
+
+impl Default for CoreScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,589 @@
+use core::{
+    hint, ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use log::warn;
+
+use crate::{
+    arch::{context, ipi, syscall, PLATFORM_MANAGER},
+    errors::SchedulerError,
+    memory::AddressSpace,
+    process::{self, Process},
+    smp::{this_core, try_this_core, CoreLocalData},
+    sync::Mutex,
+    timer,
+    traits::{InterruptManager, Platform, TimerKind, TimerManager},
+};
+
+mod affinity;
+pub use affinity::Affinity;
+
+mod core_scheduler;
+pub use core_scheduler::CoreScheduler;
+
+mod thread;
+use thread::Start;
+pub use thread::{JoinHandle, Thread, ThreadId};
+
+/// The size of each kernel thread's stack
+pub const STACK_SIZE: usize = 64 * 1024;
+
+/// How long a thread runs before it's preempted, if another is waiting for its core
+pub const TIME_SLICE: Duration = Duration::from_millis(10);
+
+/// The number of threads that haven't exited, not counting idle threads.
+/// Every run queue has room for all of them
+static THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Threads that have exited and been switched away from, waiting to be freed.
+/// They're linked through [`Thread::next_zombie`], so exiting never allocates or frees
+static ZOMBIES: AtomicPtr<Thread> = AtomicPtr::new(ptr::null_mut());
+
+/// Why the current thread is giving up its core
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Reason {
+    /// It asked to let another thread run
+    Yield,
+    /// Its time slice ran out, or a woken thread is waiting
+    Preempt,
+    /// It's waiting to be woken
+    Block,
+    /// It's finished
+    Exit,
+}
+
+/// Run a closure with interrupts disabled on the current core
+pub(crate) fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    PLATFORM_MANAGER
+        .get_interrupt_manager()
+        .without_interrupts(f)
+}
+
+/// Start scheduling on the current core, turning the code running on it into its idle thread.
+/// The core must have been registered with the core manager, and its timers set up
+pub fn init_current_core() {
+    let core = this_core();
+    let idle = Thread::new_current(core.index);
+    without_interrupts(|| {
+        core.scheduler.reserve(THREADS.load(Ordering::Acquire));
+        #[cfg(feature = "lockdep")]
+        unsafe {
+            crate::sync::lockdep::set_current(idle.held.get());
+        }
+        unsafe { core.scheduler.start(idle) };
+    });
+
+    // With the HPET or PIT, other cores' timers fire on the bootstrap core,
+    // which passes the request on. The core is also woken to balance its load,
+    // so an idle core picks up work from a busy one
+    let index = core.index;
+    if let Err(e) = timer::set_timer(
+        TIME_SLICE,
+        TimerKind::Periodic,
+        Box::new(move |_| {
+            if let Some(core) = crate::CORE_MANAGER.get_core_by_index(index) {
+                if core.scheduler.runnable() > 0 || busiest(core).is_some() {
+                    kick(index);
+                }
+            }
+        }),
+    ) {
+        warn!("Threads on core {} won't be preempted: {e:?}", core.id);
+    }
+}
+
+/// Get the thread running on the current core
+///
+/// # Panics
+/// This will panic if the scheduler isn't running on this core
+pub fn current() -> Arc<Thread> {
+    without_interrupts(|| unsafe { this_core().scheduler.current() }.cloned())
+        .expect("The scheduler isn't running on this core")
+}
+
+/// Get the thread running on the current core, if the scheduler is running on it
+pub fn try_current() -> Option<Arc<Thread>> {
+    let core = try_this_core()?;
+    without_interrupts(|| unsafe { core.scheduler.current() }.cloned())
+}
+
+/// Start a kernel thread running `f`, on the current core unless another is less loaded
+///
+/// # Panics
+/// This will panic if the scheduler isn't running on this core
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_with_affinity(Affinity::all(), f)
+        .unwrap_or_else(|_| unreachable!("The current core is always allowed"))
+}
+
+/// Start a kernel thread running `f`, which is only allowed on the given cores.
+/// It starts on the least loaded of them, preferring the current core
+///
+/// # Errors
+/// This will return an error if none of the allowed cores are running the scheduler
+///
+/// # Panics
+/// This will panic if the scheduler isn't running on this core
+pub fn spawn_with_affinity<F, T>(affinity: Affinity, f: F) -> Result<JoinHandle<T>, SchedulerError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    reap();
+    assert!(
+        this_core().scheduler.is_started(),
+        "The scheduler isn't running on this core"
+    );
+    if least_loaded(&affinity).is_none() {
+        return Err(SchedulerError::NoAllowedCores);
+    }
+
+    let result = Arc::new(Mutex::new(None));
+    let packet = result.clone();
+    let thread = Thread::new(
+        this_core().index,
+        affinity,
+        Start::Kernel(Box::new(move || {
+            let value = f();
+            *packet.lock() = Some(value);
+        })),
+    );
+    start(&thread);
+
+    Ok(JoinHandle::new(thread, result))
+}
+
+/// Start a thread in `process`, which drops straight to user mode with the registers in `frame`.
+/// It can run on any core
+///
+/// # Errors
+/// This will return an error if the process is exiting
+///
+/// # Panics
+/// This will panic if the scheduler isn't running on this core
+pub(crate) fn spawn_user(
+    process: Arc<Process>,
+    frame: syscall::SyscallFrame,
+) -> Result<Arc<Thread>, SchedulerError> {
+    reap();
+    assert!(
+        this_core().scheduler.is_started(),
+        "The scheduler isn't running on this core"
+    );
+
+    let thread = Thread::new(
+        this_core().index,
+        Affinity::all(),
+        Start::User {
+            process: process.clone(),
+            frame,
+        },
+    );
+    // It has to be in the process before it runs, or the process could exit without it
+    if !process.add_thread(&thread) {
+        return Err(SchedulerError::ProcessExiting);
+    }
+    start(&thread);
+
+    Ok(thread)
+}
+
+/// Queue a new thread on the best core for it
+fn start(thread: &Arc<Thread>) {
+    // Any core can end up running it, so they all need room for it
+    let threads = THREADS.fetch_add(1, Ordering::AcqRel) + 1;
+    let core_count = crate::CORE_MANAGER.core_count();
+    for index in 0..core_count {
+        if let Some(core) = crate::CORE_MANAGER.get_core_by_index(index) {
+            without_interrupts(|| core.scheduler.reserve(threads));
+        }
+    }
+
+    without_interrupts(|| {
+        let mut state = thread.state.lock();
+        let core = place(thread, state.core);
+        state.core = core;
+        drop(state);
+
+        if let Some(data) = crate::CORE_MANAGER.get_core_by_index(core) {
+            data.scheduler.enqueue(thread.clone());
+        }
+        if core != this_core().index {
+            kick(core);
+        }
+    });
+}
+
+/// Let another thread on this core run, if there is one
+pub fn yield_now() {
+    without_interrupts(|| schedule(Reason::Yield));
+}
+
+/// Give up the core until the current thread is woken.
+/// [`Thread::prepare_to_block`] must have been called first,
+/// and if the thread was woken since then this returns straight away
+pub(crate) fn block() {
+    without_interrupts(|| schedule(Reason::Block));
+}
+
+/// Block the current thread for at least the given duration
+///
+/// # Panics
+/// This will panic if the scheduler isn't running on this core
+pub fn sleep(duration: Duration) {
+    let timers = PLATFORM_MANAGER.get_timer_manager();
+    let deadline = timers.monotonic() + duration;
+    let thread = current();
+
+    // Other wakes can end a sleep early, so it's carried on until the deadline
+    loop {
+        let remaining = deadline.saturating_sub(timers.monotonic());
+        if remaining.is_zero() {
+            return;
+        }
+
+        thread.prepare_to_block();
+        let waker = thread.clone();
+        let set = timer::set_timer(
+            remaining,
+            TimerKind::OneShot,
+            Box::new(move |_| {
+                waker.wake();
+            }),
+        );
+
+        let handle = match set {
+            Ok(handle) => handle,
+            Err(e) => {
+                // Without a timer to wake it, the thread can only spin
+                warn!("Couldn't set a timer to sleep on: {e:?}");
+                thread.cancel_block();
+                timers.stall(remaining);
+                return;
+            }
+        };
+        block();
+        // If something else woke it, the timer would wake it again later for no reason.
+        // Having already fired is the usual case, so failing to cancel it is fine
+        let _ = handle.cancel();
+    }
+}
+
+/// End the current thread, waking any threads joining it
+///
+/// # Panics
+/// This will panic if the scheduler isn't running on this core, or if it's the idle thread
+pub fn exit() -> ! {
+    let _ = PLATFORM_MANAGER
+        .get_interrupt_manager()
+        .disable_interrupts();
+
+    // Nothing can be left on this stack that needs dropping, as it's never returned to
+    {
+        let scheduler = &this_core().scheduler;
+        let current =
+            unsafe { scheduler.current() }.expect("The scheduler isn't running on this core");
+        let idle = unsafe { scheduler.idle() };
+        assert!(
+            !idle.map_or(false, |idle| Arc::ptr_eq(idle, current)),
+            "The idle thread can't exit"
+        );
+        current.finish();
+    }
+
+    schedule(Reason::Exit);
+    unreachable!("An exited thread was switched back to");
+}
+
+/// Run the current core's idle thread, freeing exited threads and running any runnable ones,
+/// and halting while there are none. This must be called from the idle thread
+pub fn idle() -> ! {
+    let interrupts = PLATFORM_MANAGER.get_interrupt_manager();
+    loop {
+        reap();
+
+        let _ = interrupts.disable_interrupts();
+        balance();
+        if this_core().scheduler.runnable() > 0 {
+            schedule(Reason::Yield);
+            let _ = interrupts.enable_interrupts();
+        } else {
+            // Anything woken from here on interrupts the wait
+            interrupts.wait_for_interrupt();
+        }
+    }
+}
+
+/// Switch threads if the current core has been asked to.
+/// This is called at the end of interrupt handlers, once they've acknowledged the interrupt
+pub fn preempt() {
+    if let Some(core) = try_this_core() {
+        if core.scheduler.take_reschedule() {
+            schedule(Reason::Preempt);
+        }
+    }
+}
+
+/// Choose the core a runnable thread should be queued on, keeping it where it was if it's allowed
+/// to stay there, and otherwise moving it to the least loaded core it's allowed on
+fn place(thread: &Thread, core: usize) -> usize {
+    let affinity = thread.affinity();
+    let started = crate::CORE_MANAGER
+        .get_core_by_index(core)
+        .map_or(false, |core| core.scheduler.is_started());
+    if affinity.contains(core) && started {
+        return core;
+    }
+    least_loaded(&affinity).map_or(core, |core| core.index)
+}
+
+/// Find the least loaded of the given cores that's running the scheduler,
+/// preferring the current core if it's one of them
+fn least_loaded(affinity: &Affinity) -> Option<&'static CoreLocalData> {
+    let this = try_this_core().map(|core| core.index);
+    let mut best: Option<(&'static CoreLocalData, usize)> = None;
+
+    let core_count = crate::CORE_MANAGER.core_count();
+    for index in 0..core_count {
+        if !affinity.contains(index) {
+            continue;
+        }
+        let core = match crate::CORE_MANAGER.get_core_by_index(index) {
+            Some(core) if core.scheduler.is_started() => core,
+            _ => continue,
+        };
+        let load = core.scheduler.load();
+        let better = match best {
+            None => true,
+            Some((_, best_load)) => load < best_load || (load == best_load && Some(index) == this),
+        };
+        if better {
+            best = Some((core, load));
+        }
+    }
+    best.map(|(core, _)| core)
+}
+
+/// Find the most loaded core with threads waiting, if it has at least two more
+/// running or waiting than the given core, so moving one over would even them out
+fn busiest(core: &CoreLocalData) -> Option<&'static CoreLocalData> {
+    let load = core.scheduler.load();
+    let mut busiest: Option<(&'static CoreLocalData, usize)> = None;
+
+    let core_count = crate::CORE_MANAGER.core_count();
+    for index in 0..core_count {
+        let other = match crate::CORE_MANAGER.get_core_by_index(index) {
+            Some(other) if other.index != core.index => other,
+            _ => continue,
+        };
+        let other_load = other.scheduler.load();
+        if other.scheduler.runnable() > 0
+            && other_load >= load + 2
+            && busiest.map_or(true, |(_, busiest_load)| other_load > busiest_load)
+        {
+            busiest = Some((other, other_load));
+        }
+    }
+    busiest.map(|(core, _)| core)
+}
+
+/// Even out the current core's load with the busiest core's,
+/// by taking a thread off its run queue. Interrupts must be disabled
+fn balance() {
+    let core = this_core();
+    let victim = match busiest(core) {
+        Some(victim) => victim,
+        None => return,
+    };
+
+    if let Some(thread) = victim.scheduler.steal(core.index) {
+        thread.state.lock().core = core.index;
+        core.scheduler.enqueue(thread);
+    }
+}
+
+/// Move a runnable thread that isn't allowed on the current core to one it is allowed on.
+/// Interrupts must be disabled
+fn send_away(thread: Arc<Thread>) {
+    let mut state = thread.state.lock();
+    let core = place(&thread, state.core);
+    state.core = core;
+    drop(state);
+
+    if let Some(data) = crate::CORE_MANAGER.get_core_by_index(core) {
+        data.scheduler.enqueue(thread);
+    }
+    kick(core);
+}
+
+/// Get a core to look at its run queue at its next interrupt, sending it one if it's another core
+fn kick(index: usize) {
+    let core = match crate::CORE_MANAGER.get_core_by_index(index) {
+        Some(v) => v,
+        None => return,
+    };
+    core.scheduler.request_reschedule();
+
+    if try_this_core().map(|core| core.index) != Some(index) {
+        // A core that can't take IPIs yet isn't running threads either
+        let _ = ipi::send_to_core(index, ipi::RESCHEDULE_VECTOR);
+    }
+}
+
+/// Switch the current core to the next thread on its run queue, or its idle thread.
+/// Interrupts must be disabled
+fn schedule(reason: Reason) {
+    let scheduler = &this_core().scheduler;
+    let (current, idle) = match unsafe { (scheduler.current(), scheduler.idle()) } {
+        (Some(current), Some(idle)) => (current, idle),
+        _ => return,
+    };
+    let is_idle = Arc::ptr_eq(current, idle);
+
+    // Preemption comes with the periodic time slice timer, so it's when load is balanced
+    if reason == Reason::Preempt {
+        balance();
+    }
+
+    // The idle thread only runs when nothing else can, so it's never queued
+    let mut requeue = match reason {
+        Reason::Yield | Reason::Preempt => !is_idle,
+        // The idle thread can't block, as it has to be there when nothing else is, so it yields
+        Reason::Block if is_idle => false,
+        Reason::Block => {
+            let mut state = current.state.lock();
+            // It may have been woken before it could block
+            state.runnable = !state.blocked;
+            state.runnable
+        }
+        Reason::Exit => {
+            THREADS.fetch_sub(1, Ordering::AcqRel);
+            false
+        }
+    };
+
+    // Its affinity may have changed, and it's only sent away once it's been switched out
+    let index = this_core().index;
+    let migrating = requeue && !current.affinity().contains(index);
+    requeue &= !migrating;
+
+    let next = loop {
+        match scheduler.rotate(requeue.then_some(current)) {
+            Some(next) if !next.affinity().contains(index) && !Arc::ptr_eq(&next, current) => {
+                send_away(next);
+            }
+            Some(next) => break next,
+            None if requeue => return,
+            None => break idle.clone(),
+        }
+    };
+
+    // A blocking thread can be woken and queued again before it's switched away from
+    if Arc::ptr_eq(&next, current) {
+        return;
+    }
+
+    // If it was moved here from another core, that core might not be off its stack yet
+    while next.on_cpu.load(Ordering::Acquire) {
+        hint::spin_loop();
+    }
+    next.on_cpu.store(true, Ordering::Relaxed);
+
+    let current = current.clone();
+    let from = current.context.get();
+    let to = next.context.get();
+    // Kernel threads run on the kernel's table, so an address space isn't kept loaded after it's freed
+    match next.process() {
+        Some(process) => process.activate(),
+        None => AddressSpace::activate_kernel(),
+    }
+    #[cfg(feature = "lockdep")]
+    let held = next.held.get();
+    unsafe {
+        scheduler.set_previous(Arc::as_ptr(&current));
+        scheduler.set_current(next);
+    }
+    if migrating {
+        // It can't run anywhere else until it's off this core, which `on_cpu` makes sure of
+        send_away(current.clone());
+    }
+
+    // An exited thread's stack is never returned to, so nothing can be left on it.
+    // Otherwise the thread is kept alive while it's switched out, even if nothing else refers to it
+    let _current = if reason == Reason::Exit {
+        unsafe { scheduler.set_exited(current) };
+        None
+    } else {
+        Some(current)
+    };
+
+    #[cfg(feature = "lockdep")]
+    unsafe {
+        crate::sync::lockdep::set_current(held);
+    }
+    unsafe { context::switch(from, to) };
+
+    finish_switch();
+}
+
+/// Clean up after switching to a thread, on the core it's now running on.
+/// Interrupts must be disabled
+fn finish_switch() {
+    let scheduler = &this_core().scheduler;
+
+    // Either it's kept alive on its own stack until it's switched back to, or it's exited
+    let previous = unsafe { scheduler.take_previous() };
+    if !previous.is_null() {
+        unsafe { (*previous).on_cpu.store(false, Ordering::Release) };
+    }
+
+    if let Some(thread) = unsafe { scheduler.take_exited() } {
+        // Its stack is free to be freed now
+        let thread = Arc::into_raw(thread).cast_mut();
+        let mut head = ZOMBIES.load(Ordering::Acquire);
+        loop {
+            unsafe { (*thread).next_zombie.store(head, Ordering::Relaxed) };
+            match ZOMBIES.compare_exchange_weak(head, thread, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+/// Free the threads that have exited
+fn reap() {
+    let mut zombie = ZOMBIES.swap(ptr::null_mut(), Ordering::AcqRel);
+    while !zombie.is_null() {
+        let thread = unsafe { Arc::from_raw(zombie) };
+        zombie = thread.next_zombie.load(Ordering::Relaxed);
+        drop(thread);
+    }
+}
+
+/// Where every spawned thread starts, with interrupts disabled
+extern "C" fn thread_entry(_: usize) -> ! {
+    finish_switch();
+
+    let thread = current();
+    let entry = thread.entry.lock().take();
+    let user_entry = thread.user_entry.clone();
+    drop(thread);
+    let _ = PLATFORM_MANAGER.get_interrupt_manager().enable_interrupts();
+
+    if let Some(entry) = entry {
+        entry();
+    }
+    if let Some(frame) = user_entry {
+        // It may have been killed before it got this far
+        process::check_exiting();
+        unsafe { syscall::return_to_user(frame) }
+    }
+    exit()
+}
@@ -0,0 +1,288 @@
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
+};
+
+use crate::{
+    arch::{context::Context, syscall::SyscallFrame},
+    errors::SchedulerError,
+    process::Process,
+    sync::{Mutex, WaitQueue},
+};
+
+use super::{
+    current, kick, place, thread_entry, without_interrupts, yield_now, Affinity, STACK_SIZE,
+};
+
+/// The next thread ID to hand out
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The ID of a thread, unique across every core
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(pub u64);
+
+impl ThreadId {
+    /// Hand out a new ID
+    fn next() -> Self {
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Where a thread is, as far as the scheduler is concerned
+pub(super) struct SchedulingState {
+    /// The index of the core whose run queue the thread goes on
+    pub core: usize,
+    /// Set once the thread has asked to block, until it's woken
+    pub blocked: bool,
+    /// Set while the thread is on a run queue or running, and clear while it's blocked
+    pub runnable: bool,
+}
+
+/// What a new thread runs
+pub(super) enum Start {
+    /// A closure in the kernel
+    Kernel(Box<dyn FnOnce() + Send>),
+    /// User code in a process, entered with the registers in `frame`
+    User {
+        process: Arc<Process>,
+        frame: SyscallFrame,
+    },
+}
+
+/// A thread's control block
+pub struct Thread {
+    id: ThreadId,
+    /// Only locked with interrupts disabled, as threads are woken from interrupt handlers
+    pub(super) state: Mutex<SchedulingState>,
+    /// The cores the thread may run on.
+    /// Only locked with interrupts disabled, as it's read while waking and stealing threads
+    affinity: Mutex<Affinity>,
+    /// Only touched by the core the thread is running on, or switching away from
+    pub(super) context: UnsafeCell<Context>,
+    /// Set while a core is running the thread, until it's switched off its stack
+    pub(super) on_cpu: AtomicBool,
+    /// The thread's stack, or `None` if it was already running on its own, like the idle threads
+    _stack: Option<Box<[u128]>>,
+    /// What the thread runs, taken when it starts
+    pub(super) entry: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+    /// The registers the thread enters user mode with, once `entry` has run
+    pub(super) user_entry: Option<SyscallFrame>,
+    /// The process the thread runs in, or `None` for the kernel's own threads
+    process: Option<Arc<Process>>,
+    /// The threads waiting for this one to exit
+    joiners: WaitQueue,
+    finished: AtomicBool,
+    /// The next thread on the list of exited threads waiting to be freed
+    pub(super) next_zombie: AtomicPtr<Thread>,
+    /// The locks the thread holds. Only touched by the core it's running on
+    #[cfg(feature = "lockdep")]
+    pub(super) held: UnsafeCell<crate::sync::lockdep::HeldLocks>,
+}
+
+unsafe impl Send for Thread {}
+unsafe impl Sync for Thread {}
+
+impl Thread {
+    /// Make a thread on the given core which runs `start`, without queueing it
+    pub(super) fn new(core: usize, affinity: Affinity, start: Start) -> Arc<Self> {
+        // It's made of u128s so the top is 16-byte aligned
+        let stack: Box<[u128]> = core::iter::repeat(0).take(STACK_SIZE / 16).collect();
+        let top = stack.as_ptr_range().end as usize;
+        let (entry, user_entry, process) = match start {
+            Start::Kernel(entry) => (Some(entry), None, None),
+            Start::User { process, frame } => (None, Some(frame), Some(process)),
+        };
+
+        Arc::new(Self {
+            id: ThreadId::next(),
+            state: Mutex::new(SchedulingState {
+                core,
+                blocked: false,
+                runnable: true,
+            }),
+            affinity: Mutex::new(affinity),
+            context: UnsafeCell::new(unsafe { Context::new(top, thread_entry, 0) }),
+            on_cpu: AtomicBool::new(false),
+            _stack: Some(stack),
+            entry: Mutex::new(entry),
+            user_entry,
+            process,
+            joiners: WaitQueue::new(),
+            finished: AtomicBool::new(false),
+            next_zombie: AtomicPtr::new(core::ptr::null_mut()),
+            #[cfg(feature = "lockdep")]
+            held: UnsafeCell::new(crate::sync::lockdep::HeldLocks::new()),
+        })
+    }
+
+    /// Make a thread for the code already running on the given core, which is pinned to it
+    pub(super) fn new_current(core: usize) -> Arc<Self> {
+        Arc::new(Self {
+            id: ThreadId::next(),
+            state: Mutex::new(SchedulingState {
+                core,
+                blocked: false,
+                runnable: true,
+            }),
+            affinity: Mutex::new(Affinity::only(core)),
+            context: UnsafeCell::new(Context::new_current()),
+            on_cpu: AtomicBool::new(true),
+            _stack: None,
+            entry: Mutex::new(None),
+            user_entry: None,
+            process: None,
+            joiners: WaitQueue::new(),
+            finished: AtomicBool::new(false),
+            next_zombie: AtomicPtr::new(core::ptr::null_mut()),
+            #[cfg(feature = "lockdep")]
+            held: UnsafeCell::new(crate::sync::lockdep::HeldLocks::new()),
+        })
+    }
+
+    /// Get the thread's ID
+    pub const fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// Check whether the thread has exited
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    /// Get the process the thread runs in, or `None` if it's one of the kernel's own threads
+    pub const fn process(&self) -> Option<&Arc<Process>> {
+        self.process.as_ref()
+    }
+
+    /// Get the cores the thread may run on
+    pub fn affinity(&self) -> Affinity {
+        without_interrupts(|| *self.affinity.lock())
+    }
+
+    /// Set the cores the thread may run on. If it's the current thread and it isn't
+    /// allowed on this core any more, it's moved off straight away.
+    /// Otherwise it's moved the next time it's woken or comes up on its run queue.
+    /// Idle threads never leave their core
+    ///
+    /// # Errors
+    /// This will return an error if none of the allowed cores are running the scheduler
+    pub fn set_affinity(self: &Arc<Self>, affinity: Affinity) -> Result<(), SchedulerError> {
+        let core_count = crate::CORE_MANAGER.core_count();
+        let allowed = (0..core_count).any(|index| {
+            affinity.contains(index)
+                && crate::CORE_MANAGER
+                    .get_core_by_index(index)
+                    .map_or(false, |core| core.scheduler.is_started())
+        });
+        if !allowed {
+            return Err(SchedulerError::NoAllowedCores);
+        }
+
+        without_interrupts(|| *self.affinity.lock() = affinity);
+
+        let core = without_interrupts(|| self.state.lock().core);
+        if !affinity.contains(core) && Arc::ptr_eq(self, &current()) {
+            yield_now();
+        }
+        Ok(())
+    }
+
+    /// Mark the current thread as about to block, so a wake that comes
+    /// before it calls [`block`] isn't lost. This must be called from the thread itself
+    pub(crate) fn prepare_to_block(&self) {
+        without_interrupts(|| self.state.lock().blocked = true);
+    }
+
+    /// Undo [`Thread::prepare_to_block`], if the thread decided not to block after all
+    pub(crate) fn cancel_block(&self) {
+        without_interrupts(|| self.state.lock().blocked = false);
+    }
+
+    /// Wake the thread if it's blocked, or about to be. This never allocates,
+    /// so it can be called from an interrupt handler
+    ///
+    /// Returns whether the thread was blocked
+    pub fn wake(self: &Arc<Self>) -> bool {
+        without_interrupts(|| {
+            let mut state = self.state.lock();
+            if !state.blocked {
+                return false;
+            }
+            state.blocked = false;
+
+            // It hasn't switched away yet, so it just carries on
+            if state.runnable {
+                return true;
+            }
+            state.runnable = true;
+
+            let core = place(self, state.core);
+            state.core = core;
+            if let Some(data) = crate::CORE_MANAGER.get_core_by_index(core) {
+                data.scheduler.enqueue(self.clone());
+            }
+            drop(state);
+
+            kick(core);
+            true
+        })
+    }
+
+    /// Mark the thread as finished and wake every thread joining it.
+    /// Interrupts must be disabled
+    pub(super) fn finish(&self) {
+        self.finished.store(true, Ordering::Release);
+        self.joiners.wake_all();
+    }
+}
+
+impl core::fmt::Debug for Thread {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Thread")
+            .field("id", &self.id)
+            .field("finished", &self.is_finished())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A handle to a spawned thread, which can be used to wait for its result.
+/// Dropping the handle leaves the thread running
+#[derive(Debug)]
+pub struct JoinHandle<T> {
+    thread: Arc<Thread>,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub(super) fn new(thread: Arc<Thread>, result: Arc<Mutex<Option<T>>>) -> Self {
+        Self { thread, result }
+    }
+
+    /// Get the thread this is a handle to
+    pub const fn thread(&self) -> &Arc<Thread> {
+        &self.thread
+    }
+
+    /// Check whether the thread has exited
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Block until the thread exits, whether or not it returned
+    pub fn wait(&self) {
+        self.thread.joiners.wait_until(|| self.thread.is_finished());
+    }
+
+    /// Block until the thread exits, and get what it returned
+    ///
+    /// # Panics
+    /// This will panic if the thread exited without returning
+    pub fn join(self) -> T {
+        self.wait();
+
+        self.result
+            .lock()
+            .take()
+            .expect("The thread exited without a result")
+    }
+}
@@ -0,0 +1,240 @@
+use log::debug;
+
+use crate::{
+    errors::FileSystemError,
+    fs::{self, initrd::Initrd, tmpfs::TmpFs, FileTable, OpenFlags, SeekFrom},
+    memory::utilities::align,
+    traits::FileKind,
+    CORE_MANAGER,
+};
+
+/// Check the VFS, and each of the filesystems mounted at boot
+pub fn run() {
+    vfs();
+    devices();
+    procfs();
+    tmpfs();
+
+    let root = fs::read_dir(None, "/").unwrap();
+    debug!(
+        "The root holds {:?}",
+        root.iter().map(|entry| &entry.name).collect::<Vec<_>>()
+    );
+}
+
+/// Make a newc cpio archive with a directory, a file in it,
+/// and symbolic links to the file and to themselves
+#[allow(clippy::cast_possible_truncation)]
+fn archive() -> &'static [u8] {
+    let mut archive = Vec::new();
+    let mut add = |name: &str, mode: u32, data: &[u8]| {
+        let fields = [
+            0,
+            mode,
+            0,
+            0,
+            1,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            name.len() as u32 + 1,
+            0,
+        ];
+        archive.extend_from_slice(b"070701");
+        for field in fields {
+            for shift in (0..8).rev() {
+                archive.push(b"0123456789ABCDEF"[(field >> (shift * 4)) as usize & 0xF]);
+            }
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(align(archive.len(), 4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(align(archive.len(), 4), 0);
+    };
+    add("etc", 0o040_755, &[]);
+    add("etc/motd", 0o100_644, b"Hello from the initrd\n");
+    add("motd", 0o120_777, b"etc/motd");
+    add("abs", 0o120_777, b"/test/motd");
+    add("loop", 0o120_777, b"loop");
+    add("TRAILER!!!", 0, &[]);
+    Box::leak(archive.into_boxed_slice())
+}
+
+/// Check open files, and paths that cross mount points
+fn vfs() {
+    fs::mount::mount("/test", Arc::new(Initrd::parse(archive()).unwrap())).unwrap();
+    let files = FileTable::new();
+    let mut buffer = [0; 64];
+    files.change_directory("/test/etc").unwrap();
+    let fd = files.open("../etc/../motd", OpenFlags::READ, 0).unwrap();
+    let length = files.read(fd, &mut buffer).unwrap();
+    assert_eq!(&buffer[..length], b"Hello from the initrd\n");
+    assert_eq!(files.seek(fd, SeekFrom::End(-7)).unwrap(), 15);
+    let length = files.read(fd, &mut buffer).unwrap();
+    assert_eq!(&buffer[..length], b"initrd\n");
+    assert_eq!(files.stat(fd).unwrap().size, 22);
+    assert_eq!(files.write(fd, b"x"), Err(FileSystemError::BadDescriptor));
+    files.close(fd).unwrap();
+    assert_eq!(files.close(fd), Err(FileSystemError::BadDescriptor));
+
+    assert_eq!(
+        fs::resolve(None, "/test/etc/../../test/abs", true)
+            .unwrap()
+            .path(),
+        "/test/etc/motd"
+    );
+    assert_eq!(
+        fs::resolve(None, "/test/loop", true).unwrap_err(),
+        FileSystemError::TooManyLinks
+    );
+    assert_eq!(
+        files.open("/test/new", OpenFlags::WRITE | OpenFlags::CREATE, 0o644),
+        Err(FileSystemError::ReadOnly)
+    );
+    let fd = files
+        .open("/", OpenFlags::READ | OpenFlags::DIRECTORY, 0)
+        .unwrap();
+    assert!(files
+        .read_dir(fd, usize::MAX)
+        .unwrap()
+        .iter()
+        .any(|entry| entry.name == "test"));
+    assert!(files.read_dir(fd, usize::MAX).unwrap().is_empty());
+    files.close(fd).unwrap();
+    fs::mount::unmount("/test").unwrap();
+    debug!("Resolved paths and read files through the VFS");
+}
+
+/// Check the devices in `/dev`
+#[allow(clippy::cast_possible_wrap)]
+fn devices() {
+    let files = FileTable::new();
+    let mut buffer = [0; 64];
+    let read_write = OpenFlags::READ | OpenFlags::WRITE;
+    let null = files.open("/dev/null", read_write, 0).unwrap();
+    assert_eq!(files.write(null, b"gone").unwrap(), 4);
+    assert_eq!(files.read(null, &mut buffer).unwrap(), 0);
+    let zero = files.open("/dev/zero", OpenFlags::READ, 0).unwrap();
+    buffer.fill(0xFF);
+    assert_eq!(files.read(zero, &mut buffer).unwrap(), buffer.len());
+    assert!(buffer.iter().all(|&byte| byte == 0));
+    let random = files.open("/dev/random", OpenFlags::READ, 0).unwrap();
+    files.read(random, &mut buffer).unwrap();
+    assert!(buffer.iter().any(|&byte| byte != 0));
+    let serial = files.open("/dev/ttyS0", OpenFlags::WRITE, 0).unwrap();
+    files.write(serial, b"Hello from /dev/ttyS0\r\n").unwrap();
+    let kmsg = files.open("/dev/kmsg", read_write, 0).unwrap();
+    files.write(kmsg, b"Hello from /dev/kmsg").unwrap();
+    let mut log = [0; 256];
+    files
+        .seek(kmsg, SeekFrom::End(-(log.len() as i64)))
+        .unwrap();
+    let length = files.read(kmsg, &mut log).unwrap();
+    assert!(log[..length]
+        .windows(20)
+        .any(|window| window == b"Hello from /dev/kmsg"));
+    for fd in [null, zero, random, serial, kmsg] {
+        files.close(fd).unwrap();
+    }
+    debug!("Used the devices in /dev");
+}
+
+/// Check the files in `/proc`
+fn procfs() {
+    let files = FileTable::new();
+    // Generated files are read in pieces, so they have to be read to the end
+    let read_all = |path: &str| {
+        let fd = files.open(path, OpenFlags::READ, 0).unwrap();
+        let mut buffer = [0; 64];
+        let mut text = Vec::new();
+        loop {
+            let length = files.read(fd, &mut buffer).unwrap();
+            if length == 0 {
+                break;
+            }
+            text.extend_from_slice(&buffer[..length]);
+        }
+        files.close(fd).unwrap();
+        String::from_utf8(text).unwrap()
+    };
+    assert!(read_all("/proc/meminfo").starts_with("page_size 4096\n"));
+    assert!(!read_all("/proc/heap").starts_with("allocations 0\n"));
+    assert!(read_all("/proc/mounts").contains("/proc procfs\n"));
+    assert_eq!(
+        read_all("/proc/cpus").lines().count(),
+        CORE_MANAGER.core_count() + 1
+    );
+    assert!(read_all("/proc/memmap").lines().count() > 1);
+    assert!(read_all("/proc/interrupts").starts_with("vector count\n"));
+    debug!("Uptime is {}s", read_all("/proc/uptime").trim_end());
+    // The kernel's own threads aren't in a process
+    assert_eq!(fs::read_link(None, "/proc/self").unwrap(), "0");
+    assert_eq!(fs::stat(None, "/proc/self"), Err(FileSystemError::NotFound));
+
+    let tables = fs::read_dir(None, "/proc/acpi").unwrap();
+    for table in &tables {
+        let mut path = String::from("/proc/acpi/");
+        path.push_str(&table.name);
+        let fd = files.open(&path, OpenFlags::READ, 0).unwrap();
+        let size = files.stat(fd).unwrap().size;
+        let mut header = [0; 4];
+        assert_eq!(files.read(fd, &mut header).unwrap(), 4);
+        assert_eq!(&header, &table.name.as_bytes()[..4]);
+        assert!(size >= 36);
+        files.close(fd).unwrap();
+    }
+    debug!(
+        "Found ACPI tables {:?} in /proc/acpi",
+        tables.iter().map(|entry| &entry.name).collect::<Vec<_>>()
+    );
+}
+
+/// Check a tmpfs's files are stored in pages, and that it runs out of room
+fn tmpfs() {
+    let files = FileTable::new();
+    let mut buffer = [0; 64];
+    let read_write = OpenFlags::READ | OpenFlags::WRITE;
+    // Room for two pages, so running out can be checked
+    let scratch = Arc::new(TmpFs::new(2 * 4096));
+    fs::mount::mount("/scratch", scratch.clone()).unwrap();
+    fs::make_directory(None, "/scratch/d", 0o755).unwrap();
+    let fd = files
+        .open("/scratch/d/f", read_write | OpenFlags::CREATE, 0o644)
+        .unwrap();
+    files.seek(fd, SeekFrom::Start(5000)).unwrap();
+    assert_eq!(files.write(fd, b"hello").unwrap(), 5);
+    assert_eq!(files.stat(fd).unwrap().size, 5005);
+    // Only the page that was written to is allocated
+    assert_eq!(scratch.used(), 4096);
+    files.seek(fd, SeekFrom::Start(0)).unwrap();
+    assert_eq!(files.read(fd, &mut buffer).unwrap(), buffer.len());
+    assert!(buffer.iter().all(|&byte| byte == 0));
+    files.close(fd).unwrap();
+
+    fs::link(None, "/scratch/d/f", "/scratch/g").unwrap();
+    fs::unlink(None, "/scratch/d/f").unwrap();
+    fs::rename(None, "/scratch/g", "/scratch/d/h").unwrap();
+    assert_eq!(fs::stat(None, "/scratch/d/h").unwrap().size, 5005);
+
+    let fd = files.open("/scratch/d/h", read_write, 0).unwrap();
+    let data: Vec<u8> = core::iter::repeat(7).take(3 * 4096).collect();
+    // The first page is new and the second already exists, but there's no room for a third
+    assert_eq!(files.write(fd, &data).unwrap(), 2 * 4096);
+    assert_eq!(files.write(fd, b"full"), Err(FileSystemError::NoSpace));
+    files.close(fd).unwrap();
+    let fd = files
+        .open("/scratch/d/h", read_write | OpenFlags::TRUNCATE, 0)
+        .unwrap();
+    assert_eq!(scratch.used(), 0);
+    files.close(fd).unwrap();
+    fs::unlink(None, "/scratch/d/h").unwrap();
+    fs::remove_directory(None, "/scratch/d").unwrap();
+    assert!(fs::read_dir(None, "/scratch").unwrap().is_empty());
+    fs::mount::unmount("/scratch").unwrap();
+    assert_eq!(fs::stat(None, "/tmp").unwrap().kind, FileKind::Directory);
+    debug!("Wrote files in a tmpfs");
+}
@@ -0,0 +1,56 @@
+use log::info;
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    modules, process,
+    traits::{Platform, PowerManager, PowerOffKind},
+};
+
+mod filesystems;
+mod platform;
+mod processes;
+mod threads;
+
+/// Check the kernel's subsystems work on real hardware, or in an emulator, then run the test
+/// programs loaded as boot modules, and power off. What can be checked without booting has host
+/// tests instead. Anything that fails panics, and the panic handler powers off with
+/// [`PowerOffKind::Failure`]
+pub fn run() {
+    platform::run();
+    filesystems::run();
+    threads::run();
+    processes::run();
+    info!("Boot checks passed");
+    run_test_programs();
+    info!("Self-test passed");
+    PLATFORM_MANAGER
+        .get_power_manager()
+        .shutdown(PowerOffKind::Shutdown)
+}
+
+/// Run the modules with `test` on their command line, which are test programs that pass
+/// by exiting with 0
+fn run_test_programs() {
+    for module in modules::list() {
+        if module.cmdline.split_whitespace().any(|word| word == "test") {
+            let process = process::Process::spawn_program(
+                module.name(),
+                None,
+                process::Credentials::ROOT,
+                module.data,
+                &[module.path],
+                &[],
+            )
+            .unwrap();
+            let status = process.wait();
+            process.reap();
+            assert_eq!(
+                status,
+                process::ExitStatus::Exited(0),
+                "{} failed",
+                module.path
+            );
+            info!("Test program {} passed", module.path);
+        }
+    }
+}
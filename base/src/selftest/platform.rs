@@ -0,0 +1,112 @@
+use core::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use log::debug;
+
+use crate::{
+    arch::{self, PLATFORM_MANAGER},
+    smp, sync, timer,
+    traits::{InterruptManager, Platform, TimerKind, TimerManager},
+    CORE_MANAGER, SMP_REQUEST,
+};
+
+/// Check the timers, cores and locks
+pub fn run() {
+    timers();
+    cores();
+    locks();
+}
+
+/// Check stalling, and that timers fire unless they're cancelled
+fn timers() {
+    static FIRED: AtomicBool = AtomicBool::new(false);
+    static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+    let timers = PLATFORM_MANAGER.get_timer_manager();
+    let start = timers.monotonic();
+    timers.stall(Duration::from_millis(10));
+    let elapsed = timers.monotonic() - start;
+    debug!("Stalled for {elapsed:?}");
+    assert!(elapsed >= Duration::from_millis(10));
+
+    timer::set_timer(
+        Duration::from_millis(5),
+        TimerKind::OneShot,
+        Box::new(|_| FIRED.store(true, Ordering::Relaxed)),
+    )
+    .unwrap();
+    timer::set_timer(
+        Duration::from_millis(5),
+        TimerKind::OneShot,
+        Box::new(|_| CANCELLED.store(true, Ordering::Relaxed)),
+    )
+    .unwrap()
+    .cancel()
+    .unwrap();
+    timers.stall(Duration::from_millis(20));
+    assert!(FIRED.load(Ordering::Relaxed));
+    assert!(!CANCELLED.load(Ordering::Relaxed));
+    debug!("One-shot timer fired, cancelled timer didn't");
+}
+
+/// Check the bootstrap core's local data, and calling functions on other cores
+fn cores() {
+    let smp = unsafe { SMP_REQUEST.response.unwrap().as_ref() };
+    let core = smp::this_core();
+    assert_eq!(core.magic, smp::CoreLocalData::MAGIC);
+    assert_eq!(core.id, smp.bsp_lapic_id);
+    assert_eq!(core.index, 0);
+    debug!("Running on core {} (index {})", core.id, core.index);
+
+    if CORE_MANAGER.core_count() > 1 {
+        static CALLED_ON: AtomicUsize = AtomicUsize::new(0);
+        arch::ipi::call_on_core_and_wait(
+            1,
+            |_| CALLED_ON.store(smp::this_core().index, Ordering::Relaxed),
+            0,
+        )
+        .unwrap();
+        assert_eq!(CALLED_ON.load(Ordering::Relaxed), 1);
+        debug!("Called a function on core index 1");
+    }
+}
+
+/// Check spinning locks, and that interrupt guards nest
+fn locks() {
+    let mutex = sync::Mutex::new(9);
+    assert_eq!(*mutex.lock(), 9);
+
+    let interrupts = PLATFORM_MANAGER.get_interrupt_manager();
+    let enabled = interrupts.interrupts_enabled();
+    let lock = sync::IrqSpinlock::new(0);
+    {
+        let outer = sync::IrqGuard::new();
+        let mut inner = lock.lock_irqsave();
+        *inner += 1;
+        // Interrupts stay off until the last guard is dropped, whichever order they go in
+        drop(outer);
+        assert!(!interrupts.interrupts_enabled());
+    }
+    assert_eq!(interrupts.interrupts_enabled(), enabled);
+    debug!("Nested interrupt guards restored interrupts");
+
+    #[cfg(feature = "lockdep")]
+    {
+        let first = sync::Mutex::new(());
+        let second = sync::Mutex::new(());
+        let reports = sync::lockdep::report_count();
+        {
+            let _first = first.lock();
+            let _second = second.lock();
+        }
+        // This can't deadlock with one thread, but it could with two
+        {
+            let _second = second.lock();
+            let _first = first.lock();
+        }
+        assert_eq!(sync::lockdep::report_count(), reports + 1);
+        debug!("Lockdep caught a lock order inversion");
+    }
+}
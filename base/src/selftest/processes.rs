@@ -0,0 +1,351 @@
+use core::alloc::Layout;
+
+use log::debug;
+
+use crate::{
+    errors::FileSystemError,
+    fs,
+    memory::{self, AddressSpace},
+    process::{self, Credentials, ExitStatus, Fault, Pid, Process},
+    traits::MemoryFlags,
+};
+
+/// `write(1, message, 18); sched_yield(); exit(42);` with the message straight after
+const PROGRAM: [u8; 45] = [
+    0xB8, 0x01, 0x00, 0x00, 0x00, // mov eax, 1
+    0xBF, 0x01, 0x00, 0x00, 0x00, // mov edi, 1
+    0x48, 0x8D, 0x35, 0x1C, 0x00, 0x00, 0x00, // lea rsi, [rip + 28]
+    0xBA, 0x12, 0x00, 0x00, 0x00, // mov edx, 18
+    0x0F, 0x05, // syscall
+    0xB8, 0x18, 0x00, 0x00, 0x00, // mov eax, 24
+    0x0F, 0x05, // syscall
+    0xBF, 0x2A, 0x00, 0x00, 0x00, // mov edi, 42
+    0xB8, 0x3C, 0x00, 0x00, 0x00, // mov eax, 60
+    0x0F, 0x05, // syscall
+    0x0F, 0x0B, // ud2
+];
+
+/// What [`PROGRAM`] writes
+const MESSAGE: &[u8] = b"Hello from ring 3\n";
+
+/// Each system call's result is stored at the bottom of the stack page, in order
+#[rustfmt::skip]
+const SYSCALLS: [u8; 302] = [
+    0x48, 0x8D, 0x9C, 0x24, 0x00, 0xF0, 0xFF, 0xFF, // lea rbx, [rsp - 4096]
+    // getpid()
+    0xB8, 0x27, 0x00, 0x00, 0x00, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x00,
+    // mmap(0, 8192, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) into r12
+    0xB8, 0x09, 0x00, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x00, 0x00,
+    0xBE, 0x00, 0x20, 0x00, 0x00, 0xBA, 0x03, 0x00, 0x00, 0x00,
+    0x41, 0xBA, 0x22, 0x00, 0x00, 0x00, 0x49, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x41, 0xB9, 0x00, 0x00, 0x00, 0x00, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x08,
+    0x49, 0x89, 0xC4,
+    // mprotect(r12, 4096, PROT_READ), which would split the mapping
+    0xB8, 0x0A, 0x00, 0x00, 0x00, 0x4C, 0x89, 0xE7, 0xBE, 0x00, 0x10, 0x00, 0x00,
+    0xBA, 0x01, 0x00, 0x00, 0x00, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x10,
+    // mprotect(r12, 8192, PROT_READ)
+    0xB8, 0x0A, 0x00, 0x00, 0x00, 0x4C, 0x89, 0xE7, 0xBE, 0x00, 0x20, 0x00, 0x00,
+    0xBA, 0x01, 0x00, 0x00, 0x00, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x18,
+    // munmap(r12, 8192)
+    0xB8, 0x0B, 0x00, 0x00, 0x00, 0x4C, 0x89, 0xE7, 0xBE, 0x00, 0x20, 0x00, 0x00,
+    0x0F, 0x05, 0x48, 0x89, 0x43, 0x20,
+    // clock_gettime(CLOCK_MONOTONIC, rbx + 128)
+    0xB8, 0xE4, 0x00, 0x00, 0x00, 0xBF, 0x01, 0x00, 0x00, 0x00,
+    0x48, 0x8D, 0xB3, 0x80, 0x00, 0x00, 0x00, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x28,
+    // clock_gettime(CLOCK_MONOTONIC, r12), which was unmapped
+    0xB8, 0xE4, 0x00, 0x00, 0x00, 0xBF, 0x01, 0x00, 0x00, 0x00,
+    0x4C, 0x89, 0xE6, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x30,
+    // openat(AT_FDCWD, rbx + 512, O_RDONLY) into r12
+    0xB8, 0x01, 0x01, 0x00, 0x00, 0x48, 0xC7, 0xC7, 0x9C, 0xFF, 0xFF, 0xFF,
+    0x48, 0x8D, 0xB3, 0x00, 0x02, 0x00, 0x00, 0xBA, 0x00, 0x00, 0x00, 0x00,
+    0x0F, 0x05, 0x48, 0x89, 0x43, 0x38, 0x49, 0x89, 0xC4,
+    // read(r12, rbx + 256, 64)
+    0xB8, 0x00, 0x00, 0x00, 0x00, 0x4C, 0x89, 0xE7,
+    0x48, 0x8D, 0xB3, 0x00, 0x01, 0x00, 0x00, 0xBA, 0x40, 0x00, 0x00, 0x00,
+    0x0F, 0x05, 0x48, 0x89, 0x43, 0x40,
+    // close(r12), twice
+    0xB8, 0x03, 0x00, 0x00, 0x00, 0x4C, 0x89, 0xE7, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x48,
+    0xB8, 0x03, 0x00, 0x00, 0x00, 0x4C, 0x89, 0xE7, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x50,
+    // brk(0), without a heap
+    0xB8, 0x0C, 0x00, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x00, 0x00,
+    0x0F, 0x05, 0x48, 0x89, 0x43, 0x58,
+    // A system call that doesn't exist
+    0xB8, 0xF4, 0x01, 0x00, 0x00, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x60,
+    // exit_group(7)
+    0xB8, 0xE7, 0x00, 0x00, 0x00, 0xBF, 0x07, 0x00, 0x00, 0x00, 0x0F, 0x05,
+];
+
+/// Forks, and waits for the child to exit with 5
+#[rustfmt::skip]
+const FORK: [u8; 103] = [
+    0x48, 0x8D, 0x9C, 0x24, 0x00, 0xF0, 0xFF, 0xFF, // lea rbx, [rsp - 4096]
+    // fork(), with the child calling exit_group(5)
+    0xB8, 0x39, 0x00, 0x00, 0x00, 0x0F, 0x05, 0x48, 0x85, 0xC0, 0x75, 0x0C,
+    0xB8, 0xE7, 0x00, 0x00, 0x00, 0xBF, 0x05, 0x00, 0x00, 0x00, 0x0F, 0x05,
+    0x48, 0x89, 0x43, 0x00,
+    // wait4(-1, rbx + 8, 0, 0)
+    0xB8, 0x3D, 0x00, 0x00, 0x00, 0x48, 0xC7, 0xC7, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x48, 0x8D, 0x73, 0x08, 0x31, 0xD2, 0x45, 0x31, 0xD2,
+    0x0F, 0x05, 0x48, 0x89, 0x43, 0x10,
+    // wait4(-1, 0, 0, 0), with no children left
+    0xB8, 0x3D, 0x00, 0x00, 0x00, 0x48, 0xC7, 0xC7, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x31, 0xF6, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x18,
+    // getppid()
+    0xB8, 0x6E, 0x00, 0x00, 0x00, 0x0F, 0x05, 0x48, 0x89, 0x43, 0x20,
+    // exit_group(0)
+    0xB8, 0xE7, 0x00, 0x00, 0x00, 0x31, 0xFF, 0x0F, 0x05,
+];
+
+/// Replaces itself with `/tmp/hello`, after checking a path that doesn't exist fails
+#[rustfmt::skip]
+const EXEC: [u8; 57] = [
+    0x48, 0x8D, 0x9C, 0x24, 0x00, 0xF0, 0xFF, 0xFF, // lea rbx, [rsp - 4096]
+    // execve(rbx + 512, 0, 0), which doesn't exist
+    0xB8, 0x3B, 0x00, 0x00, 0x00, 0x48, 0x8D, 0xBB, 0x00, 0x02, 0x00, 0x00,
+    0x31, 0xF6, 0x31, 0xD2, 0x0F, 0x05,
+    // ud2 unless it returned -ENOENT
+    0x48, 0x83, 0xF8, 0xFE, 0x75, 0x17,
+    // execve(rbx + 528, rbx + 768, 0)
+    0xB8, 0x3B, 0x00, 0x00, 0x00, 0x48, 0x8D, 0xBB, 0x10, 0x02, 0x00, 0x00,
+    0x48, 0x8D, 0xB3, 0x00, 0x03, 0x00, 0x00, 0x31, 0xD2, 0x0F, 0x05,
+    0x0F, 0x0B,
+];
+
+/// What each program's code and stack are mapped in
+const PAGE: Layout = unsafe { Layout::from_size_align_unchecked(4096, 4096) };
+
+/// Check user processes, and the system calls they make
+pub fn run() {
+    programs();
+    syscalls();
+    elf();
+    fork();
+    exec();
+    orphans();
+}
+
+/// Make a process with its code on one page, and a stack page, which hasn't been started.
+/// Get it, and where its code and stack are
+fn load(name: &str, code: &[&[u8]]) -> (Arc<Process>, usize, usize) {
+    let process = Process::new(name, None, Credentials::ROOT).unwrap();
+    let address_space = process.address_space();
+    let entry = address_space
+        .map(None, PAGE, MemoryFlags::READABLE | MemoryFlags::EXECUTABLE)
+        .unwrap();
+    let stack = address_space
+        .map(None, PAGE, MemoryFlags::READABLE | MemoryFlags::WRITABLE)
+        .unwrap();
+    let mut offset = entry;
+    for bytes in code {
+        address_space.write_bytes(offset, bytes).unwrap();
+        offset += bytes.len();
+    }
+    (process, entry, stack)
+}
+
+/// Read the results a program stored at the bottom of its stack page
+fn results(address_space: &AddressSpace, stack: usize, count: usize) -> Vec<i64> {
+    let mut results: Vec<u8> = core::iter::repeat(0).take(count * 8).collect();
+    address_space.read_bytes(stack, &mut results).unwrap();
+    results
+        .chunks_exact(8)
+        .map(|bytes| i64::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect()
+}
+
+/// Check programs run and exit in their own processes, which can then be reaped
+fn programs() {
+    let run = |code: &[&[u8]]| {
+        let (process, entry, stack) = load("test", code);
+        process.spawn_thread(entry, stack + PAGE.size()).unwrap();
+        let status = process.wait();
+        assert!(process::get(process.pid()).is_some());
+        let mut path = String::new();
+        {
+            use core::fmt::Write;
+            write!(path, "/proc/{}/status", process.pid().0).unwrap();
+        }
+        let status_file = fs::open(None, &path, fs::OpenFlags::READ, 0).unwrap();
+        let mut text = [0; 256];
+        let length = status_file.read(&mut text).unwrap();
+        assert!(core::str::from_utf8(&text[..length])
+            .unwrap()
+            .contains("state exited\n"));
+        process.reap();
+        assert_eq!(fs::stat(None, &path), Err(FileSystemError::NotFound));
+        assert!(process::get(process.pid()).is_none());
+        status
+    };
+
+    assert_eq!(run(&[&PROGRAM, MESSAGE]), ExitStatus::Exited(42));
+    // Its first instruction is `ud2`
+    assert_eq!(
+        run(&[&PROGRAM[PROGRAM.len() - 2..]]),
+        ExitStatus::Faulted(Fault::Instruction)
+    );
+    debug!("Ran programs in their own processes");
+}
+
+/// Check system calls from user mode, and the errors they return
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss
+)]
+fn syscalls() {
+    let (process, entry, stack) = load("syscalls", &[&SYSCALLS]);
+    let address_space = process.address_space();
+    address_space
+        .write_bytes(stack + 512, b"/dev/zero\0")
+        .unwrap();
+    process.spawn_thread(entry, stack + PAGE.size()).unwrap();
+    assert_eq!(process.wait(), ExitStatus::Exited(7));
+
+    let results = results(&address_space, stack, 13);
+    let mapped = results[1] as usize;
+    assert_eq!(results[0], process.pid().0 as i64);
+    assert!(mapped % 4096 == 0 && mapped >= memory::USER_SPACE_START);
+    assert_eq!(results[2..5], [-22, 0, 0]);
+    assert_eq!(results[5..7], [0, -14]);
+    // Descriptors 0 to 2 are the console
+    assert_eq!(results[7..11], [3, 64, 0, -9]);
+    assert_eq!(results[11..13], [0, -38]);
+    let mut time = [0; 16];
+    address_space.read_bytes(stack + 128, &mut time).unwrap();
+    let nanoseconds = i64::from_ne_bytes(time[8..].try_into().unwrap());
+    assert!((0..1_000_000_000).contains(&nanoseconds));
+    assert!(address_space
+        .regions()
+        .iter()
+        .all(|region| region.start != mapped));
+    drop(address_space);
+    process.reap();
+    debug!("Made system calls from user mode");
+}
+
+/// Make [`PROGRAM`] into a position independent executable, with one segment holding the lot
+fn position_independent() -> Vec<u8> {
+    let mut program = Vec::new();
+    program.extend_from_slice(b"\x7FELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+    program.extend_from_slice(&3u16.to_le_bytes()); // type: shared object
+    program.extend_from_slice(&62u16.to_le_bytes()); // machine: x86_64
+    program.extend_from_slice(&1u32.to_le_bytes()); // version
+    program.extend_from_slice(&120u64.to_le_bytes()); // entry, after both headers
+    program.extend_from_slice(&64u64.to_le_bytes()); // program header offset
+    program.extend_from_slice(&0u64.to_le_bytes()); // section header offset
+    program.extend_from_slice(&0u32.to_le_bytes()); // flags
+    for size in [64u16, 56, 1, 0, 0, 0] {
+        program.extend_from_slice(&size.to_le_bytes());
+    }
+    let size = (120 + PROGRAM.len() + MESSAGE.len()) as u64;
+    program.extend_from_slice(&1u32.to_le_bytes()); // type: load
+    program.extend_from_slice(&5u32.to_le_bytes()); // flags: readable and executable
+    for value in [0, 0, 0, size, size, 4096u64] {
+        program.extend_from_slice(&value.to_le_bytes());
+    }
+    program.extend_from_slice(&PROGRAM);
+    program.extend_from_slice(MESSAGE);
+    program
+}
+
+/// Check an ELF program is loaded and relocated, and that its heap grows and shrinks
+fn elf() {
+    let mut program = position_independent();
+    let process = Process::spawn_program(
+        "elf",
+        None,
+        Credentials::ROOT,
+        &program,
+        &["elf", "argument"],
+        &["HOME=/"],
+    )
+    .unwrap();
+    assert_eq!(process.wait(), ExitStatus::Exited(42));
+
+    // Its heap starts on the page after it, and grows and shrinks by pages
+    let address_space = process.address_space();
+    let start = address_space.program_break();
+    assert!(start != 0 && start % 4096 == 0);
+    assert_eq!(
+        address_space.set_program_break(start + 5000).unwrap(),
+        start + 5000
+    );
+    assert_eq!(address_space.program_break(), start + 5000);
+    address_space.write_bytes(start + 4999, &[1]).unwrap();
+    assert!(address_space.is_accessible(start, 8192, true));
+    assert_eq!(address_space.set_program_break(start).unwrap(), start);
+    assert!(!address_space.is_accessible(start, 1, false));
+    assert!(address_space.set_program_break(start - 1).is_err());
+    drop(address_space);
+    process.reap();
+    program[0] = 0;
+    assert!(Process::spawn_program("elf", None, Credentials::ROOT, &program, &[], &[]).is_err());
+    debug!("Loaded and ran an ELF program");
+}
+
+/// Check forking, and waiting for children
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn fork() {
+    let (process, entry, stack) = load("fork", &[&FORK]);
+    process.spawn_thread(entry, stack + PAGE.size()).unwrap();
+    assert_eq!(process.wait(), ExitStatus::Exited(0));
+    let results = results(&process.address_space(), stack, 5);
+    let child = Pid(results[0] as u64);
+    assert!(child.0 > process.pid().0);
+    // The child's exit code is in the second byte of its status
+    assert_eq!(results[1] & 0xFFFF_FFFF, 5 << 8);
+    assert_eq!(results[2..5], [child.0 as i64, -10, 0]);
+    assert!(process::get(child).is_none());
+    process.reap();
+    debug!("Forked a process and waited for it");
+}
+
+/// Check a process's program can be replaced with one from a file
+fn exec() {
+    let program = position_independent();
+    let file = fs::open(
+        None,
+        "/tmp/hello",
+        fs::OpenFlags::WRITE | fs::OpenFlags::CREATE,
+        0o755,
+    )
+    .unwrap();
+    assert_eq!(file.write(&program).unwrap(), program.len());
+    drop(file);
+    let (process, entry, stack) = load("exec", &[&EXEC]);
+    let address_space = process.address_space();
+    address_space.write_bytes(stack + 512, b"/nope\0").unwrap();
+    address_space
+        .write_bytes(stack + 528, b"/tmp/hello\0")
+        .unwrap();
+    // The arguments are a pointer to the path, then a null one
+    address_space
+        .write_bytes(stack + 768, &(stack + 528).to_ne_bytes())
+        .unwrap();
+    drop(address_space);
+    process.spawn_thread(entry, stack + PAGE.size()).unwrap();
+    assert_eq!(process.wait(), ExitStatus::Exited(42));
+    assert_eq!(process.name(), "hello");
+    // The old program's memory went with it
+    assert!(!process.address_space().is_accessible(stack, 1, false));
+    process.reap();
+    fs::unlink(None, "/tmp/hello").unwrap();
+    debug!("Replaced a process's program");
+}
+
+/// Check a process's children go to the first process when it exits
+fn orphans() {
+    let (parent, entry, stack) = load("parent", &[&PROGRAM[PROGRAM.len() - 2..]]);
+    let child = Process::new("orphan", Some(&parent), Credentials::ROOT).unwrap();
+    assert_eq!(parent.children().len(), 1);
+    parent.spawn_thread(entry, stack + PAGE.size()).unwrap();
+    parent.wait();
+    assert_eq!(
+        child.parent().map(|process| process.pid()),
+        process::get(Pid::INIT).map(|process| process.pid())
+    );
+    assert!(parent.children().is_empty());
+    parent.reap();
+    child.reap();
+    debug!("Gave an exited process's children away");
+}
@@ -0,0 +1,130 @@
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use log::debug;
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    scheduler, smp, sync,
+    traits::{Platform, TimerManager},
+    CORE_MANAGER,
+};
+
+/// Check kernel threads, and the locks they block on
+pub fn run() {
+    sleep_and_yield();
+    affinity();
+    condition_variables();
+    lock_stress();
+}
+
+/// Check sleeping, yielding and joining
+fn sleep_and_yield() {
+    let start = PLATFORM_MANAGER.get_timer_manager().monotonic();
+    let sleeper = scheduler::spawn(|| {
+        scheduler::sleep(Duration::from_millis(20));
+        scheduler::current().id()
+    });
+    let worker = scheduler::spawn(|| (0..10u64).inspect(|_| scheduler::yield_now()).sum::<u64>());
+    assert_eq!(worker.join(), 45);
+    let id = sleeper.join();
+    let elapsed = PLATFORM_MANAGER.get_timer_manager().monotonic() - start;
+    assert!(elapsed >= Duration::from_millis(20));
+    debug!("Joined {id:?} after {elapsed:?}");
+}
+
+/// Check a thread pinned to a core stays on it
+fn affinity() {
+    let last = CORE_MANAGER.core_count() - 1;
+    let pinned = scheduler::spawn_with_affinity(scheduler::Affinity::only(last), || {
+        scheduler::yield_now();
+        smp::this_core().index
+    })
+    .unwrap();
+    assert_eq!(pinned.join(), last);
+    debug!("Ran a thread pinned to core index {last}");
+}
+
+/// Check waiting on a condition variable
+fn condition_variables() {
+    let pair = Arc::new((sync::SleepMutex::new(0u32), sync::Condvar::new()));
+    let producer = {
+        let pair = pair.clone();
+        scheduler::spawn(move || {
+            let (mutex, condvar) = &*pair;
+            for _ in 0..5 {
+                *mutex.lock() += 1;
+                condvar.notify_all();
+                scheduler::yield_now();
+            }
+        })
+    };
+    let (mutex, condvar) = &*pair;
+    let count = condvar.wait_while(mutex.lock(), |count| *count < 5);
+    assert_eq!(*count, 5);
+    drop(count);
+    producer.join();
+    debug!("Waited on a condition variable");
+}
+
+/// Fight over every kind of lock from threads on every core
+fn lock_stress() {
+    const ROUNDS: u64 = 1_000;
+    const TICKETS: u32 = 2;
+    struct Shared {
+        mutex: sync::Mutex<u64>,
+        rwlock: sync::RwLock<(u64, u64)>,
+        semaphore: sync::Semaphore,
+        holders: AtomicU32,
+    }
+    let shared = Arc::new(Shared {
+        mutex: sync::Mutex::new(0),
+        rwlock: sync::RwLock::new((0, 0)),
+        semaphore: sync::Semaphore::new(TICKETS),
+        holders: AtomicU32::new(0),
+    });
+
+    // Two threads per core, so every lock is fought over both across and within cores
+    let threads = CORE_MANAGER.core_count() * 2;
+    let handles: Vec<_> = (0..threads)
+        .map(|i| {
+            let shared = shared.clone();
+            let affinity = scheduler::Affinity::only(i % CORE_MANAGER.core_count());
+            scheduler::spawn_with_affinity(affinity, move || {
+                for round in 0..ROUNDS {
+                    *shared.mutex.lock() += 1;
+
+                    if round % 4 == 0 {
+                        let mut pair = shared.rwlock.write();
+                        pair.0 += 1;
+                        pair.1 += 1;
+                    } else {
+                        let pair = shared.rwlock.read();
+                        assert_eq!(pair.0, pair.1, "A reader saw a half-done write");
+                    }
+
+                    shared.semaphore.down();
+                    let holders = shared.holders.fetch_add(1, Ordering::AcqRel) + 1;
+                    assert!(
+                        holders <= TICKETS,
+                        "The semaphore gave out too many tickets"
+                    );
+                    shared.holders.fetch_sub(1, Ordering::AcqRel);
+                    shared.semaphore.up();
+                }
+            })
+            .unwrap()
+        })
+        .collect();
+    for handle in handles {
+        handle.join();
+    }
+
+    let threads = threads as u64;
+    assert_eq!(*shared.mutex.lock(), threads * ROUNDS);
+    assert_eq!(shared.rwlock.read().0, threads * ROUNDS / 4);
+    assert!(shared.semaphore.try_down().is_ok() && shared.semaphore.try_down().is_ok());
+    debug!("Stress tested locks with {threads} threads");
+}
@@ -1,33 +1,70 @@
-use core::alloc::Layout;
+use core::sync::atomic::AtomicUsize;
 
 use crate::{
-    errors::MemoryManagerError,
-    get_memory_manager,
-    memory::allocators::NeverAllocator,
-    sync::RwLock,
-    traits::{Init, MemoryFlags, MemoryManager},
+    arch::PLATFORM_MANAGER,
+    scheduler::CoreScheduler,
+    sync::{IrqNesting, RwLock},
+    traits::{Init, Platform},
 };
 
+/// The most cores the kernel can run on
+pub const MAX_CORES: usize = 256;
+
 /// Core-local data structure.
 /// This contains the heap allocator, scheduler, and misc. platform data
 #[allow(clippy::module_name_repetitions)]
 #[repr(C, align(0x1000))]
 pub struct CoreLocalData {
+    /// A pointer to this structure, so the platform can find it with a single load.
+    /// This must stay the first field
+    this: *const Self,
+    /// The top of the running thread's kernel stack, which system calls switch to.
+    /// The platform finds this at a fixed offset, so it must stay the second field
+    pub(crate) kernel_stack: AtomicUsize,
+    /// Where the user stack pointer is kept on entry to a system call, until it's on the kernel stack.
+    /// This must stay the third field
+    user_stack: AtomicUsize,
     /// The Core's Magic Number
     pub magic: u32,
     /// The Core's ID
     pub id: u32,
+    /// The Core's index, counting up from zero in the order cores were registered
+    pub index: usize,
     /// The Core's Heap
     pub heap: *mut (),
     /// The Core's Scheduler
-    pub scheduler: *mut (),
+    pub scheduler: CoreScheduler,
+    /// How deeply the core's interrupt-disabling guards are nested
+    pub irq: IrqNesting,
     platform_data: Box<[u8]>,
 }
 
+impl CoreLocalData {
+    /// The magic number every core's data starts with
+    pub const MAGIC: u32 = 0x1075_C04E;
+
+    /// Make the data for the core with the given ID
+    #[must_use]
+    pub fn new(id: u32, index: usize) -> Self {
+        Self {
+            this: core::ptr::null(),
+            kernel_stack: AtomicUsize::new(0),
+            user_stack: AtomicUsize::new(0),
+            magic: Self::MAGIC,
+            id,
+            index,
+            heap: core::ptr::null_mut(),
+            scheduler: CoreScheduler::new(),
+            irq: IrqNesting::new(),
+            platform_data: Box::new([]),
+        }
+    }
+}
+
 /// Core Manager
 /// Handles per-core data
 pub struct CoreManager {
-    core_refs: RwLock<Vec<(u32, *mut CoreLocalData), NeverAllocator>>,
+    cores: RwLock<Vec<&'static CoreLocalData>>,
 }
 
 impl CoreManager {
@@ -35,51 +72,68 @@ impl CoreManager {
     #[must_use]
     pub const fn new() -> Self {
         Self {
-            core_refs: RwLock::new(Vec::new_in(NeverAllocator)),
+            cores: RwLock::new(Vec::new()),
         }
     }
 
     /// Get Core Local Data for the given id
-    pub fn get_core_local_data(&self, id: u32) -> Option<*mut CoreLocalData> {
-        Some(self.core_refs.read().iter().find(|(i, _)| *i == id)?.1)
+    pub fn get_core_local_data(&self, id: u32) -> Option<&'static CoreLocalData> {
+        self.cores.read().iter().copied().find(|core| core.id == id)
+    }
+
+    /// Get Core Local Data for the given index
+    pub fn get_core_by_index(&self, index: usize) -> Option<&'static CoreLocalData> {
+        self.cores.read().get(index).copied()
     }
 
-    /// Initialize the Core this function is run on and register it with the Core Manager
-    pub fn initialize_core(&self) {}
+    /// Get the number of cores that have been registered
+    pub fn core_count(&self) -> usize {
+        self.cores.read().len()
+    }
+
+    /// Initialize the Core this function is run on and register it with the Core Manager.
+    /// Its core local data is allocated and handed to the platform, so [`super::this_core`] works
+    ///
+    /// # Panics
+    /// This will panic if more than [`MAX_CORES`] cores are registered, or if a core is registered twice
+    pub fn initialize_core(&self, id: u32) -> &'static CoreLocalData {
+        let data = {
+            let mut cores = self.cores.write();
+            assert!(
+                cores.iter().all(|core| core.id != id),
+                "Core {id} was registered twice"
+            );
+            assert!(
+                cores.len() < MAX_CORES,
+                "More than {MAX_CORES} cores started"
+            );
+
+            // Capacity was reserved up front, so this only allocates the data itself
+            let data = Box::leak(Box::new(CoreLocalData::new(id, cores.len())));
+            data.this = data;
+            let data: &'static CoreLocalData = data;
+            cores.push(data);
+            data
+        };
+
+        PLATFORM_MANAGER.set_core_local(data);
+        data
+    }
+}
+
+impl Default for CoreManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Init for CoreManager {
-    type Error = MemoryManagerError;
+    type Error = core::convert::Infallible;
 
     type Input = usize;
 
     fn init(&self, init_val: Self::Input) -> Result<(), Self::Error> {
-        let mut region = unsafe {
-            get_memory_manager().allocate_and_map(
-                get_memory_manager().get_current_table()?,
-                (*crate::SAFE_UPPER_HALF_RANGE).clone(),
-                MemoryFlags::KERNEL_ONLY | MemoryFlags::READABLE | MemoryFlags::WRITABLE,
-                Layout::from_size_align_unchecked(
-                    core::mem::size_of::<(u32, CoreLocalData)>() * init_val,
-                    core::mem::align_of::<(u32, CoreLocalData)>(),
-                ),
-            )
-        }?;
-
-        {
-            let mut data = self.core_refs.write();
-            *data = unsafe {
-                Vec::from_raw_parts_in(
-                    region
-                        .get_inner_ptr_mut()
-                        .cast::<(u32, *mut CoreLocalData)>(),
-                    0,
-                    init_val,
-                    NeverAllocator,
-                )
-            };
-        }
-
+        self.cores.write().reserve_exact(init_val.min(MAX_CORES));
         Ok(())
     }
 }
@@ -1,2 +1,19 @@
 mod core_local;
-pub use core_local::{CoreLocalData, CoreManager};
+pub use core_local::{CoreLocalData, CoreManager, MAX_CORES};
+
+mod per_core;
+pub use per_core::CoreLocal;
+
+use crate::{arch::PLATFORM_MANAGER, traits::Platform};
+
+/// Get the current core's local data.
+/// The current core must have been registered with the core manager
+pub fn this_core() -> &'static CoreLocalData {
+    PLATFORM_MANAGER.get_core_local()
+}
+
+/// Get the current core's local data, if it's been registered with the core manager.
+/// This is slower than [`this_core`], so it's for code that may run before that
+pub fn try_this_core() -> Option<&'static CoreLocalData> {
+    PLATFORM_MANAGER.try_get_core_local()
+}
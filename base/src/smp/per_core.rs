@@ -0,0 +1,98 @@
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use super::{this_core, MAX_CORES};
+
+/// A static with a separate value for each core, made on first use.
+/// Declare these with [`core_local!`](crate::core_local)
+pub struct CoreLocal<T> {
+    init: fn() -> T,
+    values: [AtomicPtr<T>; MAX_CORES],
+}
+
+impl<T> CoreLocal<T> {
+    /// A core's value that hasn't been made yet
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: AtomicPtr<T> = AtomicPtr::new(ptr::null_mut());
+
+    /// Create a new core local static, which is made with `init` on each core
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            init,
+            values: [Self::EMPTY; MAX_CORES],
+        }
+    }
+
+    /// Get the current core's value, making it if this is the first use
+    ///
+    /// # Panics
+    /// This will panic if the current core hasn't been registered with the core manager
+    pub fn get(&self) -> &T {
+        self.get_on(this_core().index)
+    }
+
+    /// Get the value of the core with the given index, making it if this is the first use
+    ///
+    /// # Panics
+    /// This will panic if the index is at least [`MAX_CORES`]
+    pub fn get_on(&self, index: usize) -> &T {
+        let slot = &self.values[index];
+
+        let value = slot.load(Ordering::Acquire);
+        if !value.is_null() {
+            return unsafe { &*value };
+        }
+
+        // Another core may be making the same value, in which case only one is kept
+        let value = Box::into_raw(Box::new((self.init)()));
+        match slot.compare_exchange(ptr::null_mut(), value, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => unsafe { &*value },
+            Err(existing) => {
+                drop(unsafe { Box::from_raw(value) });
+                unsafe { &*existing }
+            }
+        }
+    }
+
+    /// Get the value of the core with the given index, if it's been made.
+    /// This never allocates, so it's safe to use from an interrupt handler
+    pub fn try_get_on(&self, index: usize) -> Option<&T> {
+        unsafe { self.values.get(index)?.load(Ordering::Acquire).as_ref() }
+    }
+}
+
+unsafe impl<T: Send + Sync> Sync for CoreLocal<T> {}
+
+/// Declare statics with a separate value for each core
+///
+/// # Example
+/// ```rust
+/// core_local! {
+///     /// The number of interrupts this core has handled
+///     pub static INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+/// }
+///
+/// INTERRUPTS.get().fetch_add(1, Ordering::Relaxed);
+/// ```
+#[macro_export]
+macro_rules! core_local {
+    (
+        $(
+            $(#[$outer_meta:meta])*
+            $visib:vis static $ident:ident: $ty:ty = $init:expr;
+        )*
+    ) => {
+        $(
+            $(#[$outer_meta])*
+            $visib static $ident: $crate::smp::CoreLocal<$ty> = {
+                fn create_core_local_item() -> $ty {
+                    $init
+                }
+
+                $crate::smp::CoreLocal::new(create_core_local_item)
+            };
+        )*
+    };
+}
@@ -0,0 +1,50 @@
+use super::{SleepMutexGuard, WaitQueue};
+
+/// A condition variable, which threads holding a [`SleepMutex`](super::SleepMutex)
+/// block on until another thread notifies them that what they're waiting for may have changed
+#[derive(Debug, Default)]
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    /// Make a condition variable with nothing waiting on it
+    #[must_use]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub const fn new() -> Self {
+        Self {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Unlock the mutex and block until notified, locking it again before returning.
+    /// A notification after the mutex is unlocked is never missed,
+    /// but this can also return without one, so the condition must be checked again
+    pub fn wait<'a, T>(&self, guard: SleepMutexGuard<'a, T>) -> SleepMutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        self.waiters.wait_with(|| drop(guard));
+        mutex.lock()
+    }
+
+    /// Block until `condition` returns false, checking it with the mutex locked
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: SleepMutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> SleepMutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Wake one of the threads waiting, returning whether there was one
+    pub fn notify_one(&self) -> bool {
+        self.waiters.wake_one()
+    }
+
+    /// Wake every thread waiting, returning how many there were
+    pub fn notify_all(&self) -> usize {
+        self.waiters.wake_all()
+    }
+}
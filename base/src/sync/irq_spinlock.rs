@@ -0,0 +1,146 @@
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    smp::try_this_core,
+    traits::{InterruptManager, Platform},
+};
+
+use super::{Mutex, MutexGuard};
+
+/// How deeply a core's [`IrqGuard`]s are nested, and whether interrupts were enabled
+/// before the outermost one, so they're only restored when the last one is dropped
+#[derive(Debug, Default)]
+pub struct IrqNesting {
+    depth: AtomicUsize,
+    enabled: AtomicBool,
+}
+
+impl IrqNesting {
+    /// Start with no guards held
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            depth: AtomicUsize::new(0),
+            enabled: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Interrupts disabled on the current core, until every guard on it has been dropped.
+/// Guards can be dropped in any order, as each core counts how many it has
+#[derive(Debug)]
+pub struct IrqGuard {
+    /// The count on the core this was made on, or `None` if it was made before the core was registered
+    nesting: Option<&'static IrqNesting>,
+    /// Whether interrupts were enabled before this guard, if it isn't counted on a core
+    enabled: bool,
+}
+
+impl IrqGuard {
+    /// Save whether interrupts are enabled and disable them
+    #[must_use]
+    pub fn new() -> Self {
+        let interrupts = PLATFORM_MANAGER.get_interrupt_manager();
+        let enabled = interrupts.interrupts_enabled();
+        let _ = interrupts.disable_interrupts();
+
+        let nesting = try_this_core().map(|core| &core.irq);
+        if let Some(nesting) = nesting {
+            // Nothing else runs on this core now, so the count can't change underneath it
+            if nesting.depth.fetch_add(1, Ordering::Relaxed) == 0 {
+                nesting.enabled.store(enabled, Ordering::Relaxed);
+            }
+        }
+        Self { nesting, enabled }
+    }
+}
+
+impl Default for IrqGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for IrqGuard {
+    fn drop(&mut self) {
+        let enable = match self.nesting {
+            Some(nesting) => {
+                nesting.depth.fetch_sub(1, Ordering::Relaxed) == 1
+                    && nesting.enabled.load(Ordering::Relaxed)
+            }
+            None => self.enabled,
+        };
+        if enable {
+            let _ = PLATFORM_MANAGER.get_interrupt_manager().enable_interrupts();
+        }
+    }
+}
+
+/// A spinlock which keeps interrupts disabled on the locking core while it's held,
+/// so an interrupt handler on that core can't deadlock waiting for it
+#[derive(Debug)]
+pub struct IrqSpinlock<T> {
+    inner: Mutex<T>,
+}
+
+/// A lock on an [`IrqSpinlock`], which unlocks it and then restores interrupts when dropped
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct IrqSpinlockGuard<'a, T> {
+    // Fields are dropped in order, so the lock is released before interrupts are restored
+    guard: MutexGuard<'a, T>,
+    _irq: IrqGuard,
+}
+
+impl<T> IrqSpinlock<T> {
+    /// Return a new spinlock
+    ///
+    /// # Arguments
+    /// * `value` - The initial value for the spinlock
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+
+    /// Disable interrupts and lock the spinlock, looping if it's currently not available
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn lock_irqsave(&self) -> IrqSpinlockGuard<T> {
+        let irq = IrqGuard::new();
+        IrqSpinlockGuard {
+            guard: self.inner.lock(),
+            _irq: irq,
+        }
+    }
+
+    /// Disable interrupts and try to lock the spinlock, restoring them if it's not available
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn try_lock_irqsave(&self) -> Option<IrqSpinlockGuard<T>> {
+        let irq = IrqGuard::new();
+        self.inner
+            .try_lock()
+            .map(|guard| IrqSpinlockGuard { guard, _irq: irq })
+    }
+
+    /// Get the inner value of the spinlock
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T> Deref for IrqSpinlockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSpinlockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
@@ -0,0 +1,370 @@
+use core::{
+    cell::UnsafeCell,
+    panic::Location,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+};
+
+use log::error;
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    smp::{try_this_core, MAX_CORES},
+    traits::{InterruptManager, Platform},
+};
+
+/// A class of locks, named by where they were made.
+/// Every lock made at the same place is treated as the same lock when checking lock order
+pub type LockClass = &'static Location<'static>;
+
+/// The most locks a thread can hold at once and still be checked
+const MAX_HELD: usize = 32;
+
+/// The most lock classes that can be told apart
+const MAX_CLASSES: usize = 512;
+
+/// The number of words in a row of the dependency graph
+const ROW_WORDS: usize = MAX_CLASSES / 64;
+
+/// The most dependencies whose call sites are remembered for reports
+const MAX_EDGES: usize = 4096;
+
+/// A lock held by a thread
+#[derive(Clone, Copy)]
+struct Held {
+    /// The lock's address
+    lock: usize,
+    /// The index of the lock's class
+    class: usize,
+    /// Where it was taken
+    at: &'static Location<'static>,
+}
+
+/// The locks a thread holds, in the order it took them
+pub struct HeldLocks {
+    locks: [Option<Held>; MAX_HELD],
+    depth: usize,
+}
+
+impl HeldLocks {
+    /// Hold nothing
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            locks: [None; MAX_HELD],
+            depth: 0,
+        }
+    }
+
+    /// Start holding a lock, dropping it from the checks if too many are held
+    fn push(&mut self, held: Held) {
+        if self.depth < MAX_HELD {
+            self.locks[self.depth] = Some(held);
+            self.depth += 1;
+        }
+    }
+
+    /// Stop holding the most recently taken lock at the given address.
+    /// Locks that were never recorded are ignored
+    fn remove(&mut self, lock: usize) {
+        let position = self.locks[..self.depth]
+            .iter()
+            .rposition(|held| held.map_or(false, |held| held.lock == lock));
+        if let Some(position) = position {
+            self.locks.copy_within(position + 1..self.depth, position);
+            self.depth -= 1;
+            self.locks[self.depth] = None;
+        }
+    }
+
+    /// Iterate over the held locks, oldest first
+    fn iter(&self) -> impl Iterator<Item = Held> + '_ {
+        self.locks[..self.depth].iter().flatten().copied()
+    }
+}
+
+impl Default for HeldLocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The locks held by code running on a core before the scheduler starts
+struct BootHeld(UnsafeCell<HeldLocks>);
+
+unsafe impl Sync for BootHeld {}
+
+/// Where a dependency between two classes was first seen
+struct Edge {
+    from: AtomicUsize,
+    to: AtomicUsize,
+    /// Where the lock of the `from` class was taken, as a `*const Location`
+    from_at: AtomicUsize,
+    /// Where the lock of the `to` class was taken after it, as a `*const Location`
+    to_at: AtomicUsize,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_BOOT_HELD: BootHeld = BootHeld(UnsafeCell::new(HeldLocks::new()));
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CURRENT: AtomicPtr<HeldLocks> = AtomicPtr::new(ptr::null_mut());
+#[allow(clippy::declare_interior_mutable_const)]
+const NOT_BUSY: AtomicBool = AtomicBool::new(false);
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CLASS: AtomicPtr<Location<'static>> = AtomicPtr::new(ptr::null_mut());
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_DEPENDENCIES: AtomicU64 = AtomicU64::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_ROW: [AtomicU64; ROW_WORDS] = [NO_DEPENDENCIES; ROW_WORDS];
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_EDGE: Edge = Edge {
+    from: AtomicUsize::new(0),
+    to: AtomicUsize::new(0),
+    from_at: AtomicUsize::new(0),
+    to_at: AtomicUsize::new(0),
+};
+
+/// Each core's held locks before its scheduler starts
+static BOOT_HELD: [BootHeld; MAX_CORES] = [NO_BOOT_HELD; MAX_CORES];
+
+/// The held locks of the thread running on each core, or null before the scheduler starts
+static CURRENT: [AtomicPtr<HeldLocks>; MAX_CORES] = [NO_CURRENT; MAX_CORES];
+
+/// Set while a core is checking a lock, so locks taken while reporting aren't checked
+static BUSY: [AtomicBool; MAX_CORES] = [NOT_BUSY; MAX_CORES];
+
+/// The classes seen so far, hashed by address
+static CLASSES: [AtomicPtr<Location<'static>>; MAX_CLASSES] = [NO_CLASS; MAX_CLASSES];
+
+/// Set once the class table fills up, after which nothing more is checked
+static FULL: AtomicBool = AtomicBool::new(false);
+
+/// Bit `to` of row `from` is set once a lock of class `to` was taken while holding one of `from`
+static GRAPH: [[AtomicU64; ROW_WORDS]; MAX_CLASSES] = [EMPTY_ROW; MAX_CLASSES];
+
+/// Where each dependency was first seen
+static EDGES: [Edge; MAX_EDGES] = [NO_EDGE; MAX_EDGES];
+
+/// The number of dependencies seen, which can be more than are remembered
+static EDGE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of problems reported
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the number of lock order problems reported since boot
+pub fn report_count() -> usize {
+    REPORTS.load(Ordering::Relaxed)
+}
+
+/// Track the given held locks on the current core, from now until the next switch.
+/// The scheduler calls this as it switches threads
+///
+/// # Safety
+/// The held locks must stay alive while they're current, and only be touched by this core
+pub unsafe fn set_current(held: *mut HeldLocks) {
+    if let Some(core) = try_this_core() {
+        CURRENT[core.index].store(held, Ordering::Relaxed);
+    }
+}
+
+/// Record that a lock is about to be waited for, reporting it if taking it could deadlock
+#[inline(never)]
+pub(crate) fn acquire(lock: *const (), class: LockClass, at: &'static Location<'static>) {
+    with_held(|held| {
+        let class = match class_index(class) {
+            Some(class) => class,
+            None => return,
+        };
+
+        for other in held.iter() {
+            if other.lock == lock as usize {
+                report_recursion(other, at);
+                continue;
+            }
+            if other.class == class || has_edge(other.class, class) {
+                continue;
+            }
+            if path(class, other.class) {
+                report_inversion(other, class, at);
+            }
+            add_edge(other, class, at);
+        }
+
+        held.push(Held {
+            lock: lock as usize,
+            class,
+            at,
+        });
+    });
+}
+
+/// Record that a lock was taken without waiting, which can't deadlock, so nothing is checked
+#[inline(never)]
+pub(crate) fn acquired(lock: *const (), class: LockClass, at: &'static Location<'static>) {
+    with_held(|held| {
+        if let Some(class) = class_index(class) {
+            held.push(Held {
+                lock: lock as usize,
+                class,
+                at,
+            });
+        }
+    });
+}
+
+/// Record that a lock was released
+#[inline(never)]
+pub(crate) fn release(lock: *const ()) {
+    with_held(|held| held.remove(lock as usize));
+}
+
+/// Run `f` on the current thread's held locks with interrupts disabled,
+/// unless the core is already checking a lock
+fn with_held(f: impl FnOnce(&mut HeldLocks)) {
+    if FULL.load(Ordering::Relaxed) {
+        return;
+    }
+
+    PLATFORM_MANAGER
+        .get_interrupt_manager()
+        .without_interrupts(|| {
+            let index = try_this_core().map_or(0, |core| core.index);
+            if BUSY[index].swap(true, Ordering::Acquire) {
+                return;
+            }
+
+            let current = CURRENT[index].load(Ordering::Relaxed);
+            let held = if current.is_null() {
+                BOOT_HELD[index].0.get()
+            } else {
+                current
+            };
+            f(unsafe { &mut *held });
+
+            BUSY[index].store(false, Ordering::Release);
+        });
+}
+
+/// Find a class's index, adding it if it's new
+fn class_index(class: LockClass) -> Option<usize> {
+    let class = class as *const Location<'static> as *mut Location<'static>;
+    let start = (class as usize >> 3) % MAX_CLASSES;
+
+    for offset in 0..MAX_CLASSES {
+        let index = (start + offset) % MAX_CLASSES;
+        let slot = &CLASSES[index];
+        match slot.compare_exchange(ptr::null_mut(), class, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return Some(index),
+            Err(existing) if existing == class => return Some(index),
+            Err(_) => {}
+        }
+    }
+
+    if !FULL.swap(true, Ordering::Relaxed) {
+        error!("Lockdep: more than {MAX_CLASSES} lock classes, so it's turned itself off");
+    }
+    None
+}
+
+/// Check whether a lock of class `to` has been taken while holding one of class `from`
+fn has_edge(from: usize, to: usize) -> bool {
+    GRAPH[from][to / 64].load(Ordering::Relaxed) & (1 << (to % 64)) != 0
+}
+
+/// Check whether the graph has a chain of dependencies from one class to another
+fn path(from: usize, to: usize) -> bool {
+    let mut visited = [0u64; ROW_WORDS];
+    let mut stack = [0u16; MAX_CLASSES];
+    let mut depth = 1;
+    stack[0] = from as u16;
+    visited[from / 64] |= 1 << (from % 64);
+
+    while depth > 0 {
+        depth -= 1;
+        let class = stack[depth] as usize;
+        if class == to {
+            return true;
+        }
+
+        for (word, row) in GRAPH[class].iter().enumerate() {
+            let mut bits = row.load(Ordering::Relaxed) & !visited[word];
+            visited[word] |= bits;
+            while bits != 0 {
+                let next = word * 64 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                // Every class is pushed at most once, so this never overflows
+                stack[depth] = next as u16;
+                depth += 1;
+            }
+        }
+    }
+    false
+}
+
+/// Record that a lock of class `to` was taken at `at` while holding `from`
+fn add_edge(from: Held, to: usize, at: &'static Location<'static>) {
+    let bit = 1 << (to % 64);
+    if GRAPH[from.class][to / 64].fetch_or(bit, Ordering::Relaxed) & bit != 0 {
+        return;
+    }
+
+    let index = EDGE_COUNT.fetch_add(1, Ordering::Relaxed);
+    if let Some(edge) = EDGES.get(index) {
+        edge.from_at
+            .store(from.at as *const _ as usize, Ordering::Relaxed);
+        edge.to_at.store(at as *const _ as usize, Ordering::Relaxed);
+        edge.from.store(from.class, Ordering::Relaxed);
+        edge.to.store(to, Ordering::Release);
+    }
+}
+
+/// Find where a dependency was first seen, if it's remembered
+fn edge_sites(
+    from: usize,
+    to: usize,
+) -> Option<(&'static Location<'static>, &'static Location<'static>)> {
+    let count = EDGE_COUNT.load(Ordering::Relaxed).min(MAX_EDGES);
+    EDGES[..count].iter().find_map(|edge| {
+        let matches =
+            edge.to.load(Ordering::Acquire) == to && edge.from.load(Ordering::Relaxed) == from;
+        let from_at = edge.from_at.load(Ordering::Relaxed) as *const Location<'static>;
+        let to_at = edge.to_at.load(Ordering::Relaxed) as *const Location<'static>;
+        (matches && !from_at.is_null() && !to_at.is_null()).then(|| unsafe { (&*from_at, &*to_at) })
+    })
+}
+
+/// Get the class at an index
+fn class_at(index: usize) -> LockClass {
+    unsafe { &*CLASSES[index].load(Ordering::Relaxed) }
+}
+
+/// Report a thread waiting for a lock it already holds
+fn report_recursion(held: Held, at: &'static Location<'static>) {
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    error!(
+        "Lockdep: recursive locking of the lock made at {}: taken at {}, and again at {}",
+        class_at(held.class),
+        held.at,
+        at
+    );
+}
+
+/// Report a lock being taken in the opposite order to one seen before
+fn report_inversion(held: Held, class: usize, at: &'static Location<'static>) {
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    error!(
+        "Lockdep: lock order inversion: the lock made at {} is taken at {} while holding the lock made at {}, taken at {}",
+        class_at(class),
+        at,
+        class_at(held.class),
+        held.at
+    );
+    if let Some((first, second)) = edge_sites(class, held.class) {
+        error!(
+            "Lockdep: they were taken the other way round before: at {} and then at {}",
+            first, second
+        );
+    } else {
+        error!("Lockdep: they were taken the other way round before, through other locks");
+    }
+}
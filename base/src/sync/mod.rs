@@ -10,9 +10,39 @@ pub use semaphore::Semaphore;
 mod spinlock;
 pub use spinlock::Spinlock;
 
+mod irq_spinlock;
+pub use irq_spinlock::{IrqGuard, IrqNesting, IrqSpinlock, IrqSpinlockGuard};
+
+mod wait_queue;
+pub use wait_queue::WaitQueue;
+
+mod sleep_mutex;
+pub use sleep_mutex::{SleepMutex, SleepMutexGuard};
+
+mod condvar;
+pub use condvar::Condvar;
+
+mod sleep_semaphore;
+pub use sleep_semaphore::SleepSemaphore;
+
+/// Lock dependency checking, which reports locks taken in an order that could deadlock
+#[cfg(feature = "lockdep")]
+pub mod lockdep;
+
 mod lazy;
 pub(crate) use lazy::{lazy_static, Lazy};
 
+/// Wait a moment before checking a lock again
+#[inline]
+fn spin_wait() {
+    // Host test threads can be preempted while holding a lock, unlike a core with interrupts
+    // disabled, so a waiter gives up the CPU instead of spinning out its time slice
+    #[cfg(test)]
+    std::thread::yield_now();
+    #[cfg(not(test))]
+    core::hint::spin_loop();
+}
+
 /// Lie about something being sync
 #[repr(transparent)]
 pub struct FakeSyncWrapper<T> {
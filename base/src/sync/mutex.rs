@@ -1,8 +1,10 @@
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
-use core::sync::atomic::{AtomicBool, Ordering};
 
-/// A **M**utual **E**xclusion synchronization device
+use super::Spinlock;
+
+/// A **M**utual **E**xclusion synchronization device.
+/// It's a [`Spinlock`] underneath, so it's handed out fairly
 ///
 /// # Example
 /// ```rust
@@ -12,7 +14,7 @@ use core::sync::atomic::{AtomicBool, Ordering};
 /// ```
 #[derive(Debug)]
 pub struct Mutex<T: ?Sized> {
-    lock: AtomicBool,
+    lock: Spinlock,
     data: UnsafeCell<T>,
 }
 
@@ -33,20 +35,18 @@ impl<T> Mutex<T> {
     ///
     /// # Arguments
     /// * `value` - The initial value for the mutex
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new(value: T) -> Self {
         Self {
-            lock: AtomicBool::new(false),
+            lock: Spinlock::new(),
             data: UnsafeCell::new(value),
         }
     }
 
     /// Try to lock the mutex
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
-        if self.lock.swap(true, Ordering::Acquire) {
-            None
-        } else {
-            Some(MutexGuard { data: self })
-        }
+        self.lock.try_acquire().then(|| MutexGuard { data: self })
     }
 
     /// Lock the mutex, looping if it's currently not available
@@ -63,12 +63,10 @@ impl<T> Mutex<T> {
     ///
     /// assert!(!mtx.into_inner());
     /// ```
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn lock(&self) -> MutexGuard<T> {
-        loop {
-            if let Some(data) = self.try_lock() {
-                return data;
-            }
-        }
+        self.lock.acquire();
+        MutexGuard { data: self }
     }
 
     /// Get the inner value of the mutex
@@ -89,7 +87,7 @@ impl<T> Mutex<T> {
 
 impl<T> Drop for MutexGuard<'_, T> {
     fn drop(&mut self) {
-        self.data.lock.swap(false, Ordering::Release);
+        self.data.lock.release();
     }
 }
 
@@ -2,11 +2,32 @@ use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicU32, Ordering};
 
-/// A Read/Write Lock, allowing either many readers or one writer
+use super::spin_wait;
+
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
+
+#[cfg(feature = "lockdep")]
+use super::lockdep::{self, LockClass};
+
+/// Set while a writer holds the lock
+const WRITER: u32 = 1 << 31;
+/// Set while a writer is waiting for the readers to finish, which keeps new readers out
+const WRITER_WAITING: u32 = 1 << 30;
+/// The bits counting the readers holding the lock
+const READERS: u32 = WRITER_WAITING - 1;
+
+/// A Read/Write Lock, allowing either many readers or one writer.
+///
+/// Writers are preferred: once one is waiting, new readers wait behind it,
+/// so a steady stream of readers can't starve it.
+/// That means a reader mustn't take the lock again while it already holds it
 #[derive(Debug)]
 pub struct RwLock<T: ?Sized> {
-    write_lock: UnsafeCell<bool>,
-    read_locks: AtomicU32,
+    /// The writer bits and the reader count, together so they change atomically
+    state: AtomicU32,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
     data: UnsafeCell<T>,
 }
 
@@ -23,68 +44,97 @@ pub struct ReadLockGuard<'a, T> {
 }
 
 impl<T> RwLock<T> {
-    const fn get_write_lock(&self) -> bool {
-        unsafe { *self.write_lock.get() }
-    }
-
-    const fn set_write_lock(&self, val: bool) {
-        let inner = unsafe { &mut *self.write_lock.get() };
-        *inner = val;
-    }
-
     /// Create a new `RWLock`
     ///
     /// # Arguments
     /// * `value` - The initial value to use
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new(value: T) -> Self {
         Self {
-            write_lock: UnsafeCell::new(false),
-            read_locks: AtomicU32::new(0),
+            state: AtomicU32::new(0),
+            #[cfg(feature = "lockdep")]
+            class: Location::caller(),
             data: UnsafeCell::new(value),
         }
     }
 
+    /// The address lockdep knows this lock by
+    #[cfg(feature = "lockdep")]
+    fn address(&self) -> *const () {
+        (self as *const Self).cast()
+    }
+
     /// Try to get the write lock
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn try_lock(&self) -> Option<WriteLockGuard<T>> {
-        // Check if any read locks are present
-        if self.read_locks.load(Ordering::Relaxed) > 0 {
-            return None;
+        let guard = self.try_lock_unchecked();
+        #[cfg(feature = "lockdep")]
+        if guard.is_some() {
+            lockdep::acquired(self.address(), self.class, Location::caller());
         }
+        guard
+    }
 
-        // Set status to locked
-        if self.get_write_lock() {
-            None
-        } else {
-            self.set_write_lock(true);
-            Some(WriteLockGuard { data: self })
-        }
+    /// Try to get the write lock, without telling lockdep
+    fn try_lock_unchecked(&self) -> Option<WriteLockGuard<T>> {
+        self.state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                // Taking the lock satisfies this writer's wait, and any others set it again
+                (state & (WRITER | READERS) == 0).then_some((state & !WRITER_WAITING) | WRITER)
+            })
+            .ok()
+            .map(|_| WriteLockGuard { data: self })
     }
 
-    /// Get write lock to the data, won't work unless there's no read locks
+    /// Get write lock to the data, waiting for the readers to finish.
+    /// New readers wait until it's been taken
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn write(&self) -> WriteLockGuard<T> {
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(self.address(), self.class, Location::caller());
+
         loop {
-            if let Some(write_guard) = self.try_lock() {
+            if let Some(write_guard) = self.try_lock_unchecked() {
                 return write_guard;
             }
+            self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            spin_wait();
         }
     }
 
     /// Check if data is available to read, returning none if not
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn try_read(&self) -> Option<ReadLockGuard<T>> {
-        if self.get_write_lock() {
-            None
-        } else {
-            self.read_locks.fetch_add(1, Ordering::Acquire);
-            Some(ReadLockGuard { data: self })
+        let guard = self.try_read_unchecked();
+        #[cfg(feature = "lockdep")]
+        if guard.is_some() {
+            lockdep::acquired(self.address(), self.class, Location::caller());
         }
+        guard
+    }
+
+    /// Check if data is available to read, without telling lockdep
+    fn try_read_unchecked(&self) -> Option<ReadLockGuard<T>> {
+        self.state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                (state & (WRITER | WRITER_WAITING) == 0 && state & READERS != READERS)
+                    .then_some(state + 1)
+            })
+            .ok()
+            .map(|_| ReadLockGuard { data: self })
     }
 
     /// Wait until data is available, then return it
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn read(&self) -> ReadLockGuard<T> {
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(self.address(), self.class, Location::caller());
+
         loop {
-            if let Some(read_guard) = self.try_read() {
+            if let Some(read_guard) = self.try_read_unchecked() {
                 return read_guard;
             }
+            spin_wait();
         }
     }
 
@@ -107,13 +157,19 @@ impl<T> RwLock<T> {
 
 impl<T> Drop for WriteLockGuard<'_, T> {
     fn drop(&mut self) {
-        self.data.set_write_lock(false);
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.data.address());
+
+        self.data.state.fetch_and(!WRITER, Ordering::Release);
     }
 }
 
 impl<T> Drop for ReadLockGuard<'_, T> {
     fn drop(&mut self) {
-        self.data.read_locks.fetch_sub(1, Ordering::Release);
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.data.address());
+
+        self.data.state.fetch_sub(1, Ordering::Release);
     }
 }
 
@@ -140,3 +196,85 @@ impl<T> Deref for ReadLockGuard<'_, T> {
 }
 
 unsafe impl<T> Sync for RwLock<T> {}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use super::*;
+
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 10_000;
+
+    #[test]
+    fn readers_and_writers_exclude_each_other() {
+        let lock = RwLock::new(0);
+        // Writers set the top bit and readers count up, so each can see what it overlapped
+        let inside = AtomicUsize::new(0);
+        let writer = 1 << (usize::BITS - 1);
+
+        thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let (lock, inside) = (&lock, &inside);
+                scope.spawn(move || {
+                    for _ in 0..ROUNDS {
+                        if thread % 4 == 0 {
+                            let mut count = lock.write();
+                            assert_eq!(inside.fetch_or(writer, Ordering::Relaxed), 0);
+                            // Give the others a chance to get in while it's held
+                            thread::yield_now();
+                            *count += 1;
+                            inside.fetch_and(!writer, Ordering::Relaxed);
+                        } else {
+                            let _count = lock.read();
+                            assert_eq!(inside.fetch_add(1, Ordering::Relaxed) & writer, 0);
+                            thread::yield_now();
+                            inside.fetch_sub(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(lock.into_inner(), THREADS / 4 * ROUNDS);
+    }
+
+    #[test]
+    fn writers_exclude_each_other() {
+        let lock = RwLock::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..ROUNDS {
+                        if let Some(mut count) = lock.try_lock() {
+                            *count += 1;
+                        } else {
+                            *lock.write() += 1;
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(lock.into_inner(), THREADS * ROUNDS);
+    }
+
+    #[test]
+    fn waiting_writer_keeps_new_readers_out() {
+        let lock = RwLock::new(0);
+        let reader = lock.read();
+        assert!(lock.try_lock().is_none());
+
+        thread::scope(|scope| {
+            let writer = scope.spawn(|| *lock.write() += 1);
+            while lock.state.load(Ordering::Relaxed) & WRITER_WAITING == 0 {
+                spin_wait();
+            }
+            assert!(lock.try_read().is_none());
+            drop(reader);
+            writer.join().unwrap();
+        });
+        assert_eq!(lock.state.load(Ordering::Relaxed), 0);
+        assert_eq!(*lock.read(), 1);
+    }
+}
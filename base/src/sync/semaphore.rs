@@ -1,5 +1,7 @@
 use core::sync::atomic::{AtomicU32, Ordering};
 
+use super::spin_wait;
+
 /// Semaphore Errors
 #[allow(clippy::module_name_repetitions)]
 pub enum SemaphoreError {
@@ -28,13 +30,11 @@ impl Semaphore {
         self.count.fetch_add(1, Ordering::AcqRel);
     }
 
-    /// Decrease count
+    /// Decrease count, spinning until there's a ticket to take
     #[inline]
     pub fn down(&self) {
-        loop {
-            if self.try_down().is_ok() {
-                return;
-            }
+        while self.try_down().is_err() {
+            spin_wait();
         }
     }
 
@@ -45,15 +45,13 @@ impl Semaphore {
     /// `SemaphoreError::TicketsExhausted`
     #[inline]
     pub fn try_down(&self) -> Result<(), SemaphoreError> {
-        let mut value = self.count.load(Ordering::Acquire);
-        if value > 0 {
-            value -= 1;
-            self.count.store(value, Ordering::Release);
-
-            Ok(())
-        } else {
-            Err(SemaphoreError::TicketsExhausted)
-        }
+        // Taking the ticket only succeeds if nothing else took it since the count was read
+        self.count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                count.checked_sub(1)
+            })
+            .map(|_| ())
+            .map_err(|_| SemaphoreError::TicketsExhausted)
     }
 }
 
@@ -65,3 +63,57 @@ impl Default for Semaphore {
 
 unsafe impl Sync for Semaphore {}
 unsafe impl Send for Semaphore {}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 10_000;
+
+    #[test]
+    fn never_hands_out_more_tickets_than_it_has() {
+        const TICKETS: u32 = 3;
+        let semaphore = Semaphore::new(TICKETS);
+        let holders = AtomicU32::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..ROUNDS {
+                        semaphore.down();
+                        assert!(holders.fetch_add(1, Ordering::Relaxed) < TICKETS);
+                        holders.fetch_sub(1, Ordering::Relaxed);
+                        semaphore.up();
+                    }
+                });
+            }
+        });
+        // Every ticket came back, and no more than that
+        assert_eq!(semaphore.count.load(Ordering::Relaxed), TICKETS);
+    }
+
+    #[test]
+    fn tickets_arent_lost_or_duplicated() {
+        let semaphore = Semaphore::new(0);
+
+        thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let semaphore = &semaphore;
+                scope.spawn(move || {
+                    for _ in 0..ROUNDS {
+                        if thread % 2 == 0 {
+                            semaphore.up();
+                        } else {
+                            semaphore.down();
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(semaphore.count.load(Ordering::Relaxed), 0);
+        assert!(semaphore.try_down().is_err());
+    }
+}
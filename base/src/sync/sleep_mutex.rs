@@ -0,0 +1,94 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::WaitQueue;
+
+/// A mutex which blocks the threads waiting for it instead of spinning,
+/// for long critical sections. It can't be locked in interrupt context, unlike [`super::Mutex`]
+#[derive(Debug)]
+pub struct SleepMutex<T: ?Sized> {
+    lock: AtomicBool,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+/// A lock on a [`SleepMutex`], which unlocks it and wakes the next waiter when dropped
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct SleepMutexGuard<'a, T> {
+    mutex: &'a SleepMutex<T>,
+}
+
+impl<T> SleepMutex<T> {
+    /// Return a new mutex
+    ///
+    /// # Arguments
+    /// * `value` - The initial value for the mutex
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub const fn new(value: T) -> Self {
+        Self {
+            lock: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Try to lock the mutex without blocking
+    pub fn try_lock(&self) -> Option<SleepMutexGuard<T>> {
+        if self.lock.swap(true, Ordering::Acquire) {
+            None
+        } else {
+            Some(SleepMutexGuard { mutex: self })
+        }
+    }
+
+    /// Lock the mutex, blocking until it's available
+    pub fn lock(&self) -> SleepMutexGuard<T> {
+        if let Some(guard) = self.try_lock() {
+            return guard;
+        }
+        self.waiters
+            .wait_until(|| !self.lock.swap(true, Ordering::Acquire));
+        SleepMutexGuard { mutex: self }
+    }
+
+    /// Get the inner value of the mutex
+    pub fn into_inner(self) -> T
+    where
+        T: Sized,
+    {
+        self.data.into_inner()
+    }
+}
+
+impl<'a, T> SleepMutexGuard<'a, T> {
+    /// Get the mutex this guard locks
+    pub(super) const fn mutex(&self) -> &'a SleepMutex<T> {
+        self.mutex
+    }
+}
+
+impl<T> Drop for SleepMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.lock.store(false, Ordering::Release);
+        self.mutex.waiters.wake_one();
+    }
+}
+
+impl<T> Deref for SleepMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for SleepMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+unsafe impl<T: Send> Sync for SleepMutex<T> {}
+unsafe impl<T: Send> Send for SleepMutex<T> {}
@@ -0,0 +1,46 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::WaitQueue;
+
+/// A counting semaphore which blocks the threads waiting for a ticket instead of spinning.
+/// It can't be taken from in interrupt context, unlike [`super::Semaphore`],
+/// but it can be given to
+#[derive(Debug, Default)]
+pub struct SleepSemaphore {
+    count: AtomicU32,
+    waiters: WaitQueue,
+}
+
+impl SleepSemaphore {
+    /// Create a new semaphore with the intial ticket count `initial`
+    #[must_use]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub const fn new(initial: u32) -> Self {
+        Self {
+            count: AtomicU32::new(initial),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Give back a ticket, waking a thread waiting for one
+    pub fn up(&self) {
+        self.count.fetch_add(1, Ordering::AcqRel);
+        self.waiters.wake_one();
+    }
+
+    /// Take a ticket, blocking until one is available
+    pub fn down(&self) {
+        if !self.try_down() {
+            self.waiters.wait_until(|| self.try_down());
+        }
+    }
+
+    /// Take a ticket if one is available, returning whether it was
+    pub fn try_down(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+}
@@ -1,39 +1,189 @@
-use core::{
-    arch::asm,
-    sync::atomic::{AtomicBool, Ordering},
-};
+use core::sync::atomic::{AtomicU32, Ordering};
 
-/// A spinlock
+use super::spin_wait;
+
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
+
+#[cfg(feature = "lockdep")]
+use super::lockdep::{self, LockClass};
+
+/// A fair spinlock, which hands itself out in the order it was asked for.
+///
+/// Each core takes a ticket and waits for it to be served, so no core can be starved
+/// by others that keep winning the race for the lock
 #[derive(Debug)]
 pub struct Spinlock {
-    flag: AtomicBool,
+    next_ticket: AtomicU32,
+    now_serving: AtomicU32,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
 }
 
 impl Spinlock {
     /// Create a new spinlock
     #[must_use]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new() -> Self {
         Self {
-            flag: AtomicBool::new(false),
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+            #[cfg(feature = "lockdep")]
+            class: Location::caller(),
         }
     }
 
     /// Spin to acquire the lock
     #[inline]
-    pub fn aquire(&self) {
-        // Set lock if previously not
-        while self
-            .flag
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::Acquire)
-            .is_err()
-        {
-            unsafe { asm!("nop") }
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn acquire(&self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(self.address(), self.class, Location::caller());
+
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            spin_wait();
+        }
+    }
+
+    /// Acquire the lock if nothing holds it or is waiting for it, returning whether it was
+    #[inline]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn try_acquire(&self) -> bool {
+        let serving = self.now_serving.load(Ordering::Relaxed);
+        let acquired = self
+            .next_ticket
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_ok();
+
+        #[cfg(feature = "lockdep")]
+        if acquired {
+            lockdep::acquired(self.address(), self.class, Location::caller());
         }
+        acquired
     }
 
-    /// Release the lock
+    /// Release the lock, serving the next ticket. It must be held
     #[inline]
     pub fn release(&self) {
-        self.flag.store(false, Ordering::SeqCst);
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.address());
+
+        self.now_serving.fetch_add(1, Ordering::Release);
+    }
+
+    /// The address lockdep knows this lock by
+    #[cfg(feature = "lockdep")]
+    fn address(&self) -> *const () {
+        (self as *const Self).cast()
+    }
+
+    /// Check whether the lock is held
+    #[inline]
+    pub fn is_locked(&self) -> bool {
+        self.next_ticket.load(Ordering::Relaxed) != self.now_serving.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Spinlock {
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+
+    use super::*;
+
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 10_000;
+
+    #[test]
+    fn excludes_under_contention() {
+        let lock = Spinlock::new();
+        let inside = AtomicBool::new(false);
+        // Incremented with a separate load and store, so it loses updates unless the lock holds
+        let count = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..ROUNDS {
+                        lock.acquire();
+                        assert!(!inside.swap(true, Ordering::Relaxed));
+                        let held = count.load(Ordering::Relaxed);
+                        // Give the others a chance to get in while it's held
+                        thread::yield_now();
+                        count.store(held + 1, Ordering::Relaxed);
+                        inside.store(false, Ordering::Relaxed);
+                        lock.release();
+                    }
+                });
+            }
+        });
+        assert_eq!(count.load(Ordering::Relaxed), THREADS * ROUNDS);
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    fn try_acquire_fails_while_held_or_awaited() {
+        let lock = Spinlock::new();
+        assert!(lock.try_acquire());
+        assert!(!lock.try_acquire());
+
+        thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                lock.acquire();
+                lock.release();
+            });
+            // Once the waiter has its ticket, releasing serves it rather than the lock being free
+            while lock.next_ticket.load(Ordering::Relaxed) < 2 {
+                spin_wait();
+            }
+            lock.release();
+            waiter.join().unwrap();
+        });
+        assert!(!lock.is_locked());
+        assert!(lock.try_acquire());
+        lock.release();
+    }
+
+    #[test]
+    fn mixes_try_acquire_and_acquire() {
+        let lock = Spinlock::new();
+        let count = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let (lock, count) = (&lock, &count);
+                scope.spawn(move || {
+                    let mut taken = 0;
+                    while taken < ROUNDS {
+                        if thread % 2 == 0 {
+                            lock.acquire();
+                        } else if !lock.try_acquire() {
+                            spin_wait();
+                            continue;
+                        }
+                        let held = count.load(Ordering::Relaxed);
+                        thread::yield_now();
+                        count.store(held + 1, Ordering::Relaxed);
+                        lock.release();
+                        taken += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(count.load(Ordering::Relaxed), THREADS * ROUNDS);
+        assert!(!lock.is_locked());
     }
 }
@@ -0,0 +1,117 @@
+use crate::scheduler::{self, Thread};
+
+use super::Mutex;
+
+/// A queue of threads blocked until something happens, which another thread wakes them for.
+///
+/// Waiting checks its condition while holding the queue's lock, and wakers change what the
+/// condition depends on before taking it, so no wakeup is lost in between.
+/// Before the scheduler is running on a core, waiting there spins instead.
+/// Waiting must never happen in interrupt context, but waking can
+#[derive(Debug)]
+pub struct WaitQueue {
+    /// Only locked with interrupts disabled, as threads can be woken from interrupt handlers
+    threads: Mutex<Vec<Arc<Thread>>>,
+}
+
+impl WaitQueue {
+    /// Make an empty queue
+    #[must_use]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub const fn new() -> Self {
+        Self {
+            threads: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Block until `condition` returns true, which is checked every time the thread is woken
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        let thread = if let Some(thread) = scheduler::try_current() {
+            thread
+        } else {
+            while !condition() {
+                core::hint::spin_loop();
+            }
+            return;
+        };
+
+        loop {
+            let waiting = scheduler::without_interrupts(|| {
+                let mut threads = self.threads.lock();
+                // It may have been woken by something else, and still be queued from last time
+                threads.retain(|waiting| !Arc::ptr_eq(waiting, &thread));
+                if condition() {
+                    return false;
+                }
+                thread.prepare_to_block();
+                threads.push(thread.clone());
+                true
+            });
+            if !waiting {
+                return;
+            }
+            scheduler::block();
+        }
+    }
+
+    /// Queue the current thread, run `before_block`, and then block until it's woken.
+    /// Anything waking the queue after `before_block` starts wakes the thread,
+    /// but it can also be woken by other things, so it must check why it was woken
+    pub fn wait_with(&self, before_block: impl FnOnce()) {
+        let thread = if let Some(thread) = scheduler::try_current() {
+            thread
+        } else {
+            before_block();
+            return;
+        };
+
+        scheduler::without_interrupts(|| {
+            thread.prepare_to_block();
+            self.threads.lock().push(thread.clone());
+        });
+        before_block();
+        scheduler::block();
+
+        scheduler::without_interrupts(|| {
+            self.threads
+                .lock()
+                .retain(|waiting| !Arc::ptr_eq(waiting, &thread));
+        });
+    }
+
+    /// Wake the thread that's been waiting longest, returning whether there was one
+    pub fn wake_one(&self) -> bool {
+        let thread = scheduler::without_interrupts(|| {
+            let mut threads = self.threads.lock();
+            if threads.is_empty() {
+                None
+            } else {
+                Some(threads.remove(0))
+            }
+        });
+
+        // Interrupt handlers can't free threads, but a blocked thread refers to itself on its stack
+        thread.map_or(false, |thread| {
+            thread.wake();
+            true
+        })
+    }
+
+    /// Wake every waiting thread, returning how many there were
+    pub fn wake_all(&self) -> usize {
+        scheduler::without_interrupts(|| {
+            let mut threads = self.threads.lock();
+            let count = threads.len();
+            for thread in threads.drain(..) {
+                thread.wake();
+            }
+            count
+        })
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
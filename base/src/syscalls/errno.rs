@@ -0,0 +1,121 @@
+use crate::errors::{ElfError, FileSystemError, MemoryManagerError, ProcessError};
+
+/// The errors system calls return, as the negated error numbers Linux uses,
+/// so C libraries written for it understand them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(isize)]
+pub enum Errno {
+    /// Nothing exists at the path
+    NotFound = 2,
+    /// The calling thread isn't in a process
+    NoSuchProcess = 3,
+    /// Something went wrong reading or writing a device or filesystem
+    InputOutput = 5,
+    /// A program's arguments and environment are too large
+    ArgumentListTooLong = 7,
+    /// A file isn't a program that can be run
+    ExecFormat = 8,
+    /// The file descriptor isn't open, or wasn't opened for what was tried
+    BadFileDescriptor = 9,
+    /// The process has no children that can be waited for
+    NoChildren = 10,
+    /// A thread couldn't be started right now
+    TryAgain = 11,
+    /// There isn't enough memory, or the range isn't mapped
+    OutOfMemory = 12,
+    /// The file can't be used that way, like running something that isn't a regular file
+    PermissionDenied = 13,
+    /// A pointer argument doesn't point at memory the caller can access
+    BadAddress = 14,
+    /// Something is mounted there, or a filesystem is still in use
+    Busy = 16,
+    /// Something already exists there
+    AlreadyExists = 17,
+    /// A name can't be moved or linked to another filesystem
+    CrossDevice = 18,
+    /// The file doesn't support being mapped
+    NoDevice = 19,
+    /// A path went through something that isn't a directory
+    NotADirectory = 20,
+    /// A file operation was tried on a directory
+    IsADirectory = 21,
+    /// An argument was out of range, or doesn't make sense
+    InvalidArgument = 22,
+    /// The process has as many files open as it's allowed
+    TooManyOpenFiles = 24,
+    /// The filesystem has no room left
+    NoSpace = 28,
+    /// The filesystem can't be changed
+    ReadOnly = 30,
+    /// A path is longer than the kernel will read
+    NameTooLong = 36,
+    /// There's no system call with that number
+    NoSuchSyscall = 38,
+    /// A directory couldn't be removed or replaced as something is in it
+    NotEmpty = 39,
+    /// Resolving a path followed too many symbolic links
+    TooManyLinks = 40,
+    /// The operation isn't something the file or filesystem can do, or the kernel doesn't support
+    Unsupported = 95,
+}
+
+impl Errno {
+    /// Get the value a system call returns for the error
+    #[must_use]
+    pub const fn result(self) -> isize {
+        -(self as isize)
+    }
+}
+
+impl From<FileSystemError> for Errno {
+    fn from(error: FileSystemError) -> Self {
+        match error {
+            FileSystemError::NotFound => Self::NotFound,
+            FileSystemError::NotADirectory => Self::NotADirectory,
+            FileSystemError::IsADirectory => Self::IsADirectory,
+            FileSystemError::AlreadyExists => Self::AlreadyExists,
+            FileSystemError::ReadOnly => Self::ReadOnly,
+            FileSystemError::Unsupported => Self::Unsupported,
+            FileSystemError::NotASymlink | FileSystemError::InvalidArgument => {
+                Self::InvalidArgument
+            }
+            FileSystemError::TooManyLinks => Self::TooManyLinks,
+            FileSystemError::BadDescriptor => Self::BadFileDescriptor,
+            FileSystemError::TooManyOpenFiles => Self::TooManyOpenFiles,
+            FileSystemError::Busy => Self::Busy,
+            FileSystemError::NotEmpty => Self::NotEmpty,
+            FileSystemError::CrossDevice => Self::CrossDevice,
+            FileSystemError::NoSpace => Self::NoSpace,
+            FileSystemError::UnknownFormat | FileSystemError::Corrupt => Self::InputOutput,
+        }
+    }
+}
+
+impl From<ProcessError> for Errno {
+    fn from(error: ProcessError) -> Self {
+        match error {
+            ProcessError::Memory(e) | ProcessError::Elf(ElfError::Memory(e)) => Self::from(e),
+            ProcessError::Scheduler(_) => Self::TryAgain,
+            ProcessError::Elf(ElfError::ArgumentsTooLarge) => Self::ArgumentListTooLong,
+            ProcessError::Elf(_) => Self::ExecFormat,
+            ProcessError::NoChildren => Self::NoChildren,
+            ProcessError::Threaded => Self::Unsupported,
+        }
+    }
+}
+
+impl From<MemoryManagerError> for Errno {
+    fn from(error: MemoryManagerError) -> Self {
+        match error {
+            MemoryManagerError::AddressUnmapped => Self::BadAddress,
+            MemoryManagerError::AddressMapped => Self::AlreadyExists,
+            MemoryManagerError::VirtualMemoryExhausted | MemoryManagerError::Allocator(_) => {
+                Self::OutOfMemory
+            }
+            MemoryManagerError::OutOfRange
+            | MemoryManagerError::CannotMapToHugePage
+            | MemoryManagerError::Generic(_)
+            | MemoryManagerError::Address(_) => Self::InvalidArgument,
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::fs::{self, Fd, OpenFlags};
+
+use super::{current, user, Errno};
+
+/// The most one `read` or `write` copies, past which they're short, as POSIX allows
+const MAX_TRANSFER: usize = 64 * 1024;
+
+/// The directory `openat` takes to mean the working directory
+const AT_FDCWD: i32 = -100;
+
+/// Linux's `open` flags that are understood. Any others are ignored
+const O_ACCMODE: usize = 0o3;
+const O_RDONLY: usize = 0o0;
+const O_WRONLY: usize = 0o1;
+const O_RDWR: usize = 0o2;
+const O_CREAT: usize = 0o100;
+const O_EXCL: usize = 0o200;
+const O_TRUNC: usize = 0o1000;
+const O_APPEND: usize = 0o2000;
+const O_DIRECTORY: usize = 0o200_000;
+const O_NOFOLLOW: usize = 0o400_000;
+
+/// `read(fd, buffer, length)`
+pub fn read([fd, buffer, length, ..]: [usize; 6]) -> Result<usize, Errno> {
+    let file = current()?.files().get(Fd(fd))?;
+    // Nothing's read unless it can be handed over
+    user::check_access(buffer, length, true)?;
+
+    let mut bytes: Vec<u8> = core::iter::repeat(0)
+        .take(length.min(MAX_TRANSFER))
+        .collect();
+    let read = file.read(&mut bytes)?;
+    user::write_bytes(buffer, &bytes[..read])?;
+    Ok(read)
+}
+
+/// `write(fd, buffer, length)`
+pub fn write([fd, buffer, length, ..]: [usize; 6]) -> Result<usize, Errno> {
+    let file = current()?.files().get(Fd(fd))?;
+
+    let mut bytes: Vec<u8> = core::iter::repeat(0)
+        .take(length.min(MAX_TRANSFER))
+        .collect();
+    user::read_bytes(buffer, &mut bytes)?;
+    Ok(file.write(&bytes)?)
+}
+
+/// `openat(directory, path, flags, mode)`, where relative paths start from `directory`,
+/// or the working directory if it's `AT_FDCWD`
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub fn openat([directory, path, flags, mode, ..]: [usize; 6]) -> Result<usize, Errno> {
+    let path = user::read_path(path)?;
+    let flags = open_flags(flags)?;
+    let process = current()?;
+    let files = process.files();
+
+    let start = if path.starts_with('/') || directory as i32 == AT_FDCWD {
+        files.working_directory()
+    } else {
+        files.get(Fd(directory))?.dentry().clone()
+    };
+    let file = fs::open(Some(&start), &path, flags, (mode & 0o7777) as u32)?;
+    Ok(files.insert(Arc::new(file))?.0)
+}
+
+/// `close(fd)`
+pub fn close([fd, ..]: [usize; 6]) -> Result<usize, Errno> {
+    current()?.files().close(Fd(fd))?;
+    Ok(0)
+}
+
+/// Convert Linux's `open` flags to the VFS's
+fn open_flags(flags: usize) -> Result<OpenFlags, Errno> {
+    let mut open_flags = match flags & O_ACCMODE {
+        O_RDONLY => OpenFlags::READ,
+        O_WRONLY => OpenFlags::WRITE,
+        O_RDWR => OpenFlags::READ | OpenFlags::WRITE,
+        _ => return Err(Errno::InvalidArgument),
+    };
+    for (flag, open_flag) in [
+        (O_CREAT, OpenFlags::CREATE),
+        (O_EXCL, OpenFlags::EXCLUSIVE),
+        (O_TRUNC, OpenFlags::TRUNCATE),
+        (O_APPEND, OpenFlags::APPEND),
+        (O_DIRECTORY, OpenFlags::DIRECTORY),
+        (O_NOFOLLOW, OpenFlags::NO_FOLLOW),
+    ] {
+        if flags & flag != 0 {
+            open_flags |= open_flag;
+        }
+    }
+    Ok(open_flags)
+}
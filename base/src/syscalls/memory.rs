@@ -0,0 +1,82 @@
+use core::alloc::Layout;
+
+use crate::{errors::MemoryManagerError, traits::MemoryFlags};
+
+use super::{user, Errno};
+
+/// Linux's memory protections
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const PROT_EXEC: usize = 0x4;
+
+/// Linux's `mmap` flags that are understood
+const MAP_TYPE: usize = 0xF;
+const MAP_PRIVATE: usize = 0x2;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+const MAP_FIXED_NOREPLACE: usize = 0x10_0000;
+
+/// `mmap(address, length, protection, flags, fd, offset)`, which only maps private anonymous memory.
+/// Without `MAP_FIXED` or `MAP_FIXED_NOREPLACE` the address is only a hint, which is ignored
+pub fn mmap([address, length, protection, flags, _, offset]: [usize; 6]) -> Result<usize, Errno> {
+    if flags & MAP_ANONYMOUS == 0 {
+        return Err(Errno::NoDevice);
+    }
+    if flags & MAP_TYPE != MAP_PRIVATE || length == 0 || offset % 4096 != 0 {
+        return Err(Errno::InvalidArgument);
+    }
+    let layout = Layout::from_size_align(length, 4096).map_err(|_| Errno::OutOfMemory)?;
+    let address_space = user::address_space()?;
+
+    // `MAP_FIXED` replaces whatever was there, though only whole regions
+    if flags & MAP_FIXED != 0 {
+        address_space.unmap_range(address, length)?;
+    }
+    let fixed = flags & (MAP_FIXED | MAP_FIXED_NOREPLACE) != 0;
+    Ok(address_space.map(fixed.then_some(address), layout, memory_flags(protection))?)
+}
+
+/// `munmap(address, length)`, which can only unmap whole regions
+pub fn munmap([address, length, ..]: [usize; 6]) -> Result<usize, Errno> {
+    if length == 0 {
+        return Err(Errno::InvalidArgument);
+    }
+    user::address_space()?.unmap_range(address, length)?;
+    Ok(0)
+}
+
+/// `mprotect(address, length, protection)`, which can only change whole regions
+pub fn mprotect([address, length, protection, ..]: [usize; 6]) -> Result<usize, Errno> {
+    user::address_space()?
+        .protect(address, length, memory_flags(protection))
+        .map_err(|e| match e {
+            MemoryManagerError::AddressUnmapped => Errno::OutOfMemory,
+            e => Errno::from(e),
+        })?;
+    Ok(0)
+}
+
+/// `brk(address)`, which moves the end of the heap and gets where it ends.
+/// If it can't be moved, it's left where it is, so `brk(0)` gets it
+pub fn brk([address, ..]: [usize; 6]) -> Result<usize, Errno> {
+    let address_space = user::address_space()?;
+    Ok(address_space
+        .set_program_break(address)
+        .unwrap_or_else(|_| address_space.program_break()))
+}
+
+/// Convert Linux's memory protections to what memory's mapped with.
+/// Memory can't be mapped without being readable, so `PROT_NONE` is too
+fn memory_flags(protection: usize) -> MemoryFlags {
+    let mut flags = MemoryFlags::none();
+    for (protect, flag) in [
+        (PROT_READ, MemoryFlags::READABLE),
+        (PROT_WRITE, MemoryFlags::WRITABLE),
+        (PROT_EXEC, MemoryFlags::EXECUTABLE),
+    ] {
+        if protection & protect != 0 {
+            flags |= flag;
+        }
+    }
+    flags
+}
@@ -0,0 +1,108 @@
+use crate::process::{self, Process};
+
+mod errno;
+pub use errno::Errno;
+
+mod files;
+mod memory;
+mod tasks;
+mod time;
+mod user;
+
+/// The numbers system calls are made with, which are Linux's,
+/// so C libraries written for it can be ported
+pub mod numbers {
+    /// Read from a file descriptor
+    pub const READ: usize = 0;
+    /// Write to a file descriptor
+    pub const WRITE: usize = 1;
+    /// Close a file descriptor
+    pub const CLOSE: usize = 3;
+    /// Map anonymous memory
+    pub const MMAP: usize = 9;
+    /// Change what memory is mapped with
+    pub const MPROTECT: usize = 10;
+    /// Unmap memory
+    pub const MUNMAP: usize = 11;
+    /// Move the end of the heap
+    pub const BRK: usize = 12;
+    /// Let another thread run
+    pub const SCHED_YIELD: usize = 24;
+    /// Sleep for a while
+    pub const NANOSLEEP: usize = 35;
+    /// Get the calling process's ID
+    pub const GETPID: usize = 39;
+    /// Make a child process that's a copy of the calling one
+    pub const FORK: usize = 57;
+    /// The same as `fork`, as the child's memory is always a copy
+    pub const VFORK: usize = 58;
+    /// Replace the calling process's program
+    pub const EXECVE: usize = 59;
+    /// End the calling thread, and its process if it's the last one
+    pub const EXIT: usize = 60;
+    /// Wait for a child process to exit, and reap it
+    pub const WAIT4: usize = 61;
+    /// Get the calling process's parent's ID
+    pub const GETPPID: usize = 110;
+    /// Read a clock
+    pub const CLOCK_GETTIME: usize = 228;
+    /// End the calling process, and all of its threads
+    pub const EXIT_GROUP: usize = 231;
+    /// Open a file, relative to a directory
+    pub const OPENAT: usize = 257;
+}
+
+/// A system call, which is given its six arguments and returns its result
+type Handler = fn([usize; 6]) -> Result<usize, Errno>;
+
+/// How many system call numbers there are room for
+const TABLE_SIZE: usize = 512;
+
+/// The handler for each system call number
+static TABLE: [Option<Handler>; TABLE_SIZE] = {
+    let mut table: [Option<Handler>; TABLE_SIZE] = [None; TABLE_SIZE];
+    table[numbers::READ] = Some(files::read);
+    table[numbers::WRITE] = Some(files::write);
+    table[numbers::CLOSE] = Some(files::close);
+    table[numbers::MMAP] = Some(memory::mmap);
+    table[numbers::MPROTECT] = Some(memory::mprotect);
+    table[numbers::MUNMAP] = Some(memory::munmap);
+    table[numbers::BRK] = Some(memory::brk);
+    table[numbers::SCHED_YIELD] = Some(tasks::sched_yield);
+    table[numbers::NANOSLEEP] = Some(time::nanosleep);
+    table[numbers::GETPID] = Some(tasks::getpid);
+    table[numbers::FORK] = Some(tasks::fork);
+    table[numbers::VFORK] = Some(tasks::fork);
+    table[numbers::EXECVE] = Some(tasks::execve);
+    table[numbers::EXIT] = Some(tasks::exit);
+    table[numbers::WAIT4] = Some(tasks::wait4);
+    table[numbers::GETPPID] = Some(tasks::getppid);
+    table[numbers::CLOCK_GETTIME] = Some(time::clock_gettime);
+    table[numbers::EXIT_GROUP] = Some(tasks::exit_group);
+    table[numbers::OPENAT] = Some(files::openat);
+    table
+};
+
+/// Get the process making the system call
+fn current() -> Result<Arc<Process>, Errno> {
+    process::current().ok_or(Errno::NoSuchProcess)
+}
+
+/// Run the system call with the given number, from the calling thread,
+/// and get the result to return to user mode.
+///
+/// Failures return a negated error number. If the thread's process was told to exit in the meantime, the thread ends instead
+#[must_use]
+#[allow(clippy::cast_possible_wrap)]
+pub fn dispatch(number: usize, arguments: [usize; 6]) -> isize {
+    let result = TABLE
+        .get(number)
+        .copied()
+        .flatten()
+        .map_or(Err(Errno::NoSuchSyscall), |handler| handler(arguments));
+    process::check_exiting();
+    match result {
+        Ok(value) => value as isize,
+        Err(errno) => errno.result(),
+    }
+}
@@ -0,0 +1,146 @@
+use log::debug;
+
+use crate::{
+    arch::syscall::{self, SyscallFrame},
+    fs::{self, OpenFlags},
+    process::{self, ExitStatus, Fault, Pid, Process},
+    scheduler,
+    traits::FileKind,
+};
+
+use super::{current, user, Errno};
+
+/// The `wait4` option to return straight away if no child has exited
+const WNOHANG: usize = 1;
+
+/// The signals Linux would kill a process with for each kind of fault
+const SIGILL: i32 = 4;
+const SIGFPE: i32 = 8;
+const SIGKILL: i32 = 9;
+const SIGSEGV: i32 = 11;
+
+/// `sched_yield()`
+#[allow(clippy::unnecessary_wraps)]
+pub fn sched_yield(_: [usize; 6]) -> Result<usize, Errno> {
+    scheduler::yield_now();
+    Ok(0)
+}
+
+/// `getpid()`
+#[allow(clippy::cast_possible_truncation)]
+pub fn getpid(_: [usize; 6]) -> Result<usize, Errno> {
+    current().map(|process| process.pid().0 as usize)
+}
+
+/// `getppid()`, which is 0 for a process without a parent
+#[allow(clippy::cast_possible_truncation)]
+pub fn getppid(_: [usize; 6]) -> Result<usize, Errno> {
+    let parent = current()?.parent();
+    Ok(parent.map_or(0, |parent| parent.pid().0 as usize))
+}
+
+/// `fork()`, which returns the child's ID in the parent, and 0 in the child
+#[allow(clippy::cast_possible_truncation)]
+pub fn fork(_: [usize; 6]) -> Result<usize, Errno> {
+    let mut frame = unsafe { syscall::current_frame() }.clone();
+    frame.rax = 0;
+    let child = current()?.fork(frame)?;
+    Ok(child.pid().0 as usize)
+}
+
+/// `execve(path, arguments, environment)`, which only returns if it fails.
+/// Otherwise the calling thread carries on from the new program's entry, with nothing in its registers.
+/// It fails with `EOPNOTSUPP` if the process has other threads, which Linux would end
+pub fn execve([path, arguments, environment, ..]: [usize; 6]) -> Result<usize, Errno> {
+    let path = user::read_path(path)?;
+    let arguments = user::read_strings(arguments)?;
+    let environment = user::read_strings(environment)?;
+    let process = current()?;
+    let program = read_program(&process, &path)?;
+
+    let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+    let environment: Vec<&str> = environment.iter().map(String::as_str).collect();
+    let name = path.rsplit('/').next().unwrap_or(&path);
+    let (entry, stack) = process.exec(name, &program, &arguments, &environment)?;
+
+    *unsafe { syscall::current_frame() } = SyscallFrame::new_user(entry, stack);
+    Ok(0)
+}
+
+/// `wait4(pid, status, options, usage)`, where `pid` is a child's ID, or anything else for any child,
+/// as there are no process groups. Only `WNOHANG` is understood, and no resource usage is reported
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub fn wait4([pid, status, options, ..]: [usize; 6]) -> Result<usize, Errno> {
+    let pid = u64::try_from(pid as i32)
+        .ok()
+        .filter(|&pid| pid > 0)
+        .map(Pid);
+    // The child's gone once it's reaped, so its status has to have somewhere to go
+    if status != 0 {
+        user::check_access(status, 4, true)?;
+    }
+
+    match current()?.wait_child(pid, options & WNOHANG == 0)? {
+        Some((pid, exit_status)) => {
+            if status != 0 {
+                user::write_bytes(status, &wait_status(exit_status).to_ne_bytes())?;
+            }
+            Ok(pid.0 as usize)
+        }
+        None => Ok(0),
+    }
+}
+
+/// `exit(status)`
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub fn exit([status, ..]: [usize; 6]) -> Result<usize, Errno> {
+    debug!(
+        "{:?} in process {} exited with status {}",
+        scheduler::current().id(),
+        process::current_pid().0,
+        status as i32
+    );
+    process::exit_thread(ExitStatus::Exited(status as i32))
+}
+
+/// `exit_group(status)`, which ends every thread in the process
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub fn exit_group([status, ..]: [usize; 6]) -> Result<usize, Errno> {
+    debug!(
+        "Process {} exited with status {}",
+        process::current_pid().0,
+        status as i32
+    );
+    process::exit(ExitStatus::Exited(status as i32))
+}
+
+/// Read the whole of a program, which has to be a regular file
+fn read_program(process: &Process, path: &str) -> Result<Vec<u8>, Errno> {
+    let start = process.files().working_directory();
+    let file = fs::open(Some(&start), path, OpenFlags::READ, 0)?;
+    if file.metadata()?.kind != FileKind::File {
+        return Err(Errno::PermissionDenied);
+    }
+
+    let mut program = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(program);
+        }
+        program.extend_from_slice(&buffer[..read]);
+    }
+}
+
+/// Encode how a process ended the way `wait` reports it: an exit code in the second byte,
+/// or the signal Linux would have killed it with in the first
+const fn wait_status(status: ExitStatus) -> i32 {
+    match status {
+        ExitStatus::Exited(code) => (code & 0xFF) << 8,
+        ExitStatus::Faulted(Fault::Instruction) => SIGILL,
+        ExitStatus::Faulted(Fault::Arithmetic) => SIGFPE,
+        ExitStatus::Faulted(Fault::Memory) => SIGSEGV,
+        ExitStatus::Faulted(Fault::Other) => SIGKILL,
+    }
+}
@@ -0,0 +1,74 @@
+use core::time::Duration;
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    scheduler,
+    traits::{Platform, TimerManager},
+};
+
+use super::{user, Errno};
+
+/// Linux's clocks that are understood
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const CLOCK_MONOTONIC_RAW: usize = 4;
+const CLOCK_REALTIME_COARSE: usize = 5;
+const CLOCK_MONOTONIC_COARSE: usize = 6;
+const CLOCK_BOOTTIME: usize = 7;
+
+/// The size of a `struct timespec`, which is a 64-bit count of seconds and one of nanoseconds
+const TIMESPEC_SIZE: usize = 16;
+
+/// `clock_gettime(clock, time)`. The real-time clock counts from boot
+/// until the platform has read the time
+pub fn clock_gettime([clock, time, ..]: [usize; 6]) -> Result<usize, Errno> {
+    let timers = PLATFORM_MANAGER.get_timer_manager();
+    let now = match clock {
+        CLOCK_REALTIME | CLOCK_REALTIME_COARSE => {
+            timers.now().unwrap_or_else(|| timers.monotonic())
+        }
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {
+            timers.monotonic()
+        }
+        _ => return Err(Errno::InvalidArgument),
+    };
+    write_timespec(time, now)?;
+    Ok(0)
+}
+
+/// `nanosleep(duration, remaining)`. Sleeps are never interrupted, so nothing is remaining
+pub fn nanosleep([duration, ..]: [usize; 6]) -> Result<usize, Errno> {
+    scheduler::sleep(read_timespec(duration)?);
+    Ok(0)
+}
+
+/// Read a `struct timespec` from user mode
+///
+/// # Errors
+/// This will return an error if it can't be read, or is negative or has too many nanoseconds
+fn read_timespec(address: usize) -> Result<Duration, Errno> {
+    let mut bytes = [0; TIMESPEC_SIZE];
+    user::read_bytes(address, &mut bytes)?;
+    let (seconds, nanoseconds) = bytes.split_at(8);
+    let seconds = i64::from_ne_bytes(seconds.try_into().unwrap_or_default());
+    let nanoseconds = i64::from_ne_bytes(nanoseconds.try_into().unwrap_or_default());
+
+    match (u64::try_from(seconds), u32::try_from(nanoseconds)) {
+        (Ok(seconds), Ok(nanoseconds)) if nanoseconds < 1_000_000_000 => {
+            Ok(Duration::new(seconds, nanoseconds))
+        }
+        _ => Err(Errno::InvalidArgument),
+    }
+}
+
+/// Write a `struct timespec` to user mode
+///
+/// # Errors
+/// This will return an error if it can't be written
+#[allow(clippy::cast_possible_wrap)]
+fn write_timespec(address: usize, time: Duration) -> Result<(), Errno> {
+    let mut bytes = [0; TIMESPEC_SIZE];
+    bytes[..8].copy_from_slice(&(time.as_secs() as i64).to_ne_bytes());
+    bytes[8..].copy_from_slice(&i64::from(time.subsec_nanos()).to_ne_bytes());
+    user::write_bytes(address, &bytes)
+}
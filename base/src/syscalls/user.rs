@@ -0,0 +1,113 @@
+use crate::memory::AddressSpace;
+
+use super::{current, Errno};
+
+/// The longest path that's read from user mode, including its terminating nul, as on Linux
+const PATH_MAX: usize = 4096;
+
+/// The longest argument or environment string that's read from user mode, as on Linux
+const MAX_ARGUMENT_LENGTH: usize = 32 * 4096;
+
+/// The most arguments or environment strings that are read from user mode
+const MAX_ARGUMENTS: usize = 4096;
+
+/// Get the address space of the process making the system call
+pub fn address_space() -> Result<Arc<AddressSpace>, Errno> {
+    current().map(|process| process.address_space())
+}
+
+/// Copy `buffer.len()` bytes from user mode's `address`
+///
+/// # Errors
+/// This will return [`Errno::BadAddress`] if any of them can't be read by user mode
+pub fn read_bytes(address: usize, buffer: &mut [u8]) -> Result<(), Errno> {
+    address_space()?
+        .read_bytes(address, buffer)
+        .map_err(|_| Errno::BadAddress)
+}
+
+/// Check that user mode can access all of `length` bytes from `address`,
+/// and write to them if `write` is set
+///
+/// # Errors
+/// This will return [`Errno::BadAddress`] if it can't
+pub fn check_access(address: usize, length: usize, write: bool) -> Result<(), Errno> {
+    address_space()?
+        .is_accessible(address, length, write)
+        .then_some(())
+        .ok_or(Errno::BadAddress)
+}
+
+/// Copy `bytes` to user mode's `address`
+///
+/// # Errors
+/// This will return [`Errno::BadAddress`] if any of it can't be written by user mode
+pub fn write_bytes(address: usize, bytes: &[u8]) -> Result<(), Errno> {
+    check_access(address, bytes.len(), true)?;
+    address_space()?
+        .write_bytes(address, bytes)
+        .map_err(|_| Errno::BadAddress)
+}
+
+/// Read a nul terminated path from user mode's `address`
+///
+/// # Errors
+/// This will return an error if it can't be read by user mode, it's too long, or it isn't UTF-8
+pub fn read_path(address: usize) -> Result<String, Errno> {
+    read_string(address, PATH_MAX).and_then(|path| path.ok_or(Errno::NameTooLong))
+}
+
+/// Read a null terminated array of pointers to nul terminated strings from user mode's `address`,
+/// like a program's arguments. A null `address` is taken as an empty array
+///
+/// # Errors
+/// This will return an error if they can't be read by user mode, they're too long,
+/// or they aren't UTF-8
+pub fn read_strings(address: usize) -> Result<Vec<String>, Errno> {
+    let mut strings = Vec::new();
+    if address == 0 {
+        return Ok(strings);
+    }
+    for index in 0..=MAX_ARGUMENTS {
+        let mut pointer = [0; 8];
+        let entry = index
+            .checked_mul(8)
+            .and_then(|offset| address.checked_add(offset))
+            .ok_or(Errno::BadAddress)?;
+        read_bytes(entry, &mut pointer)?;
+        let pointer = usize::from_ne_bytes(pointer);
+        if pointer == 0 {
+            return Ok(strings);
+        }
+        strings.push(read_string(pointer, MAX_ARGUMENT_LENGTH)?.ok_or(Errno::ArgumentListTooLong)?);
+    }
+    Err(Errno::ArgumentListTooLong)
+}
+
+/// Read a nul terminated string of up to `max` bytes, including the nul,
+/// from user mode's `address`, or get `None` if it's longer
+///
+/// # Errors
+/// This will return an error if it can't be read by user mode, or it isn't UTF-8
+fn read_string(address: usize, max: usize) -> Result<Option<String>, Errno> {
+    let address_space = address_space()?;
+    let mut string = Vec::new();
+    let mut next = address;
+    // It's read a page at a time, as the page after its end might not be mapped
+    while string.len() < max {
+        let length = (4096 - next % 4096).min(max - string.len());
+        let start = string.len();
+        string.resize(start + length, 0);
+        address_space
+            .read_bytes(next, &mut string[start..])
+            .map_err(|_| Errno::BadAddress)?;
+        if let Some(end) = string[start..].iter().position(|&byte| byte == 0) {
+            string.truncate(start + end);
+            return String::from_utf8(string)
+                .map(Some)
+                .map_err(|_| Errno::InvalidArgument);
+        }
+        next = next.checked_add(length).ok_or(Errno::BadAddress)?;
+    }
+    Ok(None)
+}
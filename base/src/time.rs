@@ -0,0 +1,151 @@
+use core::{fmt, time::Duration};
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    traits::{Platform, TimerManager},
+};
+
+/// A calendar date and time, in UTC
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    /// The year
+    pub year: u16,
+    /// The month, from 1 to 12
+    pub month: u8,
+    /// The day of the month, from 1 to 31
+    pub day: u8,
+    /// The hour, from 0 to 23
+    pub hour: u8,
+    /// The minute, from 0 to 59
+    pub minute: u8,
+    /// The second, from 0 to 59
+    pub second: u8,
+    /// The nanosecond, from 0 to 999,999,999
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    /// Seconds in a day
+    const SECONDS_PER_DAY: u64 = 86_400;
+
+    /// Days in a 400 year era
+    const DAYS_PER_ERA: u64 = 146_097;
+
+    /// Days from 0000-03-01 to 1970-01-01
+    const EPOCH_OFFSET: u64 = 719_468;
+
+    /// Make a date and time from the time since the UNIX epoch
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn from_unix(time: Duration) -> Self {
+        let seconds = time.as_secs();
+        let days = seconds / Self::SECONDS_PER_DAY + Self::EPOCH_OFFSET;
+        let of_day = seconds % Self::SECONDS_PER_DAY;
+
+        // Years are counted from March, so the leap day is at the end of the year
+        let era = days / Self::DAYS_PER_ERA;
+        let day_of_era = days % Self::DAYS_PER_ERA;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (of_day / 3600) as u8,
+            minute: (of_day / 60 % 60) as u8,
+            second: (of_day % 60) as u8,
+            nanosecond: time.subsec_nanos(),
+        }
+    }
+
+    /// Get the time since the UNIX epoch. Times before the epoch are clamped to it
+    #[must_use]
+    pub fn to_unix(&self) -> Duration {
+        let month = u64::from(self.month);
+        let year = u64::from(self.year) - u64::from(month <= 2);
+
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + u64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = (era * Self::DAYS_PER_ERA + day_of_era).saturating_sub(Self::EPOCH_OFFSET);
+
+        Duration::new(
+            days * Self::SECONDS_PER_DAY
+                + u64::from(self.hour) * 3600
+                + u64::from(self.minute) * 60
+                + u64::from(self.second),
+            self.nanosecond,
+        )
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Format as ISO 8601, to the millisecond
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.nanosecond / 1_000_000
+        )
+    }
+}
+
+/// Get the time since the UNIX epoch, or `None` if the wall clock hasn't been read yet
+pub fn now() -> Option<Duration> {
+    PLATFORM_MANAGER.get_timer_manager().now()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epoch() {
+        let epoch = DateTime::from_unix(Duration::ZERO);
+        assert_eq!((epoch.year, epoch.month, epoch.day), (1970, 1, 1));
+        assert_eq!((epoch.hour, epoch.minute, epoch.second), (0, 0, 0));
+        assert_eq!(epoch.to_unix(), Duration::ZERO);
+    }
+
+    #[test]
+    fn leap_days() {
+        // 2000 is a leap year, as it's divisible by 400, and 2100 isn't
+        let leap = DateTime::from_unix(Duration::from_secs(951_782_400));
+        assert_eq!((leap.year, leap.month, leap.day), (2000, 2, 29));
+        let after = DateTime::from_unix(Duration::from_secs(4_107_542_400));
+        assert_eq!((after.year, after.month, after.day), (2100, 3, 1));
+    }
+
+    #[test]
+    fn round_trip() {
+        // A day and a bit apart, from the epoch to past 2100
+        for seconds in (0..5_000_000_000).step_by(90_061) {
+            let time = Duration::new(seconds, 123_456_789);
+            assert_eq!(DateTime::from_unix(time).to_unix(), time);
+        }
+    }
+
+    #[test]
+    fn display() {
+        let time = DateTime::from_unix(Duration::new(1_700_000_000, 987_654_321));
+        assert_eq!(time.to_string(), "2023-11-14T22:13:20.987Z");
+    }
+}
@@ -0,0 +1,324 @@
+use core::{cmp::Reverse, time::Duration};
+
+use crate::{
+    arch::PLATFORM_MANAGER,
+    errors::TimerManagerError,
+    traits::{Platform, TimerCallback, TimerId, TimerKind, TimerManager},
+};
+
+/// A handle to a timer, which can be used to cancel it.
+/// Dropping the handle leaves the timer running
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerHandle {
+    /// The index of the core whose queue the timer is in
+    pub core: usize,
+    /// The timer's ID, unique across every core
+    pub id: TimerId,
+}
+
+impl TimerHandle {
+    /// Cancel the timer, so it won't fire again
+    ///
+    /// # Errors
+    /// This will return an error if the timer has already finished or been cancelled
+    pub fn cancel(&self) -> Result<(), TimerManagerError> {
+        PLATFORM_MANAGER.get_timer_manager().clear_timer(self)
+    }
+}
+
+/// Set a timer on the current core, see [`TimerManager::set_timer`]
+///
+/// # Errors
+/// This will return an error if the timer couldn't be set
+pub fn set_timer(
+    duration: Duration,
+    kind: TimerKind,
+    callback: TimerCallback,
+) -> Result<TimerHandle, TimerManagerError> {
+    PLATFORM_MANAGER
+        .get_timer_manager()
+        .set_timer(duration, kind, callback)
+}
+
+/// A timer in a [`TimerQueue`]
+struct TimerEvent {
+    deadline: Duration,
+    period: Option<Duration>,
+    /// This is taken while the callback runs, so it's called without holding the queue's lock
+    callback: Option<TimerCallback>,
+    /// Set if the timer was cancelled while its callback was running
+    cancelled: bool,
+    /// Set once a one-shot timer has fired, or a cancelled timer's callback has returned.
+    /// Finished timers are dropped on the next insertion, outside of interrupt context
+    finished: bool,
+}
+
+/// A core's pending timers, ordered by deadline
+///
+/// Firing a timer never allocates or frees memory, so it's safe to do from an interrupt handler.
+/// Cancelled timers leave their deadline in the heap, which is skipped once it reaches the top
+pub struct TimerQueue {
+    deadlines: BinaryHeap<Reverse<(Duration, TimerId)>>,
+    events: BTreeMap<TimerId, TimerEvent>,
+}
+
+impl TimerQueue {
+    /// Make an empty queue
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            deadlines: BinaryHeap::new(),
+            events: BTreeMap::new(),
+        }
+    }
+
+    /// Add a timer
+    ///
+    /// # Errors
+    /// This will return an error if a periodic timer has a period of zero
+    pub fn insert(
+        &mut self,
+        id: TimerId,
+        deadline: Duration,
+        kind: TimerKind,
+        duration: Duration,
+        callback: TimerCallback,
+    ) -> Result<(), TimerManagerError> {
+        if kind == TimerKind::Periodic && duration.is_zero() {
+            return Err(TimerManagerError::InvalidDuration);
+        }
+
+        self.events
+            .retain(|_, event| !(event.finished && event.callback.is_some()));
+
+        self.events.insert(
+            id,
+            TimerEvent {
+                deadline,
+                period: (kind == TimerKind::Periodic).then_some(duration),
+                callback: Some(callback),
+                cancelled: false,
+                finished: false,
+            },
+        );
+        self.deadlines.push(Reverse((deadline, id)));
+        Ok(())
+    }
+
+    /// Cancel a timer
+    ///
+    /// # Errors
+    /// This will return an error if the timer has already finished or been cancelled
+    pub fn cancel(&mut self, id: TimerId) -> Result<(), TimerManagerError> {
+        let event = self
+            .events
+            .get_mut(&id)
+            .filter(|event| !event.finished && !event.cancelled)
+            .ok_or(TimerManagerError::TimerNotPresent)?;
+
+        if event.callback.is_some() {
+            self.events.remove(&id);
+        } else {
+            // The callback is running, so it's put back and dropped later
+            event.cancelled = true;
+        }
+        Ok(())
+    }
+
+    /// Pop deadlines that no longer belong to a pending timer
+    fn skip_stale(&mut self) {
+        while let Some(Reverse((deadline, id))) = self.deadlines.peek().copied() {
+            match self.events.get(&id) {
+                Some(event) if !event.finished && event.deadline == deadline => return,
+                _ => {
+                    self.deadlines.pop();
+                }
+            }
+        }
+    }
+
+    /// Get the earliest deadline of a pending timer
+    pub fn next_deadline(&mut self) -> Option<Duration> {
+        self.skip_stale();
+        self.deadlines
+            .peek()
+            .map(|Reverse((deadline, _))| *deadline)
+    }
+
+    /// Take the callback of a timer whose deadline has passed, rescheduling it if it's periodic.
+    /// The callback must be handed back with [`TimerQueue::restore`] once it's been called
+    pub fn take_expired(&mut self, now: Duration) -> Option<(TimerId, TimerCallback)> {
+        let deadline = self.next_deadline()?;
+        if deadline > now {
+            return None;
+        }
+
+        let Reverse((_, id)) = self.deadlines.pop()?;
+        let event = self.events.get_mut(&id)?;
+        match event.period {
+            Some(period) => {
+                // Skip any periods that were missed, rather than firing in a burst
+                event.deadline = (event.deadline + period).max(now + period);
+                // This can't allocate, as an entry was just popped
+                self.deadlines.push(Reverse((event.deadline, id)));
+            }
+            None => event.finished = true,
+        }
+
+        event.callback.take().map(|callback| (id, callback))
+    }
+
+    /// Hand back a callback taken by [`TimerQueue::take_expired`]
+    pub fn restore(&mut self, id: TimerId, callback: TimerCallback) {
+        if let Some(event) = self.events.get_mut(&id) {
+            event.callback = Some(callback);
+            if event.cancelled {
+                event.finished = true;
+            }
+        }
+    }
+}
+
+impl Default for TimerQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn nothing() -> TimerCallback {
+        Box::new(|_| {})
+    }
+
+    /// Fire everything due by `now`, the way the timer interrupt does, and get what fired in order
+    fn fire(queue: &mut TimerQueue, now: Duration) -> Vec<TimerId> {
+        let mut fired = Vec::new();
+        while let Some((id, mut callback)) = queue.take_expired(now) {
+            callback(id);
+            queue.restore(id, callback);
+            fired.push(id);
+        }
+        fired
+    }
+
+    #[test]
+    fn fires_in_deadline_order() {
+        let mut queue = TimerQueue::new();
+        for (id, deadline) in [(1, 30), (2, 10), (3, 20)] {
+            queue
+                .insert(
+                    TimerId(id),
+                    millis(deadline),
+                    TimerKind::OneShot,
+                    millis(deadline),
+                    nothing(),
+                )
+                .unwrap();
+        }
+        assert_eq!(queue.next_deadline(), Some(millis(10)));
+        assert!(fire(&mut queue, millis(5)).is_empty());
+        assert_eq!(fire(&mut queue, millis(25)), [TimerId(2), TimerId(3)]);
+        assert_eq!(fire(&mut queue, millis(30)), [TimerId(1)]);
+        assert_eq!(queue.next_deadline(), None);
+        // One-shot timers are finished once they've fired
+        assert!(queue.cancel(TimerId(1)).is_err());
+    }
+
+    #[test]
+    fn cancelled_timers_dont_fire() {
+        let mut queue = TimerQueue::new();
+        queue
+            .insert(
+                TimerId(1),
+                millis(10),
+                TimerKind::OneShot,
+                millis(10),
+                nothing(),
+            )
+            .unwrap();
+        queue
+            .insert(
+                TimerId(2),
+                millis(20),
+                TimerKind::OneShot,
+                millis(20),
+                nothing(),
+            )
+            .unwrap();
+        queue.cancel(TimerId(1)).unwrap();
+        assert!(queue.cancel(TimerId(1)).is_err());
+        // The cancelled deadline is skipped rather than reported
+        assert_eq!(queue.next_deadline(), Some(millis(20)));
+        assert_eq!(fire(&mut queue, millis(50)), [TimerId(2)]);
+    }
+
+    #[test]
+    fn periodic_timers_skip_missed_periods() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let mut queue = TimerQueue::new();
+        queue
+            .insert(
+                TimerId(1),
+                millis(10),
+                TimerKind::Periodic,
+                millis(10),
+                Box::new(|_| {
+                    CALLS.fetch_add(1, Ordering::Relaxed);
+                }),
+            )
+            .unwrap();
+        assert_eq!(fire(&mut queue, millis(10)), [TimerId(1)]);
+        assert_eq!(queue.next_deadline(), Some(millis(20)));
+        // Far behind, it fires once and is rescheduled a period from now
+        assert_eq!(fire(&mut queue, millis(95)), [TimerId(1)]);
+        assert_eq!(queue.next_deadline(), Some(millis(105)));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+        queue.cancel(TimerId(1)).unwrap();
+        assert_eq!(queue.next_deadline(), None);
+    }
+
+    #[test]
+    fn periodic_timers_need_a_period() {
+        let mut queue = TimerQueue::new();
+        assert!(matches!(
+            queue.insert(
+                TimerId(1),
+                millis(0),
+                TimerKind::Periodic,
+                Duration::ZERO,
+                nothing()
+            ),
+            Err(TimerManagerError::InvalidDuration)
+        ));
+    }
+
+    #[test]
+    fn cancelling_from_a_running_callback() {
+        let mut queue = TimerQueue::new();
+        queue
+            .insert(
+                TimerId(1),
+                millis(10),
+                TimerKind::Periodic,
+                millis(10),
+                nothing(),
+            )
+            .unwrap();
+        let (id, callback) = queue.take_expired(millis(10)).unwrap();
+        // While its callback runs it's cancelled, so it's finished once it's handed back
+        queue.cancel(id).unwrap();
+        assert!(queue.cancel(id).is_err());
+        queue.restore(id, callback);
+        assert_eq!(queue.next_deadline(), None);
+        assert!(fire(&mut queue, millis(100)).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::errors::FileSystemError;
+
+/// The number of an inode, unique within its filesystem
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InodeId(pub u64);
+
+/// What an inode is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    /// A regular file
+    File,
+    /// A directory
+    Directory,
+    /// A symbolic link
+    Symlink,
+    /// A character device, which is read and written a byte at a time
+    CharDevice,
+}
+
+/// What's known about an inode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// The inode's number
+    pub inode: InodeId,
+    /// What the inode is
+    pub kind: FileKind,
+    /// The permission bits
+    pub mode: u32,
+    /// The size of the contents, or of a symbolic link's target
+    pub size: u64,
+    /// The number of names the inode has
+    pub links: u32,
+}
+
+/// A name in a directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    /// The name
+    pub name: String,
+    /// The inode it names
+    pub inode: InodeId,
+    /// What the inode is
+    pub kind: FileKind,
+}
+
+/// Trait for a filesystem that can be mounted in the VFS.
+/// Everything is addressed by inode number, and paths are resolved by the VFS a name at a time
+pub trait FileSystem: Send + Sync {
+    /// Get the filesystem's type, like `initrd`
+    fn name(&self) -> &'static str;
+
+    /// Get the root directory's inode number
+    fn root(&self) -> InodeId;
+
+    /// Find a name in a directory
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there, or `directory` isn't a directory
+    fn lookup(&self, directory: InodeId, name: &str) -> Result<InodeId, FileSystemError>;
+
+    /// Get what's known about an inode
+    ///
+    /// # Errors
+    /// This will return an error if the inode doesn't exist
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FileSystemError>;
+
+    /// Copy a file's contents from `offset` into `buffer`, and get how much was copied,
+    /// which is less than asked for at the end of the file
+    ///
+    /// # Errors
+    /// This will return an error if the inode doesn't exist or is a directory
+    fn read(
+        &self,
+        inode: InodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, FileSystemError>;
+
+    /// Copy `buffer` into a file at `offset`, growing it if need be, and get how much was copied
+    ///
+    /// # Errors
+    /// This will return an error if the inode doesn't exist, is a directory,
+    /// or the filesystem is read-only, which it is unless this is implemented
+    fn write(&self, inode: InodeId, offset: u64, buffer: &[u8]) -> Result<usize, FileSystemError> {
+        let _ = (inode, offset, buffer);
+        Err(FileSystemError::ReadOnly)
+    }
+
+    /// Set a file's size, zeroing anything it grows by
+    ///
+    /// # Errors
+    /// This will return an error if the inode doesn't exist, is a directory,
+    /// or the filesystem is read-only, which it is unless this is implemented
+    fn truncate(&self, inode: InodeId, size: u64) -> Result<(), FileSystemError> {
+        let _ = (inode, size);
+        Err(FileSystemError::ReadOnly)
+    }
+
+    /// Get what's in a directory, not including `.` and `..`
+    ///
+    /// # Errors
+    /// This will return an error if the inode doesn't exist or isn't a directory
+    fn read_dir(&self, directory: InodeId) -> Result<Vec<DirectoryEntry>, FileSystemError>;
+
+    /// Get a symbolic link's target
+    ///
+    /// # Errors
+    /// This will return an error if the inode doesn't exist or isn't a symbolic link
+    fn read_link(&self, inode: InodeId) -> Result<String, FileSystemError>;
+
+    /// Make an empty file or directory in a directory
+    ///
+    /// # Errors
+    /// This will return an error if the name is taken, `directory` isn't a directory,
+    /// or the filesystem is read-only, which it is unless this is implemented
+    fn create(
+        &self,
+        directory: InodeId,
+        name: &str,
+        kind: FileKind,
+        mode: u32,
+    ) -> Result<InodeId, FileSystemError> {
+        let _ = (directory, name, kind, mode);
+        Err(FileSystemError::ReadOnly)
+    }
+
+    /// Give an inode that isn't a directory another name, in a directory
+    ///
+    /// # Errors
+    /// This will return an error if the name is taken, `inode` is a directory,
+    /// or the filesystem is read-only, which it is unless this is implemented
+    fn link(&self, inode: InodeId, directory: InodeId, name: &str) -> Result<(), FileSystemError> {
+        let _ = (inode, directory, name);
+        Err(FileSystemError::ReadOnly)
+    }
+
+    /// Remove a name that isn't a directory from a directory.
+    /// The inode is freed once it has no names left
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there or is a directory,
+    /// or the filesystem is read-only, which it is unless this is implemented
+    fn unlink(&self, directory: InodeId, name: &str) -> Result<(), FileSystemError> {
+        let _ = (directory, name);
+        Err(FileSystemError::ReadOnly)
+    }
+
+    /// Remove an empty directory from a directory
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there, isn't a directory, or isn't empty,
+    /// or the filesystem is read-only, which it is unless this is implemented
+    fn remove_directory(&self, directory: InodeId, name: &str) -> Result<(), FileSystemError> {
+        let _ = (directory, name);
+        Err(FileSystemError::ReadOnly)
+    }
+
+    /// Move a name to another, possibly in another directory, replacing whatever was there.
+    /// A directory can only replace an empty directory, and anything else can't replace one
+    ///
+    /// # Errors
+    /// This will return an error if the name isn't there, it can't replace what's at the new name,
+    /// a directory would be moved inside itself,
+    /// or the filesystem is read-only, which it is unless this is implemented
+    fn rename(
+        &self,
+        from_directory: InodeId,
+        from_name: &str,
+        to_directory: InodeId,
+        to_name: &str,
+    ) -> Result<(), FileSystemError> {
+        let _ = (from_directory, from_name, to_directory, to_name);
+        Err(FileSystemError::ReadOnly)
+    }
+}
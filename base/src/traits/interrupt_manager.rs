@@ -21,6 +21,29 @@ pub unsafe trait InterruptManager {
     /// This should be a cause for concern
     fn enable_interrupts(&self) -> Result<(), InterruptManagerError>;
 
+    /// Check whether interrupts are enabled on the current core
+    fn interrupts_enabled(&self) -> bool;
+
+    /// Run a closure with interrupts disabled on the current core,
+    /// restoring them afterwards if they were enabled
+    fn without_interrupts<R>(&self, f: impl FnOnce() -> R) -> R {
+        let enabled = self.interrupts_enabled();
+        if enabled {
+            let _ = self.disable_interrupts();
+        }
+
+        let result = f();
+
+        if enabled {
+            let _ = self.enable_interrupts();
+        }
+        result
+    }
+
+    /// Enable interrupts and wait for one to arrive.
+    /// An interrupt arriving as they're enabled still ends the wait
+    fn wait_for_interrupt(&self);
+
     /// Set the interrupt handler. This should only be done once.
     ///
     /// # Errors
@@ -1,4 +1,4 @@
-use core::alloc::Layout;
+use core::{alloc::Layout, ops::Range};
 
 use crate::{
     errors::{GenericError, MemoryManagerError},
@@ -15,6 +15,7 @@ use super::PhysicalAllocator;
 
 bitflags! {
     /// Memory flags that can be used when mapping something
+    #[derive(Debug)]
     pub struct MemoryFlags: u64 {
         const KERNEL_ONLY = 1 << 0;
         const READABLE = 1 << 1;
@@ -69,7 +70,8 @@ pub unsafe trait MemoryManager {
         flags: MemoryFlags,
     ) -> Result<(), MemoryManagerError>;
 
-    /// Unmap a given virtual address
+    /// Unmap a given virtual address.
+    /// This doesn't flush the TLB, see [`MemoryManager::unmap_range`]
     ///
     /// # Safety
     /// The specified root table must be mapped in memory
@@ -83,6 +85,124 @@ pub unsafe trait MemoryManager {
         addr: AlignedAddress<Virtual>,
     ) -> Result<(), MemoryManagerError>;
 
+    /// Change the flags of a mapped virtual address.
+    /// This doesn't flush the TLB, see [`MemoryManager::protect_range`]
+    ///
+    /// # Safety
+    /// The specified root table must be mapped in memory
+    /// Code relying on the old flags must no longer be using the memory
+    ///
+    /// # Errors
+    /// This will return an error if the address isn't mapped, or is in a huge page
+    unsafe fn protect(
+        &self,
+        rtable: &mut Self::RootTable,
+        addr: AlignedAddress<Virtual>,
+        flags: MemoryFlags,
+    ) -> Result<(), MemoryManagerError>;
+
+    /// Invalidate any cached translations of the given virtual address ranges, on every core
+    fn flush_tlb(&self, ranges: &[Range<usize>]);
+
+    /// Unmap `pages` pages starting at a given virtual address,
+    /// then flush them from every core's TLB
+    ///
+    /// # Safety
+    /// The specified root table must be mapped in memory
+    /// This memory must not be in use by the kernel, otherwise undefined behavior may occur
+    ///
+    /// # Errors
+    /// This will return an error if any page is unable to be unmapped.
+    /// The pages before it are still unmapped and flushed
+    unsafe fn unmap_range(
+        &self,
+        rtable: &mut Self::RootTable,
+        addr: AlignedAddress<Virtual>,
+        pages: usize,
+    ) -> Result<(), MemoryManagerError> {
+        let start = usize::try_from(addr.inner().into_raw())
+            .map_err(|_| MemoryManagerError::Generic(GenericError::IntConversionError))?;
+
+        let mut result = Ok(());
+        let mut end = start;
+        for page in (start..start + pages * 4096).step_by(4096) {
+            result = AlignedAddress::<Virtual>::new(page as *const ())
+                .map_err(MemoryManagerError::Address)
+                .and_then(|page| self.unmap(rtable, page));
+            if result.is_err() {
+                break;
+            }
+            end = page + 4096;
+        }
+
+        self.flush_tlb(core::slice::from_ref(&(start..end)));
+        result
+    }
+
+    /// Change the flags of `pages` pages starting at a given virtual address,
+    /// then flush them from every core's TLB
+    ///
+    /// # Safety
+    /// The specified root table must be mapped in memory
+    /// Code relying on the old flags must no longer be using the memory
+    ///
+    /// # Errors
+    /// This will return an error if any page is unable to be changed.
+    /// The pages before it are still changed and flushed
+    unsafe fn protect_range(
+        &self,
+        rtable: &mut Self::RootTable,
+        addr: AlignedAddress<Virtual>,
+        pages: usize,
+        flags: MemoryFlags,
+    ) -> Result<(), MemoryManagerError> {
+        let start = usize::try_from(addr.inner().into_raw())
+            .map_err(|_| MemoryManagerError::Generic(GenericError::IntConversionError))?;
+
+        let mut result = Ok(());
+        let mut end = start;
+        for page in (start..start + pages * 4096).step_by(4096) {
+            result = AlignedAddress::<Virtual>::new(page as *const ())
+                .map_err(MemoryManagerError::Address)
+                .and_then(|page| self.protect(rtable, page, flags));
+            if result.is_err() {
+                break;
+            }
+            end = page + 4096;
+        }
+
+        self.flush_tlb(core::slice::from_ref(&(start..end)));
+        result
+    }
+
+    /// Make a root table for a new address space, which shares every mapping the kernel's has
+    /// outside user space, and has nothing mapped in user space.
+    /// Any kernel mappings made later, through any root table, are seen by all of them
+    ///
+    /// # Safety
+    /// The kernel's root table must be mapped in memory
+    ///
+    /// # Errors
+    /// This will return an error if the table, or any of the kernel's tables it shares, can't be allocated
+    unsafe fn new_user_table(
+        &self,
+        kernel: &mut Self::RootTable,
+    ) -> Result<&'static mut Self::RootTable, MemoryManagerError>;
+
+    /// Free a root table made by [`MemoryManager::new_user_table`],
+    /// along with the tables under it in user space.
+    /// The frames mapped in user space aren't freed, as they belong to whoever mapped them
+    ///
+    /// # Safety
+    /// The table must not be in use on any core, and must never be used again
+    ///
+    /// # Errors
+    /// This will return an error if any of the tables can't be freed
+    unsafe fn free_user_table(
+        &self,
+        table: &'static mut Self::RootTable,
+    ) -> Result<(), MemoryManagerError>;
+
     /// Try to find the physical address for a given virtual address
     ///
     /// If the given root table is not mapped in memory, results are undefined
@@ -141,7 +261,7 @@ pub unsafe trait MemoryManager {
             .allocate(layout)
             .map_err(MemoryManagerError::Allocator)?;
 
-        let pages = align(layout.size(), 4096);
+        let pages = align(layout.size(), 4096) / 4096;
 
         let free_area = self
             .find_free_mapping_area(rtable, allowed_range, pages, layout.align())
@@ -185,7 +305,7 @@ pub unsafe trait MemoryManager {
         addr: AlignedAddress<Virtual>,
         layout: Layout,
     ) -> Result<(), MemoryManagerError> {
-        let pages = align(layout.size(), 4096);
+        let pages = align(layout.size(), 4096) / 4096;
 
         let phys_addr = self
             .virtual_to_physical(&*rtable, addr.into())
@@ -194,15 +314,6 @@ pub unsafe trait MemoryManager {
             .map_err(MemoryManagerError::Address)?;
         PHYSICAL_ALLOCATOR.deallocate(phys_addr, layout);
 
-        #[allow(clippy::cast_possible_truncation)]
-        for addr in (addr.inner().into_raw() as usize
-            ..(addr.inner().into_raw() as usize + (pages * 4096)))
-            .step_by(4096)
-            .filter_map(|addr| AlignedAddress::<Virtual>::new(addr as *const ()).ok())
-        {
-            self.unmap(&mut *rtable, addr)?;
-        }
-
-        Ok(())
+        self.unmap_range(rtable, addr, pages)
     }
 }
@@ -1,3 +1,6 @@
+mod filesystem;
+pub use filesystem::{DirectoryEntry, FileKind, FileSystem, InodeId, Metadata};
+
 mod init;
 pub use init::Init;
 
@@ -20,4 +23,4 @@ mod platform_address;
 pub use platform_address::PlatformAddress;
 
 mod timer_manager;
-pub use timer_manager::TimerManager;
+pub use timer_manager::{TimerCallback, TimerId, TimerKind, TimerManager};
@@ -33,13 +33,22 @@ pub unsafe trait Platform: Init {
     /// Get the platform's power manager
     fn get_power_manager(&'static self) -> &'static Self::PowerManager;
 
-    /// Get the platform's text output
-    #[allow(clippy::mut_from_ref)]
-    fn get_text_output(&'static self) -> &'static mut Self::TextOutput;
+    /// Get the platform's timer manager
+    fn get_timer_manager(&'static self) -> &'static Self::TimerManager;
+
+    /// Get a handle to the platform's text output, which can be written to from any core
+    fn get_text_output(&'static self) -> Self::TextOutput;
 
     /// Initialize the cure this is ran on
     fn initialize_current_core(&'static self);
 
-    /// Get the core local data structure
+    /// Get the core local data structure.
+    /// The current core's must have been set with [`Platform::set_core_local`]
     fn get_core_local(&'static self) -> &'static CoreLocalData;
+
+    /// Get the core local data structure, if the current core's has been set
+    fn try_get_core_local(&'static self) -> Option<&'static CoreLocalData>;
+
+    /// Set the current core's core local data structure
+    fn set_core_local(&'static self, data: &'static CoreLocalData);
 }
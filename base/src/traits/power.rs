@@ -2,6 +2,8 @@ use crate::errors::PowerManagerError;
 
 /// Possible power states
 pub enum PowerState {
+    /// Fully on and running
+    Working,
     /// Peripherals off
     Standby,
     /// Clock down cpu
@@ -18,6 +20,8 @@ pub enum PowerOffKind {
     Reboot,
     /// Shutdown
     Shutdown,
+    /// Shutdown, telling an emulator that something failed when it can be told
+    Failure,
 }
 
 /// Trait for managing power on a platform
@@ -1,10 +1,60 @@
-use crate::errors::TimerManagerError;
+use core::time::Duration;
+
+use crate::{errors::TimerManagerError, timer::TimerHandle};
+
+/// The ID of a timer, unique across every core
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId(pub u64);
+
+/// How often a timer fires
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerKind {
+    /// Fire once, after which the timer is cleared
+    OneShot,
+    /// Fire repeatedly, until the timer is cleared
+    Periodic,
+}
+
+/// The function called when a timer fires.
+/// It's ran in interrupt context, so it must not block or allocate
+pub type TimerCallback = Box<dyn FnMut(TimerId) + Send>;
 
 /// Trait for managing timers
+///
+/// # Safety
+/// The clock returned by [`TimerManager::monotonic`] must never go backwards
 pub unsafe trait TimerManager {
-    /// Set a timer
-    fn set_timer(&self, _: u64, _: f64, _: u64) -> Result<(), TimerManagerError>;
+    /// Get the time since the clock source was initialized
+    fn monotonic(&self) -> Duration;
+
+    /// Get the wall-clock time, as the time since the UNIX epoch.
+    /// This is `None` until the platform has read its real-time clock
+    fn now(&self) -> Option<Duration>;
+
+    /// Busy wait for the given duration
+    fn stall(&self, duration: Duration) {
+        let end = self.monotonic() + duration;
+        while self.monotonic() < end {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Set a timer on the current core, which calls `callback` once `duration` has passed
+    /// and, if it's periodic, every `duration` after that
+    ///
+    /// # Errors
+    /// This will return an error if the timer manager isn't initialized,
+    /// or if a periodic timer has a period of zero
+    fn set_timer(
+        &self,
+        duration: Duration,
+        kind: TimerKind,
+        callback: TimerCallback,
+    ) -> Result<TimerHandle, TimerManagerError>;
 
-    /// Clear a timer
-    fn clear_timer(&self, _: u64) -> Result<(), TimerManagerError>;
+    /// Clear a timer, so it won't fire again
+    ///
+    /// # Errors
+    /// This will return an error if the timer doesn't exist or has already finished
+    fn clear_timer(&self, handle: &TimerHandle) -> Result<(), TimerManagerError>;
 }
@@ -1,6 +1,8 @@
-use std::str::FromStr;
+use std::{path::Path, str::FromStr};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use duct::cmd;
+use log::info;
 
 use thiserror::Error;
 
@@ -98,6 +100,48 @@ impl FromStr for Target {
     }
 }
 
-pub fn build_target(release: bool, target: Target) -> Result<()> {
-    anyhow::bail!("Unimplemented")
+impl TargetArch {
+    /// The Rust target the kernel is built for, which its linker script is named after
+    fn triple(self) -> &'static str {
+        match self {
+            Self::X86_64 => "x86_64-unknown-none",
+        }
+    }
+}
+
+pub fn build_target(release: bool, target: Target, initrd: Option<&Path>) -> Result<()> {
+    match (target.arch, target.firmware, target.bootloader) {
+        (
+            TargetArch::X86_64,
+            TargetFirmware::Bios | TargetFirmware::Uefi,
+            TargetBootloader::Limine,
+        ) => {}
+        (arch, firmware, bootloader) => {
+            bail!("{bootloader:?} can't boot {arch:?} with {firmware:?} firmware")
+        }
+    }
+
+    let triple = target.arch.triple();
+    let kernel = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("base");
+    let linker_script = kernel.join("src/misc").join(format!("{triple}.ld"));
+    let rustflags = format!("-Clink-args=-pie -Clink-args=-T{}", linker_script.display());
+
+    let mut arguments = vec!["build", "--target", triple];
+    if release {
+        arguments.push("--release");
+    }
+    // Run from the kernel's directory, so its config builds the standard library for the target
+    info!("Building the kernel for {triple}");
+    cmd("cargo", &arguments)
+        .dir(&kernel)
+        .env("RUSTFLAGS", rustflags)
+        .run()?;
+
+    if let Some(directory) = initrd {
+        // Loaded as a Limine module, with `initrd` on its command line
+        crate::initrd::pack(directory, Path::new("target/initrd.cpio"))?;
+    }
+    Ok(())
 }
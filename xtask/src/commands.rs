@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 
 use crate::builder::Target;
@@ -25,6 +27,9 @@ pub enum Command {
     Build {
         #[clap(short, long)]
         target: Target,
+        /// A directory to pack into the initial ramdisk
+        #[clap(long)]
+        initrd: Option<PathBuf>,
     },
 }
 
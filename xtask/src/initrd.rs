@@ -0,0 +1,124 @@
+use std::{
+    fs::{self, Metadata},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+
+const S_IFREG: u32 = 0o100_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFLNK: u32 = 0o120_000;
+
+/// Pack a directory into a newc cpio archive, which the kernel unpacks as its initrd
+pub fn pack(directory: &Path, output: &Path) -> Result<()> {
+    let mut archive = Vec::new();
+    let mut inode = 0;
+    add_directory(&mut archive, directory, "", &mut inode)?;
+    write_entry(&mut archive, 0, "TRAILER!!!", 0, 1, &[])?;
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, &archive).with_context(|| format!("Couldn't write {}", output.display()))?;
+    info!(
+        "Packed {} into {} ({} bytes)",
+        directory.display(),
+        output.display(),
+        archive.len()
+    );
+    Ok(())
+}
+
+fn add_directory(
+    archive: &mut Vec<u8>,
+    directory: &Path,
+    prefix: &str,
+    inode: &mut u32,
+) -> Result<()> {
+    let mut entries = fs::read_dir(directory)
+        .with_context(|| format!("Couldn't read {}", directory.display()))?
+        .collect::<Result<Vec<_>, _>>()?;
+    // Sorted so the same directory always packs the same way
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow!("{:?} isn't UTF-8", name))?;
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let metadata = fs::symlink_metadata(entry.path())?;
+        *inode += 1;
+
+        if metadata.is_dir() {
+            write_entry(archive, *inode, &path, S_IFDIR | permissions(&metadata), 2, &[])?;
+            add_directory(archive, &entry.path(), &path, inode)?;
+        } else if metadata.file_type().is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            let target = target
+                .to_str()
+                .ok_or_else(|| anyhow!("The target of {} isn't UTF-8", path))?;
+            write_entry(archive, *inode, &path, S_IFLNK | 0o777, 1, target.as_bytes())?;
+        } else if metadata.is_file() {
+            let data = fs::read(entry.path())?;
+            write_entry(archive, *inode, &path, S_IFREG | permissions(&metadata), 1, &data)?;
+        } else {
+            warn!("Skipping {}, which isn't a file, directory or link", path);
+        }
+    }
+    Ok(())
+}
+
+fn write_entry(
+    archive: &mut Vec<u8>,
+    inode: u32,
+    path: &str,
+    mode: u32,
+    links: u32,
+    data: &[u8],
+) -> Result<()> {
+    let size = u32::try_from(data.len()).with_context(|| format!("{} is too big", path))?;
+    let name_size = u32::try_from(path.len() + 1)?;
+    // The owner, times and devices are all left as zero
+    let fields = [
+        inode, mode, 0, 0, links, 0, size, 0, 0, 0, 0, name_size, 0,
+    ];
+
+    archive.extend_from_slice(b"070701");
+    for field in fields {
+        archive.extend_from_slice(format!("{:08X}", field).as_bytes());
+    }
+    archive.extend_from_slice(path.as_bytes());
+    archive.push(0);
+    pad(archive);
+    archive.extend_from_slice(data);
+    pad(archive);
+    Ok(())
+}
+
+/// The name and data are each padded to four bytes
+fn pad(archive: &mut Vec<u8>) {
+    archive.resize((archive.len() + 3) & !3, 0);
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &Metadata) -> u32 {
+    if metadata.is_dir() {
+        0o755
+    } else if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
@@ -26,7 +26,6 @@ pub fn enable_logging(verbosity: usize) -> anyhow::Result<()> {
             0 => LevelFilter::Info,
             1 => LevelFilter::Debug,
             2.. => LevelFilter::Trace,
-            _ => unreachable!(),
         })
         .chain(std::io::stdout())
         .apply()?;
@@ -4,6 +4,7 @@ use clap::Parser;
 
 mod builder;
 mod commands;
+mod initrd;
 mod logger;
 mod tools;
 
@@ -29,7 +30,10 @@ fn main() -> Result<()> {
             commands::ToolAction::Install { to_add } => install_tools(to_add.as_slice())?,
             commands::ToolAction::Uninstall { to_remove } => uninstall_tools(to_remove.as_slice())?,
         },
-        commands::Command::Build { ref target } => todo!(),
+        commands::Command::Build {
+            ref target,
+            ref initrd,
+        } => builder::build_target(args.release, *target, initrd.as_deref())?,
     }
 
     println!("{:#?}", args);
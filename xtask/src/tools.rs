@@ -24,12 +24,12 @@ pub enum Tool {
     QemuX86_64(ToolInfo),
 }
 
-impl ToString for Tool {
-    fn to_string(&self) -> String {
-        match self {
-            Tool::Git(_) => "git".to_owned(),
-            Tool::QemuX86_64(_) => "qemu".to_owned(),
-        }
+impl Display for Tool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Tool::Git(_) => "git",
+            Tool::QemuX86_64(_) => "qemu",
+        })
     }
 }
 